@@ -0,0 +1,44 @@
+//! Benchmarks of the processing pipeline, run them with `cargo bench`
+//!
+//! Run `cargo bench -- --save-baseline before` before a change and
+//! `cargo bench -- --baseline before` after it to find regressions
+
+use std::hint::black_box;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use dots::{OutputPath, World};
+
+/// Number of files in the synthetic repo
+const FILES: usize = 10_000;
+
+fn benches(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    dots::generate_fixture(dir.path(), FILES).unwrap();
+
+    c.bench_function("world construction", |b| {
+        b.iter(|| World::new(black_box(dir.path())).unwrap());
+    });
+
+    c.bench_function("interpolation parsing", |b| {
+        b.iter(|| {
+            black_box("{config_dir}/foo/{cache_dir}/{temp_dir}/bar")
+                .parse::<OutputPath>()
+                .unwrap()
+        });
+    });
+
+    c.bench_function("plan computation", |b| {
+        b.iter_batched(
+            || World::new(dir.path()).unwrap(),
+            |world| world.process().unwrap(),
+            BatchSize::LargeInput,
+        );
+    });
+}
+
+criterion_group! {
+    name = group;
+    config = Criterion::default().sample_size(10);
+    targets = benches
+}
+criterion_main!(group);
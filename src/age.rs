@@ -0,0 +1,34 @@
+//! Decrypts files encrypted with [`age`](https://age-encryption.org), by running it
+//!
+//! The plaintext is only kept in memory, it is never written to disk
+
+use std::path::Path;
+use std::process::Command;
+
+use eyre::{Context as _, Result, bail, eyre};
+
+use crate::stdx::PathExt as _;
+
+/// Environment variable with the identity that decrypts the config, set by `--identity`
+pub const IDENTITY_VAR: &str = "DOTS_IDENTITY";
+
+/// Decrypt the file at `path` with the `identity`
+pub fn decrypt(path: &Path, identity: &Path) -> Result<String> {
+    let output = Command::new("age")
+        .arg("--decrypt")
+        .arg("--identity")
+        .arg(identity)
+        .arg(path)
+        .output()
+        .context("failed to run `age`")?;
+
+    if !output.status.success() {
+        bail!(
+            "failed to decrypt {}: {}",
+            path.show(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    String::from_utf8(output.stdout).with_context(|| eyre!("{} is not valid UTF-8", path.show()))
+}
@@ -1,60 +1,807 @@
 //! Contains [`Analysis`]
 
-use std::path::PathBuf;
-use std::{fs, io};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
 
-use simply_colored::*;
+use eyre::{Context as _, Result, bail, eyre};
+use serde::{Deserialize, Serialize};
 
-use crate::PathExt as _;
+use crate::asset::Asset;
+use crate::cache::RenderCache;
+use crate::cancel::CancelToken;
+use crate::index::ApplyIndex;
+use crate::journal::Journal;
+use crate::lockfile::NewlyLocked;
+use crate::operation::{Executor, Operation, Watchdog};
+use crate::phase::{self, Phase};
+use crate::plan::{ApplyReport, Failure, Plan, SCHEMA_VERSION, Summary};
+use crate::probe;
+use crate::protect::Protect;
+use crate::sensitive::{REDACTED, Sensitive};
+use crate::stdx::PathExt as _;
+use crate::termux::Termux;
+use crate::user::User;
 
 /// Write contents to the path
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "dots-schema", derive(schemars::JsonSchema))]
 pub struct WritePath {
     /// Path to write
     pub path: PathBuf,
     /// What to write
-    pub contents: String,
+    #[serde(flatten)]
+    pub contents: Contents,
+    /// File in an `input` directory that `contents` were rendered from, or which an
+    /// asset is copied from
+    ///
+    /// `None` for links
+    pub source: Option<PathBuf>,
+    /// Flush the file and its directory to disk after writing it
+    #[serde(default)]
+    pub durable: bool,
+    /// Why the entry that writes the file exists, from its `description`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Create a symlink to the `source` instead of writing `contents`, which are the same
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub symlink: bool,
+    /// Permissions that the file is created with, e.g. `0o600` for a file that others
+    /// must never read. The default permissions if `None`, or on platforms without them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+}
+
+/// What a [`WritePath`] writes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(from = "RawContents", into = "RawContents")]
+#[cfg_attr(feature = "dots-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "dots-schema", schemars(with = "RawContents"))]
+pub enum Contents {
+    /// Text, e.g. a rendered template
+    Text(String),
+    /// A file which is not text, copied as-is from the [`WritePath::source`]
+    Asset(Asset),
+    /// Text which contains a secret, such as a `secret` variable or an encrypted SSH host,
+    /// so it is never shown, cached or printed in a [`Plan`]
+    Secret(Sensitive<String>),
+}
+
+impl Contents {
+    /// The text, which is empty for an asset and [`REDACTED`] for a secret
+    pub fn text(&self) -> &str {
+        match self {
+            Self::Text(text) => text,
+            Self::Asset(_) => "",
+            Self::Secret(_) => REDACTED,
+        }
+    }
+
+    /// The text, including a secret, which must not be shown. Empty for an asset
+    pub fn expose(&self) -> &str {
+        match self {
+            Self::Secret(secret) => secret.expose(),
+            contents => contents.text(),
+        }
+    }
+
+    /// The asset, if the contents are not text
+    pub fn asset(&self) -> Option<&Asset> {
+        match self {
+            Self::Text(_) | Self::Secret(_) => None,
+            Self::Asset(asset) => Some(asset),
+        }
+    }
+
+    /// Whether the contents contain a secret
+    pub fn is_sensitive(&self) -> bool {
+        matches!(self, Self::Secret(_))
+    }
+}
+
+/// How [`Contents`] are serialized in a [`Plan`]
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "dots-schema", derive(schemars::JsonSchema))]
+struct RawContents {
+    /// What to write, empty for an asset and [`REDACTED`] if it contains a secret
+    contents: String,
+    /// If the `source` is not text, it is copied as-is and `contents` are empty
+    asset: Option<Asset>,
+    /// `contents` contain a secret
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    sensitive: bool,
+}
+
+impl From<RawContents> for Contents {
+    fn from(
+        RawContents {
+            contents,
+            asset,
+            sensitive,
+        }: RawContents,
+    ) -> Self {
+        match asset {
+            Some(asset) => Self::Asset(asset),
+            None if sensitive => Self::Secret(Sensitive::containing(contents)),
+            None => Self::Text(contents),
+        }
+    }
+}
+
+impl From<Contents> for RawContents {
+    fn from(contents: Contents) -> Self {
+        let sensitive = contents.is_sensitive();
+        let text = contents.text().to_string();
+        Self {
+            contents: text,
+            asset: contents.asset().cloned(),
+            sensitive,
+        }
+    }
+}
+
+impl WritePath {
+    /// Files at least this large are not replaced with empty files, see [`Analysis::allow_truncate`]
+    const TRUNCATE_THRESHOLD: u64 = 1024;
+
+    /// Compare the file on disk with what will be written to it
+    pub fn state(&self) -> FileState {
+        if self.symlink {
+            return match fs::read_link(&self.path) {
+                Ok(target) if Some(&target) == self.source.as_ref() => FileState::UpToDate,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => FileState::Missing,
+                Ok(_) | Err(_) => FileState::Modified,
+            };
+        }
+
+        let text = match &self.contents {
+            Contents::Text(text) => text,
+            Contents::Secret(secret) => secret.expose(),
+            Contents::Asset(asset) => {
+                return match Asset::hash_file(&self.path) {
+                    Ok(hash) if hash == asset.hash => FileState::UpToDate,
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => FileState::Missing,
+                    Ok(_) | Err(_) => FileState::Modified,
+                };
+            }
+        };
+
+        match fs::read(&self.path) {
+            Ok(contents) if contents == text.as_bytes() => FileState::UpToDate,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => FileState::Missing,
+            // if we can't read it, we can't confirm that it's up to date either
+            Ok(_) | Err(_) => FileState::Modified,
+        }
+    }
+
+    /// Hash of what will be written
+    pub fn hash(&self) -> String {
+        if self.symlink
+            && let Some(source) = &self.source
+        {
+            return format!("symlink:{}", source.to_string_lossy());
+        }
+
+        match &self.contents {
+            Contents::Text(_) | Contents::Secret(_) => sha256::digest(self.contents.expose()),
+            Contents::Asset(asset) => format!("blake3:{}", asset.hash),
+        }
+    }
+
+    /// Whether writing would replace a file which is not small with an empty file
+    pub fn truncates(&self) -> bool {
+        self.size() == 0
+            && fs::metadata(&self.path).is_ok_and(|meta| meta.len() >= Self::TRUNCATE_THRESHOLD)
+    }
+
+    /// Size of what will be written, in bytes
+    pub fn size(&self) -> u64 {
+        match &self.contents {
+            Contents::Text(_) | Contents::Secret(_) => self.contents.expose().len() as u64,
+            Contents::Asset(asset) => asset.len,
+        }
+    }
+}
+
+/// State of a file on disk, compared to what `dots` will write to it
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FileState {
+    /// File on disk has the same contents as what will be written
+    UpToDate,
+    /// File on disk has different contents than what will be written
+    Modified,
+    /// File does not exist
+    Missing,
+}
+
+impl fmt::Display for FileState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::UpToDate => "up to date",
+            Self::Modified => "modified",
+            Self::Missing => "missing",
+        })
+    }
+}
+
+/// Existing files which an apply replaces, other than the ones that `dots` wrote before
+/// and nobody modified since then
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Overwrites {
+    /// Files which are overwritten
+    pub overwritten: Vec<PathBuf>,
+    /// Files which are moved to backups before they are overwritten
+    pub backed_up: Vec<PathBuf>,
+    /// Number of files which are removed, because they are not in the repo
+    pub removed: usize,
 }
 
 /// Analysis represents finished computation
 #[derive(Debug)]
 pub struct Analysis {
-    /// A list of paths to write
-    pub writes: Vec<WritePath>,
+    /// Every change to make, in order
+    pub operations: Vec<Operation>,
+    /// Errors of entries that failed, which did not prevent the rest from being applied
+    ///
+    /// See [`OnError`](crate::config::OnError)
+    pub errors: Vec<eyre::Error>,
+    /// Existing files which are renamed to `<name>.orig.dots` instead of being replaced
+    ///
+    /// See [`FirstOverwrite`](crate::config::FirstOverwrite)
+    pub backups: BTreeSet<PathBuf>,
+    /// When to warn about and give up on operations that are stuck
+    pub watchdog: Watchdog,
+    /// Keep extended attributes of files that are replaced, such as SELinux contexts
+    pub preserve_xattrs: bool,
+    /// Output directories which are built next to where they are and swapped in at once
+    ///
+    /// Everything inside of them that is not written by `dots` is removed
+    pub atomic_dirs: BTreeSet<PathBuf>,
+    /// Files which `dots` only manages a part of, such as a managed block, which are never
+    /// removed when the repo no longer produces them
+    pub partial: BTreeSet<PathBuf>,
+    /// Paths which are never touched, even if an operation would
+    pub protect: Protect,
+    /// Allow replacing files that are not small with empty files
+    ///
+    /// Otherwise it is treated as a mistake, such as a template which rendered to nothing
+    pub allow_truncate: bool,
+    /// Every template rendered by this run, to be saved for the next run
+    pub cache: RenderCache,
+    /// Phase of each path that is not applied in [`Phase::Main`]
+    ///
+    /// Once an operation fails, the operations of later phases are skipped
+    pub phases: BTreeMap<PathBuf, Phase>,
+    /// Links which were fetched for the first time, locked in `dots.lock` once they are applied
+    pub newly_locked: NewlyLocked,
+    /// Termux, if `dots` runs inside of it, whose shared storage can not have symlinks
+    pub termux: Option<Termux>,
+    /// Stops the apply before the next operation, which is Ctrl-C unless it is built with another
+    pub cancel: CancelToken,
 }
 
 impl Analysis {
+    /// Build an analysis out of operations that were computed by something other than [`World`](crate::World)
+    ///
+    /// ```
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let dir = dir.path();
+    ///
+    /// dots::Analysis::builder()
+    ///     .write(dir.join("out/foo.txt"), "foo")
+    ///     .operation(dots::Operation::SetMode {
+    ///         path: dir.join("out/foo.txt"),
+    ///         mode: 0o600,
+    ///     })
+    ///     .build()
+    ///     .finish(&dir.join("journal"), None)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(std::fs::read_to_string(dir.join("out/foo.txt")).unwrap(), "foo");
+    /// ```
+    pub fn builder() -> AnalysisBuilder {
+        AnalysisBuilder::default()
+    }
+
+    /// Everything that [`Analysis::finish`] is going to do
+    ///
+    /// Also finds the operations which are going to fail, e.g. because they write into
+    /// a directory that the user has no permission to write into
+    pub fn plan(&self) -> Plan {
+        let blocked = probe::obstacles(self.probed_paths())
+            .into_iter()
+            .map(|(obstacle, path)| Failure {
+                path: Some(path.to_path_buf()),
+                error: obstacle.reason(),
+            })
+            .collect();
+
+        Plan {
+            version: SCHEMA_VERSION,
+            // secrets are serialized as `REDACTED`
+            operations: self.operations.clone(),
+            backups: self.backups.clone(),
+            errors: self.errors.iter().map(ToString::to_string).collect(),
+            blocked,
+        }
+    }
+
+    /// What [`Analysis::finish`] is going to do to each file, compared to what is on disk
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+
+        for operation in &self.operations {
+            match operation {
+                Operation::Write(write) => match write.state() {
+                    FileState::Missing => summary.created.push(write.path.clone()),
+                    FileState::Modified => summary.modified.push(write.path.clone()),
+                    FileState::UpToDate => summary.unchanged.push(write.path.clone()),
+                },
+                Operation::Delete { path } | Operation::Evict { path, .. } => {
+                    summary.removed.push(path.clone());
+                }
+                _ => (),
+            }
+        }
+
+        summary
+    }
+
+    /// Paths of operations which must be writable
+    ///
+    /// Files which are unlocked before they are written are immutable on purpose
+    fn probed_paths(&self) -> impl Iterator<Item = &Path> {
+        let unlocked = self
+            .operations
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Unlock { path } => Some(path.as_path()),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+
+        self.operations
+            .iter()
+            .filter_map(Operation::path)
+            .filter(move |path| !unlocked.contains(path))
+    }
+
+    /// Every file that will be written
+    pub fn writes(&self) -> impl Iterator<Item = &WritePath> {
+        self.operations
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Write(write) => Some(write),
+                _ => None,
+            })
+    }
+
+    /// Existing files which the apply replaces, according to the journal and the index
+    /// in `journal_dir`
+    ///
+    /// Routine updates of files which `dots` wrote before and nobody modified since then
+    /// are not included
+    pub fn overwrites(&self, journal_dir: &Path) -> Result<Overwrites> {
+        let managed = Journal::managed(journal_dir)?;
+        let index = ApplyIndex::load(journal_dir)?;
+
+        let (backed_up, overwritten) = self
+            .writes()
+            .filter(|write| {
+                (!managed.contains(&write.path) || index.is_modified(&write.path))
+                    && write.state() == FileState::Modified
+            })
+            .map(|write| write.path.clone())
+            .partition(|path| self.backups.contains(path));
+        let removed = self
+            .operations
+            .iter()
+            .filter(|operation| {
+                matches!(
+                    operation,
+                    Operation::Delete { .. } | Operation::Evict { .. }
+                )
+            })
+            .count();
+
+        Ok(Overwrites {
+            overwritten,
+            backed_up,
+            removed,
+        })
+    }
+
     /// Finish the analysis
-    pub fn finish(self) {
-        for WritePath { path, contents } in self.writes {
-            let contents = contents.to_string();
-
-            if let Err(err) = match fs::remove_file(&path) {
-                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
-                Err(err) => Err(err),
-                Ok(()) => Ok(()),
-            } {
-                log::error!("failed to remove file {}: {err}", path.show());
-                continue;
+    ///
+    /// Before any file is touched, every file is checked to be writable, and a
+    /// [`Journal`] is written to `journal_dir` so that an interrupted apply can be
+    /// resumed or rolled back.
+    pub fn finish(self, journal_dir: &Path, owner: Option<&User>) -> Result<ApplyReport> {
+        let mut index = ApplyIndex::load(journal_dir)?;
+        let outputs = self
+            .writes()
+            .map(|write| write.path.clone())
+            .collect::<BTreeSet<_>>();
+
+        let (unchanged, operations) =
+            self.operations
+                .into_iter()
+                .partition::<Vec<_>, _>(|operation| match operation {
+                    // atomic directories are built from scratch, including unchanged files
+                    Operation::Write(write) => {
+                        !self
+                            .atomic_dirs
+                            .iter()
+                            .any(|dir| write.path.starts_with(dir))
+                            && index.is_written(&write.path, &write.hash())
+                    }
+                    _ => false,
+                });
+
+        if !unchanged.is_empty() {
+            log::debug!(target: "apply", "{} files are unchanged since the last apply", unchanged.len());
+        }
+
+        // files which are written again are recorded once they are. Files which are not part
+        // of this analysis are kept until they are pruned, it may only write some of them
+        let unchanged = unchanged
+            .iter()
+            .filter_map(Operation::path)
+            .map(Path::to_path_buf)
+            .collect::<BTreeSet<_>>();
+        index
+            .outputs
+            .retain(|path, _| unchanged.contains(path) || !outputs.contains(path));
+
+        // files which are unlocked before they are written are immutable on purpose
+        let unlocked = operations
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Unlock { path } => Some(path),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+        probe::probe(
+            operations
+                .iter()
+                .filter_map(Operation::path)
+                .filter(|path| !unlocked.contains(&path.to_path_buf())),
+        )?;
+
+        if !self.allow_truncate {
+            let truncated = operations
+                .iter()
+                .filter_map(|operation| match operation {
+                    Operation::Write(write) if write.truncates() => {
+                        Some(format!("  {}", write.path.show()))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            if !truncated.is_empty() {
+                bail!(
+                    "refusing to replace {} files with empty files:\n{}\npass `--allow-truncate` if this is intended",
+                    truncated.len(),
+                    truncated.join("\n")
+                );
             }
+        }
+
+        let mut journal = Journal::begin(
+            journal_dir,
+            operations.iter().filter_map(Operation::journaled_path),
+        )?;
+
+        let operations = stage(
+            operations,
+            &self.atomic_dirs,
+            &Journal::managed(journal_dir)?,
+        )?;
+        journal.record_swaps(
+            journal_dir,
+            operations.iter().filter_map(Operation::journaled_swap),
+        )?;
+
+        let mut executor =
+            Executor::new(&self.backups, owner, &mut index, self.watchdog, self.termux);
+        executor.preserve_xattrs = self.preserve_xattrs;
+        executor.protect = self.protect;
+        executor.removed_dir = Some(Journal::removed_dir(journal_dir));
 
-            log::warn!("{RED}removed{RESET} {}", path.show());
+        let mut failed = vec![];
+        let mut current_phase = Phase::Pre;
+        let mut operations = operations.into_iter();
+        while !self.cancel.is_cancelled()
+            && let Some(operation) = operations.next()
+        {
+            let path = operation.path().map(Path::to_path_buf);
 
-            let Some(dir) = path.parent() else {
-                log::error!("failed to obtain parent of {}", path.show());
+            // later phases may depend on the files of earlier ones
+            let operation_phase = phase::of(&operation, &self.phases);
+            if operation_phase > current_phase && !failed.is_empty() {
+                let err = eyre!("skipped {operation}, an operation of an earlier phase failed");
+                log::error!(target: "apply", "{err}");
+                failed.push(Failure {
+                    path,
+                    error: err.to_string(),
+                });
                 continue;
-            };
+            }
+            current_phase = current_phase.max(operation_phase);
 
-            // 2. Create parent directory which will contain the file downloaded from the link
-            if let Err(err) = fs::create_dir_all(dir) {
-                log::error!("failed to create directory for {}: {err}", dir.show());
+            // swapping in a directory with missing files would remove them
+            if let Operation::SwapDir { staging, .. } = &operation
+                && failed.iter().any(|failure: &Failure| {
+                    failure
+                        .path
+                        .as_ref()
+                        .is_some_and(|path| path.starts_with(staging))
+                })
+            {
+                let _ = fs::remove_dir_all(staging);
+                let err = eyre!("not swapping in {operation}, some of its files failed");
+                log::error!(target: "apply", "{err}");
+                failed.push(Failure {
+                    path,
+                    error: err.to_string(),
+                });
+                continue;
             }
 
-            if let Err(err) = fs::write(&path, contents) {
-                log::error!("failed to write to {}: {err}", path.show());
+            if let Err(err) = executor.execute(operation) {
+                log::error!(target: "apply", "{err}");
+                failed.push(Failure {
+                    path,
+                    error: err.to_string(),
+                });
             }
+        }
 
-            log::info!("wrote to {}", path.show());
+        // operations that were given up on could otherwise change files after they are indexed
+        executor.wait();
+        let written = executor.written;
+        for (path, indexed) in &mut index.outputs {
+            if outputs.contains(path) {
+                indexed.partial = self.partial.contains(path);
+            }
+        }
+        index.save(journal_dir)?;
+        if let Err(err) = self.newly_locked.save() {
+            log::warn!("{err}");
         }
+
+        // the journal stays, so the next run can resume or roll back the rest
+        let cancelled = operations.len() != 0;
+        if cancelled {
+            log::warn!(
+                target: "apply",
+                "cancelled, {} operations were not applied",
+                operations.len()
+            );
+        } else {
+            Journal::complete(journal_dir)?;
+        }
+
+        Ok(ApplyReport {
+            version: SCHEMA_VERSION,
+            written,
+            unchanged: unchanged.into_iter().collect(),
+            failed,
+            cancelled,
+            errors: self.errors.iter().map(ToString::to_string).collect(),
+        })
     }
 }
+
+/// Write the files inside of each of the `atomic_dirs` into a staging directory next to it,
+/// followed by an operation that swaps the staging directory in
+///
+/// The old directory is backed up if it contains files that `dots` did not write
+fn stage(
+    operations: Vec<Operation>,
+    atomic_dirs: &BTreeSet<PathBuf>,
+    managed: &BTreeSet<PathBuf>,
+) -> Result<Vec<Operation>> {
+    let mut staged = BTreeMap::<&Path, Vec<WritePath>>::new();
+    let mut rest = vec![];
+
+    for operation in operations {
+        let dir = match &operation {
+            Operation::Write(write) => atomic_dirs.iter().find(|dir| write.path.starts_with(dir)),
+            _ => None,
+        };
+
+        match (dir, operation) {
+            (Some(dir), Operation::Write(write)) => staged.entry(dir).or_default().push(write),
+            (_, operation) => rest.push(operation),
+        }
+    }
+
+    for (dir, writes) in staged {
+        let staging = staging_path(dir);
+        match fs::remove_dir_all(&staging) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(err).with_context(|| {
+                    eyre!("failed to remove old staging directory {}", staging.show())
+                });
+            }
+            _ => (),
+        }
+
+        let written = writes
+            .iter()
+            .map(|write| write.path.as_path())
+            .collect::<BTreeSet<_>>();
+        let backup = walkdir::WalkDir::new(dir)
+            .into_iter()
+            .flatten()
+            .filter(|entry| !entry.file_type().is_dir())
+            .any(|entry| !written.contains(entry.path()) && !managed.contains(entry.path()));
+
+        rest.extend(writes.into_iter().map(|mut write| {
+            if let Ok(relative) = write.path.strip_prefix(dir) {
+                write.path = staging.join(relative);
+            }
+            Operation::Write(write)
+        }));
+        rest.push(Operation::SwapDir {
+            path: dir.to_path_buf(),
+            staging,
+            backup,
+        });
+    }
+
+    Ok(rest)
+}
+
+/// Directory that the new files of the atomic directory `dir` are written into
+fn staging_path(dir: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(dir.file_name().unwrap_or_default());
+    name.push(".dots-staging");
+    dir.with_file_name(name)
+}
+
+/// Builds an [`Analysis`], see [`Analysis::builder`]
+#[derive(Debug, Default)]
+pub struct AnalysisBuilder {
+    /// Every change to make, in order
+    operations: Vec<Operation>,
+    /// Existing files which are backed up instead of being replaced
+    backups: BTreeSet<PathBuf>,
+    /// When to warn about and give up on operations that are stuck
+    watchdog: Watchdog,
+    /// Keep extended attributes of files that are replaced
+    preserve_xattrs: bool,
+    /// Directories which are swapped in at once
+    atomic_dirs: BTreeSet<PathBuf>,
+    /// Files which are only partially managed
+    partial: BTreeSet<PathBuf>,
+    /// Paths which are never touched
+    protect: Protect,
+    /// Allow replacing files that are not small with empty files
+    allow_truncate: bool,
+    /// Termux, whose shared storage can not have symlinks
+    termux: Option<Termux>,
+    /// Stops the apply, Ctrl-C if there is none
+    cancel: Option<CancelToken>,
+}
+
+impl AnalysisBuilder {
+    /// Write `contents` to the file at `path`
+    #[must_use]
+    pub fn write(self, path: impl Into<PathBuf>, contents: impl Into<String>) -> Self {
+        self.operation(Operation::Write(WritePath {
+            path: path.into(),
+            contents: Contents::Text(contents.into()),
+            source: None,
+            durable: false,
+            description: None,
+            symlink: false,
+            mode: None,
+        }))
+    }
+
+    /// Add an operation, which is executed after every operation added before it
+    #[must_use]
+    pub fn operation(mut self, operation: Operation) -> Self {
+        self.operations.push(operation);
+        self
+    }
+
+    /// Rename the existing file at `path` to `<name>.orig.dots` instead of replacing it
+    #[must_use]
+    pub fn backup(mut self, path: impl Into<PathBuf>) -> Self {
+        self.backups.insert(path.into());
+        self
+    }
+
+    /// Warn about and give up on operations which are stuck, see [`Watchdog`]
+    #[must_use]
+    pub fn watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = watchdog;
+        self
+    }
+
+    /// Keep extended attributes of files that are replaced, such as SELinux contexts
+    #[must_use]
+    pub fn preserve_xattrs(mut self, preserve_xattrs: bool) -> Self {
+        self.preserve_xattrs = preserve_xattrs;
+        self
+    }
+
+    /// Build the directory at `path` next to it and swap it in at once, removing everything
+    /// inside of it that is not written by the analysis
+    #[must_use]
+    pub fn atomic_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.atomic_dirs.insert(path.into());
+        self
+    }
+
+    /// `dots` only manages a part of the file at `path`, so it is never removed when it is
+    /// no longer written
+    #[must_use]
+    pub fn partial(mut self, path: impl Into<PathBuf>) -> Self {
+        self.partial.insert(path.into());
+        self
+    }
+
+    /// Never touch paths which match any of the globs in `protect`
+    #[must_use]
+    pub fn protect(mut self, protect: Protect) -> Self {
+        self.protect = protect;
+        self
+    }
+
+    /// Allow replacing files that are not small with empty files
+    #[must_use]
+    pub fn allow_truncate(mut self, allow_truncate: bool) -> Self {
+        self.allow_truncate = allow_truncate;
+        self
+    }
+
+    /// Copy files instead of creating symlinks in the shared storage of `termux`, when
+    /// applying inside of it
+    #[must_use]
+    pub fn termux(mut self, termux: Option<Termux>) -> Self {
+        self.termux = termux;
+        self
+    }
+
+    /// Stop the apply when `cancel` is cancelled, instead of on Ctrl-C
+    #[must_use]
+    pub fn cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Finish building
+    #[must_use]
+    pub fn build(self) -> Analysis {
+        Analysis {
+            operations: self.operations,
+            errors: vec![],
+            backups: self.backups,
+            watchdog: self.watchdog,
+            preserve_xattrs: self.preserve_xattrs,
+            atomic_dirs: self.atomic_dirs,
+            partial: self.partial,
+            protect: self.protect,
+            allow_truncate: self.allow_truncate,
+            cache: RenderCache::default(),
+            phases: BTreeMap::new(),
+            newly_locked: NewlyLocked::default(),
+            termux: self.termux,
+            cancel: self.cancel.unwrap_or_else(CancelToken::ctrl_c),
+        }
+    }
+}
+
+/// Where the file at `path` is moved to, when it is backed up instead of being replaced
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".orig.dots");
+    path.with_file_name(name)
+}
@@ -0,0 +1,134 @@
+//! Contains [`Asset`]
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use eyre::{Context as _, Result, eyre};
+use serde::{Deserialize, Serialize};
+
+use crate::stdx::PathExt as _;
+
+/// Files at least this large are memory-mapped instead of being read into memory
+const MMAP_THRESHOLD: u64 = 1024 * 1024;
+
+/// Contents of a file in an `input` directory
+#[derive(Debug, Clone)]
+pub enum Input {
+    /// Text, which can be a template
+    Text(String),
+    /// Anything else, which is copied as-is
+    Asset(Asset),
+}
+
+/// A file which is not text, such as an image or a font
+///
+/// Its contents are never held in memory as a whole, it is copied from its source instead
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "dots-schema", derive(schemars::JsonSchema))]
+pub struct Asset {
+    /// BLAKE3 hash of the contents
+    pub hash: String,
+    /// Size of the file, in bytes
+    pub len: u64,
+}
+
+/// Read the file at `path`, keeping only the hash of files which are not text
+pub fn read(path: &Path) -> Result<Input> {
+    let context = || eyre!("failed to read path {}", path.show());
+
+    let len = fs::metadata(path).with_context(context)?.len();
+
+    if len >= MMAP_THRESHOLD {
+        let mmap = mmap(path).with_context(context)?;
+
+        return Ok(match str::from_utf8(&mmap) {
+            Ok(text) => Input::Text(text.to_string()),
+            Err(_) => Input::Asset(Asset {
+                hash: blake3::Hasher::new()
+                    .update_rayon(&mmap)
+                    .finalize()
+                    .to_hex()
+                    .to_string(),
+                len,
+            }),
+        });
+    }
+
+    let bytes = fs::read(path).with_context(context)?;
+
+    Ok(match String::from_utf8(bytes) {
+        Ok(text) => Input::Text(text),
+        Err(err) => Input::Asset(Asset {
+            hash: blake3::hash(err.as_bytes()).to_hex().to_string(),
+            len,
+        }),
+    })
+}
+
+impl Input {
+    /// The text, `None` if the contents are not text
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            Self::Asset(_) => None,
+        }
+    }
+}
+
+impl Asset {
+    /// BLAKE3 hash of the file at `path`
+    pub fn hash_file(path: &Path) -> io::Result<String> {
+        Ok(blake3::Hasher::new()
+            .update_mmap_rayon(path)?
+            .finalize()
+            .to_hex()
+            .to_string())
+    }
+}
+
+/// Map the file at `path` into memory
+fn mmap(path: &Path) -> io::Result<memmap2::Mmap> {
+    let file = fs::File::open(path)?;
+
+    // SAFETY: the map is only read while hashing or copying it into a `String`.
+    // If another process truncates the file at that moment the read may fault,
+    // which is the same risk every tool that maps files takes
+    unsafe { memmap2::Mmap::map(&file) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_is_asset() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = dir.path().join("text.txt");
+        let binary = dir.path().join("binary.bin");
+        let large = dir.path().join("large.bin");
+
+        fs::write(&text, "foo").unwrap();
+        fs::write(&binary, [0xff, 0xfe, 0x00]).unwrap();
+        let large_contents = (0..MMAP_THRESHOLD + 1)
+            .map(|byte| (byte % 251) as u8 | 0x80)
+            .collect::<Vec<_>>();
+        fs::write(&large, &large_contents).unwrap();
+
+        assert!(matches!(read(&text).unwrap(), Input::Text(text) if text == "foo"));
+
+        let Input::Asset(asset) = read(&binary).unwrap() else {
+            panic!("binary file is not an asset");
+        };
+        assert_eq!(asset.len, 3);
+        assert_eq!(asset.hash, Asset::hash_file(&binary).unwrap());
+
+        let Input::Asset(asset) = read(&large).unwrap() else {
+            panic!("large binary file is not an asset");
+        };
+        assert_eq!(
+            asset.hash,
+            blake3::hash(&large_contents).to_hex().to_string()
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Contains [`Autostart`]
+//!
+//! Programs which start at login are declared with the same file on every desktop,
+//! but each platform reads it from somewhere else: a `.desktop` file in
+//! `{config_dir}/autostart`, a launch agent in `~/Library/LaunchAgents`, or a script
+//! in the Startup folder on Windows
+
+use eyre::{Result, bail};
+use serde::Deserialize;
+
+use crate::analysis::{Contents, WritePath};
+use crate::output_path::OutputPath;
+
+/// A program which starts at login, in the `[[autostart]]` section of the config
+#[derive(Deserialize, Debug)]
+pub struct Autostart {
+    /// Name of the program, the entry is called `dots-<name>`
+    pub name: String,
+    /// Command to run, with `sh` or with `cmd` on Windows
+    pub command: String,
+    /// What the program is for, shown by desktops that list autostart entries
+    pub description: Option<String>,
+}
+
+impl Autostart {
+    /// File which starts the program at login on `os`, as in `std::env::consts::OS`
+    pub fn entry(&self, os: &str) -> Result<WritePath> {
+        if self.name.is_empty()
+            || !self
+                .name
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+        {
+            bail!(
+                "name of autostart `{}` can only contain letters, digits, `-` and `_`",
+                self.name
+            );
+        }
+        let name = format!("dots-{}", self.name);
+        let description = self
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("{name}, managed by dots"));
+
+        let (path, contents) = match os {
+            "macos" => (
+                format!("~/Library/LaunchAgents/{name}.plist"),
+                launch_agent(&name, &self.command),
+            ),
+            "windows" => (
+                format!("{{config_dir}}/Microsoft/Windows/Start Menu/Programs/Startup/{name}.cmd"),
+                format!("@echo off\r\nrem {description}\r\n{}\r\n", self.command),
+            ),
+            _ => (
+                format!("{{config_dir}}/autostart/{name}.desktop"),
+                desktop_entry(&name, &description, &self.command),
+            ),
+        };
+
+        Ok(WritePath {
+            path: path.parse::<OutputPath>()?.into_inner(),
+            contents: Contents::Text(contents),
+            source: None,
+            durable: false,
+            description: self.description.clone(),
+            symlink: false,
+            mode: None,
+        })
+    }
+}
+
+/// Autostart entry of the desktop, in `{config_dir}/autostart`
+fn desktop_entry(name: &str, description: &str, command: &str) -> String {
+    format!(
+        "[Desktop Entry]\nType=Application\nName={name}\nComment={description}\n\
+         Exec=/bin/sh -c \"{}\"\nX-GNOME-Autostart-enabled=true\n",
+        escape_exec(command)
+    )
+}
+
+/// Launch agent which runs at login, in `~/Library/LaunchAgents`
+fn launch_agent(name: &str, command: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n<dict>\n\
+         \t<key>Label</key>\n\t<string>{name}</string>\n\
+         \t<key>ProgramArguments</key>\n\t<array>\n\
+         \t\t<string>/bin/sh</string>\n\t\t<string>-c</string>\n\t\t<string>{}</string>\n\
+         \t</array>\n\
+         \t<key>RunAtLoad</key>\n\t<true/>\n\
+         </dict>\n</plist>\n",
+        escape_xml(command)
+    )
+}
+
+/// Escape `command` inside of a quoted argument of `Exec` in a `.desktop` file
+///
+/// Quoting escapes are applied first, then the backslashes are escaped again as in any
+/// string of a keyfile, and `%` is doubled so it is not a field code
+fn escape_exec(command: &str) -> String {
+    command
+        .chars()
+        .fold(String::new(), |mut escaped, ch| {
+            if matches!(ch, '"' | '`' | '$' | '\\') {
+                escaped.push('\\');
+            }
+            escaped.push(ch);
+            escaped
+        })
+        .replace('\\', "\\\\")
+        .replace('%', "%%")
+}
+
+/// Escape `s` inside of an XML element
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry() {
+        let autostart = Autostart {
+            name: "kanshi".to_string(),
+            command: "kanshi --config \"$HOME/kanshi\" 100%".to_string(),
+            description: None,
+        };
+
+        let linux = autostart.entry("linux").unwrap();
+        assert!(linux.path.ends_with("autostart/dots-kanshi.desktop"));
+        assert_eq!(
+            linux.contents.text(),
+            "[Desktop Entry]\nType=Application\nName=dots-kanshi\n\
+             Comment=dots-kanshi, managed by dots\n\
+             Exec=/bin/sh -c \"kanshi --config \\\\\"\\\\$HOME/kanshi\\\\\" 100%%\"\n\
+             X-GNOME-Autostart-enabled=true\n"
+        );
+
+        let macos = autostart.entry("macos").unwrap();
+        assert!(
+            macos
+                .path
+                .ends_with("Library/LaunchAgents/dots-kanshi.plist")
+        );
+        assert!(
+            macos
+                .contents
+                .text()
+                .contains("<string>kanshi --config \"$HOME/kanshi\" 100%</string>")
+        );
+
+        let windows = autostart.entry("windows").unwrap();
+        assert!(windows.path.ends_with("Startup/dots-kanshi.cmd"));
+
+        let invalid = Autostart {
+            name: "my app".to_string(),
+            command: "app".to_string(),
+            description: None,
+        };
+        assert!(invalid.entry("linux").is_err());
+    }
+}
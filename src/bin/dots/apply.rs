@@ -0,0 +1,432 @@
+//! `dots apply`, along with resuming or rolling back an apply which did not finish, and pruning
+//! the files which earlier applies wrote
+
+use dots::{
+    Analysis, ApplyIndex, Cli, DiffTool, Event, FileState, FirstOverwrite, Journal, Machines,
+    Overwrites, PathExt as _, User,
+};
+use eyre::{Context as _, Result, bail};
+use itertools::Itertools as _;
+use simply_colored::*;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead as _, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tap::Pipe as _;
+
+use crate::check::scan_secrets;
+use crate::{analyze, answer_prompts, notify, process, report, send_report, world};
+
+/// Apply once, after sleeping for a random duration shorter than `jitter`
+pub fn after_jitter(cli: &Cli, owner: Option<&User>, jitter: Option<Duration>) -> Result<()> {
+    if !sleep_jitter(jitter)? {
+        log::info!("cancelled, nothing was applied");
+        return Ok(());
+    }
+
+    apply(cli, owner).inspect_err(|err| notify(cli, Event::ApplyFailed, &format!("{err}")))
+}
+
+/// Print what `dots apply` is going to do as JSON
+pub fn plan(cli: &Cli) -> Result<()> {
+    let plan = analyze(cli)?.plan();
+    for blocked in &plan.blocked {
+        if let Some(path) = &blocked.path {
+            log::warn!("writing {} will fail: {}", path.show(), blocked.error);
+        }
+    }
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&plan)
+            .context("failed to serialize plan")?
+            .pipe_deref(dots::redact)
+    );
+    Ok(())
+}
+
+/// Write every managed file to its location
+pub fn apply(cli: &Cli, owner: Option<&User>) -> Result<()> {
+    let journal_dir = Journal::dir()?;
+    // the journal is created inside of the home directory of the `owner`, who must be able to use it later
+    let created_journal_dirs = journal_dir
+        .ancestors()
+        .take_while(|ancestor| !ancestor.exists())
+        .map(Path::to_path_buf)
+        .collect_vec();
+
+    if let Some(journal) = Journal::load(&journal_dir)?
+        && !recover(journal, &journal_dir, cli.context().is_interactive())?
+    {
+        return Ok(());
+    }
+
+    answer_prompts(cli)?;
+    let world = world(cli)?;
+    scan_secrets(&world)?;
+    let root = world.root.clone();
+    let hash = world.hash();
+    let profiles = world.profiles.clone();
+    let first_overwrite = world.first_overwrite;
+    let diff_tool = world.diff_tool.clone();
+    let confirm_overwrites = world.confirm_overwrites;
+    let fc_cache = world.fc_cache && !world.fonts.is_empty();
+    let prune = world.prune;
+    let mut analysis = process(cli, world)?;
+
+    let interactive = cli.context().is_interactive();
+    let backups = backups(
+        &analysis,
+        first_overwrite,
+        &journal_dir,
+        interactive,
+        &diff_tool,
+    )?;
+    analysis.backups.extend(backups);
+    analysis.allow_truncate = cli.allow_truncate;
+
+    if !cli.yes && !confirm(&analysis, &journal_dir, confirm_overwrites, interactive)? {
+        log::info!("nothing was applied");
+        return Ok(());
+    }
+
+    let produced = analysis
+        .writes()
+        .map(|write| write.path.clone())
+        .collect::<BTreeSet<_>>();
+
+    dots::handle_ctrl_c()?;
+    let report = analysis.finish(&journal_dir, owner)?;
+
+    if let Some(owner) = owner {
+        walkdir::WalkDir::new(&journal_dir)
+            .into_iter()
+            .flatten()
+            .map(walkdir::DirEntry::into_path)
+            .chain(created_journal_dirs)
+            .try_for_each(|path| owner.chown(&path))?;
+    }
+
+    if fc_cache {
+        let font_dir = dots::font_dir()?;
+        if report
+            .written
+            .iter()
+            .any(|path| path.starts_with(&font_dir))
+        {
+            dots::refresh_fonts(&font_dir);
+        }
+    }
+
+    if cli.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report)
+                .context("failed to serialize report")?
+                .pipe_deref(dots::redact)
+        );
+    }
+
+    send_report(cli, &report);
+
+    if report.cancelled {
+        bail!(
+            "the apply was cancelled after writing {} files, run `dots` again to resume it or roll it back",
+            report.written.len()
+        );
+    }
+
+    // errors of entries which failed, but did not prevent the other entries from being applied
+    if report.errors.is_empty() {
+        Machines::record(&journal_dir, &root, hash, profiles)?;
+        orphans(&journal_dir, &produced, prune)?;
+    } else {
+        log::error!(
+            "{} errors, only the {} files which did not fail were applied:",
+            report.errors.len(),
+            report.written.len() + report.unchanged.len()
+        );
+        for err in &report.errors {
+            log::error!("{err}");
+        }
+
+        bail!("encountered errors");
+    }
+
+    Ok(())
+}
+
+/// Print what `dots apply` would do to each file, without doing it
+pub fn dry_run(cli: &Cli) -> Result<()> {
+    let summary = analyze(cli)?.summary();
+
+    if cli.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&summary).context("failed to serialize summary")?
+        );
+        return Ok(());
+    }
+
+    for (action, color, paths) in [
+        ("create", GREEN, &summary.created),
+        ("modify", YELLOW, &summary.modified),
+        ("remove", RED, &summary.removed),
+    ] {
+        for path in paths {
+            println!("{color}{action}{RESET} {}", path.show());
+        }
+    }
+
+    log::info!(
+        "would create {}, modify {} and remove {} files, {} are unchanged",
+        summary.created.len(),
+        summary.modified.len(),
+        summary.removed.len(),
+        summary.unchanged.len()
+    );
+
+    Ok(())
+}
+
+/// Remove the files which earlier applies wrote, but are not `produced` by the repo anymore,
+/// if `prune`, or else only report them
+fn orphans(journal_dir: &Path, produced: &BTreeSet<PathBuf>, prune: bool) -> Result<()> {
+    let mut index = ApplyIndex::load(journal_dir)?;
+
+    if prune {
+        index.prune(produced)?;
+        return index.save(journal_dir);
+    }
+
+    let orphans = index.orphans(produced);
+    if !orphans.is_empty() {
+        log::info!(
+            "{} files are no longer produced by the repo, run `dots prune` to remove them:\n{}",
+            orphans.len(),
+            orphans
+                .iter()
+                .map(|orphan| format!("  {}", orphan.show()))
+                .join("\n")
+        );
+    }
+
+    Ok(())
+}
+
+/// Remove the files which earlier applies wrote, but are not produced by the repo anymore,
+/// or only list them if `dry_run`
+pub fn prune(cli: &Cli, dry_run: bool) -> Result<()> {
+    let analysis = analyze(cli)?;
+    if !analysis.errors.is_empty() {
+        return Err(report(analysis.errors))
+            .context("not pruning, the files of entries which failed are unknown");
+    }
+
+    let journal_dir = Journal::dir()?;
+    if Journal::load(&journal_dir)?.is_some() {
+        bail!("the last apply was interrupted, run `dots` to resume it or roll it back first");
+    }
+
+    let produced = analysis
+        .writes()
+        .map(|write| write.path.clone())
+        .collect::<BTreeSet<_>>();
+    let mut index = ApplyIndex::load(&journal_dir)?;
+
+    if dry_run {
+        for orphan in index.orphans(&produced) {
+            println!("{}", orphan.show());
+        }
+        return Ok(());
+    }
+
+    let removed = index.prune(&produced)?;
+    index.save(&journal_dir)?;
+    log::info!("removed {} files", removed.len());
+
+    Ok(())
+}
+
+/// Sleep for a random duration shorter than `jitter`
+///
+/// Returns `false` if it was cancelled with Ctrl-C
+fn sleep_jitter(jitter: Option<Duration>) -> Result<bool> {
+    let Some(jitter) = jitter else {
+        return Ok(true);
+    };
+
+    dots::handle_ctrl_c()?;
+    let jitter = dots::jitter(jitter);
+    log::info!("applying in {}s", jitter.as_secs());
+    Ok(dots::cancellable_sleep(jitter))
+}
+
+/// Apply every `period` until cancelled, each after sleeping for a random `jitter`
+///
+/// Failed applies are reported, and do not stop the next ones
+pub fn scheduled(
+    cli: &Cli,
+    owner: Option<&User>,
+    period: Duration,
+    jitter: Option<Duration>,
+) -> Result<()> {
+    // Ctrl-C stops the sleep between applies too, not only an apply
+    dots::handle_ctrl_c()?;
+
+    let mut next = Instant::now();
+    while sleep_jitter(jitter)? {
+        if let Err(err) = apply(cli, owner) {
+            log::error!("{err:#}");
+            notify(cli, Event::ApplyFailed, &format!("{err}"));
+        }
+
+        // the period starts from the previous apply, so the jitter does not add up,
+        // but applies that were missed, e.g. while suspended, are not caught up on
+        next = (next + period).max(Instant::now());
+        if !dots::cancellable_sleep(next.saturating_duration_since(Instant::now())) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// If the apply overwrites or removes more than `threshold` existing files which `dots`
+/// did not write or which were modified since then, summarize what it is going to do and
+/// ask whether to continue
+///
+/// Returns `true` if the apply should continue. When `dots` is not `interactive`, nobody can
+/// answer, so it continues with a warning
+fn confirm(
+    analysis: &Analysis,
+    journal_dir: &Path,
+    threshold: usize,
+    interactive: bool,
+) -> Result<bool> {
+    let Overwrites {
+        overwritten,
+        backed_up,
+        removed,
+    } = analysis.overwrites(journal_dir)?;
+
+    if overwritten.len() + removed <= threshold {
+        return Ok(true);
+    }
+
+    let mut summary = format!("will overwrite {} existing files", overwritten.len());
+    if removed != 0 {
+        summary.push_str(&format!(
+            ", back up and remove {removed} files which are not in the repo"
+        ));
+    }
+    if !backed_up.is_empty() {
+        summary.push_str(&format!(", and move {} to backups", backed_up.len()));
+    }
+
+    if !interactive {
+        log::warn!("{summary}. not running interactively, so applying anyway");
+        return Ok(true);
+    }
+
+    eprint!("{summary}. continue? [y/N] ");
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    match answer.trim() {
+        "y" | "yes" => Ok(true),
+        "n" | "no" | "" => Ok(false),
+        answer => bail!("unknown answer: {answer}"),
+    }
+}
+
+/// Files that `dots` is going to write to for the first time, but which already exist
+/// with different contents, and should be backed up instead of being replaced
+///
+/// Every one of them is backed up if `dots` is not `interactive`, instead of asking.
+/// The answer can also be to show the difference with the `diff_tool` first
+fn backups(
+    analysis: &Analysis,
+    first_overwrite: FirstOverwrite,
+    journal_dir: &Path,
+    interactive: bool,
+    diff_tool: &DiffTool,
+) -> Result<BTreeSet<PathBuf>> {
+    if first_overwrite == FirstOverwrite::Replace {
+        return Ok(BTreeSet::new());
+    }
+
+    let managed = Journal::managed(journal_dir)?;
+    let taken_over = analysis
+        .writes()
+        .filter(|write| !managed.contains(&write.path) && write.state() == FileState::Modified);
+
+    if first_overwrite == FirstOverwrite::Backup || !interactive {
+        return Ok(taken_over.map(|write| write.path.clone()).collect());
+    }
+
+    let mut backups = BTreeSet::new();
+    for write in taken_over {
+        loop {
+            eprint!(
+                "{} is not managed by `dots` yet. back it up to {}? [Y/n/d(iff)] ",
+                write.path.show(),
+                dots::backup_path(&write.path).show()
+            );
+            io::stderr().flush()?;
+
+            let mut answer = String::new();
+            io::stdin().lock().read_line(&mut answer)?;
+
+            match answer.trim() {
+                "y" | "yes" | "" => {
+                    backups.insert(write.path.clone());
+                }
+                "n" | "no" => (),
+                "d" | "diff" => {
+                    if let Some(diff) = diff_tool.show(write)? {
+                        eprint!("{}", dots::redact(&diff));
+                    }
+                    continue;
+                }
+                answer => bail!("unknown answer: {answer}"),
+            }
+            break;
+        }
+    }
+
+    Ok(backups)
+}
+
+/// The last apply did not finish. Ask the user if they want to resume it or roll it back
+///
+/// Returns `true` if the apply should continue, which it always does if `dots` is not
+/// `interactive`
+fn recover(journal: Journal, journal_dir: &Path, interactive: bool) -> Result<bool> {
+    log::warn!(
+        "the last apply did not finish, {} files may be in an inconsistent state",
+        journal.entries.len()
+    );
+
+    if !interactive {
+        log::warn!("not running interactively, resuming the apply");
+        return Ok(true);
+    }
+
+    eprint!("{GREEN}r{RESET}esume the apply, or roll{GREEN}b{RESET}ack the affected files? [r/b] ");
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    match answer.trim() {
+        "r" | "resume" | "" => Ok(true),
+        "b" | "rollback" => {
+            journal.rollback(journal_dir)?;
+            log::info!("rolled back the last apply");
+            Ok(false)
+        }
+        answer => bail!("unknown answer: {answer}"),
+    }
+}
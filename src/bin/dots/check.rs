@@ -0,0 +1,140 @@
+//! Commands which compare what the repo describes with what is on disk, without changing
+//! anything
+
+use dots::{ApplyIndex, Cli, Event, FileState, Journal, Operation, World};
+use eyre::{Result, bail};
+use itertools::Itertools as _;
+use tap::Pipe as _;
+
+use crate::{analyze, notify, pager, process, world};
+
+/// Check that every managed file on disk is the same as what `dots` would write to it
+pub fn check(cli: &Cli) -> Result<()> {
+    let analysis = analyze(cli)?;
+
+    let drifted = analysis
+        .writes()
+        .filter_map(|write| match write.state() {
+            FileState::UpToDate => None,
+            state => Some(format!("{state} {}", write.path.to_string_lossy())),
+        })
+        // settings are only applied when they differ
+        .chain(
+            analysis
+                .operations
+                .iter()
+                .filter(|operation| {
+                    matches!(
+                        operation,
+                        Operation::LoadDconf { .. }
+                            | Operation::WriteDefaults { .. }
+                            | Operation::OverrideFlatpak { .. }
+                    )
+                })
+                .map(|operation| format!("would {operation}")),
+        )
+        .collect_vec();
+
+    if drifted.is_empty() {
+        log::info!(
+            "all {} managed files are up to date",
+            analysis.writes().count()
+        );
+        return Ok(());
+    }
+
+    let message = format!(
+        "{} managed files and settings differ from the repo:\n{}",
+        drifted.len(),
+        drifted.join("\n")
+    );
+    notify(cli, Event::Drift, &message);
+
+    bail!(message)
+}
+
+/// Show the state of every file that `dots` manages now or managed before
+pub fn status(cli: &Cli) -> Result<()> {
+    let analysis = analyze(cli)?;
+    let journal_dir = Journal::dir()?;
+
+    dots::status(analysis.writes(), &ApplyIndex::load(&journal_dir)?)
+        .pipe_deref(dots::status_report)
+        .pipe_deref(|output| dots::page(output, pager(cli)))
+}
+
+/// Show how every managed file on disk differs from what `dots` would write to it
+pub fn diff(cli: &Cli) -> Result<()> {
+    let world = world(cli)?;
+    let tool = world.diff_tool.clone();
+    let analysis = process(cli, world)?;
+
+    let diffs = analysis
+        .writes()
+        .map(|write| tool.show(write))
+        .filter_map_ok(|diff| diff)
+        .collect::<Result<Vec<_>>>()?;
+
+    if diffs.is_empty() {
+        log::info!(
+            "all {} managed files are up to date",
+            analysis.writes().count()
+        );
+        return Ok(());
+    }
+
+    // a diff tool shows the differences by itself
+    let output = diffs.concat();
+    if !output.is_empty() {
+        dots::page(&dots::redact(&output), pager(cli))?;
+    }
+
+    bail!(
+        "{} managed files differ from what `dots` would write to them",
+        diffs.len()
+    )
+}
+
+/// Check the config and the variables used by every template
+pub fn validate(cli: &Cli) -> Result<()> {
+    let world = world(cli)?;
+    scan_secrets(&world)?;
+    let lint = dots::lint(&world)?;
+    print!("{lint}");
+    let undefined = lint.undefined.len();
+
+    let analysis = process(cli, world)?;
+
+    if undefined > 0 {
+        bail!("{undefined} variables are used by templates, but not defined in `[vars]`");
+    }
+
+    log::info!(
+        "config is valid, {} files would be written",
+        analysis.writes().count()
+    );
+
+    Ok(())
+}
+
+/// Refuse to continue if any source looks like it contains a secret, with `scan_secrets = true`
+pub fn scan_secrets(world: &World) -> Result<()> {
+    if !world.scan_secrets {
+        return Ok(());
+    }
+
+    let leaks = dots::leaks(world);
+    for leak in &leaks {
+        log::error!("{leak}");
+    }
+    if !leaks.is_empty() {
+        bail!(
+            "{} lines look like they contain secrets, remove them or encrypt the files. \
+             add `{}` to a line if it is not a secret",
+            leaks.len(),
+            dots::ALLOW_SECRET
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,20 @@
+//! `dots completions --install`
+
+use clap_complete::Shell;
+use dots::{Analysis, Completions, Journal, Operation, PathExt as _, User};
+use eyre::Result;
+
+/// Install the completions for `shell` into the directory it loads them from, owned by `owner`
+pub fn install(shell: Shell, owner: Option<&User>) -> Result<()> {
+    let dir = Journal::dir()?;
+    let mut completions = Completions::load(&dir)?;
+    let write = completions.install(shell)?;
+    let path = write.path.clone();
+    Analysis::builder()
+        .operation(Operation::Write(write))
+        .build()
+        .finish(&dir, owner)?;
+    completions.save(&dir)?;
+    log::info!("installed completions for {shell} into {}", path.show());
+    Ok(())
+}
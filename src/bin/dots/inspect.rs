@@ -0,0 +1,167 @@
+//! Commands which show what `dots` manages, without changing anything
+
+use dots::{
+    Cli, Entry, Environment, ExportDirs, ExportFormat, Journal, Machines, PathExt as _, Stats,
+    WritePath,
+};
+use eyre::{Context as _, ContextCompat as _, Result};
+use itertools::Itertools as _;
+use regex::RegexBuilder;
+use simply_colored::*;
+use std::path::Path;
+use tap::Pipe as _;
+
+use crate::check::scan_secrets;
+use crate::{analyze, pager, process, world};
+
+/// List every file that `dots` manages
+pub fn list(cli: &Cli) -> Result<()> {
+    let analysis = analyze(cli)?;
+
+    for err in &analysis.errors {
+        log::error!("{err}");
+    }
+
+    analysis
+        .writes()
+        .sorted_by_key(|write| &write.path)
+        .fold(String::new(), |output, write| {
+            let description = write
+                .description
+                .as_ref()
+                .map(|description| format!("  {BLACK}# {description}{RESET}"))
+                .unwrap_or_default();
+            format!("{output}{}{description}\n", write.path.show())
+        })
+        .pipe_deref(|output| dots::page(output, pager(cli)))
+}
+
+/// Export every managed file into the format of another tool, refusing to export secrets
+/// unless `allow_sensitive`
+pub fn export(
+    cli: &Cli,
+    format: ExportFormat,
+    dir: Option<&Path>,
+    allow_sensitive: bool,
+) -> Result<()> {
+    let world = world(cli)?;
+    scan_secrets(&world)?;
+    let root = world.root.clone();
+    let analysis = process(cli, world)?;
+    dots::check_sensitive(analysis.writes(), allow_sensitive)?;
+    // in case a secret ended up in a file which is not known to contain it
+    let redact = |output: String| {
+        if allow_sensitive {
+            output
+        } else {
+            dots::redact(&output).into_owned()
+        }
+    };
+
+    let environment = Environment::current()?;
+    let dirs = ExportDirs {
+        root: &root,
+        home: &environment.home_dir,
+        config: &environment.config_dir,
+    };
+
+    match format {
+        ExportFormat::HomeManager => {
+            print!("{}", redact(dots::home_manager(analysis.writes(), &dirs)));
+        }
+        ExportFormat::AnsibleCopyTasks => {
+            println!(
+                "{}",
+                redact(dots::ansible_copy_tasks(analysis.writes(), &dirs)?)
+            );
+        }
+        ExportFormat::Stow => {
+            let dir = dir.context("`--format stow` requires a directory to export into")?;
+            let packages = dots::stow(analysis.writes(), &dirs, dir)?;
+
+            log::info!(
+                "exported to {}, install with: stow --dir '{}' --target ~ {}",
+                dir.show(),
+                dir.to_string_lossy(),
+                packages.join(" ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Report problems with this machine and with the repo, with `unused` also the sources
+/// which are never written
+pub fn doctor(cli: &Cli, unused: bool) -> Result<()> {
+    let mut report = dots::doctor_tools(&cli.dir()?)?;
+    let world = world(cli)?;
+    report.push_str(&dots::doctor_duplicates(&world));
+    if unused {
+        let root = world.root.clone();
+        let unused_inputs = world.unused_inputs.clone();
+        let analysis = process(cli, world)?;
+        let unused = dots::unused(&unused_inputs, &analysis);
+        report.push_str(&dots::doctor_unused(&root, &unused));
+    }
+    if report.is_empty() {
+        log::info!("found no problems");
+    } else {
+        print!("{report}");
+    }
+    Ok(())
+}
+
+/// Search the source of every managed file for `pattern`, or what will be written if `rendered`
+pub fn search(cli: &Cli, pattern: &str, rendered: bool, ignore_case: bool) -> Result<()> {
+    let pattern = RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .context("invalid pattern")?;
+    let analysis = analyze(cli)?;
+
+    dots::search(analysis.writes(), &pattern, rendered)
+        .pipe_deref(|output| dots::page(&dots::redact(output), pager(cli)))
+}
+
+/// Summarize the dotfiles repo
+pub fn stats(cli: &Cli) -> Result<()> {
+    let world = world(cli)?;
+    let mut stats = Stats::new(&world);
+    stats.total_size = process(cli, world)?.writes().map(WritePath::size).sum();
+    stats.last_apply = Journal::last_apply(&Journal::dir()?)?;
+
+    print!("{stats}");
+    Ok(())
+}
+
+/// List every machine the repo was applied on, and whether it is behind the repo
+pub fn machines(cli: &Cli) -> Result<()> {
+    let world = world(cli)?;
+
+    Machines::load(&Journal::dir()?)?
+        .show(&world.hash())
+        .pipe_deref(|output| dots::page(output, pager(cli)))
+}
+
+/// Show how the `entry` of the config is resolved, and which files it writes
+pub fn explain(cli: &Cli, entry: &Entry) -> Result<()> {
+    let analysis = analyze(cli)?;
+
+    print!(
+        "{}",
+        dots::redact(&dots::explain(entry, &cli.dir()?, analysis.writes())?)
+    );
+    Ok(())
+}
+
+/// Show where to learn more about the `app`, along with its files and links
+pub fn info(cli: &Cli, app: &str) -> Result<()> {
+    let analysis = analyze(cli)?;
+
+    print!(
+        "{}",
+        dots::redact(&dots::info(app, &cli.dir()?, analysis.writes())?)
+    );
+    Ok(())
+}
@@ -0,0 +1,333 @@
+//! `dots` is a cozy dotfiles manager
+
+mod apply;
+mod check;
+mod completions;
+mod inspect;
+mod repo;
+mod vars;
+mod watch;
+
+use clap::{CommandFactory as _, Parser as _};
+use dots::{
+    AbsorbAction, Analysis, ApplyReport, CacheAction, Cli, Command, CompletionKind, Completions,
+    Environment, Event, FixtureAction, Journal, Limiter, LinkCache, LocalVars, Notify,
+    PackagesAction, PathExt as _, RenderCache, Reporter, RunContext, ToolsAction, User, VarsAction,
+    World,
+};
+use eyre::{Result, eyre};
+use simply_colored::*;
+use std::borrow::Cow;
+use std::io::Write as _;
+use tap::Pipe as _;
+
+use log::Level;
+
+fn main() -> Result<()> {
+    // completions that `dots completions` printed run `dots` with `$COMPLETE` set
+    clap_complete::CompleteEnv::with_factory(Cli::command).complete();
+
+    let mut cli = Cli::parse();
+    // detected once, so every part of the run agrees on it
+    let context = cli.context();
+    cli.context = Some(context);
+    // failing to obtain it is only an error for the commands which need the repo
+    cli.dir = std::env::current_dir().ok();
+    let colors = context.colors();
+
+    env_logger::Builder::new()
+        .filter_level(cli.verbosity.into())
+        // an empty string does not add any filters
+        .parse_filters(cli.log.as_deref().unwrap_or_default())
+        .format(move |buf, record| {
+            let color = match record.level() {
+                Level::Error => RED,
+                Level::Warn => YELLOW,
+                Level::Info => GREEN,
+                Level::Debug => BLUE,
+                Level::Trace => CYAN,
+            };
+            let level = record.level();
+            let message = dots::redact(&record.args().to_string()).into_owned();
+
+            if !colors {
+                return writeln!(buf, "[{level}] {}", dots::strip_colors(&message));
+            }
+            writeln!(buf, "{BLACK}[{color}{level}{BLACK}]{RESET} {message}",)
+        })
+        .init();
+
+    if colors {
+        let _ = color_eyre::install();
+    }
+
+    let fallbacks = context.fallbacks();
+    for (var, value) in &fallbacks {
+        log::debug!("{var} is not set, using {}", value.to_string_lossy());
+    }
+    // SAFETY: this runs before any other threads are spawned
+    unsafe {
+        for (var, value) in fallbacks {
+            std::env::set_var(var, value);
+        }
+    }
+
+    if let Some(identity) = &cli.identity {
+        // SAFETY: this runs before any other threads are spawned
+        unsafe { std::env::set_var(dots::IDENTITY_VAR, identity) };
+    }
+
+    let user = cli.user.as_deref().map(User::from_name).transpose()?;
+    // every directory, such as `{config_dir}`, is resolved relative to the other home
+    // directory instead of the one of the user running `dots`
+    let home = cli
+        .home
+        .as_deref()
+        .or_else(|| user.as_ref().map(|user| user.home.as_path()))
+        .map(|home| Environment::detect().map(|environment| environment.with_home(home)))
+        .transpose()?;
+
+    let result = match home {
+        Some(environment) => environment.scope(|| run(&cli, user.as_ref())),
+        None => run(&cli, user.as_ref()),
+    };
+
+    // errors can contain secrets, e.g. in the contents of a file that failed to be written
+    result.map_err(|err| {
+        let message = format!("{err:?}");
+        match dots::redact(&message) {
+            Cow::Borrowed(_) => err,
+            Cow::Owned(redacted) => eyre!(redacted),
+        }
+    })
+}
+
+/// Run the command of the `cli`
+fn run(cli: &Cli, user: Option<&User>) -> Result<()> {
+    if cli.print_hashes {
+        return print_hashes(cli);
+    }
+
+    match cli.command.clone().unwrap_or_default() {
+        Command::Apply { dry_run: true, .. } => apply::dry_run(cli),
+        Command::Apply {
+            schedule: None,
+            jitter,
+            dry_run: false,
+        } => apply::after_jitter(cli, user, jitter),
+        Command::Apply {
+            schedule: Some(schedule),
+            jitter,
+            dry_run: false,
+        } => apply::scheduled(cli, user, schedule.0, jitter),
+        Command::Plan => apply::plan(cli),
+        Command::List => inspect::list(cli),
+        Command::Check => check::check(cli),
+        Command::Status => check::status(cli),
+        Command::Prune { dry_run } => apply::prune(cli, dry_run),
+        Command::Diff => check::diff(cli),
+        Command::Watch {
+            enforce,
+            ref save,
+            interval,
+        } => watch::watch(cli, user, enforce, save.as_deref(), interval),
+        Command::Validate => check::validate(cli),
+        Command::Vars { action: None } => vars::list(cli),
+        Command::Vars {
+            action:
+                Some(VarsAction::Set {
+                    ref name,
+                    ref value,
+                }),
+        } => vars::set(name, value),
+        Command::Vars {
+            action: Some(VarsAction::Unset { ref name }),
+        } => vars::unset(name),
+        Command::Migrate => repo::migrate(cli),
+        Command::Fmt { check } => repo::fmt(cli, check),
+        Command::Absorb {
+            action: AbsorbAction::Dconf,
+        } => repo::absorb_dconf(cli),
+        Command::Tools {
+            action: ToolsAction::Sync,
+        } => cli.dir()?.pipe_deref(dots::sync_tools),
+        Command::Packages {
+            action: PackagesAction::Export { format },
+        } => repo::export_packages(cli, format),
+        Command::Packages {
+            action: PackagesAction::Import { ref brewfile },
+        } => repo::import_brewfile(cli, brewfile),
+        Command::Vendor { check: false } => repo::vendor(cli),
+        Command::Vendor { check: true } => repo::check_vendor(cli),
+        Command::Update { ref urls } => repo::update(cli, urls),
+        Command::Doctor { unused } => inspect::doctor(cli, unused),
+        Command::Search {
+            ref pattern,
+            rendered,
+            ignore_case,
+        } => inspect::search(cli, pattern, rendered, ignore_case),
+        Command::Edit { ref file } => dots::edit(file, &cli.dir()?),
+        Command::Cache {
+            action: CacheAction::Clear,
+        } => {
+            let dir = RenderCache::dir()?;
+            RenderCache::clear(&dir)?;
+            log::info!("cleared the cache in {}", dir.show());
+            Ok(())
+        }
+        Command::Stats => inspect::stats(cli),
+        Command::Machines => inspect::machines(cli),
+        Command::Explain { ref entry } => inspect::explain(cli, entry),
+        Command::Info { ref app } => inspect::info(cli, app),
+        Command::Export {
+            format,
+            ref dir,
+            allow_sensitive,
+        } => inspect::export(cli, format, dir.as_deref(), allow_sensitive),
+        Command::Completions {
+            shell,
+            install: false,
+        } => {
+            print!("{}", Completions::script(shell)?);
+            Ok(())
+        }
+        Command::Completions {
+            shell,
+            install: true,
+        } => completions::install(shell, user),
+        Command::BenchFixture {
+            action: FixtureAction::Generate { ref dir, files },
+        } => {
+            dots::generate_fixture(dir, files)?;
+            log::info!("generated {files} files in {}", dir.show());
+            Ok(())
+        }
+        Command::Complete { kind } => complete(cli, kind),
+    }
+}
+
+/// Print values for dynamic shell completions
+fn complete(cli: &Cli, kind: CompletionKind) -> Result<()> {
+    // completions must never print errors, they would end up in the user's prompt
+    let candidates = cli
+        .dir()
+        .and_then(|cwd| dots::candidates(kind, &cwd))
+        .unwrap_or_default();
+
+    for candidate in candidates {
+        println!("{candidate}");
+    }
+
+    Ok(())
+}
+
+/// Print the actual sha256 of every link, warning about the ones which do not match their pin
+fn print_hashes(cli: &Cli) -> Result<()> {
+    let world = world(cli)?;
+
+    for (url, actual, mismatched) in world.link_hashes() {
+        if mismatched {
+            log::warn!("{url} does not match its `sha256`");
+        }
+        println!("{actual}  {url}");
+    }
+
+    Ok(())
+}
+
+/// Read the inputs of `dots`
+fn world(cli: &Cli) -> Result<World> {
+    if cli.refresh {
+        LinkCache::new(&RenderCache::dir()?).clear()?;
+    }
+
+    let cwd = cli.dir()?;
+    let world = if cli.profiles.is_empty() && !cli.strict {
+        World::new(&cwd)
+    } else {
+        let mut environment = Environment::current()?;
+        if !cli.profiles.is_empty() {
+            environment.profiles = Some(cli.profiles.clone());
+        }
+        environment.strict |= cli.strict;
+        environment.scope(|| World::new(&cwd))
+    };
+
+    world.map_err(report)?.pipe(|mut world| {
+        world.gate_system(cli.system)?;
+        // installed on this machine, instead of being in the repo
+        world.install_completions(&Completions::load(&Journal::dir()?)?)?;
+        if !cli.no_cache {
+            world.cache = RenderCache::load(&RenderCache::dir()?)?;
+        }
+        Ok(world)
+    })
+}
+
+/// Compute what `dots` is going to do with the `world`, saving rendered templates for the next run
+fn process(cli: &Cli, world: World) -> Result<Analysis> {
+    let analysis = world.process().map_err(report)?;
+
+    if !cli.no_cache {
+        // the cache only makes the next run faster, failing to save it is not an error
+        if let Err(err) = RenderCache::dir().and_then(|dir| analysis.cache.save(&dir)) {
+            log::debug!("{err}");
+        }
+    }
+
+    Ok(analysis)
+}
+
+/// Compute what `dots` is going to do
+fn analyze(cli: &Cli) -> Result<Analysis> {
+    process(cli, world(cli)?)
+}
+
+/// Log every error
+fn report(errs: Vec<eyre::Error>) -> eyre::Error {
+    for err in errs {
+        log::error!("{err}");
+    }
+
+    eyre!("encountered errors")
+}
+
+/// Whether long output is piped into `$PAGER`
+fn pager(cli: &Cli) -> bool {
+    !cli.no_pager && cli.context() == RunContext::Interactive
+}
+
+/// Ask for the variables which are answered once on each machine, unless `dots` is
+/// not interactive
+fn answer_prompts(cli: &Cli) -> Result<()> {
+    let Some(dir) = Environment::current()?.local_vars else {
+        return Ok(());
+    };
+    let cwd = cli.dir()?;
+
+    LocalVars::answer_prompts(&dir, &cwd, cli.context().is_interactive())
+}
+
+/// Send a notification to every destination in the `[notify]` config,
+/// logging failures instead of returning them
+fn notify(cli: &Cli, event: Event, message: &str) {
+    let result = cli
+        .dir()
+        .and_then(|cwd| Notify::discover(&cwd)?.send(&Limiter::discover(&cwd)?, event, message));
+
+    if let Err(err) = result {
+        log::error!("failed to send notification: {err}");
+    }
+}
+
+/// Send the `report` of an apply to every destination in the `[report]` config,
+/// logging failures instead of returning them
+fn send_report(cli: &Cli, report: &ApplyReport) {
+    let result = cli
+        .dir()
+        .and_then(|cwd| Reporter::discover(&cwd)?.send(&Limiter::discover(&cwd)?, report));
+
+    if let Err(err) = result {
+        log::error!("failed to send apply report: {err}");
+    }
+}
@@ -0,0 +1,77 @@
+//! Commands which change the repo, instead of the files that it manages
+
+use dots::{Cli, PathExt as _};
+use eyre::{Result, bail};
+use std::path::Path;
+
+/// Upgrade the config to the current format, keeping its comments
+pub fn migrate(cli: &Cli) -> Result<()> {
+    let changes = dots::migrate_file(&cli.dir()?)?;
+    for change in &changes {
+        log::info!("{change}");
+    }
+    log::info!(
+        "the config uses version {} of the format",
+        dots::FORMAT_VERSION
+    );
+    Ok(())
+}
+
+/// Format the config, or only `check` that it is formatted
+pub fn fmt(cli: &Cli, check: bool) -> Result<()> {
+    let changed = dots::format_file(&cli.dir()?, check)?;
+    match (changed, check) {
+        (true, true) => bail!("the config is not formatted, run `dots fmt`"),
+        (true, false) => log::info!("formatted the config"),
+        (false, _) => log::info!("the config is already formatted"),
+    }
+    Ok(())
+}
+
+/// Copy the `dconf` settings which are managed by `dots` from the system back into the repo
+pub fn absorb_dconf(cli: &Cli) -> Result<()> {
+    dots::absorb_dconf(&cli.dir()?)
+}
+
+/// Print the packages of the config in the `format` of another tool
+pub fn export_packages(cli: &Cli, format: dots::PackagesFormat) -> Result<()> {
+    dots::export_packages(&cli.dir()?, format)
+}
+
+/// Add the packages of the `brewfile` to the config
+pub fn import_brewfile(cli: &Cli, brewfile: &Path) -> Result<()> {
+    let imported = dots::import_brewfile(&cli.dir()?, brewfile)?;
+    log::info!("imported {imported} packages from {}", brewfile.show());
+    Ok(())
+}
+
+/// Save the contents of every link into the repo
+pub fn vendor(cli: &Cli) -> Result<()> {
+    let vendored = dots::vendor(&cli.dir()?)?;
+    log::info!("vendored {vendored} links into `{}`", dots::Vendor::DIR);
+    Ok(())
+}
+
+/// Check that every vendored link is the same as its upstream
+pub fn check_vendor(cli: &Cli) -> Result<()> {
+    let outdated = dots::check_vendor(&cli.dir()?)?;
+    for link in &outdated {
+        link.log();
+    }
+    if !outdated.is_empty() {
+        bail!(
+            "{} vendored links differ from upstream, run `dots vendor` to update them",
+            outdated.len()
+        );
+    }
+    log::info!("every vendored link is up to date");
+    Ok(())
+}
+
+/// Fetch the links at `urls` again, or every link if there are none, and record their
+/// current contents in `dots.lock`
+pub fn update(cli: &Cli, urls: &[String]) -> Result<()> {
+    let updated = dots::update_lockfile(&cli.dir()?, urls)?;
+    log::info!("updated {updated} links in `{}`", dots::Lockfile::FILE_NAME);
+    Ok(())
+}
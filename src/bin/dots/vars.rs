@@ -0,0 +1,58 @@
+//! `dots vars`, which lists the variables and sets them on this machine
+
+use dots::{Cli, Journal, LocalVars};
+use eyre::{Result, bail};
+use itertools::Itertools as _;
+use simply_colored::*;
+use tap::Pipe as _;
+
+use crate::{answer_prompts, pager, world};
+
+/// List every variable available in templates, along with where its value comes from
+pub fn list(cli: &Cli) -> Result<()> {
+    answer_prompts(cli)?;
+    let world = world(cli)?;
+
+    // secrets are not in `vars`, and are never shown
+    let secrets = world
+        .secrets
+        .keys()
+        .map(|name| (name, dots::REDACTED.to_string()));
+
+    world
+        .vars
+        .iter()
+        .map(|(name, value)| (name, value.to_string()))
+        .chain(secrets)
+        .sorted()
+        .fold(String::new(), |output, (name, value)| {
+            let source = world.var_sources.get(name);
+            let source = source
+                .map(|source| format!("  {BLACK}# {source}{RESET}"))
+                .unwrap_or_default();
+            format!("{output}{name} = {value}{source}\n")
+        })
+        .pipe_deref(|output| dots::page(&dots::redact(output), pager(cli)))
+}
+
+/// Set the variable `name` to `value` on this machine
+pub fn set(name: &str, value: &str) -> Result<()> {
+    let dir = Journal::dir()?;
+    let mut local = LocalVars::load(&dir)?;
+    local.set(name.to_string(), value)?;
+    local.save(&dir)?;
+    log::info!("set `{name}` to `{}` on this machine", local.vars[name]);
+    Ok(())
+}
+
+/// Remove the variable `name` from this machine
+pub fn unset(name: &str) -> Result<()> {
+    let dir = Journal::dir()?;
+    let mut local = LocalVars::load(&dir)?;
+    if local.vars.remove(name).is_none() {
+        bail!("`{name}` is not set on this machine");
+    }
+    local.save(&dir)?;
+    log::info!("unset `{name}` on this machine");
+    Ok(())
+}
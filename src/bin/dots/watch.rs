@@ -0,0 +1,100 @@
+//! `dots watch`
+
+use dots::{Cli, Event, PathExt as _, User};
+use eyre::Result;
+use itertools::Itertools as _;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::apply::apply;
+use crate::{analyze, notify};
+
+/// Report managed files which are changed outside of `dots` every `interval` seconds,
+/// re-applying them if `enforce`
+pub fn watch(
+    cli: &Cli,
+    owner: Option<&User>,
+    enforce: bool,
+    save: Option<&Path>,
+    interval: u64,
+) -> Result<()> {
+    // the repo is authoritative, re-applying must never wait for an answer
+    let apply_cli = Cli {
+        yes: true,
+        ..cli.clone()
+    };
+
+    // a config which is broken from the start is an error, later errors are only logged
+    log::info!("watching {} managed files", analyze(cli)?.writes().count());
+    // without `enforce` changes stay, and are only reported once
+    let mut reported = BTreeMap::new();
+    while !dots::is_cancelled() {
+        let analysis = match analyze(cli) {
+            Ok(analysis) => analysis,
+            // e.g. the config is being edited, the next check can succeed
+            Err(err) => {
+                log::error!("{err:#}");
+                dots::cancellable_sleep(Duration::from_secs(interval));
+                continue;
+            }
+        };
+        let mut changes = dots::changes(&analysis);
+        let seen = std::mem::replace(
+            &mut reported,
+            changes
+                .iter()
+                .map(|change| (change.path.clone(), change.contents.clone()))
+                .collect(),
+        );
+        changes.retain(|change| seen.get(&change.path) != Some(&change.contents));
+
+        if !changes.is_empty() {
+            let review = save.map(|save| {
+                save.join(
+                    jiff::Timestamp::now()
+                        .strftime("%Y-%m-%dT%H-%M-%SZ")
+                        .to_string(),
+                )
+            });
+            for change in &changes {
+                change.log();
+                if let Some(review) = &review {
+                    match change.save(review) {
+                        Ok(Some(saved)) => {
+                            log::info!("saved {} to {}", change.path.show(), saved.show());
+                        }
+                        Ok(None) => {}
+                        Err(err) => log::error!("{err:#}"),
+                    }
+                }
+            }
+
+            if enforce {
+                if let Err(err) = apply(&apply_cli, owner) {
+                    log::error!("{err:#}");
+                    notify(cli, Event::ApplyFailed, &format!("{err}"));
+                }
+                // the same change must be enforced again if it comes back
+                reported.clear();
+            } else {
+                notify(
+                    cli,
+                    Event::Drift,
+                    &format!(
+                        "{} managed files were changed outside of `dots`:\n{}",
+                        changes.len(),
+                        changes
+                            .iter()
+                            .map(|change| change.path.to_string_lossy())
+                            .join("\n")
+                    ),
+                );
+            }
+        }
+
+        dots::cancellable_sleep(Duration::from_secs(interval));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! Managed blocks, which are the only part of an otherwise unmanaged file that `dots`
+//! writes to
+//!
+//! ```text
+//! # >>> dots >>>
+//! ...
+//! # <<< dots <<<
+//! ```
+
+use eyre::{Result, bail};
+
+/// Text after the comment token on the first line of a managed block
+const BEGIN: &str = ">>> dots >>>";
+
+/// Text after the comment token on the last line of a managed block
+const END: &str = "<<< dots <<<";
+
+/// Replace the managed block in `contents` with `block`, or append it if there is none
+///
+/// `comment` is the line comment token of the file, e.g. `#`
+pub fn replace(contents: &str, comment: &str, block: &str) -> Result<String> {
+    let begin = format!("{comment} {BEGIN}");
+    let end = format!("{comment} {END}");
+    let block = format!("{begin}\n{}\n{end}\n", block.trim_end());
+
+    let lines = contents.lines().collect::<Vec<_>>();
+    let start = lines.iter().position(|line| line.trim() == begin);
+    let stop = lines.iter().position(|line| line.trim() == end);
+
+    match (start, stop) {
+        (Some(start), Some(stop)) if start < stop => {
+            let before = lines[..start].iter().map(|line| format!("{line}\n"));
+            let after = lines[stop + 1..].iter().map(|line| format!("{line}\n"));
+            Ok(before.chain([block]).chain(after).collect())
+        }
+        (None, None) if contents.is_empty() => Ok(block),
+        (None, None) => {
+            let separator = if contents.ends_with('\n') {
+                "\n"
+            } else {
+                "\n\n"
+            };
+            Ok(format!("{contents}{separator}{block}"))
+        }
+        _ => bail!("managed block is missing `{begin}` or `{end}`, or they are out of order"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn replace() {
+        assert_eq!(
+            super::replace("[user]\n\tname = me\n", "#", "[include]").unwrap(),
+            "[user]\n\tname = me\n\n# >>> dots >>>\n[include]\n# <<< dots <<<\n"
+        );
+        assert_eq!(
+            super::replace("a\n# >>> dots >>>\nold\n# <<< dots <<<\nb\n", "#", "new\n").unwrap(),
+            "a\n# >>> dots >>>\nnew\n# <<< dots <<<\nb\n"
+        );
+        assert!(super::replace("# <<< dots <<<\n# >>> dots >>>\n", "#", "new").is_err());
+    }
+}
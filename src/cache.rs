@@ -0,0 +1,201 @@
+//! Contains [`RenderCache`] and [`LinkCache`]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use eyre::{Context as _, Result, eyre};
+use serde::{Deserialize, Serialize};
+use tap::Pipe as _;
+
+use crate::environment::Environment;
+use crate::fetch::{Fetched, Limiter};
+use crate::stdx::PathExt as _;
+
+/// Rendered templates from earlier runs, so a template does not have to be
+/// rendered again when nothing it depends on has changed
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct RenderCache {
+    /// Rendered contents, keyed by [`RenderCache::key`]
+    #[serde(default)]
+    pub rendered: BTreeMap<String, String>,
+}
+
+impl RenderCache {
+    /// Name of the cache file, inside of the cache directory
+    const FILE_NAME: &str = "rendered.toml";
+
+    /// Directory where the cache is stored, e.g. `~/.cache/dots`
+    pub fn dir() -> Result<PathBuf> {
+        Ok(Environment::current()?.cache_dir.join("dots"))
+    }
+
+    /// Load the cache
+    ///
+    /// A cache which can not be parsed is discarded, it will be rebuilt by the next run
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(Self::FILE_NAME);
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|err| {
+                log::debug!("discarding cache {}: {err}", path.show());
+                Self::default()
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(err) => return Err(err).with_context(|| eyre!("failed to read {}", path.show())),
+        }
+        .pipe(Ok)
+    }
+
+    /// Write the cache to disk
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)
+            .with_context(|| eyre!("failed to create cache directory {}", dir.show()))?;
+
+        let path = dir.join(Self::FILE_NAME);
+        let contents = toml::to_string(self).context("failed to serialize cache")?;
+        fs::write(&path, contents).with_context(|| eyre!("failed to write {}", path.show()))
+    }
+
+    /// Key of the `template` rendered with `data`
+    ///
+    /// Includes the version of `dots`, because a different version may render differently
+    pub fn key(template: &str, data: &impl Serialize) -> String {
+        let data = serde_json::to_string(data).unwrap_or_default();
+
+        format!("{}\0{template}\0{data}", env!("CARGO_PKG_VERSION")).pipe(sha256::digest)
+    }
+
+    /// Remove everything that is cached, which is rebuilt by the next run
+    pub fn clear(dir: &Path) -> Result<()> {
+        match fs::remove_dir_all(dir) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(err).with_context(|| eyre!("failed to remove {}", dir.show()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Links pinned with `sha256` which were fetched by earlier runs
+///
+/// A pinned link can not change without its pin changing too, so it is only fetched once.
+/// Each link is stored in a file named after its URL and its pin
+pub struct LinkCache {
+    /// Directory containing the links
+    dir: PathBuf,
+}
+
+impl LinkCache {
+    /// Name of the directory containing the links, inside of the cache directory
+    const DIR_NAME: &str = "links";
+
+    /// The links cached inside of the cache directory `dir`
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.join(Self::DIR_NAME),
+        }
+    }
+
+    /// Contents of the link at `url`, which are only fetched if they are not cached
+    ///
+    /// Links without a `sha256` are always fetched, through the `limiter`
+    pub fn get(&self, limiter: &Limiter, url: &str, sha256: Option<&str>) -> Result<Fetched> {
+        let Some(sha256) = sha256 else {
+            return limiter.fetch(url);
+        };
+        let path = self
+            .dir
+            .join(format!("{url}\0{sha256}").pipe(sha256::digest));
+
+        if let Ok(bytes) = fs::read(&path)
+            && sha256::digest(&bytes) == sha256
+        {
+            log::debug!(target: "network", "using cached {url}");
+            return Ok(Fetched {
+                bytes,
+                etag: None,
+                last_modified: None,
+            });
+        }
+
+        let fetched = limiter.fetch(url)?;
+        // a link which does not match its pin is an error later, and is not worth caching
+        if sha256::digest(&fetched.bytes) == sha256 {
+            // the cache only makes the next run faster, failing to save it is not an error
+            if let Err(err) =
+                fs::create_dir_all(&self.dir).and_then(|()| fs::write(&path, &fetched.bytes))
+            {
+                log::debug!("failed to cache {url}: {err}");
+            }
+        }
+
+        Ok(fetched)
+    }
+
+    /// Remove every cached link, so they are fetched again
+    pub fn clear(&self) -> Result<()> {
+        RenderCache::clear(&self.dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("dots");
+
+        assert_eq!(RenderCache::load(&dir).unwrap(), RenderCache::default());
+
+        let key = RenderCache::key("{{foo}}", &BTreeMap::<u8, u8>::new());
+        assert_ne!(key, RenderCache::key("{{bar}}", &BTreeMap::<u8, u8>::new()));
+
+        let cache = RenderCache {
+            rendered: BTreeMap::from([(key, "rendered".to_string())]),
+        };
+        cache.save(&dir).unwrap();
+
+        assert_eq!(RenderCache::load(&dir).unwrap(), cache);
+
+        RenderCache::clear(&dir).unwrap();
+        assert!(!dir.exists());
+        RenderCache::clear(&dir).unwrap();
+    }
+
+    #[test]
+    fn cached_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let links = LinkCache::new(dir.path());
+        let url = "http://127.0.0.1:0/theme";
+        let sha256 = sha256::digest("theme");
+        let limiter = Limiter::default();
+
+        // nothing listens on port 0, so the link can only come from the cache
+        assert!(links.get(&limiter, url, Some(&sha256)).is_err());
+        fs::create_dir_all(&links.dir).unwrap();
+        fs::write(
+            links
+                .dir
+                .join(format!("{url}\0{sha256}").pipe(sha256::digest)),
+            "theme",
+        )
+        .unwrap();
+        assert_eq!(
+            links.get(&limiter, url, Some(&sha256)).unwrap().bytes,
+            b"theme"
+        );
+        // a different pin is a different link
+        assert!(
+            links
+                .get(&limiter, url, Some(&sha256::digest("other")))
+                .is_err()
+        );
+
+        links.clear().unwrap();
+        assert!(links.get(&limiter, url, Some(&sha256)).is_err());
+    }
+}
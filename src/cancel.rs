@@ -0,0 +1,152 @@
+//! Contains [`CancelToken`] and [`handle_ctrl_c`]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, LazyLock, Mutex, PoisonError};
+use std::time::Duration;
+
+use eyre::{Context as _, Result};
+
+/// Set once the Ctrl-C handler is set
+static HANDLED: AtomicBool = AtomicBool::new(false);
+
+/// Cancelled when the user presses Ctrl-C, see [`handle_ctrl_c`]
+static CTRL_C: LazyLock<CancelToken> = LazyLock::new(CancelToken::default);
+
+/// Tells an apply, and the sleeps around it, to stop
+///
+/// Clones are cancelled together
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<Cancel>);
+
+/// Shared by the clones of a [`CancelToken`]
+#[derive(Debug, Default)]
+struct Cancel {
+    /// Set when the apply should stop
+    cancelled: AtomicBool,
+    /// Held while checking [`Cancel::cancelled`] in [`CancelToken::sleep`], so it can not
+    /// miss [`Cancel::wake`]
+    sleeping: Mutex<()>,
+    /// Notified when the token is cancelled, which wakes up [`CancelToken::sleep`]
+    wake: Condvar,
+}
+
+impl CancelToken {
+    /// The token that Ctrl-C cancels
+    pub fn ctrl_c() -> Self {
+        CTRL_C.clone()
+    }
+
+    /// Stop the apply, waking up every [`CancelToken::sleep`]
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+
+        let _sleeping = self
+            .0
+            .sleeping
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.0.wake.notify_all();
+    }
+
+    /// Whether the apply should stop
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Sleep for `duration`, or until the token is cancelled
+    ///
+    /// Returns `false` if it was cancelled
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let sleeping = self
+            .0
+            .sleeping
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let _sleeping = self
+            .0
+            .wake
+            .wait_timeout_while(sleeping, duration, |()| !self.is_cancelled())
+            .unwrap_or_else(PoisonError::into_inner);
+
+        !self.is_cancelled()
+    }
+}
+
+/// On Ctrl-C, let the operation that is in progress finish and stop before the next one,
+/// instead of killing the process between removing a file and writing it
+///
+/// Pressing Ctrl-C again exits immediately, the [`Journal`](crate::Journal) is left
+/// behind so the next run can resume or roll back the apply.
+///
+/// Can be called before every apply, e.g. by `dots watch`, the handler is only set once
+pub fn handle_ctrl_c() -> Result<()> {
+    if HANDLED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    ctrlc::set_handler(|| {
+        if CTRL_C.is_cancelled() {
+            std::process::exit(130);
+        }
+        CTRL_C.cancel();
+
+        log::warn!(target: "apply", "cancelling after the current operation, press Ctrl-C again to exit now");
+    })
+    .context("failed to set the Ctrl-C handler")
+}
+
+/// Whether the apply should stop, because Ctrl-C was pressed
+pub fn is_cancelled() -> bool {
+    CTRL_C.is_cancelled()
+}
+
+/// Sleep for `duration`, or until Ctrl-C is pressed, e.g. between scheduled applies
+///
+/// Returns `false` if it was cancelled
+pub fn cancellable_sleep(duration: Duration) -> bool {
+    CTRL_C.sleep(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::CancelToken;
+    use crate::Analysis;
+    use crate::journal::Journal;
+
+    #[test]
+    fn cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let journal_dir = dir.join("journal");
+        let cancel = CancelToken::default();
+
+        // a sleep is woken up when the apply is cancelled
+        let start = Instant::now();
+        let sleep = thread::spawn({
+            let cancel = cancel.clone();
+            move || cancel.sleep(Duration::from_secs(60))
+        });
+        thread::sleep(Duration::from_millis(50));
+        cancel.cancel();
+        assert!(!sleep.join().unwrap());
+        assert!(start.elapsed() < Duration::from_secs(30));
+        // only the apply of this token is cancelled
+        assert!(!super::is_cancelled());
+
+        let report = Analysis::builder()
+            .write(dir.join("foo"), "foo")
+            .cancel(cancel)
+            .build()
+            .finish(&journal_dir, None)
+            .unwrap();
+
+        assert!(report.cancelled);
+        assert!(report.written.is_empty());
+        assert!(!dir.join("foo").exists());
+        // the next run can resume the apply
+        assert!(Journal::load(&journal_dir).unwrap().is_some());
+    }
+}
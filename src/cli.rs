@@ -1,10 +1,21 @@
 //! The CLI interface
 
 use clap::{
-    Parser,
+    Parser, Subcommand,
     builder::styling::{AnsiColor, Effects},
 };
 
+use eyre::{Context as _, Result};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::complete::{self, CompletionKind};
+use crate::context::RunContext;
+use crate::explain::Entry;
+use crate::export::ExportFormat;
+use crate::packages::PackagesFormat;
+use crate::schedule::{self, Schedule};
+
 /// Styles for the CLI
 const STYLES: clap::builder::Styles = clap::builder::Styles::styled()
     .header(AnsiColor::BrightGreen.on_default().effects(Effects::BOLD))
@@ -16,11 +27,368 @@ const STYLES: clap::builder::Styles = clap::builder::Styles::styled()
     .invalid(AnsiColor::BrightYellow.on_default().effects(Effects::BOLD));
 
 /// Command-line interface
-#[derive(Parser, Debug, Copy, Clone)]
+#[derive(Parser, Debug, Clone)]
 #[command(version, styles = STYLES, long_about = None)]
 #[allow(clippy::struct_excessive_bools, reason = "normal for CLIs")]
 pub struct Cli {
     /// Control how much is logged
     #[command(flatten)]
     pub verbosity: clap_verbosity_flag::Verbosity<clap_verbosity_flag::InfoLevel>,
+    /// Control how much is logged for each subsystem, e.g. `network=debug,apply=warn`
+    ///
+    /// Subsystems are `network` (fetching links), `apply` (writing files) and
+    /// `journal` (recovering from an interrupted apply).
+    ///
+    /// Takes precedence over `-v` and `-q`
+    #[arg(long, value_name = "FILTERS", global = true)]
+    pub log: Option<String>,
+    /// Turn warnings into errors, e.g. links without a `sha256`, invalid markers or variables
+    /// which are not defined
+    #[arg(long, global = true)]
+    pub strict: bool,
+    /// Do not pipe long output into `$PAGER`
+    #[arg(long, global = true)]
+    pub no_pager: bool,
+    /// Render every template, instead of reusing what was rendered by earlier runs
+    #[arg(long, global = true)]
+    pub no_cache: bool,
+    /// Fetch every link, instead of reusing the links pinned with `sha256` which were
+    /// fetched by earlier runs
+    #[arg(long, global = true)]
+    pub refresh: bool,
+    /// Do not ask for confirmation before overwriting many existing files
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+    /// Replace files with empty files, which is refused by default because it is
+    /// usually caused by a broken template
+    #[arg(long, global = true)]
+    pub allow_truncate: bool,
+    /// Also write the `[[udev_rule]]`s and `[sysctl]` into `/etc`, which needs root
+    #[arg(long, global = true)]
+    pub system: bool,
+    /// Write files into the home directory of this user, and make them owned by it
+    ///
+    /// Useful when building container images as root
+    #[arg(long, value_name = "NAME", global = true)]
+    pub user: Option<String>,
+    /// Treat this directory as the home directory, instead of detecting it from the environment
+    ///
+    /// The config, data and cache directories are inside of it. Takes precedence over
+    /// the home directory of `--user`
+    #[arg(long, value_name = "PATH", global = true)]
+    pub home: Option<PathBuf>,
+    /// Apply the entries of this profile, instead of the `default_profiles` and the
+    /// profiles whose `when` matches. Can be passed several times
+    #[arg(long = "profile", value_name = "NAME", global = true)]
+    pub profiles: Vec<String>,
+    /// Fetch every link and print its actual sha256 instead of running the command, so
+    /// the links can be pinned with `sha256 = "..."`
+    #[arg(long)]
+    pub print_hashes: bool,
+    /// Identity which decrypts `dots.toml.age`, when the config is encrypted with `age`
+    ///
+    /// The config is only decrypted in memory. Can also be set with `DOTS_IDENTITY`
+    #[arg(long, value_name = "PATH", global = true)]
+    pub identity: Option<PathBuf>,
+    /// Print an `ApplyReport` of what `dots apply` did as JSON, or the `Summary` of what it
+    /// would do with `--dry-run`, instead of only logging it
+    #[arg(long, global = true)]
+    pub json: bool,
+    /// Where `dots` runs, detected from the environment if omitted
+    ///
+    /// `service` never prompts, pages or colors the logs, and fills in `$HOME`,
+    /// `$XDG_RUNTIME_DIR` and `$DBUS_SESSION_BUS_ADDRESS` if they are missing
+    #[arg(long, global = true)]
+    pub context: Option<RunContext>,
+    /// Directory that `dots` runs in, from which the repo is found
+    #[arg(skip)]
+    pub dir: Option<PathBuf>,
+    /// What to do. Applies the config if omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// A subcommand of `dots`
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Write every managed file to its location
+    Apply {
+        /// Keep running, and apply this often: `hourly`, `daily`, `weekly` or a duration like `30m`
+        #[arg(long)]
+        schedule: Option<Schedule>,
+        /// Sleep for a random duration shorter than this before each apply, e.g. `10m`
+        ///
+        /// Spreads out machines which apply the same repo at the same time
+        #[arg(long, value_parser = schedule::duration)]
+        jitter: Option<Duration>,
+        /// Print which files would be created, modified, removed or left unchanged, without
+        /// changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print everything that `dots apply` is going to do as JSON, without doing it
+    Plan,
+    /// List every file that `dots` manages
+    List,
+    /// Check that every managed file on disk is the same as what `dots` would write to it
+    ///
+    /// Exits with a non-zero code and sends a notification if any of them differ
+    Check,
+    /// Show whether every managed file is up to date, and files that `dots` wrote
+    /// before but the repo no longer contains
+    Status,
+    /// Remove the files which earlier applies wrote, but the repo no longer produces, e.g.
+    /// because their source was removed
+    ///
+    /// Files that were modified after `dots` wrote them are kept
+    Prune {
+        /// Only list the files, without removing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show how every managed file on disk differs from what `dots apply` would write to it
+    ///
+    /// Exits with a non-zero code if any of them differ
+    Diff,
+    /// Watch every managed file, reporting the ones which are changed outside of `dots`
+    Watch {
+        /// Re-apply managed files as soon as they are changed, so the repo stays authoritative
+        #[arg(long)]
+        enforce: bool,
+        /// Save each changed file into this directory before it is re-applied, for review
+        #[arg(long, value_name = "DIR")]
+        save: Option<PathBuf>,
+        /// Seconds between checks of the managed files
+        #[arg(long, value_name = "SECONDS", default_value_t = 2)]
+        interval: u64,
+    },
+    /// List every variable available in templates, and where its value comes from
+    Vars {
+        /// Change the variables of this machine instead
+        #[command(subcommand)]
+        action: Option<VarsAction>,
+    },
+    /// Upgrade the config to the current format, keeping its comments
+    Migrate,
+    /// Normalize the config: group its tables in a fixed order, put the keys of every
+    /// table in the same order and sort the `[[package]]`s, keeping its comments
+    Fmt {
+        /// Only check that the config is normalized, without changing it
+        ///
+        /// Exits with a non-zero code if it is not
+        #[arg(long)]
+        check: bool,
+    },
+    /// Check that the config is valid and that every template only uses variables
+    /// which are defined in `[vars]`, without writing anything
+    ///
+    /// Also reports variables which are defined, but not used by any template
+    Validate,
+    /// Copy settings which are managed by `dots` from the system back into the repo
+    Absorb {
+        /// Which settings to copy
+        #[command(subcommand)]
+        action: AbsorbAction,
+    },
+    /// Manage the CLI tools that the configs depend on
+    Tools {
+        /// What to do with the tools
+        #[command(subcommand)]
+        action: ToolsAction,
+    },
+    /// Manage the Homebrew packages of the repo
+    Packages {
+        /// What to do with the packages
+        #[command(subcommand)]
+        action: PackagesAction,
+    },
+    /// Save the contents of every `[[link]]` into `vendor/`, so the repo can be applied
+    /// without network
+    Vendor {
+        /// Compare the vendored links with their upstreams instead, without changing them
+        ///
+        /// Exits with a non-zero code if any of them differ
+        #[arg(long)]
+        check: bool,
+    },
+    /// Fetch every `[[link]]` again, and record its current contents in `dots.lock`
+    ///
+    /// Links whose contents differ from `dots.lock` fail until it is updated
+    Update {
+        /// Resolved URLs of the links to update, every link if omitted
+        urls: Vec<String>,
+    },
+    /// Report problems with this machine, such as `[[tool]]`s which are not installed,
+    /// and with the repo, such as sources which are copies of each other
+    Doctor {
+        /// Also report sources which are never written, such as ones that another source
+        /// always overwrites
+        #[arg(long)]
+        unused: bool,
+    },
+    /// Search the source of every managed file, showing where each match is written to
+    Search {
+        /// Regular expression to search for
+        pattern: String,
+        /// Search what will be written, after rendering templates and including links
+        #[arg(long)]
+        rendered: bool,
+        /// Search case insensitively
+        #[arg(short, long)]
+        ignore_case: bool,
+    },
+    /// Open the source of a managed file in `$VISUAL` or `$EDITOR`
+    Edit {
+        /// Path of the file, relative to the config
+        #[arg(add = complete::completer(CompletionKind::Files))]
+        file: PathBuf,
+    },
+    /// Manage what is cached in `{cache_dir}/dots`, such as rendered templates and links
+    Cache {
+        /// What to do with the cache
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Summarize the dotfiles repo
+    Stats,
+    /// List every machine the repo was applied on, and whether it is behind the repo
+    Machines,
+    /// Show how a single entry of the config is resolved, and which files it writes
+    Explain {
+        /// Entry to explain
+        #[command(subcommand)]
+        entry: Entry,
+    },
+    /// Show where to learn more about an app in `[apps]`, along with its files and links
+    Info {
+        /// Name of the app, which is its directory inside of `[apps] dir`
+        app: String,
+    },
+    /// Export every managed file into the format of another tool, printing it to stdout
+    Export {
+        /// Format to export to
+        #[arg(long)]
+        format: ExportFormat,
+        /// Directory to export into, for formats that produce more than a single file
+        dir: Option<PathBuf>,
+        /// Export files which contain secrets, such as `secret` variables, in plaintext
+        #[arg(long)]
+        allow_sensitive: bool,
+    },
+    /// Print shell completions
+    Completions {
+        /// Shell to print completions for
+        shell: clap_complete::Shell,
+        /// Write them into the directory that the shell loads completions from instead,
+        /// and keep them up to date with every `dots apply`
+        #[arg(long)]
+        install: bool,
+    },
+    /// Generate a synthetic dotfiles repo, for benchmarking
+    #[command(name = "bench-fixture", hide = true)]
+    BenchFixture {
+        /// What to do with the fixture
+        #[command(subcommand)]
+        action: FixtureAction,
+    },
+    /// Print values for dynamic shell completions, one per line
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        /// What to complete
+        kind: CompletionKind,
+    },
+}
+
+impl Cli {
+    /// Where `dots` runs, according to `--context` or else its environment
+    pub fn context(&self) -> RunContext {
+        self.context.unwrap_or_else(RunContext::detect)
+    }
+
+    /// Directory that `dots` runs in, or else the current directory
+    pub fn dir(&self) -> Result<PathBuf> {
+        self.dir.clone().map_or_else(
+            || std::env::current_dir().context("failed to obtain current working directory"),
+            Ok,
+        )
+    }
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Self::Apply {
+            schedule: None,
+            jitter: None,
+            dry_run: false,
+        }
+    }
+}
+
+/// Subcommand of `dots tools`
+#[derive(Subcommand, Debug, Clone)]
+pub enum ToolsAction {
+    /// Install every `[[tool]]` which is not installed, or has a different version
+    Sync,
+}
+
+/// Subcommand of `dots vars`
+#[derive(Subcommand, Debug, Clone)]
+pub enum VarsAction {
+    /// Set a variable on this machine only, overriding `[vars]` without editing the repo
+    Set {
+        /// Name of the variable
+        name: String,
+        /// TOML value of the variable, e.g. `16` or `true`, and a string otherwise
+        value: String,
+    },
+    /// Remove a variable that was set with `dots vars set`
+    Unset {
+        /// Name of the variable
+        name: String,
+    },
+}
+
+/// Subcommand of `dots absorb`
+#[derive(Subcommand, Debug, Clone)]
+pub enum AbsorbAction {
+    /// Dump the settings of every `[[dconf]]` into its file
+    Dconf,
+}
+
+/// Subcommand of `dots cache`
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheAction {
+    /// Remove everything that is cached, so the next run renders every template and
+    /// fetches every link
+    Clear,
+}
+
+/// Subcommand of `dots packages`
+#[derive(Subcommand, Debug, Clone)]
+pub enum PackagesAction {
+    /// Print every `[[package]]`, in the format of another tool
+    Export {
+        /// Format to print the packages in
+        #[arg(long)]
+        format: PackagesFormat,
+    },
+    /// Add the packages of another tool to the config, skipping ones it already declares
+    Import {
+        /// `Brewfile` to import
+        #[arg(long, value_name = "FILE")]
+        brewfile: PathBuf,
+    },
+}
+
+/// Subcommand of `dots bench-fixture`
+#[derive(Subcommand, Debug, Clone)]
+pub enum FixtureAction {
+    /// Write the fixture into a directory
+    Generate {
+        /// Directory to write the fixture into
+        dir: PathBuf,
+        /// Number of files in the fixture
+        #[arg(long, default_value_t = 10_000)]
+        files: usize,
+    },
 }
@@ -0,0 +1,242 @@
+//! Helpers of templates which convert colors between formats, so a single palette
+//! can be used by apps that want different formats
+//!
+//! Each of them takes a color like `#1e1e2e`, `1e1e2e`, `#fff` or `#1e1e2ecc`
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use eyre::{Result, bail, eyre};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError, RenderErrorReason,
+    ScopedJson,
+};
+use serde_json::Value;
+
+/// Converts a color, with the argument of the helper if it takes one
+type Convert = fn(Color, Option<f64>) -> Result<String>;
+
+/// Name of every helper, along with what it converts the color to
+pub const HELPERS: &[(&str, Convert)] = &[
+    // #1e1e2e
+    ("hex", |color, _| Ok(color.hex())),
+    // 1e1e2e
+    ("hex_no_hash", |color, _| Ok(color.hex()[1..].to_string())),
+    // rgb(30, 30, 46)
+    ("rgb", |color, _| Ok(color.rgb())),
+    // hsl(240, 21%, 15%)
+    ("hsl", |color, _| Ok(color.hsl())),
+    // #1e1e2ecc
+    ("alpha", |color, alpha| {
+        let alpha = alpha.ok_or_else(|| eyre!("`alpha` takes the opacity, e.g. 0.8"))?;
+        if !(0.0..=1.0).contains(&alpha) {
+            bail!("opacity must be between 0 and 1, found {alpha}");
+        }
+        Ok(Color { alpha, ..color }.hex())
+    }),
+];
+
+/// A color with an opacity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    /// Red
+    pub red: u8,
+    /// Green
+    pub green: u8,
+    /// Blue
+    pub blue: u8,
+    /// Opacity, from 0 to 1
+    pub alpha: f64,
+}
+
+impl FromStr for Color {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        let invalid = || eyre!("invalid color `{s}`, expected e.g. `#1e1e2e`");
+
+        // `#fff` is `#ffffff`
+        let hex = if hex.len() == 3 || hex.len() == 4 {
+            hex.chars().flat_map(|ch| [ch, ch]).collect()
+        } else {
+            hex.to_string()
+        };
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+            return Err(invalid());
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+
+        Ok(Self {
+            red: channel(0)?,
+            green: channel(2)?,
+            blue: channel(4)?,
+            alpha: if hex.len() == 8 {
+                f64::from(channel(6)?) / 255.0
+            } else {
+                1.0
+            },
+        })
+    }
+}
+
+impl Color {
+    /// Whether the color is not fully opaque
+    fn is_transparent(self) -> bool {
+        self.alpha < 1.0
+    }
+
+    /// `#1e1e2e`, or `#1e1e2ecc` if it is transparent
+    pub fn hex(self) -> String {
+        let Self {
+            red, green, blue, ..
+        } = self;
+
+        if self.is_transparent() {
+            #[expect(
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss,
+                reason = "alpha is between 0 and 1"
+            )]
+            let alpha = (self.alpha * 255.0).round() as u8;
+            format!("#{red:02x}{green:02x}{blue:02x}{alpha:02x}")
+        } else {
+            format!("#{red:02x}{green:02x}{blue:02x}")
+        }
+    }
+
+    /// `rgb(30, 30, 46)`, or `rgba(30, 30, 46, 0.8)` if it is transparent
+    pub fn rgb(self) -> String {
+        let Self {
+            red,
+            green,
+            blue,
+            alpha,
+        } = self;
+
+        if self.is_transparent() {
+            format!("rgba({red}, {green}, {blue}, {})", round(alpha))
+        } else {
+            format!("rgb({red}, {green}, {blue})")
+        }
+    }
+
+    /// `hsl(240, 21%, 15%)`, or `hsla(240, 21%, 15%, 0.8)` if it is transparent
+    pub fn hsl(self) -> String {
+        let [red, green, blue] = [self.red, self.green, self.blue].map(|c| f64::from(c) / 255.0);
+
+        let max = red.max(green).max(blue);
+        let min = red.min(green).min(blue);
+        let delta = max - min;
+        let lightness = f64::midpoint(max, min);
+
+        let (hue, saturation) = if delta == 0.0 {
+            (0.0, 0.0)
+        } else {
+            let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+            let hue = if max == red {
+                ((green - blue) / delta).rem_euclid(6.0)
+            } else if max == green {
+                (blue - red) / delta + 2.0
+            } else {
+                (red - green) / delta + 4.0
+            };
+            (hue * 60.0, saturation)
+        };
+
+        let hue = hue.round();
+        let saturation = (saturation * 100.0).round();
+        let lightness = (lightness * 100.0).round();
+
+        if self.is_transparent() {
+            format!(
+                "hsla({hue}, {saturation}%, {lightness}%, {})",
+                round(self.alpha)
+            )
+        } else {
+            format!("hsl({hue}, {saturation}%, {lightness}%)")
+        }
+    }
+}
+
+/// Round the opacity to 2 decimal places, which is what it is written with
+fn round(alpha: f64) -> f64 {
+    (alpha * 100.0).round() / 100.0
+}
+
+/// Register every helper into `handlebars`, used like `{{ rgb color.base }}`
+/// or `{{ rgb (alpha color.base 0.8) }}`
+pub fn register(handlebars: &mut Handlebars) {
+    for &(name, convert) in HELPERS {
+        handlebars.register_helper(name, Box::new(ColorHelper(convert)));
+    }
+}
+
+/// Register every helper into `tera` as a filter, used like `{{ color.base | rgb }}`
+/// or `{{ color.base | alpha(value=0.8) | rgb }}`
+pub fn register_filters(tera: &mut tera::Tera) {
+    for &(name, convert) in HELPERS {
+        tera.register_filter(name, move |value: &Value, args: &HashMap<String, Value>| {
+            let color = value
+                .as_str()
+                .ok_or_else(|| tera::Error::msg(format!("`{name}` takes a color")))?;
+            let arg = args.get("value").and_then(Value::as_f64);
+
+            color
+                .parse()
+                .and_then(|color| convert(color, arg))
+                .map(Value::String)
+                .map_err(|err| tera::Error::msg(format!("{name}: {err}")))
+        });
+    }
+}
+
+/// Handlebars helper that converts a color
+struct ColorHelper(Convert);
+
+impl HelperDef for ColorHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let name = h.name();
+        let color = h
+            .param(0)
+            .and_then(|param| param.value().as_str())
+            .ok_or_else(|| RenderErrorReason::Other(format!("`{name}` takes a color")))?;
+        let arg = h.param(1).and_then(|param| param.value().as_f64());
+
+        color
+            .parse()
+            .and_then(|color| (self.0)(color, arg))
+            .map(|converted| ScopedJson::Derived(Value::String(converted)))
+            .map_err(|err| RenderErrorReason::Other(format!("{name}: {err}")).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert() {
+        let color = "#1e1e2e".parse::<Color>().unwrap();
+
+        assert_eq!(color.hex(), "#1e1e2e");
+        assert_eq!(color.rgb(), "rgb(30, 30, 46)");
+        assert_eq!(color.hsl(), "hsl(240, 21%, 15%)");
+
+        let color = "#1e1e2ecc".parse::<Color>().unwrap();
+        assert_eq!(color.hex(), "#1e1e2ecc");
+        assert_eq!(color.rgb(), "rgba(30, 30, 46, 0.8)");
+        assert_eq!(color.hsl(), "hsla(240, 21%, 15%, 0.8)");
+
+        assert_eq!("fff".parse::<Color>().unwrap().hex(), "#ffffff");
+        assert!("#12345".parse::<Color>().is_err());
+        assert!("#gggggg".parse::<Color>().is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! Contains [`candidates`]
+//!
+//! The completions that `dots completions` prints call back into `dots` for every completion,
+//! which completes values from the config with [`completer`]
+
+use std::env;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use clap_complete::engine::{ArgValueCompleter, CompletionCandidate};
+use eyre::Result;
+
+use crate::config::Config;
+
+/// Kind of value to complete in shell completions
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum CompletionKind {
+    /// The `input` of every `[[dir]]`
+    Dirs,
+    /// Every file inside of the `input` of a `[[dir]]`, relative to the config file
+    Files,
+    /// The position of every `[[dir]]`, counting from 1
+    Positions,
+    /// The `name` of every `[[package]]`
+    Packages,
+}
+
+/// Values that shell completions should offer for the given `kind`
+///
+/// This only reads the config file and walks the input directories, it does not
+/// download any links because completions must be fast.
+pub fn candidates(kind: CompletionKind, cwd: &Path) -> Result<Vec<String>> {
+    let config = Config::discover(cwd)?;
+
+    let candidates = match kind {
+        CompletionKind::Dirs => config
+            .dirs
+            .iter()
+            .map(|dir| dir.input.to_string_lossy().to_string())
+            .collect(),
+        CompletionKind::Positions => (1..=config.dirs.len()).map(|n| n.to_string()).collect(),
+        CompletionKind::Packages => config
+            .packages
+            .iter()
+            .map(|package| package.name.clone())
+            .collect(),
+        CompletionKind::Files => {
+            let mut files = vec![];
+            for dir in &config.dirs {
+                let input = config.root.join(&dir.input);
+                let ignored = config.ignore.matcher(&input, &dir.ignore)?;
+                files.extend(
+                    walkdir::WalkDir::new(input)
+                        .into_iter()
+                        .filter_entry(|entry| {
+                            !ignored.is_ignored(entry.path(), entry.file_type().is_dir())
+                        })
+                        .flatten()
+                        .filter(|entry| entry.file_type().is_file())
+                        .filter_map(|entry| {
+                            entry
+                                .path()
+                                .strip_prefix(&config.root)
+                                .ok()
+                                .map(|path| path.to_string_lossy().to_string())
+                        }),
+                );
+            }
+            files
+        }
+    };
+
+    Ok(candidates)
+}
+
+/// Complete an argument with the [`candidates`] of `kind` in the current directory
+pub fn completer(kind: CompletionKind) -> ArgValueCompleter {
+    ArgValueCompleter::new(move |current: &OsStr| {
+        let current = current.to_string_lossy();
+        // completions must never print errors, they would end up in the user's prompt
+        env::current_dir()
+            .map_err(eyre::Error::from)
+            .and_then(|cwd| candidates(kind, &cwd))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(&*current))
+            .map(CompletionCandidate::new)
+            .collect()
+    })
+}
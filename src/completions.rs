@@ -0,0 +1,208 @@
+//! Contains [`Completions`]
+//!
+//! `dots completions --install` writes the completions of `dots` into the directory that
+//! each shell loads them from. They become managed files, which every `dots apply` updates
+//! along with `dots` itself, and which `dots list` shows
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use clap_complete::Shell;
+use clap_complete::env::Shells;
+use eyre::{Context as _, ContextCompat as _, Result, bail, eyre};
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{Contents, WritePath};
+use crate::output_path::OutputPath;
+use crate::stdx::PathExt as _;
+
+/// Shells that the completions of `dots` are installed for on this machine
+///
+/// They are stored in the data directory, since another machine may use other shells
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct Completions {
+    /// Name of each shell, e.g. `fish`
+    #[serde(default)]
+    pub shells: BTreeSet<String>,
+}
+
+impl Completions {
+    /// Name of the file containing the shells, inside of the journal directory
+    const FILE_NAME: &str = "completions.toml";
+
+    /// Load the shells from the journal directory `dir`
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(Self::FILE_NAME);
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                toml::from_str(&contents).with_context(|| eyre!("failed to parse {}", path.show()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| eyre!("failed to read {}", path.show())),
+        }
+    }
+
+    /// Write the shells into the journal directory `dir`
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir).with_context(|| eyre!("failed to create {}", dir.show()))?;
+
+        let path = dir.join(Self::FILE_NAME);
+        let contents = toml::to_string(self).context("failed to serialize completions")?;
+        fs::write(&path, contents).with_context(|| eyre!("failed to write {}", path.show()))
+    }
+
+    /// Install the completions for `shell`, failing if it has no directory to load them from
+    pub fn install(&mut self, shell: Shell) -> Result<WritePath> {
+        let write = write(shell, false)?;
+        self.shells.insert(shell.to_string());
+        Ok(write)
+    }
+
+    /// Completions of `dots` for `shell`
+    ///
+    /// They run `dots` with `$COMPLETE` set for every completion, which completes the
+    /// subcommands and flags along with values from the config, such as the position of a `[[dir]]`
+    pub fn script(shell: Shell) -> Result<String> {
+        let shells = Shells::builtins();
+        let completer = shells
+            .completer(&shell.to_string())
+            .with_context(|| eyre!("{shell} has no completions"))?;
+
+        let mut script = vec![];
+        completer
+            .write_registration("COMPLETE", "dots", "dots", "dots", &mut script)
+            .with_context(|| eyre!("failed to generate completions for {shell}"))?;
+        String::from_utf8(script).context("completions are not UTF-8")
+    }
+
+    /// Completions of every installed shell
+    pub fn writes(&self, durable: bool) -> Result<Vec<WritePath>> {
+        self.shells
+            .iter()
+            .map(|shell| {
+                shell
+                    .parse::<Shell>()
+                    .map_err(|err| eyre!("invalid shell `{shell}` in completions: {err}"))
+                    .and_then(|shell| write(shell, durable))
+            })
+            .collect()
+    }
+}
+
+/// Path that `shell` loads the completions of `dots` from
+fn path(shell: Shell) -> Result<OutputPath> {
+    match shell {
+        Shell::Bash => "{data_dir}/bash-completion/completions/dots",
+        // not loaded unless it is in `$fpath`, but it is the most common directory for it
+        Shell::Zsh => "~/.zfunc/_dots",
+        Shell::Fish => "{config_dir}/fish/completions/dots.fish",
+        Shell::Elvish => "{config_dir}/elvish/lib/dots.elv",
+        _ => bail!(
+            "{shell} has no directory to load completions from, use `dots completions {shell}`"
+        ),
+    }
+    .parse()
+}
+
+/// Write the completions for `shell` into the directory it loads them from
+fn write(shell: Shell, durable: bool) -> Result<WritePath> {
+    Ok(WritePath {
+        path: path(shell)?.into_inner(),
+        contents: Contents::Text(Completions::script(shell)?),
+        source: None,
+        durable,
+        description: Some(format!("completions of `dots` for {shell}")),
+        symlink: false,
+        mode: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory as _;
+
+    use super::*;
+    use crate::cli::Cli;
+    use crate::environment::Environment;
+
+    #[test]
+    fn install() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("dots");
+        assert_eq!(Completions::load(&dir).unwrap(), Completions::default());
+
+        let mut completions = Completions::default();
+        Environment::new("/home/me").scope(|| {
+            let write = completions.install(Shell::Fish).unwrap();
+            assert_eq!(
+                write.path.to_string_lossy(),
+                "/home/me/.config/fish/completions/dots.fish"
+            );
+            assert!(
+                write
+                    .contents
+                    .text()
+                    .contains("COMPLETE=fish dots -- (commandline --current-process")
+            );
+            assert!(completions.install(Shell::PowerShell).is_err());
+
+            assert_eq!(
+                completions.writes(true).unwrap(),
+                vec![WritePath {
+                    durable: true,
+                    ..write
+                }]
+            );
+        });
+        completions.save(&dir).unwrap();
+
+        assert_eq!(Completions::load(&dir).unwrap(), completions);
+
+        // `$XDG_CONFIG_HOME` and `$XDG_DATA_HOME` are where the shells look
+        Environment {
+            config_dir: "/xdg/config".into(),
+            data_dir: "/xdg/data".into(),
+            ..Environment::new("/home/me")
+        }
+        .scope(|| {
+            assert_eq!(
+                completions
+                    .writes(false)
+                    .unwrap()
+                    .into_iter()
+                    .map(|write| write.path.to_string_lossy().into_owned())
+                    .collect::<Vec<_>>(),
+                ["/xdg/config/fish/completions/dots.fish"]
+            );
+            assert_eq!(
+                path(Shell::Bash).unwrap().into_inner().to_string_lossy(),
+                "/xdg/data/bash-completion/completions/dots"
+            );
+        });
+    }
+
+    #[test]
+    fn complete() {
+        let complete = |line: &str| {
+            let args = line.split(' ').map(Into::into).collect::<Vec<_>>();
+            let index = args.len() - 1;
+            clap_complete::engine::complete(&mut Cli::command(), args, index, None)
+                .unwrap()
+                .into_iter()
+                .map(|candidate| candidate.get_value().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(complete("dots explain ").starts_with(&["dir".to_string(), "link".to_string()]));
+        assert!(complete("dots completions f").contains(&"fish".to_string()));
+        // outside of a repo there is nothing to complete, and nothing fails
+        assert!(
+            complete("dots explain dir ")
+                .iter()
+                .all(|candidate| candidate.starts_with("--"))
+        );
+    }
+}
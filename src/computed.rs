@@ -0,0 +1,516 @@
+//! Contains [`Expr`]
+//!
+//! A variable in `[vars]` can be computed from the facts about the machine and from
+//! other variables, e.g. `font_size = { expr = 'if facts.hostname == "tv" { 16 } else { 11 }' }`.
+//! Each of them is evaluated once per run, when it is first needed, so they can use each
+//! other in any order
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use eyre::{Context as _, Result, bail, eyre};
+
+/// Key of the table which makes a variable computed
+const EXPR: &str = "expr";
+
+/// An expression of a computed variable
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// A string, number or boolean
+    Literal(toml::Value),
+    /// A variable, e.g. `facts.hostname`
+    Var(Vec<String>),
+    /// `!a` or `-a`
+    Unary(UnaryOp, Box<Self>),
+    /// `a + b`, `a == b`, `a && b` and so on
+    Binary(BinaryOp, Box<Self>, Box<Self>),
+    /// `if a { b } else { c }`
+    If(Box<Self>, Box<Self>, Box<Self>),
+}
+
+/// Operator with a single operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    /// `!`
+    Not,
+    /// `-`
+    Neg,
+}
+
+/// Operator with two operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    /// `||`
+    Or,
+    /// `&&`
+    And,
+    /// `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `<`
+    Lt,
+    /// `<=`
+    Le,
+    /// `>`
+    Gt,
+    /// `>=`
+    Ge,
+    /// `+`, which also joins strings
+    Add,
+    /// `-`
+    Sub,
+    /// `*`
+    Mul,
+    /// `/`
+    Div,
+    /// `%`
+    Rem,
+}
+
+impl BinaryOp {
+    /// How tightly the operator binds, higher binds tighter
+    fn precedence(self) -> u8 {
+        match self {
+            Self::Or => 1,
+            Self::And => 2,
+            Self::Eq | Self::Ne | Self::Lt | Self::Le | Self::Gt | Self::Ge => 3,
+            Self::Add | Self::Sub => 4,
+            Self::Mul | Self::Div | Self::Rem => 5,
+        }
+    }
+}
+
+/// A token of an expression
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A string, number or boolean
+    Literal(toml::Value),
+    /// A name, which is a keyword or part of a variable
+    Ident(String),
+    /// Punctuation, e.g. `==` or `{`
+    Punct(&'static str),
+}
+
+/// Punctuation, longest first so `==` is not read as `=`
+const PUNCTUATION: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", "{", "}",
+    ".",
+];
+
+/// Split `source` into tokens
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = source.trim_start();
+
+    while let Some(ch) = rest.chars().next() {
+        if let Some(punct) = PUNCTUATION.iter().find(|punct| rest.starts_with(**punct)) {
+            tokens.push(Token::Punct(punct));
+            rest = &rest[punct.len()..];
+        } else if ch == '"' || ch == '\'' {
+            let (string, after) = rest[1..]
+                .split_once(ch)
+                .ok_or_else(|| eyre!("string is never closed with {ch}"))?;
+            tokens.push(Token::Literal(string.into()));
+            rest = after;
+        } else if ch.is_ascii_digit() {
+            let end = rest
+                .find(|ch: char| !(ch.is_ascii_digit() || ch == '.' || ch == '_'))
+                .unwrap_or(rest.len());
+            let number = rest[..end].replace('_', "");
+            let value = match number.parse::<i64>() {
+                Ok(int) => int.into(),
+                Err(_) => number
+                    .parse::<f64>()
+                    .map_err(|_| eyre!("invalid number `{number}`"))?
+                    .into(),
+            };
+            tokens.push(Token::Literal(value));
+            rest = &rest[end..];
+        } else if ch.is_alphabetic() || ch == '_' {
+            let end = rest
+                .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(match &rest[..end] {
+                "true" => Token::Literal(true.into()),
+                "false" => Token::Literal(false.into()),
+                ident => Token::Ident(ident.to_string()),
+            });
+            rest = &rest[end..];
+        } else {
+            bail!("unexpected `{ch}`");
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Parses tokens into an [`Expr`]
+struct Parser {
+    /// Tokens of the expression
+    tokens: Vec<Token>,
+    /// Index of the next token
+    position: usize,
+}
+
+impl Parser {
+    /// The next token, without consuming it
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    /// Consume the next token
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Consume the punctuation `punct`, or fail
+    fn expect(&mut self, punct: &str) -> Result<()> {
+        match self.next() {
+            Some(Token::Punct(found)) if found == punct => Ok(()),
+            Some(token) => bail!("expected `{punct}`, found {token:?}"),
+            None => bail!("expected `{punct}`, found the end"),
+        }
+    }
+
+    /// Parse operators which bind at least as tightly as `precedence`
+    fn binary(&mut self, precedence: u8) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+
+        while let Some(Token::Punct(punct)) = self.peek() {
+            let op = match *punct {
+                "||" => BinaryOp::Or,
+                "&&" => BinaryOp::And,
+                "==" => BinaryOp::Eq,
+                "!=" => BinaryOp::Ne,
+                "<" => BinaryOp::Lt,
+                "<=" => BinaryOp::Le,
+                ">" => BinaryOp::Gt,
+                ">=" => BinaryOp::Ge,
+                "+" => BinaryOp::Add,
+                "-" => BinaryOp::Sub,
+                "*" => BinaryOp::Mul,
+                "/" => BinaryOp::Div,
+                "%" => BinaryOp::Rem,
+                _ => break,
+            };
+            if op.precedence() < precedence {
+                break;
+            }
+            self.position += 1;
+            let rhs = self.binary(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    /// Parse `!a`, `-a` or a single value
+    fn unary(&mut self) -> Result<Expr> {
+        let op = match self.peek() {
+            Some(Token::Punct("!")) => UnaryOp::Not,
+            Some(Token::Punct("-")) => UnaryOp::Neg,
+            _ => return self.primary(),
+        };
+        self.position += 1;
+
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    /// Parse a literal, a variable, an `if` or an expression in parentheses
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Literal(value)) => Ok(Expr::Literal(value)),
+            Some(Token::Ident(ident)) if ident == "if" => self.if_else(),
+            Some(Token::Ident(ident)) => {
+                let mut path = vec![ident];
+                while self.peek() == Some(&Token::Punct(".")) {
+                    self.position += 1;
+                    match self.next() {
+                        Some(Token::Ident(ident)) => path.push(ident),
+                        _ => bail!("expected a name after `.`"),
+                    }
+                }
+                Ok(Expr::Var(path))
+            }
+            Some(Token::Punct("(")) => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(token) => bail!("unexpected {token:?}"),
+            None => bail!("unexpected end of the expression"),
+        }
+    }
+
+    /// Parse the rest of `if a { b } else { c }`, after the `if`
+    fn if_else(&mut self) -> Result<Expr> {
+        let condition = self.binary(0)?;
+        let then = self.block()?;
+
+        match self.next() {
+            Some(Token::Ident(ident)) if ident == "else" => (),
+            _ => bail!("`if` must have an `else`, so it always has a value"),
+        }
+        let otherwise = match self.peek() {
+            Some(Token::Ident(ident)) if ident == "if" => {
+                self.position += 1;
+                self.if_else()?
+            }
+            _ => self.block()?,
+        };
+
+        Ok(Expr::If(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    /// Parse `{ a }`
+    fn block(&mut self) -> Result<Expr> {
+        self.expect("{")?;
+        let expr = self.binary(0)?;
+        self.expect("}")?;
+        Ok(expr)
+    }
+}
+
+impl std::str::FromStr for Expr {
+    type Err = eyre::Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let expr = parser.binary(0)?;
+        if let Some(token) = parser.peek() {
+            bail!("unexpected {token:?} after the expression");
+        }
+
+        Ok(expr)
+    }
+}
+
+impl Expr {
+    /// Evaluate the expression, where `lookup` returns the value of a variable
+    pub fn eval(
+        &self,
+        lookup: &mut dyn FnMut(&[String]) -> Result<toml::Value>,
+    ) -> Result<toml::Value> {
+        use toml::Value::{Boolean, Float, Integer};
+
+        Ok(match self {
+            Self::Literal(value) => value.clone(),
+            Self::Var(path) => lookup(path)?,
+            Self::Unary(op, operand) => match (op, operand.eval(lookup)?) {
+                (UnaryOp::Not, Boolean(bool)) => Boolean(!bool),
+                (UnaryOp::Neg, Integer(int)) => Integer(-int),
+                (UnaryOp::Neg, Float(float)) => Float(-float),
+                (op, value) => bail!("can not apply {op:?} to {value}"),
+            },
+            Self::If(condition, then, otherwise) => match condition.eval(lookup)? {
+                Boolean(true) => then.eval(lookup)?,
+                Boolean(false) => otherwise.eval(lookup)?,
+                value => bail!("condition of `if` must be a boolean, found {value}"),
+            },
+            // the right side is only evaluated when it is needed
+            Self::Binary(BinaryOp::And, lhs, rhs) => match lhs.eval(lookup)? {
+                Boolean(true) => rhs.eval(lookup)?,
+                Boolean(false) => Boolean(false),
+                value => bail!("`&&` takes booleans, found {value}"),
+            },
+            Self::Binary(BinaryOp::Or, lhs, rhs) => match lhs.eval(lookup)? {
+                Boolean(true) => Boolean(true),
+                Boolean(false) => rhs.eval(lookup)?,
+                value => bail!("`||` takes booleans, found {value}"),
+            },
+            Self::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(lookup)?, rhs.eval(lookup)?);
+                binary(*op, lhs, rhs)?
+            }
+        })
+    }
+}
+
+/// Apply `op` to `lhs` and `rhs`, except for `&&` and `||`
+fn binary(op: BinaryOp, lhs: toml::Value, rhs: toml::Value) -> Result<toml::Value> {
+    use toml::Value::{Boolean, Float, Integer, String};
+
+    #[expect(clippy::cast_precision_loss, reason = "integers are mixed with floats")]
+    let as_float = |value: &toml::Value| match value {
+        Integer(int) => Some(*int as f64),
+        Float(float) => Some(*float),
+        _ => None,
+    };
+
+    Ok(match (op, &lhs, &rhs) {
+        (BinaryOp::Eq, _, _) => Boolean(equal(&lhs, &rhs)),
+        (BinaryOp::Ne, _, _) => Boolean(!equal(&lhs, &rhs)),
+        (BinaryOp::Add, String(lhs), String(rhs)) => String(format!("{lhs}{rhs}")),
+        (BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge, String(lhs), String(rhs)) => {
+            Boolean(compare(op, lhs.cmp(rhs)))
+        }
+        (BinaryOp::Div | BinaryOp::Rem, Integer(_), Integer(0)) => bail!("division by zero"),
+        (_, Integer(lhs), Integer(rhs)) => match op {
+            BinaryOp::Add => Integer(lhs.wrapping_add(*rhs)),
+            BinaryOp::Sub => Integer(lhs.wrapping_sub(*rhs)),
+            BinaryOp::Mul => Integer(lhs.wrapping_mul(*rhs)),
+            BinaryOp::Div => Integer(lhs / rhs),
+            BinaryOp::Rem => Integer(lhs % rhs),
+            _ => Boolean(compare(op, lhs.cmp(rhs))),
+        },
+        _ => {
+            let (Some(lhs_float), Some(rhs_float)) = (as_float(&lhs), as_float(&rhs)) else {
+                bail!("can not apply {op:?} to {lhs} and {rhs}");
+            };
+            match op {
+                BinaryOp::Add => Float(lhs_float + rhs_float),
+                BinaryOp::Sub => Float(lhs_float - rhs_float),
+                BinaryOp::Mul => Float(lhs_float * rhs_float),
+                BinaryOp::Div => Float(lhs_float / rhs_float),
+                BinaryOp::Rem => Float(lhs_float % rhs_float),
+                _ => {
+                    let ordering = lhs_float
+                        .partial_cmp(&rhs_float)
+                        .ok_or_else(|| eyre!("can not compare {lhs} and {rhs}"))?;
+                    Boolean(compare(op, ordering))
+                }
+            }
+        }
+    })
+}
+
+/// Whether two values are equal, where `1 == 1.0`
+fn equal(lhs: &toml::Value, rhs: &toml::Value) -> bool {
+    #[expect(clippy::cast_precision_loss, reason = "integers are mixed with floats")]
+    match (lhs, rhs) {
+        (toml::Value::Integer(int), toml::Value::Float(float))
+        | (toml::Value::Float(float), toml::Value::Integer(int)) => *int as f64 == *float,
+        _ => lhs == rhs,
+    }
+}
+
+/// Whether `ordering` satisfies the comparison `op`
+fn compare(op: BinaryOp, ordering: std::cmp::Ordering) -> bool {
+    match op {
+        BinaryOp::Lt => ordering.is_lt(),
+        BinaryOp::Le => ordering.is_le(),
+        BinaryOp::Gt => ordering.is_gt(),
+        _ => ordering.is_ge(),
+    }
+}
+
+/// Evaluates computed variables as they are needed
+struct Resolver {
+    /// Variables which are not computed
+    vars: toml::Table,
+    /// Computed variables which were not evaluated yet
+    computed: BTreeMap<String, Expr>,
+    /// Computed variables which are being evaluated, to find ones that use themselves
+    evaluating: BTreeSet<String>,
+}
+
+impl Resolver {
+    /// Value of the variable at `path`, evaluating it if it is computed
+    fn lookup(&mut self, path: &[String]) -> Result<toml::Value> {
+        let [name, fields @ ..] = path else {
+            bail!("empty variable");
+        };
+
+        if let Some(expr) = self.computed.remove(name) {
+            self.evaluating.insert(name.clone());
+            let value = expr
+                .eval(&mut |path| self.lookup(path))
+                .with_context(|| eyre!("failed to compute `{name}`"))?;
+            self.evaluating.remove(name);
+            self.vars.insert(name.clone(), value);
+        } else if self.evaluating.contains(name) {
+            bail!("computed variable `{name}` uses itself");
+        }
+
+        let mut value = self
+            .vars
+            .get(name)
+            .ok_or_else(|| eyre!("variable `{name}` is not defined"))?;
+        for field in fields {
+            value = value
+                .get(field)
+                .ok_or_else(|| eyre!("variable `{}` is not defined", path.join(".")))?;
+        }
+
+        Ok(value.clone())
+    }
+}
+
+/// Whether `value` is a computed variable, which is a table with a single `expr`
+pub fn is_computed(value: &toml::Value) -> bool {
+    value
+        .as_table()
+        .is_some_and(|table| table.len() == 1 && table.contains_key(EXPR))
+}
+
+/// Evaluate every computed variable of `vars`, replacing it with its value
+pub fn resolve(vars: toml::Table) -> Result<toml::Table> {
+    let mut resolver = Resolver {
+        vars: toml::Table::new(),
+        computed: BTreeMap::new(),
+        evaluating: BTreeSet::new(),
+    };
+
+    for (name, value) in vars {
+        match value {
+            toml::Value::Table(table) if table.len() == 1 && table.contains_key(EXPR) => {
+                let source = table[EXPR]
+                    .as_str()
+                    .ok_or_else(|| eyre!("`expr` of variable `{name}` must be a string"))?;
+                let expr = source
+                    .parse::<Expr>()
+                    .with_context(|| eyre!("invalid expression of variable `{name}`"))?;
+                resolver.computed.insert(name, expr);
+            }
+            value => {
+                resolver.vars.insert(name, value);
+            }
+        }
+    }
+
+    let names = resolver.computed.keys().cloned().collect::<Vec<_>>();
+    for name in names {
+        resolver.lookup(&[name])?;
+    }
+
+    Ok(resolver.vars)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn resolve() {
+        let vars = toml::toml! {
+            facts = { hostname = "tv" }
+            base = 11
+            font_size = { expr = "if facts.hostname == \"tv\" { big } else { base }" }
+            big = { expr = "base + 5" }
+            font = { expr = "'JetBrains Mono ' + (if font_size >= 16 && !false { 'Bold' } else { 'Regular' })" }
+            ratio = { expr = "-base / 2 * 1.5" }
+        };
+        let vars = super::resolve(vars).unwrap();
+
+        assert_eq!(vars["font_size"], toml::Value::Integer(16));
+        assert_eq!(vars["big"], toml::Value::Integer(16));
+        assert_eq!(vars["font"], toml::Value::from("JetBrains Mono Bold"));
+        assert_eq!(vars["ratio"], toml::Value::Float(-7.5));
+
+        let cycle = toml::toml! {
+            a = { expr = "b" }
+            b = { expr = "a + 1" }
+        };
+        assert!(super::resolve(cycle).is_err());
+        assert!(super::resolve(toml::toml! { a = { expr = "if true { 1 }" } }).is_err());
+        assert!(super::resolve(toml::toml! { a = { expr = "missing.field" } }).is_err());
+    }
+}
@@ -1,14 +1,49 @@
 //! Config for `dots`
 
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::iter;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
 use clap::Parser;
-use eyre::Result;
+use eyre::{Context as _, ContextCompat as _, Result, eyre};
+use itertools::Itertools as _;
 use serde::{Deserialize, Serialize};
 use tap::Pipe as _;
 
+use crate::age;
+use crate::autostart::Autostart;
+use crate::dconf::Dconf;
+use crate::defaults::Defaults;
+use crate::diff::DiffTool;
+use crate::engine::{Delimiters, Engine};
+use crate::env::EnvVar;
+use crate::environment::Environment;
+use crate::fetch::Fetch;
+use crate::flatpak::FlatpakOverride;
+use crate::font::Font;
+use crate::git::Git;
+use crate::ignored::Ignore;
+use crate::known_apps;
+use crate::migrate;
+use crate::mimeapps::MimeApps;
+use crate::notify::Notify;
+use crate::operation::Watchdog;
 use crate::output_path::OutputPath;
+use crate::packages::Package;
+use crate::phase::Phase;
+use crate::profile::Profile;
+use crate::reporter::Reporter;
+use crate::shell::ShellIntegration;
+use crate::ssh::Ssh;
+use crate::stdx::{self, PathExt as _};
+use crate::system::UdevRule;
+use crate::theme::Theme;
+use crate::timer::Timer;
+use crate::tools::Tool;
+use crate::when::When;
+use crate::wsl::LineEndings;
 
 /// Configuration for `dots`
 #[derive(Deserialize, Debug)]
@@ -24,6 +59,263 @@ pub struct Config {
     #[serde(rename = "dir", default)]
     /// List of directories to process
     pub dirs: Vec<Dir>,
+    /// Settings to load into dconf
+    #[serde(default)]
+    pub dconf: Vec<Dconf>,
+    /// Preferences to set with `defaults write`, only on macOS
+    #[serde(default)]
+    pub defaults: Vec<Defaults>,
+    /// Permissions of Flatpak apps, set with `flatpak override --user`, only on Linux
+    #[serde(rename = "flatpak_override", default)]
+    pub flatpak_overrides: Vec<FlatpakOverride>,
+    /// Default applications, merged into `mimeapps.list`
+    #[serde(default)]
+    pub mimeapps: Vec<MimeApps>,
+    /// Files included from the gitconfig, with a managed block in it
+    pub git: Option<Git>,
+    /// SSH config, assembled from a fragment for each host
+    pub ssh: Option<Ssh>,
+    /// Programs which start at login
+    #[serde(default)]
+    pub autostart: Vec<Autostart>,
+    /// Rules dropped into `/etc/udev/rules.d`, only on Linux
+    #[serde(rename = "udev_rule", default)]
+    pub udev_rules: Vec<UdevRule>,
+    /// Kernel parameters dropped into `/etc/sysctl.d`, only on Linux
+    #[serde(default)]
+    pub sysctl: BTreeMap<String, toml::Value>,
+    /// Jobs which run on a schedule, installed as systemd user timers
+    #[serde(rename = "timer", default)]
+    pub timers: Vec<Timer>,
+    /// Aliases and functions contributed by each `[[dir]]`, compiled into a file per shell
+    pub shell: Option<ShellIntegration>,
+    /// Environment variables, compiled into a file per shell and into `environment.d`
+    #[serde(default)]
+    pub env: BTreeMap<String, EnvVar>,
+    /// CLI tools that the configs depend on, installed by `dots tools sync`
+    #[serde(rename = "tool", default)]
+    pub tools: Vec<Tool>,
+    /// Packages installed with Homebrew, exported into a `Brewfile`
+    #[serde(rename = "package", default)]
+    pub packages: Vec<Package>,
+    /// Fonts to install into the font directory of the platform
+    #[serde(rename = "font", default)]
+    pub fonts: Vec<Font>,
+    /// Run `fc-cache` after installing fonts
+    #[serde(default)]
+    pub fc_cache: bool,
+    /// What to do when some entries fail
+    #[serde(default)]
+    pub on_error: OnError,
+    /// Turn conditions that would otherwise only be logged into errors
+    #[serde(default)]
+    pub strict: bool,
+    /// Refuse to apply sources which look like they contain secrets, such as tokens
+    /// or private keys
+    #[serde(default)]
+    pub scan_secrets: bool,
+    /// What to do with files that exist before `dots` writes to them for the first time
+    #[serde(default)]
+    pub first_overwrite: FirstOverwrite,
+    /// Ask for confirmation before an apply that overwrites more than this many existing files
+    #[serde(default = "default_confirm_overwrites")]
+    pub confirm_overwrites: usize,
+    /// Warn about operations which take longer than this many seconds
+    #[serde(default = "default_stuck_after")]
+    pub stuck_after: u64,
+    /// Give up on operations which take longer than this many seconds, and continue with the rest
+    pub operation_timeout: Option<u64>,
+    /// Flush every written file and its directory to disk before continuing,
+    /// so it survives a crash or a power loss
+    ///
+    /// Can be overridden by each `[[dir]]` and `[[link]]`
+    #[serde(default)]
+    pub durable: bool,
+    /// Keep extended attributes of files that are replaced, such as SELinux contexts
+    #[serde(default)]
+    pub preserve_xattrs: bool,
+    /// Remove files that earlier applies wrote, but the repo no longer produces, at the
+    /// end of each apply, instead of only reporting them
+    #[serde(default)]
+    pub prune: bool,
+    /// Globs of paths that are never written to or removed, no matter what the
+    /// `[[dir]]`s and `[[link]]`s resolve to
+    #[serde(default)]
+    pub protect: Vec<OutputPath>,
+    /// Variables available in templates
+    #[serde(default)]
+    pub vars: toml::Table,
+    /// Color scheme, whose palette is available in templates as `theme`
+    pub theme: Option<Theme>,
+    /// Template engine that renders files, `"none"` copies them as-is
+    ///
+    /// Can be overridden by each `[[dir]]` and by the marker of each file
+    #[serde(default)]
+    pub engine: Engine,
+    /// How files end up in their output directories, `"symlink"` links them to the repo
+    ///
+    /// Can be overridden by each `[[dir]]`
+    #[serde(default)]
+    pub mode: Mode,
+    /// Where to send notifications about drift and failed applies
+    #[serde(default)]
+    pub notify: Notify,
+    /// Where to send the report of each apply
+    #[serde(default)]
+    pub report: Reporter,
+    /// Limits of fetching links, so hosts with many of them do not refuse the requests
+    #[serde(default)]
+    pub fetch: Fetch,
+    /// Program which shows differences in `dots diff`, instead of the built-in diff
+    #[serde(default)]
+    pub diff: DiffTool,
+    /// Directory of apps, each of which is an implicit `[[dir]]`
+    pub apps: Option<Apps>,
+    /// Sets of entries which are only applied on some machines
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Profiles which are active when `--profile` is not passed
+    #[serde(default)]
+    pub default_profiles: Vec<String>,
+    /// Files inside of the `input` of every `[[dir]]` which are not written
+    #[serde(default)]
+    pub ignore: Ignore,
+}
+
+/// Directory where each subdirectory is an app, for example `apps/helix` is
+/// written to `{config_dir}/helix` without needing a `[[dir]]` for it
+#[derive(Deserialize, Debug)]
+pub struct Apps {
+    /// Directory that contains a directory for each app, relative to the config file
+    pub dir: PathBuf,
+    /// Output directory of specific apps, when it is not `{config_dir}/<app>`
+    ///
+    /// Takes precedence over the built-in locations of well-known apps
+    #[serde(default)]
+    pub output: BTreeMap<String, OutputPath>,
+    /// Where to learn more about specific apps, shown by `dots info <app>`
+    #[serde(default)]
+    pub info: BTreeMap<String, AppInfo>,
+}
+
+/// What someone new to the repo needs to know about an app, in `[apps.info.<app>]`
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct AppInfo {
+    /// Website of the app
+    pub homepage: Option<String>,
+    /// Documentation of the config of the app
+    pub docs: Option<String>,
+    /// Anything else worth knowing, such as why the config is the way it is
+    pub notes: Option<String>,
+}
+
+impl Apps {
+    /// A `[[dir]]` for each app inside of `root`
+    ///
+    /// Apps are written to, in order of priority:
+    ///
+    /// 1. Their entry in [`Apps::output`]
+    /// 2. Where the app reads its config from on this platform, if it is well-known
+    /// 3. `{config_dir}/<app>`
+    fn dirs(&self, root: &Path) -> Result<Vec<Dir>> {
+        let path = root.join(&self.dir);
+        let config_dir = Environment::current()?.config_dir;
+
+        let mut apps = vec![];
+        for entry in std::fs::read_dir(&path)
+            .with_context(|| eyre!("failed to read apps directory {}", path.show()))?
+        {
+            let entry =
+                entry.with_context(|| eyre!("failed to read apps directory {}", path.show()))?;
+            if entry.file_type()?.is_dir() {
+                apps.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+
+        apps.into_iter()
+            .sorted()
+            .map(|app| {
+                let output = if let Some(output) = self.output.get(&app) {
+                    output.clone()
+                } else if let Some(output) = known_apps::output(&app) {
+                    output.parse()?
+                } else {
+                    OutputPath::new(config_dir.join(&app))
+                };
+
+                Ok(Dir {
+                    input: self.dir.join(&app),
+                    description: None,
+                    output,
+                    also: Vec::new(),
+                    durable: None,
+                    readonly: false,
+                    immutable: false,
+                    engine: None,
+                    mode: None,
+                    line_endings: None,
+                    delimiters: None,
+                    atomic: false,
+                    exact: false,
+                    phase: Phase::Main,
+                    when: When::default(),
+                    profiles: Vec::new(),
+                    ignore: Vec::new(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// How the files of a `[[dir]]` end up in its output directory
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// Write a copy of each file
+    #[default]
+    Copy,
+    /// Create a symlink to each file in the repo, like GNU stow
+    ///
+    /// Files which are rendered differently from their source, e.g. templates, are still copied
+    Symlink,
+}
+
+/// What to do with the entries that did not fail, when some entries fail
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnError {
+    /// Do not apply anything
+    #[default]
+    Abort,
+    /// Skip every `[[dir]]` and `[[link]]` that had a failure, apply the rest
+    SkipEntry,
+    /// Apply every file that did not fail, even if other files of the same entry failed
+    ApplyValid,
+}
+
+/// Default for [`Config::confirm_overwrites`]
+const fn default_confirm_overwrites() -> usize {
+    10
+}
+
+/// Default for [`Config::stuck_after`]
+const fn default_stuck_after() -> u64 {
+    10
+}
+
+/// What to do with a file that `dots` is going to write to for the first time, when it already
+/// exists with different contents
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FirstOverwrite {
+    /// Rename the file to `<name>.orig.dots`
+    Backup,
+    /// Replace the file
+    #[default]
+    Replace,
+    /// Ask for each file whether to back it up, backing it up when not running interactively
+    Ask,
 }
 
 pub const GITHUB: &str = "https://github.com/nik-rev/dots";
@@ -31,6 +323,92 @@ pub const GITHUB: &str = "https://github.com/nik-rev/dots";
 impl Config {
     /// Name of the config file for `dots` to search for
     pub const FILE_NAME: &str = "dots.toml";
+
+    /// Name of the config file when it is encrypted with `age`, which is decrypted in memory
+    pub const ENCRYPTED_FILE_NAME: &str = "dots.toml.age";
+
+    /// When to warn about and give up on operations that are stuck
+    pub fn watchdog(&self) -> Watchdog {
+        Watchdog {
+            stuck_after: Duration::from_secs(self.stuck_after),
+            timeout: self.operation_timeout.map(Duration::from_secs),
+        }
+    }
+
+    /// Find the closest directory containing the config file, traversing upwards from `cwd`
+    pub fn find(cwd: &Path) -> Result<PathBuf> {
+        cwd.pipe_ref(stdx::traverse_upwards)
+            .find(|dir| {
+                dir.join(Self::FILE_NAME).exists() || dir.join(Self::ENCRYPTED_FILE_NAME).exists()
+            })
+            .with_context(|| {
+                eyre!(
+                    "failed to find directory that contains a `{}`. traversed upwards from {}",
+                    Self::FILE_NAME,
+                    cwd.show()
+                )
+            })
+    }
+
+    /// Contents of the config file in `root`, decrypting it if only the encrypted one exists
+    ///
+    /// The encrypted config is decrypted with the identity in `DOTS_IDENTITY`, which is set
+    /// by `--identity`
+    fn read(root: &Path) -> Result<String> {
+        let encrypted = root.join(Self::ENCRYPTED_FILE_NAME);
+        if root.join(Self::FILE_NAME).exists() || !encrypted.exists() {
+            return root
+                .join(Self::FILE_NAME)
+                .pipe(std::fs::read_to_string)
+                .with_context(|| eyre!("failed to read config file {}", Self::FILE_NAME));
+        }
+
+        let identity = Environment::current()?
+            .var(age::IDENTITY_VAR)
+            .with_context(|| {
+                eyre!(
+                    "{} is encrypted, pass `--identity` to decrypt it",
+                    Self::ENCRYPTED_FILE_NAME
+                )
+            })?;
+        age::decrypt(&encrypted, Path::new(&identity)).context("failed to decrypt config file")
+    }
+
+    /// Path of the config file in `root` which `dots` can change, which is not encrypted
+    pub fn editable(root: &Path) -> Result<PathBuf> {
+        let path = root.join(Self::FILE_NAME);
+        if !path.exists() && root.join(Self::ENCRYPTED_FILE_NAME).exists() {
+            eyre::bail!(
+                "{} is encrypted, decrypt it to change it",
+                Self::ENCRYPTED_FILE_NAME
+            );
+        }
+
+        Ok(path)
+    }
+
+    /// Find the closest directory containing the config file, traversing upwards from `cwd`,
+    /// and parse the config file in it
+    pub fn discover(cwd: &Path) -> Result<Self> {
+        // Directory which contains the config file
+        let root = Self::find(cwd)?;
+
+        let contents = Self::read(&root)?;
+
+        // configs in the current format are parsed from the text, so errors point at a line
+        match migrate::upgrade(&contents)? {
+            Some(upgraded) => toml::Value::Table(upgraded).try_into::<Self>(),
+            None => toml::de::from_str::<Self>(&contents),
+        }
+        .context("failed to parse config file")?
+        .pipe(|mut conf| {
+            if let Some(apps) = &conf.apps {
+                conf.dirs.extend(apps.dirs(&root)?);
+            }
+            conf.root = root;
+            Ok(conf)
+        })
+    }
 }
 
 /// Arguments that the marker takes
@@ -45,18 +423,50 @@ pub struct Marker {
     /// Write file to this path
     #[arg(long)]
     pub path: Option<OutputPath>,
+    /// Render the file with this engine, instead of the one of its `[[dir]]`
+    #[arg(long)]
+    pub engine: Option<Engine>,
+    /// Use these delimiters instead of `{{` and `}}`, e.g. `--delimiters '<<' '>>'`
+    #[arg(long, num_args = 2, value_names = ["OPEN", "CLOSE"])]
+    pub delimiters: Option<Vec<String>>,
 }
 
 impl Marker {
     /// Marker to use in files to add extra info about them
     pub const MARKER: &str = "@dots ";
+
+    /// Parse the marker on the first line of `contents`, if it has one
+    pub fn find(contents: &str) -> Option<Result<Self>> {
+        let first_line = contents.lines().next()?;
+
+        first_line
+            .get(first_line.find(Self::MARKER)? + Self::MARKER.len()..)?
+            .parse::<Self>()
+            .pipe(Some)
+    }
+
+    /// [`Marker::delimiters`]
+    pub fn delimiters(&self) -> Result<Option<Delimiters>> {
+        self.delimiters
+            .clone()
+            .map(|delimiters| {
+                <[String; 2]>::try_from(delimiters)
+                    .map_err(|_| eyre!("`--delimiters` takes 2 values"))
+                    .and_then(Delimiters::try_from)
+            })
+            .transpose()
+    }
 }
 
 impl FromStr for Marker {
     type Err = eyre::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        shellwords::split(s)?.pipe(Marker::try_parse_from)?.pipe(Ok)
+        shellwords::split(s)?
+            // the first argument is the name of the binary
+            .pipe(|args| iter::once(Self::MARKER.trim().to_string()).chain(args))
+            .pipe(Marker::try_parse_from)?
+            .pipe(Ok)
     }
 }
 
@@ -65,15 +475,70 @@ impl FromStr for Marker {
 pub struct Dir {
     /// Local path to a directory that will be interpreted
     pub input: PathBuf,
+    /// Why the entry exists, shown by `dots list`, `dots explain` and in errors
+    pub description: Option<String>,
     /// Output directory
     pub output: OutputPath,
+    /// More output directories, which get the same files as `output`
+    ///
+    /// For sources that several apps share, so they are kept in the repo only once
+    #[serde(default)]
+    pub also: Vec<OutputPath>,
+    /// Overrides [`Config::durable`] for these files
+    pub durable: Option<bool>,
+    /// Remove write permission from these files after writing them, so other programs
+    /// can not change them
+    #[serde(default)]
+    pub readonly: bool,
+    /// Also set the immutable attribute of these files, with `readonly = true`
+    #[serde(default)]
+    pub immutable: bool,
+    /// Overrides [`Config::engine`] for these files
+    pub engine: Option<Engine>,
+    /// Overrides [`Config::mode`] for these files
+    pub mode: Option<Mode>,
+    /// Line endings of these files, CRLF for files written to the Windows side from
+    /// inside of WSL and unchanged otherwise
+    pub line_endings: Option<LineEndings>,
+    /// Delimiters used instead of `{{` and `}}` by these files
+    pub delimiters: Option<Delimiters>,
+    /// Build the output directory next to it and swap it in at once, so the app never sees
+    /// a partially updated directory
+    ///
+    /// Everything inside of the output directory that is not written by `dots` is removed
+    #[serde(default)]
+    pub atomic: bool,
+    /// Remove files inside of the output directory which are not in the `input` directory,
+    /// backing them up to `<name>.orig.dots`
+    #[serde(default)]
+    pub exact: bool,
+    /// Phase in which these files are written, `"pre"` or `"post"` to write them before or
+    /// after every other entry
+    #[serde(default)]
+    pub phase: Phase,
+    /// Only write these files on machines which match, e.g. `{ os = "linux" }`
+    #[serde(default)]
+    pub when: When,
+    /// Only write these files when one of these `[profiles]` is active
+    #[serde(default)]
+    pub profiles: Vec<String>,
+    /// Files inside of the `input` directory which are not written, in addition to `[ignore]`
+    ///
+    /// Patterns have the syntax of `.gitignore`, e.g. `["*.md", ".git/"]`
+    #[serde(default)]
+    pub ignore: Vec<String>,
 }
 
 /// A link representing a file to be fetched
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Link {
     /// URL to the link, e.g. `https://raw.githubusercontent.com/catppuccin/nushell/05987d258cb765a881ee1f2f2b65276c8b379658/themes/catppuccin_mocha.nu`
+    ///
+    /// Can use variables from `[vars]` and the `github:` shorthand, e.g.
+    /// `github:catppuccin/nushell@05987d2:themes/catppuccin_{flavor}.nu`
     pub url: String,
+    /// Why the entry exists, shown by `dots list`, `dots explain` and in errors
+    pub description: Option<String>,
     /// Path where to write the file to in the `config` directory,
     /// e.g. `nushell/catppuccin.nu` writes to `config/nushell/catppuccin.nu` if `config` in `Config` is `"config"`
     pub path: PathBuf,
@@ -83,4 +548,22 @@ pub struct Link {
     /// This marker is not interpreted. Instead, the marker is written to the
     /// file as-is
     pub marker: Option<String>,
+    /// Overrides [`Config::durable`] for this file
+    pub durable: Option<bool>,
+    /// Remove write permission from this file after writing it, so other programs
+    /// can not change it
+    #[serde(default)]
+    pub readonly: bool,
+    /// Also set the immutable attribute of this file, with `readonly = true`
+    #[serde(default)]
+    pub immutable: bool,
+    /// Phase in which this file is written
+    #[serde(default)]
+    pub phase: Phase,
+    /// Only write this file on machines which match, e.g. `{ hostname = "workstation" }`
+    #[serde(default, skip_serializing_if = "When::is_always")]
+    pub when: When,
+    /// Only write this file when one of these `[profiles]` is active
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<String>,
 }
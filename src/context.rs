@@ -0,0 +1,223 @@
+//! Contains [`RunContext`]
+//!
+//! `dots` runs from a terminal, or unattended from systemd or cron. There, nobody answers
+//! prompts, colors end up as escape codes in the logs, and the environment of the session,
+//! such as `$HOME` or the address of the D-Bus session bus, may be missing
+
+use std::borrow::Cow;
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, IsTerminal as _};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+/// Where `dots` runs
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunContext {
+    /// From a terminal, where prompts can be answered
+    Interactive,
+    /// Unattended, e.g. from systemd or cron: never prompts, pages or logs colors, and
+    /// fills in the environment of the session if it is missing
+    Service,
+}
+
+impl RunContext {
+    /// Detect where `dots` runs from its environment
+    pub fn detect() -> Self {
+        Self::from_signals(
+            env::var_os("INVOCATION_ID").is_some() || env::var_os("JOURNAL_STREAM").is_some(),
+            io::stdin().is_terminal(),
+            env::var_os("TERM").is_some(),
+        )
+    }
+
+    /// Where `dots` runs, given whether systemd started it, whether stdin is a terminal and
+    /// whether `$TERM` is set
+    ///
+    /// Cron sets neither a terminal nor `$TERM`. Input piped into `dots` inside of a
+    /// terminal is still interactive
+    fn from_signals(systemd: bool, terminal: bool, term: bool) -> Self {
+        if !terminal && (systemd || !term) {
+            Self::Service
+        } else {
+            Self::Interactive
+        }
+    }
+
+    /// Whether prompts can be answered
+    pub fn is_interactive(self) -> bool {
+        self == Self::Interactive && io::stdin().is_terminal()
+    }
+
+    /// Whether logs are colored
+    pub fn colors(self) -> bool {
+        self == Self::Interactive && env::var_os("NO_COLOR").is_none_or(|var| var.is_empty())
+    }
+
+    /// Environment variables of the session that are missing and can be derived, for
+    /// services that are started without them
+    ///
+    /// `$HOME` comes from the user database, and `$XDG_RUNTIME_DIR` and
+    /// `$DBUS_SESSION_BUS_ADDRESS` from `/run/user/<uid>`, if it exists
+    #[cfg(unix)]
+    pub fn fallbacks(self) -> Vec<(&'static str, OsString)> {
+        if self != Self::Service {
+            return vec![];
+        }
+
+        // SAFETY: `getuid` is always successful
+        let uid = unsafe { libc::getuid() };
+
+        fallbacks(
+            |var| env::var_os(var).filter(|value| !value.is_empty()),
+            uid,
+            || crate::user::User::current().ok().map(|user| user.home),
+            Path::exists,
+        )
+    }
+
+    /// Environment variables of the session that are missing and can be derived
+    #[cfg(not(unix))]
+    pub fn fallbacks(self) -> Vec<(&'static str, OsString)> {
+        vec![]
+    }
+}
+
+/// Missing environment variables of a session, where `var` reads a variable, `uid` is the
+/// user running `dots`, `home` looks up their home directory and `exists` checks a path
+#[cfg_attr(
+    not(unix),
+    allow(dead_code, reason = "only services on unix have fallbacks")
+)]
+fn fallbacks(
+    var: impl Fn(&str) -> Option<OsString>,
+    uid: u32,
+    home: impl FnOnce() -> Option<PathBuf>,
+    exists: impl Fn(&Path) -> bool,
+) -> Vec<(&'static str, OsString)> {
+    let mut fallbacks = vec![];
+
+    if var("HOME").is_none()
+        && let Some(home) = home()
+    {
+        fallbacks.push(("HOME", home.into_os_string()));
+    }
+
+    let runtime_dir = match var("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) => Some(PathBuf::from(runtime_dir)),
+        None => {
+            let runtime_dir = PathBuf::from(format!("/run/user/{uid}"));
+            exists(&runtime_dir).then(|| {
+                fallbacks.push(("XDG_RUNTIME_DIR", runtime_dir.clone().into_os_string()));
+                runtime_dir
+            })
+        }
+    };
+
+    if var("DBUS_SESSION_BUS_ADDRESS").is_none()
+        && let Some(bus) = runtime_dir
+            .map(|runtime_dir| runtime_dir.join("bus"))
+            .filter(|bus| exists(bus))
+    {
+        let mut address = OsString::from("unix:path=");
+        address.push(bus);
+        fallbacks.push(("DBUS_SESSION_BUS_ADDRESS", address));
+    }
+
+    fallbacks
+}
+
+/// `message` without the escape codes which color it
+pub fn strip_colors(message: &str) -> Cow<'_, str> {
+    if !message.contains('\x1b') {
+        return Cow::Borrowed(message);
+    }
+
+    let mut stripped = String::with_capacity(message.len());
+    let mut chars = message.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' {
+            stripped.push(ch);
+            continue;
+        }
+        // `ESC [ <parameters> <final byte>`, where the final byte is a letter
+        if chars.clone().next() == Some('[') {
+            chars.next();
+            for ch in chars.by_ref() {
+                if ch.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        }
+    }
+
+    Cow::Owned(stripped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        assert_eq!(
+            RunContext::from_signals(true, false, true),
+            RunContext::Service
+        );
+        assert_eq!(
+            RunContext::from_signals(false, false, false),
+            RunContext::Service
+        );
+        assert_eq!(
+            RunContext::from_signals(false, false, true),
+            RunContext::Interactive
+        );
+        assert_eq!(
+            RunContext::from_signals(true, true, true),
+            RunContext::Interactive
+        );
+    }
+
+    #[test]
+    fn fallbacks() {
+        let existing = [Path::new("/run/user/1000"), Path::new("/run/user/1000/bus")];
+
+        assert_eq!(
+            super::fallbacks(
+                |_| None,
+                1000,
+                || Some("/home/me".into()),
+                |path| existing.contains(&path),
+            ),
+            [
+                ("HOME", "/home/me".into()),
+                ("XDG_RUNTIME_DIR", "/run/user/1000".into()),
+                (
+                    "DBUS_SESSION_BUS_ADDRESS",
+                    "unix:path=/run/user/1000/bus".into()
+                ),
+            ]
+        );
+
+        // nothing that is set is replaced
+        assert_eq!(
+            super::fallbacks(
+                |var| Some(format!("<{var}>").into()),
+                1000,
+                || Some("/home/me".into()),
+                |path| existing.contains(&path),
+            ),
+            []
+        );
+    }
+
+    #[test]
+    fn strip() {
+        assert_eq!(
+            strip_colors("\x1b[90m[\x1b[32mINFO\x1b[90m]\x1b[0m wrote"),
+            "[INFO] wrote"
+        );
+        assert!(matches!(strip_colors("plain"), Cow::Borrowed("plain")));
+    }
+}
@@ -0,0 +1,241 @@
+//! Contains [`Dconf`]
+//!
+//! GNOME and many GTK apps keep their settings in dconf instead of files.
+//! They are written and read as keyfiles with `dconf load` and `dconf dump`
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use eyre::{Context as _, ContextCompat as _, Result, bail, eyre};
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::stdx::PathExt as _;
+
+/// Settings under a single dconf directory, in the `[[dconf]]` section of the config
+#[derive(Deserialize, Debug)]
+pub struct Dconf {
+    /// Directory of the settings, e.g. `/org/gnome/desktop/interface/`
+    pub path: String,
+    /// Keyfile with the settings, relative to the config file
+    ///
+    /// Is in the format of `dconf dump`, where `[/]` is the `path` itself
+    pub file: PathBuf,
+}
+
+/// Settings of a `[[dconf]]` entry, along with what they currently are
+#[derive(Debug)]
+pub struct DconfSettings {
+    /// Directory of the settings, which ends with a `/`
+    pub path: String,
+    /// Contents of the keyfile in the repo
+    pub settings: String,
+    /// Output of `dconf dump`, or `None` if it failed
+    pub current: Option<String>,
+}
+
+/// Keys of every section of a keyfile
+type Keyfile = BTreeMap<String, BTreeMap<String, String>>;
+
+impl Dconf {
+    /// Directory of the settings, with the trailing `/` that dconf requires
+    pub fn dir(&self) -> Result<String> {
+        if !self.path.starts_with('/') {
+            bail!("dconf path `{}` must start with `/`", self.path);
+        }
+
+        Ok(if self.path.ends_with('/') {
+            self.path.clone()
+        } else {
+            format!("{}/", self.path)
+        })
+    }
+
+    /// Read the settings in the repo, and what they currently are
+    pub fn read(&self, root: &Path) -> Result<DconfSettings> {
+        let file = root.join(&self.file);
+        let settings = fs::read_to_string(&file)
+            .with_context(|| eyre!("failed to read dconf settings {}", file.show()))?;
+        let path = self.dir()?;
+
+        parse(&settings).with_context(|| eyre!("invalid dconf settings {}", file.show()))?;
+
+        Ok(DconfSettings {
+            current: dump(&path).ok(),
+            path,
+            settings,
+        })
+    }
+
+    /// Write the current settings into the file in the repo
+    pub fn absorb(&self, root: &Path) -> Result<()> {
+        let file = root.join(&self.file);
+        let settings = dump(&self.dir()?)?;
+
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| eyre!("failed to create directory {}", parent.show()))?;
+        }
+        fs::write(&file, settings).with_context(|| eyre!("failed to write {}", file.show()))?;
+
+        log::info!(
+            "absorbed dconf settings of {} into {}",
+            self.path,
+            file.show()
+        );
+
+        Ok(())
+    }
+}
+
+/// Write the current settings of every `[[dconf]]` into its file in the repo
+pub fn absorb_dconf(cwd: &Path) -> Result<()> {
+    let config = Config::discover(cwd)?;
+    if config.dconf.is_empty() {
+        bail!("there are no `[[dconf]]` entries to absorb");
+    }
+
+    config
+        .dconf
+        .iter()
+        .try_for_each(|dconf| dconf.absorb(&config.root))
+}
+
+impl DconfSettings {
+    /// Whether every key in the repo already has the same value
+    pub fn is_applied(&self) -> bool {
+        let Some(current) = self
+            .current
+            .as_deref()
+            .and_then(|current| parse(current).ok())
+        else {
+            return false;
+        };
+        let Ok(settings) = parse(&self.settings) else {
+            return false;
+        };
+
+        settings.iter().all(|(section, keys)| {
+            keys.iter().all(|(key, value)| {
+                current
+                    .get(section)
+                    .and_then(|current| current.get(key))
+                    .is_some_and(|current| current == value)
+            })
+        })
+    }
+}
+
+/// Parse a keyfile, as written by `dconf dump`
+fn parse(keyfile: &str) -> Result<Keyfile> {
+    let mut parsed = Keyfile::new();
+    let mut section = None;
+
+    for (i, line) in keyfile.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = Some(name.to_string());
+            parsed.entry(name.to_string()).or_default();
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| eyre!("line {} is neither `[section]` nor `key=value`", i + 1))?;
+        let section = section
+            .as_ref()
+            .with_context(|| eyre!("line {} is outside of a `[section]`", i + 1))?;
+        parsed
+            .entry(section.clone())
+            .or_default()
+            .insert(key.trim().to_string(), value.trim().to_string());
+    }
+
+    Ok(parsed)
+}
+
+/// Current settings under the dconf directory `path`
+fn dump(path: &str) -> Result<String> {
+    let output = Command::new("dconf")
+        .args(["dump", path])
+        .output()
+        .context("failed to run `dconf dump`")?;
+
+    if !output.status.success() {
+        bail!(
+            "`dconf dump {path}` failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    String::from_utf8(output.stdout).context("`dconf dump` printed invalid UTF-8")
+}
+
+/// Write `settings` into the dconf directory `path`, keeping keys that are not in them
+pub fn load(path: &str, settings: &str) -> Result<()> {
+    let mut child = Command::new("dconf")
+        .args(["load", path])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to run `dconf load`")?;
+
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(settings.as_bytes())
+        .context("failed to write settings to `dconf load`")?;
+
+    let output = child
+        .wait_with_output()
+        .context("failed to run `dconf load`")?;
+    if !output.status.success() {
+        bail!(
+            "`dconf load {path}` failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_applied() {
+        let settings = |settings: &str, current: &str| DconfSettings {
+            path: "/org/gnome/desktop/".to_string(),
+            settings: settings.to_string(),
+            current: Some(current.to_string()),
+        };
+        let current = "[interface]\ncolor-scheme='prefer-dark'\nfont-name='Inter 11'\n\n[wm/preferences]\nbutton-layout='close:'\n";
+
+        assert!(settings("[interface]\ncolor-scheme = 'prefer-dark'\n", current).is_applied());
+        assert!(!settings("[interface]\ncolor-scheme='default'\n", current).is_applied());
+        assert!(!settings("[privacy]\nremember-recent-files=false\n", current).is_applied());
+        assert!(
+            !DconfSettings {
+                current: None,
+                ..settings("", current)
+            }
+            .is_applied()
+        );
+
+        assert!(parse("key=value").is_err());
+    }
+}
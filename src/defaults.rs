@@ -0,0 +1,175 @@
+//! Contains [`Defaults`]
+//!
+//! Preferences of macOS apps are kept in plists, which are rewritten by the apps
+//! and can not be managed as files. They are written with `defaults write` instead
+
+use std::process::Command;
+
+use eyre::{Context as _, Result, bail};
+use serde::{Deserialize, Serialize};
+
+/// A single preference, in the `[[defaults]]` section of the config
+#[derive(Deserialize, Debug)]
+pub struct Defaults {
+    /// Domain of the preference, e.g. `com.apple.dock`
+    pub domain: String,
+    /// Name of the preference, e.g. `autohide`
+    pub key: String,
+    /// Type of the value, inferred from `value` if omitted
+    #[serde(rename = "type")]
+    pub kind: Option<DefaultsType>,
+    /// Value of the preference
+    pub value: toml::Value,
+}
+
+/// Type of the value of a preference
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "dots-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum DefaultsType {
+    /// Text
+    String,
+    /// Whole number
+    Int,
+    /// Number with a fraction
+    Float,
+    /// `true` or `false`
+    Bool,
+}
+
+/// A preference, along with what it currently is
+#[derive(Debug)]
+pub struct DefaultsValue {
+    /// Domain of the preference
+    pub domain: String,
+    /// Name of the preference
+    pub key: String,
+    /// Type of the value
+    pub kind: DefaultsType,
+    /// Value, as it is passed to `defaults write`
+    pub value: String,
+    /// Output of `defaults read`, or `None` if the preference is not set
+    pub current: Option<String>,
+}
+
+impl DefaultsType {
+    /// Flag of `defaults write` for the type
+    fn flag(self) -> &'static str {
+        match self {
+            Self::String => "-string",
+            Self::Int => "-int",
+            Self::Float => "-float",
+            Self::Bool => "-bool",
+        }
+    }
+}
+
+impl Defaults {
+    /// Resolve the type and the value of the preference, and read what it currently is
+    pub fn read(&self) -> Result<DefaultsValue> {
+        let kind = match (self.kind, &self.value) {
+            (Some(kind), _) => kind,
+            (None, toml::Value::String(_)) => DefaultsType::String,
+            (None, toml::Value::Integer(_)) => DefaultsType::Int,
+            (None, toml::Value::Float(_)) => DefaultsType::Float,
+            (None, toml::Value::Boolean(_)) => DefaultsType::Bool,
+            (None, _) => bail!(
+                "value of `{}` in `{}` must be a string, number or boolean",
+                self.key,
+                self.domain
+            ),
+        };
+        let value = match &self.value {
+            toml::Value::String(string) => string.clone(),
+            value => value.to_string(),
+        };
+
+        Ok(DefaultsValue {
+            current: read(&self.domain, &self.key),
+            domain: self.domain.clone(),
+            key: self.key.clone(),
+            kind,
+            value,
+        })
+    }
+}
+
+impl DefaultsValue {
+    /// Whether the preference already has the value
+    pub fn is_applied(&self) -> bool {
+        let Some(current) = &self.current else {
+            return false;
+        };
+
+        match self.kind {
+            // `defaults read` prints booleans as `1` and `0`
+            DefaultsType::Bool => {
+                let value = matches!(self.value.as_str(), "true" | "yes" | "1");
+                current == if value { "1" } else { "0" }
+            }
+            DefaultsType::Float => current
+                .parse::<f64>()
+                .ok()
+                .zip(self.value.parse::<f64>().ok())
+                .is_some_and(|(current, value)| current == value),
+            DefaultsType::String | DefaultsType::Int => *current == self.value,
+        }
+    }
+}
+
+/// Current value of the preference, `None` if it is not set or `defaults` is not available
+fn read(domain: &str, key: &str) -> Option<String> {
+    if !cfg!(target_os = "macos") {
+        return None;
+    }
+
+    let output = Command::new("defaults")
+        .args(["read", domain, key])
+        .output()
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Set the preference `key` in `domain` to `value`
+pub fn write(domain: &str, key: &str, kind: DefaultsType, value: &str) -> Result<()> {
+    let output = Command::new("defaults")
+        .args(["write", domain, key, kind.flag(), value])
+        .output()
+        .context("failed to run `defaults write`")?;
+
+    if !output.status.success() {
+        bail!(
+            "`defaults write {domain} {key}` failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_applied() {
+        let value = |kind, value: &str, current: &str| DefaultsValue {
+            domain: "com.apple.dock".to_string(),
+            key: "autohide".to_string(),
+            kind,
+            value: value.to_string(),
+            current: Some(current.to_string()),
+        };
+
+        assert!(value(DefaultsType::Bool, "true", "1").is_applied());
+        assert!(!value(DefaultsType::Bool, "false", "1").is_applied());
+        assert!(value(DefaultsType::Float, "0.5", "0.50").is_applied());
+        assert!(value(DefaultsType::Int, "36", "36").is_applied());
+        assert!(!value(DefaultsType::String, "left", "bottom").is_applied());
+    }
+}
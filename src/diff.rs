@@ -0,0 +1,327 @@
+//! Contains [`Diff`], [`diff`] and [`DiffTool`]
+
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use eyre::{Context as _, ContextCompat as _, Result, bail, eyre};
+use serde::Deserialize;
+use similar::{ChangeTag, TextDiff};
+use simply_colored::*;
+use tap::Pipe as _;
+
+use crate::analysis::{Contents, FileState, WritePath};
+use crate::stdx::PathExt as _;
+
+/// Line-based difference between the contents of a file and what will be written to it
+pub struct Diff<'a>(TextDiff<'a, 'a, str>);
+
+impl<'a> Diff<'a> {
+    /// Number of lines of context to show around each hunk
+    const CONTEXT: usize = 3;
+
+    /// Compute the difference between `old` and `new`
+    pub fn new(old: &'a str, new: &'a str) -> Self {
+        Self(TextDiff::from_lines(old, new))
+    }
+
+    /// Number of lines that were `(inserted, deleted)`
+    pub fn stat(&self) -> (usize, usize) {
+        self.0
+            .iter_all_changes()
+            .fold((0, 0), |(inserted, deleted), change| match change.tag() {
+                ChangeTag::Insert => (inserted + 1, deleted),
+                ChangeTag::Delete => (inserted, deleted + 1),
+                ChangeTag::Equal => (inserted, deleted),
+            })
+    }
+
+    /// Compact colored stat, like `+12/-3`
+    pub fn show_stat(&self) -> String {
+        let (inserted, deleted) = self.stat();
+        format!("{GREEN}+{inserted}{RESET}/{RED}-{deleted}{RESET}")
+    }
+
+    /// Colored hunks of the unified diff, without the `---` and `+++` header
+    pub fn show_hunks(&self) -> String {
+        let mut out = String::new();
+
+        for hunk in self
+            .0
+            .unified_diff()
+            .context_radius(Self::CONTEXT)
+            .iter_hunks()
+        {
+            out.push_str(&format!("{CYAN}{}{RESET}\n", hunk.header()));
+
+            for change in hunk.iter_changes() {
+                let (sign, color) = match change.tag() {
+                    ChangeTag::Insert => ('+', GREEN),
+                    ChangeTag::Delete => ('-', RED),
+                    ChangeTag::Equal => (' ', ""),
+                };
+
+                out.push_str(&format!("{color}{sign}{}{RESET}", change.value()));
+
+                if change.missing_newline() {
+                    out.push('\n');
+                }
+            }
+        }
+
+        out
+    }
+}
+
+/// Colored diff from the file on disk to what the `write` writes to it, `None` if they are
+/// the same
+pub fn diff(write: &WritePath) -> Result<Option<String>> {
+    let state = write.state();
+    if state == FileState::UpToDate {
+        return Ok(None);
+    }
+
+    let header = format!("{BOLD}{}{RESET}", write.path.show());
+
+    if write.symlink
+        && let Some(source) = &write.source
+    {
+        return Ok(Some(format!(
+            "{header} {BLACK}(link to {}){RESET}\n",
+            source.show()
+        )));
+    }
+
+    let text = match &write.contents {
+        Contents::Text(text) => text,
+        Contents::Secret(secret) => secret.expose(),
+        Contents::Asset(asset) => {
+            return Ok(Some(format!(
+                "{header} {BLACK}({state}, {} bytes){RESET}\n",
+                asset.len
+            )));
+        }
+    };
+
+    // a missing or binary file is treated as empty
+    let old = match fs::read(&write.path) {
+        Ok(old) => String::from_utf8(old).unwrap_or_default(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => {
+            return Err(err).with_context(|| eyre!("failed to read {}", write.path.show()));
+        }
+    };
+    let diff = Diff::new(&old, text);
+
+    // the hunks would show the secrets
+    if write.contents.is_sensitive() {
+        return Ok(Some(format!(
+            "{header} {} {BLACK}(hunks are hidden because it contains secrets){RESET}\n",
+            diff.show_stat()
+        )));
+    }
+
+    Ok(Some(format!(
+        "{header} {}\n{}",
+        diff.show_stat(),
+        diff.show_hunks()
+    )))
+}
+
+/// Program which shows differences instead of the built-in diff, in the `[diff]` section
+/// of the config
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DiffTool {
+    /// Command which compares two files, e.g. `"difft"` or `"meld"`
+    ///
+    /// `{old}` is replaced with a file that has the contents on disk, `{new}` with one that has
+    /// what `dots` will write and `{path}` with where it is written to. The old and new files
+    /// are appended if the command has neither of them
+    pub tool: Option<String>,
+}
+
+impl DiffTool {
+    /// Show how the file on disk differs from what the `write` writes to it, `None` if they
+    /// are the same
+    ///
+    /// The tool shows the difference itself, and the built-in diff is returned without one
+    pub fn show(&self, write: &WritePath) -> Result<Option<String>> {
+        // the tool would show the secrets
+        let Some(tool) = self
+            .tool
+            .as_ref()
+            .filter(|_| !write.contents.is_sensitive())
+        else {
+            return diff(write);
+        };
+        if write.state() == FileState::UpToDate {
+            return Ok(None);
+        }
+
+        let old = match fs::read(&write.path) {
+            Ok(old) => old,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => {
+                return Err(err).with_context(|| eyre!("failed to read {}", write.path.show()));
+            }
+        };
+        let new = match &write.source {
+            Some(source) if write.contents.asset().is_some() || write.symlink => fs::read(source)
+                .with_context(|| eyre!("failed to read {}", source.show()))?
+                .pipe(Cow::Owned),
+            _ => Cow::Borrowed(write.contents.text().as_bytes()),
+        };
+
+        run(tool, &write.path, &old, &new).map(|()| Some(String::new()))
+    }
+}
+
+/// Run the diff `tool` on temporary files with the `old` and `new` contents of `path`
+///
+/// The tool never gets the file on disk, so editing the old file in it does not change it.
+/// The temporary files can contain secrets, so they are inside of a directory that only
+/// the current user can read, which is removed afterwards
+fn run(tool: &str, path: &Path, old: &[u8], new: &[u8]) -> Result<()> {
+    let dir = tempfile::Builder::new()
+        .prefix("dots-diff-")
+        .tempdir()
+        .context("failed to create a temporary directory for the diff tool")?;
+    // tools use the name of the file to highlight it
+    let name = path
+        .file_name()
+        .map_or_else(|| "file".into(), |name| name.to_string_lossy().into_owned());
+    let (old_path, new_path) = (
+        dir.path().join("old").join(&name),
+        dir.path().join("new").join(&name),
+    );
+
+    for (temp, contents) in [(&old_path, old), (&new_path, new)] {
+        if let Some(parent) = temp.parent() {
+            fs::create_dir(parent).with_context(|| eyre!("failed to create {}", parent.show()))?;
+        }
+        fs::write(temp, contents).with_context(|| eyre!("failed to write {}", temp.show()))?;
+    }
+
+    let words = args(tool, &old_path, &new_path, path)?;
+    let (program, args) = words.split_first().context("diff tool is empty")?;
+
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| eyre!("failed to run diff tool `{tool}`"))?;
+    // like `diff`, most tools exit with 1 when the files differ
+    if !status.success() && status.code() != Some(1) {
+        bail!("diff tool `{tool}` failed with {status}");
+    }
+
+    Ok(())
+}
+
+/// Arguments of the diff `tool`, comparing the files at `old` and `new` for `path`
+fn args(tool: &str, old: &Path, new: &Path, path: &Path) -> Result<Vec<String>> {
+    let mut words = shellwords::split(tool).with_context(|| eyre!("invalid diff tool `{tool}`"))?;
+
+    if !words
+        .iter()
+        .any(|word| word.contains("{old}") || word.contains("{new}"))
+    {
+        words.extend(["{old}".to_string(), "{new}".to_string()]);
+    }
+
+    Ok(words
+        .into_iter()
+        .map(|word| {
+            word.replace("{old}", &old.to_string_lossy())
+                .replace("{new}", &new.to_string_lossy())
+                .replace("{path}", &path.to_string_lossy())
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensitive::Sensitive;
+
+    #[test]
+    fn stat() {
+        assert_eq!(Diff::new("a\nb\nc\n", "a\nB\nc\nd\n").stat(), (2, 1));
+        assert_eq!(Diff::new("a\n", "a\n").stat(), (0, 0));
+        assert_eq!(Diff::new("", "a\nb\n").stat(), (2, 0));
+    }
+
+    #[test]
+    fn write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        let write = |contents: &str| WritePath {
+            path: path.clone(),
+            contents: Contents::Text(contents.to_string()),
+            source: None,
+            durable: false,
+            description: None,
+            symlink: false,
+            mode: None,
+        };
+
+        let missing = diff(&write("theme = mocha\n")).unwrap().unwrap();
+        assert!(missing.contains("+theme = mocha"));
+
+        fs::write(&path, "theme = latte\n").unwrap();
+        let modified = diff(&write("theme = mocha\n")).unwrap().unwrap();
+        assert!(modified.contains("-theme = latte"));
+        assert!(modified.contains("+theme = mocha"));
+
+        assert_eq!(diff(&write("theme = latte\n")).unwrap(), None);
+
+        let secret = diff(&WritePath {
+            contents: Contents::Secret(Sensitive::containing("token = hunter22\n".to_string())),
+            ..write("")
+        })
+        .unwrap()
+        .unwrap();
+        assert!(secret.contains("contains secrets"));
+        assert!(!secret.contains("hunter22"));
+        assert!(!secret.contains("latte"));
+    }
+
+    #[test]
+    fn args() {
+        let args = |tool| {
+            super::args(
+                tool,
+                Path::new("/tmp/old/config"),
+                Path::new("/tmp/new/config"),
+                Path::new("/home/me/.config/app/config"),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            args("difft"),
+            ["difft", "/tmp/old/config", "/tmp/new/config"]
+        );
+        assert_eq!(
+            args("delta --file-style=omit {new} {old}"),
+            [
+                "delta",
+                "--file-style=omit",
+                "/tmp/new/config",
+                "/tmp/old/config"
+            ]
+        );
+        assert_eq!(
+            args("meld {old} {new} --label '{path}'"),
+            [
+                "meld",
+                "/tmp/old/config",
+                "/tmp/new/config",
+                "--label",
+                "/home/me/.config/app/config"
+            ]
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! Contains [`duplicates`]
+//!
+//! The same file is often copied into several `[[dir]]`s, e.g. one config shared by two
+//! editors, and the copies drift apart over time. `dots doctor` reports them, so the
+//! file can be kept once and written to every output with `also`
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use itertools::Itertools as _;
+use simply_colored::*;
+
+use crate::asset::Input;
+use crate::world::World;
+
+/// Sources with the same contents, relative to the config file
+///
+/// Empty files are never reported, as they are usually placeholders
+pub fn duplicates(world: &World) -> Vec<Vec<PathBuf>> {
+    let mut by_hash = BTreeMap::<String, Vec<PathBuf>>::new();
+    for file in world
+        .files
+        .iter()
+        // sources written to several outputs with `also` are only kept once
+        .unique_by(|file| &file.old_location)
+    {
+        let hash = match &file.contents {
+            Input::Asset(asset) => format!("blake3:{}", asset.hash),
+            Input::Text(text) if text.is_empty() => continue,
+            Input::Text(text) => sha256::digest(text),
+        };
+        let source = file
+            .old_location
+            .strip_prefix(&world.root)
+            .unwrap_or(&file.old_location)
+            .to_path_buf();
+
+        by_hash.entry(hash).or_default().push(source);
+    }
+
+    by_hash
+        .into_values()
+        .filter(|sources| sources.len() > 1)
+        .map(|sources| sources.into_iter().sorted().collect_vec())
+        .sorted()
+        .collect()
+}
+
+/// Report of every group of [`duplicates`], for `dots doctor`
+pub fn doctor_duplicates(world: &World) -> String {
+    let mut report = String::new();
+    let duplicates = duplicates(world);
+    if duplicates.is_empty() {
+        return report;
+    }
+
+    _ = writeln!(report, "{BOLD}duplicate sources{RESET}");
+    for sources in duplicates {
+        _ = writeln!(
+            report,
+            "  {YELLOW}!{RESET} {} are the same, keep one of them and add the other outputs to `also` of its `[[dir]]`",
+            sources
+                .iter()
+                .map(|source| source.to_string_lossy())
+                .join(", ")
+        );
+    }
+
+    report
+}
@@ -0,0 +1,91 @@
+//! Contains [`edit`]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use eyre::{Context as _, ContextCompat as _, Result, bail, eyre};
+
+use crate::complete::{self, CompletionKind};
+use crate::config::Config;
+use crate::stdx::PathExt as _;
+
+/// Editor to use when neither `$VISUAL` nor `$EDITOR` is set
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Source of the managed `file`, relative to the root of the config closest to `cwd`
+///
+/// `file` is one of the [`CompletionKind::Files`], so it must be inside of the `input` of
+/// a `[[dir]]` and not ignored
+fn source(file: &Path, cwd: &Path) -> Result<PathBuf> {
+    let config = Config::discover(cwd)?;
+    let managed = complete::candidates(CompletionKind::Files, cwd)?;
+
+    if !managed.iter().any(|managed| Path::new(managed) == file) {
+        bail!(
+            "{} is not managed by `dots`, it is not inside of the `input` of any `[[dir]]`",
+            file.show()
+        );
+    }
+
+    Ok(config.root.join(file))
+}
+
+/// Open the source of the managed `file` in `$VISUAL` or `$EDITOR`
+pub fn edit(file: &Path, cwd: &Path) -> Result<()> {
+    let source = source(file, cwd)?;
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    let words = shellwords::split(&editor).with_context(|| eyre!("invalid editor `{editor}`"))?;
+    let (program, args) = words.split_first().context("editor is empty")?;
+
+    let status = Command::new(program)
+        .args(args)
+        .arg(&source)
+        .status()
+        .with_context(|| eyre!("failed to run editor `{editor}`"))?;
+    if !status.success() {
+        bail!("editor `{editor}` failed with {status}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn source() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        fs::write(
+            dir.join("dots.toml"),
+            r#"
+            [[dir]]
+            input = "configs"
+            output = "/out"
+            "#,
+        )
+        .unwrap();
+        fs::create_dir_all(dir.join("configs/helix")).unwrap();
+        fs::write(dir.join("configs/helix/config.toml"), "").unwrap();
+        fs::write(dir.join("notes.md"), "").unwrap();
+
+        assert_eq!(
+            super::source(Path::new("configs/helix/config.toml"), &dir.join("configs")).unwrap(),
+            dir.join("configs/helix/config.toml")
+        );
+        assert!(
+            super::source(Path::new("notes.md"), dir)
+                .unwrap_err()
+                .to_string()
+                .contains("is not managed by `dots`")
+        );
+    }
+}
@@ -0,0 +1,313 @@
+//! Contains [`Engine`]
+
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use eyre::{Context as _, Result, bail, eyre};
+use handlebars::{Handlebars, RenderErrorReason};
+use serde::{Deserialize, Serialize};
+
+use crate::color;
+use crate::existing;
+use crate::outputs::{self, Output};
+
+/// Template engine that renders a file
+#[derive(
+    Serialize, Deserialize, ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Engine {
+    /// [handlebars](https://handlebarsjs.com/)
+    #[default]
+    Handlebars,
+    /// [tera](https://keats.github.io/tera/), which is similar to jinja2
+    Tera,
+    /// Copy the file as-is
+    None,
+}
+
+/// Delimiters which are used instead of `{{` and `}}`, for files which already contain them,
+/// e.g. `["<<", ">>"]`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "[String; 2]", into = "[String; 2]")]
+pub struct Delimiters {
+    /// Starts an expression
+    pub open: String,
+    /// Ends an expression
+    pub close: String,
+}
+
+impl TryFrom<[String; 2]> for Delimiters {
+    type Error = eyre::Error;
+
+    fn try_from([open, close]: [String; 2]) -> Result<Self> {
+        if open.is_empty() || close.is_empty() {
+            bail!("delimiters can not be empty");
+        }
+
+        Ok(Self { open, close })
+    }
+}
+
+impl From<Delimiters> for [String; 2] {
+    fn from(Delimiters { open, close }: Delimiters) -> Self {
+        [open, close]
+    }
+}
+
+impl Engine {
+    /// Render the `template` with `vars`, where `outputs` are the other files it can use
+    ///
+    /// With `delimiters`, the `template` is first translated to the syntax of the engine:
+    /// text between them becomes an expression, and everything else is kept as-is
+    ///
+    /// With `strict`, variables and environment variables which are not defined are errors
+    /// instead of rendering as nothing. Tera always fails on them
+    pub fn render(
+        self,
+        template: &str,
+        delimiters: Option<&Delimiters>,
+        vars: &toml::Table,
+        outputs: &BTreeMap<String, Output>,
+        strict: bool,
+    ) -> Result<String> {
+        let template = match delimiters {
+            Some(delimiters) if self != Self::None => self.translate(template, delimiters)?,
+            _ => template.to_string(),
+        };
+
+        match self {
+            Self::Handlebars => {
+                let mut handlebars = Handlebars::new();
+                handlebars.set_strict_mode(strict);
+                color::register(&mut handlebars);
+                existing::register(&mut handlebars);
+                outputs::register(&mut handlebars, outputs);
+                handlebars
+                    .register_template_string("t1", template)
+                    .context("failed to parse template")?;
+                handlebars
+                    .render("t1", vars)
+                    .map_err(|err| {
+                        let missing = match err.reason() {
+                            RenderErrorReason::MissingVariable(Some(path)) => {
+                                Some(match path.strip_prefix("env.") {
+                                    Some(name) => {
+                                        format!("environment variable `{name}` is not set")
+                                    }
+                                    None => format!("variable `{path}` is not defined"),
+                                })
+                            }
+                            _ => None,
+                        };
+                        match missing {
+                            Some(missing) => eyre::Report::new(err).wrap_err(missing),
+                            None => eyre::Report::new(err),
+                        }
+                    })
+                    .context("failed to render template")
+            }
+            Self::Tera => {
+                let context =
+                    tera::Context::from_serialize(vars).context("invalid variables for tera")?;
+                let mut tera = tera::Tera::default();
+                color::register_filters(&mut tera);
+                existing::register_function(&mut tera);
+                outputs::register_function(&mut tera, outputs);
+                tera.add_raw_template("t1", &template)
+                    .and_then(|()| tera.render("t1", &context))
+                    // the error itself only says "Failed to render", the reason is in its source
+                    .map_err(|err| eyre!("{}", error_chain(&err)))
+                    .context("failed to render template")
+            }
+            Self::None => Ok(template),
+        }
+    }
+
+    /// Translate a `template` which uses `delimiters` into the syntax of this engine
+    pub fn translate(self, template: &str, delimiters: &Delimiters) -> Result<String> {
+        let mut translated = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some((text, after_open)) = rest.split_once(&delimiters.open) {
+            let Some((expression, after_close)) = after_open.split_once(&delimiters.close) else {
+                bail!(
+                    "`{}` is never closed with `{}`",
+                    delimiters.open,
+                    delimiters.close
+                );
+            };
+            translated.push_str(&self.literal(text));
+            translated.push_str("{{");
+            translated.push_str(expression);
+            translated.push_str("}}");
+            rest = after_close;
+        }
+        translated.push_str(&self.literal(rest));
+
+        Ok(translated)
+    }
+
+    /// `text` which renders as-is with this engine
+    fn literal(self, text: &str) -> String {
+        match self {
+            Self::Handlebars if text.contains("{{") => {
+                format!("{{{{{{{{raw}}}}}}}}{text}{{{{{{{{/raw}}}}}}}}")
+            }
+            Self::Tera if text.contains("{{") || text.contains("{%") || text.contains("{#") => {
+                format!("{{% raw %}}{text}{{% endraw %}}")
+            }
+            Self::Handlebars | Self::Tera | Self::None => text.to_string(),
+        }
+    }
+}
+
+/// The error and all of its sources
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut chain = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        chain.push_str(": ");
+        chain.push_str(&err.to_string());
+        source = err.source();
+    }
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let vars = toml::toml! {
+            name = "dots"
+            colors = ["red", "blue"]
+        };
+        let angle = Delimiters::try_from(["<<".to_string(), ">>".to_string()]).unwrap();
+
+        assert_eq!(
+            Engine::Handlebars
+                .render("{{ name }}", None, &vars, &BTreeMap::new(), false)
+                .unwrap(),
+            "dots"
+        );
+        assert_eq!(
+            Engine::Tera
+                .render(
+                    "{{ name | upper }} {% for c in colors %}{{ c }}{% endfor %}",
+                    None,
+                    &vars,
+                    &BTreeMap::new(),
+                    false
+                )
+                .unwrap(),
+            "DOTS redblue"
+        );
+        assert_eq!(
+            Engine::None
+                .render("{{ name }}", Some(&angle), &vars, &BTreeMap::new(), false)
+                .unwrap(),
+            "{{ name }}"
+        );
+        assert_eq!(
+            Engine::Handlebars
+                .render(
+                    "{{ literal }} << name >> {",
+                    Some(&angle),
+                    &vars,
+                    &BTreeMap::new(),
+                    false
+                )
+                .unwrap(),
+            "{{ literal }} dots {"
+        );
+        assert_eq!(
+            Engine::Tera
+                .render(
+                    "{% literal %} << name >>",
+                    Some(&angle),
+                    &vars,
+                    &BTreeMap::new(),
+                    false
+                )
+                .unwrap(),
+            "{% literal %} dots"
+        );
+        assert!(
+            Engine::Handlebars
+                .render("<< name", Some(&angle), &vars, &BTreeMap::new(), false)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn strict() {
+        let vars = toml::toml! {
+            name = "dots"
+            env = { EDITOR = "hx" }
+        };
+        let render = |engine: Engine, template, strict| {
+            engine
+                .render(template, None, &vars, &BTreeMap::new(), strict)
+                .map_err(|err| format!("{err:#}"))
+        };
+
+        assert_eq!(
+            render(Engine::Handlebars, "{{ name }}{{ nmae }}", false).unwrap(),
+            "dots"
+        );
+        assert!(
+            render(Engine::Handlebars, "{{ name }}{{ nmae }}", true)
+                .unwrap_err()
+                .contains("variable `nmae` is not defined")
+        );
+        assert_eq!(
+            render(Engine::Handlebars, "{{ env.EDITOR }}", true).unwrap(),
+            "hx"
+        );
+        assert!(
+            render(Engine::Handlebars, "{{ env.VISUAL }}", true)
+                .unwrap_err()
+                .contains("environment variable `VISUAL` is not set")
+        );
+        assert!(render(Engine::Tera, "{{ nmae }}", false).is_err());
+    }
+
+    #[test]
+    fn colors() {
+        let vars = toml::toml! {
+            color = { base = "#1e1e2e" }
+        };
+
+        assert_eq!(
+            Engine::Handlebars
+                .render(
+                    "{{ rgb (alpha color.base 0.8) }} {{ hex_no_hash color.base }}",
+                    None,
+                    &vars,
+                    &BTreeMap::new(),
+                    false
+                )
+                .unwrap(),
+            "rgba(30, 30, 46, 0.8) 1e1e2e"
+        );
+        assert_eq!(
+            Engine::Tera
+                .render(
+                    "{{ color.base | alpha(value=0.8) | rgb }} {{ color.base | hsl }}",
+                    None,
+                    &vars,
+                    &BTreeMap::new(),
+                    false
+                )
+                .unwrap(),
+            "rgba(30, 30, 46, 0.8) hsl(240, 21%, 15%)"
+        );
+        assert!(
+            Engine::Handlebars
+                .render("{{ rgb color.bsae }}", None, &vars, &BTreeMap::new(), false)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Contains [`EnvVar`]
+//!
+//! Environment variables are declared once in `[env]`, and compiled into a file for
+//! each shell, and into `environment.d` for programs that systemd starts
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use eyre::{Result, bail};
+use serde::Deserialize;
+
+use crate::analysis::{Contents, WritePath};
+use crate::output_path::OutputPath;
+use crate::shell::{GENERATED, Shell};
+
+/// Value of a variable in the `[env]` section of the config
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum EnvVar {
+    /// Set the variable to this value
+    Value(String),
+    /// Add paths to a variable which is a list of paths, like `PATH`
+    ///
+    /// Paths can use `~/` and the same directories as `output`, e.g. `{data_dir}`
+    Paths {
+        /// Paths to add before the current value
+        #[serde(default)]
+        prepend: Vec<String>,
+        /// Paths to add after the current value
+        #[serde(default)]
+        append: Vec<String>,
+    },
+}
+
+/// Directory of the `environment.d` file, which only exists on platforms with systemd
+pub fn environment_d() -> Result<Option<PathBuf>> {
+    if cfg!(any(target_os = "macos", windows)) {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        "{config_dir}/environment.d"
+            .parse::<OutputPath>()?
+            .into_inner(),
+    ))
+}
+
+/// Compile `env` into `env.<ext>` inside of `output` for each shell, and into
+/// `dots.conf` inside of `environment_d`
+pub fn compile(
+    env: &BTreeMap<String, EnvVar>,
+    output: &Path,
+    environment_d: Option<&Path>,
+    durable: bool,
+) -> Result<Vec<WritePath>> {
+    let env = env
+        .iter()
+        .map(|(name, var)| {
+            if !is_name(name) {
+                bail!(
+                    "name of environment variable `{name}` can only contain letters, digits and `_`"
+                );
+            }
+            let var = match var {
+                EnvVar::Value(value) => EnvVar::Value(value.clone()),
+                EnvVar::Paths { prepend, append } => EnvVar::Paths {
+                    prepend: expand(prepend)?,
+                    append: expand(append)?,
+                },
+            };
+            Ok((name.as_str(), var))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let write = |path: PathBuf, contents: String| WritePath {
+        path,
+        contents: Contents::Text(contents),
+        source: None,
+        durable,
+        description: None,
+        symlink: false,
+        mode: None,
+    };
+
+    Ok(Shell::ALL
+        .into_iter()
+        .map(|shell| {
+            let contents = env
+                .iter()
+                .fold(format!("{GENERATED}\n"), |contents, (name, var)| {
+                    format!("{contents}{}\n", line(shell, name, var))
+                });
+            write(output.join(format!("env.{}", shell.extension())), contents)
+        })
+        .chain(environment_d.map(|dir| {
+            let contents = env
+                .iter()
+                .fold(format!("{GENERATED}\n"), |contents, (name, var)| {
+                    format!("{contents}{}\n", environment_d_line(name, var))
+                });
+            write(dir.join("dots.conf"), contents)
+        }))
+        .collect())
+}
+
+/// Whether `name` is a valid name of an environment variable in every shell
+fn is_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// Expand `~/` and directories like `{data_dir}` in each path
+fn expand(paths: &[String]) -> Result<Vec<String>> {
+    paths
+        .iter()
+        .map(|path| {
+            Ok(path
+                .parse::<OutputPath>()?
+                .into_inner()
+                .to_string_lossy()
+                .to_string())
+        })
+        .collect()
+}
+
+/// Line which exports the variable in `shell`
+fn line(shell: Shell, name: &str, var: &EnvVar) -> String {
+    match (shell, var) {
+        (Shell::Sh, EnvVar::Value(value)) => format!("export {name}={}", quote_sh(value)),
+        (Shell::Sh, EnvVar::Paths { prepend, append }) => {
+            let paths = prepend
+                .iter()
+                .map(|path| quote_sh(path))
+                .chain([format!("\"${name}\"")])
+                .chain(append.iter().map(|path| quote_sh(path)))
+                .collect::<Vec<_>>()
+                .join(":");
+            format!("export {name}={paths}")
+        }
+        (Shell::Nu, EnvVar::Value(value)) => format!("$env.{name} = {}", quote_nu(value)),
+        (Shell::Nu, EnvVar::Paths { prepend, append }) => {
+            let list = |paths: &[String]| {
+                paths
+                    .iter()
+                    .map(|path| quote_nu(path))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            // nushell converts only `PATH` to a list and back by default
+            let join = if name.eq_ignore_ascii_case("path") {
+                ""
+            } else {
+                " | str join (char esep)"
+            };
+            format!(
+                "$env.{name} = ($env.{name}? | default [] | split row (char esep) | prepend [{}] | append [{}]{join})",
+                list(prepend),
+                list(append)
+            )
+        }
+        (Shell::Fish, EnvVar::Value(value)) => format!("set -gx {name} {}", quote_fish(value)),
+        (Shell::Fish, EnvVar::Paths { prepend, append }) => {
+            let paths = prepend
+                .iter()
+                .map(|path| quote_fish(path))
+                .chain([format!("${name}")])
+                .chain(append.iter().map(|path| quote_fish(path)))
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("set -gx {name} {paths}")
+        }
+    }
+}
+
+/// Line which sets the variable in `environment.d`
+fn environment_d_line(name: &str, var: &EnvVar) -> String {
+    let escape = |value: &str| value.replace('\\', "\\\\").replace('$', "\\$");
+    match var {
+        EnvVar::Value(value) => format!("{name}={}", escape(value)),
+        EnvVar::Paths { prepend, append } => {
+            let paths = prepend
+                .iter()
+                .map(|path| escape(path))
+                .chain([format!("${{{name}}}")])
+                .chain(append.iter().map(|path| escape(path)))
+                .collect::<Vec<_>>()
+                .join(":");
+            format!("{name}={paths}")
+        }
+    }
+}
+
+/// Quote `value` for `sh`, where nothing inside of single quotes is special
+fn quote_sh(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Quote `value` for nushell
+fn quote_nu(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', r"\\").replace('"', "\\\""))
+}
+
+/// Quote `value` for fish, where only `\` and `'` are special inside of single quotes
+fn quote_fish(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line() {
+        let editor = EnvVar::Value("it's hx".to_string());
+        let path = EnvVar::Paths {
+            prepend: vec!["/home/me/.cargo/bin".to_string()],
+            append: vec!["/opt/bin".to_string()],
+        };
+
+        assert_eq!(
+            super::line(Shell::Sh, "EDITOR", &editor),
+            r"export EDITOR='it'\''s hx'"
+        );
+        assert_eq!(
+            super::line(Shell::Sh, "PATH", &path),
+            r#"export PATH='/home/me/.cargo/bin':"$PATH":'/opt/bin'"#
+        );
+        assert_eq!(
+            super::line(Shell::Nu, "PATH", &path),
+            r#"$env.PATH = ($env.PATH? | default [] | split row (char esep) | prepend ["/home/me/.cargo/bin"] | append ["/opt/bin"])"#
+        );
+        assert_eq!(
+            super::line(Shell::Fish, "EDITOR", &editor),
+            r"set -gx EDITOR 'it\'s hx'"
+        );
+        assert_eq!(
+            super::line(Shell::Fish, "PATH", &path),
+            "set -gx PATH '/home/me/.cargo/bin' $PATH '/opt/bin'"
+        );
+        assert_eq!(
+            environment_d_line("PATH", &path),
+            "PATH=/home/me/.cargo/bin:${PATH}:/opt/bin"
+        );
+
+        assert!(is_name("XDG_CONFIG_HOME"));
+        assert!(!is_name("1PASSWORD"));
+        assert!(!is_name("FOO-BAR"));
+    }
+}
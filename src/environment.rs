@@ -0,0 +1,254 @@
+//! Contains [`Environment`]
+//!
+//! Everything that `dots` reads from the machine it runs on to resolve the config: base
+//! directories, the hostname, the OS and environment variables. Tests, including the
+//! ones users write for their own repos, replace it with [`Environment::scope`], so a
+//! repo resolves the same way on every machine
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+
+use etcetera::BaseStrategy as _;
+use eyre::{Context as _, Result};
+
+use crate::termux::{self, Termux};
+use crate::wsl::{self, Windows};
+
+thread_local! {
+    /// Environment which replaces the real one on this thread, set by [`Environment::scope`]
+    static OVERRIDE: RefCell<Option<Environment>> = const { RefCell::new(None) };
+}
+
+/// The machine that `dots` resolves the config for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Environment {
+    /// Home directory, which `~/` expands to
+    pub home_dir: PathBuf,
+    /// `{config_dir}`
+    pub config_dir: PathBuf,
+    /// `{data_dir}`
+    pub data_dir: PathBuf,
+    /// `{cache_dir}`
+    pub cache_dir: PathBuf,
+    /// `{state_dir}`, if the platform has one
+    pub state_dir: Option<PathBuf>,
+    /// `{runtime_dir}`, if the platform has one
+    pub runtime_dir: Option<PathBuf>,
+    /// Hostname of the machine
+    pub hostname: String,
+    /// Operating system, as in [`std::env::consts::OS`]
+    pub os: String,
+    /// Family of the operating system, as in [`std::env::consts::FAMILY`]
+    pub family: String,
+    /// Architecture of the CPU, as in [`std::env::consts::ARCH`]
+    pub arch: String,
+    /// Environment variables, the ones of the process if `None`
+    pub vars: Option<BTreeMap<String, String>>,
+    /// Profiles selected with `--profile`, which replace the ones that are active by default
+    pub profiles: Option<Vec<String>>,
+    /// Set by `--strict`, which makes every config strict as if it had `strict = true`
+    pub strict: bool,
+    /// Directory with the variables set on this machine with `dots vars set`, none to not
+    /// read any
+    pub local_vars: Option<PathBuf>,
+    /// The Windows side of the machine, if `dots` runs inside of WSL
+    pub windows: Option<Windows>,
+    /// Directories of Termux, if `dots` runs inside of it
+    pub termux: Option<Termux>,
+}
+
+impl Environment {
+    /// The machine that `dots` runs on
+    pub fn detect() -> Result<Self> {
+        let strategy =
+            etcetera::choose_base_strategy().context("failed to obtain base strategy")?;
+
+        let mut environment = Self {
+            // the journal directory
+            local_vars: Some(strategy.data_dir().join("dots")),
+            home_dir: strategy.home_dir().to_path_buf(),
+            config_dir: strategy.config_dir(),
+            data_dir: strategy.data_dir(),
+            cache_dir: strategy.cache_dir(),
+            state_dir: strategy.state_dir(),
+            runtime_dir: strategy.runtime_dir(),
+            hostname: gethostname::gethostname().to_string_lossy().to_string(),
+            os: env::consts::OS.to_string(),
+            family: env::consts::FAMILY.to_string(),
+            arch: env::consts::ARCH.to_string(),
+            vars: None,
+            profiles: None,
+            strict: false,
+            windows: None,
+            termux: None,
+        };
+        // detected from the environment variables
+        environment.windows = wsl::windows(&environment);
+        environment.termux = termux::termux(&environment);
+
+        Ok(environment)
+    }
+
+    /// A Linux machine called `localhost` with the XDG base directories inside of `home_dir`,
+    /// no environment variables and no variables set with `dots vars set`, which is neither
+    /// WSL nor Termux, the same on every platform
+    pub fn new(home_dir: impl Into<PathBuf>) -> Self {
+        let home_dir = home_dir.into();
+
+        Self {
+            config_dir: home_dir.join(".config"),
+            data_dir: home_dir.join(".local/share"),
+            cache_dir: home_dir.join(".cache"),
+            state_dir: Some(home_dir.join(".local/state")),
+            runtime_dir: None,
+            hostname: "localhost".to_string(),
+            os: "linux".to_string(),
+            family: "unix".to_string(),
+            arch: "x86_64".to_string(),
+            vars: Some(BTreeMap::new()),
+            profiles: None,
+            strict: false,
+            local_vars: None,
+            windows: None,
+            termux: None,
+            home_dir,
+        }
+    }
+
+    /// The same machine, but with `home_dir` as the home directory, as with `--home`
+    ///
+    /// The base directories are the XDG ones inside of `home_dir`, because `XDG_*_HOME`
+    /// point into the home directory of the user running `dots`
+    #[must_use]
+    pub fn with_home(self, home_dir: impl Into<PathBuf>) -> Self {
+        let home_dir = home_dir.into();
+        let mut vars = self.vars.unwrap_or_else(|| {
+            env::vars_os()
+                .filter_map(|(name, value)| {
+                    Some((name.into_string().ok()?, value.into_string().ok()?))
+                })
+                .collect()
+        });
+        vars.insert("HOME".to_string(), home_dir.to_string_lossy().to_string());
+        for var in [
+            "XDG_CONFIG_HOME",
+            "XDG_DATA_HOME",
+            "XDG_CACHE_HOME",
+            "XDG_STATE_HOME",
+        ] {
+            vars.remove(var);
+        }
+
+        let base = Self::new(&home_dir);
+        Self {
+            local_vars: self.local_vars.map(|_| base.data_dir.join("dots")),
+            config_dir: base.config_dir,
+            data_dir: base.data_dir,
+            cache_dir: base.cache_dir,
+            state_dir: base.state_dir,
+            vars: Some(vars),
+            home_dir,
+            ..self
+        }
+    }
+
+    /// The environment that this thread resolves the config for, set by [`Environment::scope`]
+    /// or else the real one
+    pub fn current() -> Result<Self> {
+        match OVERRIDE.with_borrow(Clone::clone) {
+            Some(environment) => Ok(environment),
+            None => Self::detect(),
+        }
+    }
+
+    /// Run `f` with this environment instead of the real one, e.g. to create a
+    /// [`World`](crate::World) for another machine
+    ///
+    /// Only applies to the current thread
+    pub fn scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        /// Restores the previous environment when dropped, even if `f` panics
+        struct Restore(Option<Environment>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let previous = self.0.take();
+                OVERRIDE.set(previous);
+            }
+        }
+
+        let _restore = Restore(OVERRIDE.replace(Some(self.clone())));
+        f()
+    }
+
+    /// Value of the environment variable `name`
+    pub fn var(&self, name: &str) -> Option<String> {
+        match &self.vars {
+            Some(vars) => vars.get(name).cloned(),
+            None => env::var(name).ok(),
+        }
+    }
+}
+
+/// Hostname of the [current](Environment::current) environment, which does not need the base
+/// directories
+pub fn hostname() -> String {
+    OVERRIDE.with_borrow(|environment| match environment {
+        Some(environment) => environment.hostname.clone(),
+        None => gethostname::gethostname().to_string_lossy().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope() {
+        let environment = Environment {
+            hostname: "workstation".to_string(),
+            vars: Some(BTreeMap::from([("EDITOR".to_string(), "hx".to_string())])),
+            ..Environment::new("/home/me")
+        };
+
+        environment.scope(|| {
+            let current = Environment::current().unwrap();
+            assert_eq!(current.config_dir, PathBuf::from("/home/me/.config"));
+            assert_eq!(current.var("EDITOR").as_deref(), Some("hx"));
+            assert_eq!(current.var("HOME"), None);
+            assert_eq!(hostname(), "workstation");
+
+            // scopes nest
+            Environment::new("/home/other").scope(|| assert_eq!(hostname(), "localhost"));
+            assert_eq!(hostname(), "workstation");
+        });
+
+        assert_eq!(hostname(), gethostname::gethostname().to_string_lossy());
+    }
+
+    #[test]
+    fn with_home() {
+        let environment = Environment {
+            hostname: "workstation".to_string(),
+            vars: Some(BTreeMap::from([
+                ("HOME".to_string(), "/root".to_string()),
+                ("XDG_CONFIG_HOME".to_string(), "/root/.config".to_string()),
+                ("EDITOR".to_string(), "hx".to_string()),
+            ])),
+            local_vars: Some(PathBuf::from("/root/.local/share/dots")),
+            ..Environment::new("/root")
+        }
+        .with_home("/home/me");
+
+        assert_eq!(environment.config_dir, PathBuf::from("/home/me/.config"));
+        assert_eq!(
+            environment.local_vars,
+            Some(PathBuf::from("/home/me/.local/share/dots"))
+        );
+        assert_eq!(environment.hostname, "workstation");
+        assert_eq!(environment.var("HOME").as_deref(), Some("/home/me"));
+        assert_eq!(environment.var("XDG_CONFIG_HOME"), None);
+        assert_eq!(environment.var("EDITOR").as_deref(), Some("hx"));
+    }
+}
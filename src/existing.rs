@@ -0,0 +1,198 @@
+//! Contains [`existing`]
+//!
+//! When a file which an app wrote itself becomes managed, some of its values only make sense
+//! on this machine, e.g. a device ID. Templates read them from the file on disk with
+//! `existing`, so they are carried forward into the managed file
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError, RenderErrorReason,
+    ScopedJson,
+};
+use serde_json::Value;
+
+use crate::analysis::backup_path;
+use crate::output_path::OutputPath;
+
+/// Value of `key` in the file at `path`, or in its backup `<name>.orig.dots` if the file
+/// does not exist
+///
+/// The file is parsed according to its extension as TOML, JSON or YAML, where `key` can be
+/// dotted like `device.id`. Other files have a `key = value` or `key: value` on each line.
+/// It is `null` if neither file exists or if the file does not contain `key`
+pub fn existing(path: &str, key: &str) -> Result<Value, String> {
+    let path = path
+        .parse::<OutputPath>()
+        .map_err(|err| format!("`existing`: {err}"))?
+        .into_inner();
+
+    let contents = match read(&path)? {
+        Some(contents) => contents,
+        None => match read(&backup_path(&path))? {
+            Some(contents) => contents,
+            None => return Ok(Value::Null),
+        },
+    };
+
+    let document = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str::<Value>(&contents).map_err(|err| err.to_string()),
+        Some("json") => serde_json::from_str::<Value>(&contents).map_err(|err| err.to_string()),
+        Some("yaml" | "yml") => {
+            serde_yaml::from_str::<Value>(&contents).map_err(|err| err.to_string())
+        }
+        _ => return Ok(line(&contents, key).map_or(Value::Null, Value::from)),
+    }
+    .map_err(|err| {
+        format!(
+            "`existing`: failed to parse {}: {err}",
+            path.to_string_lossy()
+        )
+    })?;
+
+    Ok(key
+        .split('.')
+        .try_fold(&document, |value, part| value.get(part))
+        .cloned()
+        .unwrap_or(Value::Null))
+}
+
+/// Contents of the file at `path`, `None` if it does not exist
+fn read(path: &Path) -> Result<Option<String>, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!(
+            "`existing`: failed to read {}: {err}",
+            path.to_string_lossy()
+        )),
+    }
+}
+
+/// Value of the first line of `contents` which is like `key = value` or `key: value`
+fn line<'a>(contents: &'a str, key: &str) -> Option<&'a str> {
+    contents.lines().find_map(|line| {
+        let line = line.trim();
+        if line.starts_with(['#', ';']) {
+            return None;
+        }
+        let (name, value) = line.split_at(line.find(['=', ':'])?);
+        if name.trim() != key {
+            return None;
+        }
+
+        let value = value[1..].trim();
+        let unquoted = ['"', '\''].into_iter().find_map(|quote| {
+            value
+                .strip_prefix(quote)
+                .and_then(|value| value.strip_suffix(quote))
+        });
+        Some(unquoted.unwrap_or(value))
+    })
+}
+
+/// Whether `template` uses `existing`, so it can not be cached
+pub fn is_used(template: &str) -> bool {
+    template.contains("existing")
+}
+
+/// Register `existing` into `handlebars`, used like `{{ existing "~/.config/app.conf" "device_id" }}`
+pub fn register(handlebars: &mut Handlebars) {
+    handlebars.register_helper("existing", Box::new(ExistingHelper));
+}
+
+/// Register `existing` into `tera` as a function, used like
+/// `{{ existing(path="~/.config/app.conf", key="device_id") }}`
+pub fn register_function(tera: &mut tera::Tera) {
+    tera.register_function("existing", |args: &HashMap<String, Value>| {
+        let (Some(path), Some(key)) = (
+            args.get("path").and_then(Value::as_str),
+            args.get("key").and_then(Value::as_str),
+        ) else {
+            return Err(tera::Error::msg(
+                "`existing` takes the path and the key, e.g. `path=\"~/.config/app.conf\", key=\"device_id\"`",
+            ));
+        };
+
+        existing(path, key).map_err(tera::Error::msg)
+    });
+}
+
+/// Handlebars helper that reads a value from a file on disk
+struct ExistingHelper;
+
+impl HelperDef for ExistingHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let (Some(path), Some(key)) = (
+            h.param(0).and_then(|param| param.value().as_str()),
+            h.param(1).and_then(|param| param.value().as_str()),
+        ) else {
+            return Err(RenderErrorReason::Other(
+                "`existing` takes the path and the key, e.g. `existing \"~/.config/app.conf\" \"device_id\"`"
+                    .to_string(),
+            )
+            .into());
+        };
+
+        existing(path, key)
+            .map(ScopedJson::Derived)
+            .map_err(|err| RenderErrorReason::Other(err).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn existing() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        fs::write(dir.join("app.toml"), "[device]\nid = 42\n").unwrap();
+        fs::write(
+            dir.join("app.conf"),
+            "# device_id = 1\ndevice_id = \"abc\"\nname: laptop\n",
+        )
+        .unwrap();
+        fs::write(dir.join("moved.json.orig.dots"), r#"{"token": "t"}"#).unwrap();
+        fs::write(dir.join("broken.json"), "{").unwrap();
+
+        assert_eq!(
+            super::existing(&path("app.toml"), "device.id").unwrap(),
+            Value::from(42)
+        );
+        assert_eq!(
+            super::existing(&path("app.conf"), "device_id").unwrap(),
+            Value::from("abc")
+        );
+        assert_eq!(
+            super::existing(&path("app.conf"), "name").unwrap(),
+            Value::from("laptop")
+        );
+        // read from the backup of the unmanaged file
+        assert_eq!(
+            super::existing(&path("moved.json"), "token").unwrap(),
+            Value::from("t")
+        );
+        assert_eq!(
+            super::existing(&path("app.toml"), "device.name").unwrap(),
+            Value::Null
+        );
+        assert_eq!(
+            super::existing(&path("missing.conf"), "id").unwrap(),
+            Value::Null
+        );
+        assert!(super::existing(&path("broken.json"), "id").is_err());
+    }
+}
@@ -0,0 +1,442 @@
+//! Contains [`explain`]
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use eyre::{Result, bail};
+use itertools::Itertools as _;
+use simply_colored::*;
+
+use crate::analysis::WritePath;
+use crate::complete::{self, CompletionKind};
+use crate::config::Config;
+use crate::packages;
+use crate::stdx::PathExt as _;
+
+/// Entry of the config to explain
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum Entry {
+    /// A `[[dir]]`, by its position in the config, counting from 1
+    Dir {
+        /// Position of the `[[dir]]`
+        #[arg(add = complete::completer(CompletionKind::Positions))]
+        position: usize,
+    },
+    /// A `[[link]]`, by a part of its `url` or `path`
+    Link {
+        /// Text to search for in the `url` and `path` of every link
+        query: String,
+    },
+    /// A `[[package]]`, by its `name`
+    Package {
+        /// Name of the package
+        #[arg(add = complete::completer(CompletionKind::Packages))]
+        name: String,
+    },
+}
+
+/// Describe how `entry` of the config closest to `cwd` resolves into the `writes`
+pub fn explain<'a>(
+    entry: &Entry,
+    cwd: &Path,
+    writes: impl IntoIterator<Item = &'a WritePath>,
+) -> Result<String> {
+    let config = Config::discover(cwd)?;
+    let writes = writes.into_iter().collect_vec();
+
+    match entry {
+        Entry::Dir { position } => explain_dir(*position, &config, &writes),
+        Entry::Link { query } => explain_link(query, &config, &writes),
+        Entry::Package { name } => explain_package(name, &config),
+    }
+}
+
+/// Describe the `[[dir]]` at `position`, counting from 1
+fn explain_dir(position: usize, config: &Config, writes: &[&WritePath]) -> Result<String> {
+    let Some(dir) = position
+        .checked_sub(1)
+        .and_then(|index| config.dirs.get(index))
+    else {
+        bail!(
+            "there is no `[[dir]]` number {position}, the config has {}",
+            config.dirs.len()
+        );
+    };
+
+    let input = config.root.join(&dir.input);
+    let output = dir.output.as_ref();
+    let files = files(&input, output, writes);
+
+    let mut explanation = String::new();
+    writeln!(explanation, "{GREEN}[[dir]]{RESET} number {position}")?;
+    if let Some(description) = &dir.description {
+        writeln!(explanation, "{GREEN}about{RESET}   {description}")?;
+    }
+    writeln!(explanation, "{GREEN}input{RESET}   {}", input.show())?;
+    writeln!(explanation, "{GREEN}output{RESET}  {}", output.show())?;
+    writeln!(explanation, "{GREEN}files{RESET}   {}", files.len())?;
+    explanation.extend(files);
+
+    Ok(explanation)
+}
+
+/// A line for each file in the `input` directory, showing where `writes` write it to
+fn files(input: &Path, output: &Path, writes: &[&WritePath]) -> Vec<String> {
+    writes
+        .iter()
+        .filter_map(|write| Some((write.source.as_ref()?.strip_prefix(input).ok()?, write)))
+        // a file can only be in another output if a marker moved it
+        .filter(|(relative, write)| {
+            write.path == output.join(relative) || !write.path.starts_with(output)
+        })
+        .sorted_by_key(|(relative, _)| *relative)
+        .map(|(relative, write)| {
+            let mut notes = vec![];
+            if write.path != output.join(relative) {
+                notes.push("moved by a marker");
+            }
+            if write
+                .source
+                .as_ref()
+                .and_then(|source| fs::read_to_string(source).ok())
+                .is_some_and(|source| source != write.contents.text())
+            {
+                notes.push("rendered");
+            }
+
+            let notes = if notes.is_empty() {
+                String::new()
+            } else {
+                format!(" {BLACK}({}){RESET}", notes.join(", "))
+            };
+
+            format!(
+                "  {} {BLACK}→{RESET} {}{notes}\n",
+                relative.show(),
+                write.path.show()
+            )
+        })
+        .collect_vec()
+}
+
+/// Describe the single `[[link]]` whose `url` or `path` contains `query`
+fn explain_link(query: &str, config: &Config, writes: &[&WritePath]) -> Result<String> {
+    let matches = config
+        .links
+        .iter()
+        .filter(|link| link.url.contains(query) || link.path.to_string_lossy().contains(query))
+        .collect_vec();
+
+    let link = match matches.as_slice() {
+        [link] => link,
+        [] => bail!("no `[[link]]` has `{query}` in its `url` or `path`"),
+        links => bail!(
+            "{} links have `{query}` in their `url` or `path`, be more specific:\n{}",
+            links.len(),
+            links
+                .iter()
+                .map(|link| format!("  {}", link.url))
+                .join("\n")
+        ),
+    };
+
+    let path = config.root.join(&link.path);
+    let size = writes.iter().find(|write| write.path == path).map_or_else(
+        || "not written".to_string(),
+        |write| format!("{} bytes", write.size()),
+    );
+
+    let mut explanation = String::new();
+    writeln!(
+        explanation,
+        "{GREEN}[[link]]{RESET} {BLUE}{}{RESET}",
+        link.url
+    )?;
+    if let Some(description) = &link.description {
+        writeln!(explanation, "{GREEN}about{RESET}   {description}")?;
+    }
+    writeln!(explanation, "{GREEN}path{RESET}    {}", path.show())?;
+    writeln!(
+        explanation,
+        "{GREEN}sha256{RESET}  {}",
+        link.sha256.as_deref().unwrap_or("not pinned")
+    )?;
+    writeln!(
+        explanation,
+        "{GREEN}marker{RESET}  {}",
+        link.marker.as_deref().unwrap_or("none")
+    )?;
+    writeln!(explanation, "{GREEN}size{RESET}    {size}")?;
+
+    Ok(explanation)
+}
+
+/// Describe the `[[package]]` called `name`
+fn explain_package(name: &str, config: &Config) -> Result<String> {
+    let Some(package) = config.packages.iter().find(|package| package.name == name) else {
+        bail!(
+            "there is no `[[package]]` called {name}, the packages are: {}",
+            config
+                .packages
+                .iter()
+                .map(|package| &package.name)
+                .join(", ")
+        );
+    };
+
+    let mut explanation = String::new();
+    writeln!(explanation, "{GREEN}[[package]]{RESET} {name}")?;
+    writeln!(
+        explanation,
+        "{GREEN}kind{RESET}      {}",
+        package.kind.name()
+    )?;
+    if let Some(id) = package.id {
+        writeln!(explanation, "{GREEN}id{RESET}        {id}")?;
+    }
+    write!(
+        explanation,
+        "{GREEN}brewfile{RESET}  {}",
+        packages::brewfile(std::slice::from_ref(package))?
+    )?;
+
+    Ok(explanation)
+}
+
+/// Describe the `app` in `[apps]` of the config closest to `cwd`: where to learn more about
+/// it, and how its files and links resolve into the `writes`
+pub fn info<'a>(
+    app: &str,
+    cwd: &Path,
+    writes: impl IntoIterator<Item = &'a WritePath>,
+) -> Result<String> {
+    let config = Config::discover(cwd)?;
+    let writes = writes.into_iter().collect_vec();
+    let Some(apps) = &config.apps else {
+        bail!("the config has no `[apps]`");
+    };
+
+    let input = apps.dir.join(app);
+    let Some(dir) = config.dirs.iter().find(|dir| dir.input == input) else {
+        bail!(
+            "there is no app {app} in {}, the apps are: {}",
+            apps.dir.show(),
+            config
+                .dirs
+                .iter()
+                .filter_map(|dir| dir.input.strip_prefix(&apps.dir).ok())
+                .map(|app| app.to_string_lossy())
+                .join(", ")
+        );
+    };
+
+    let input = config.root.join(&dir.input);
+    let output = dir.output.as_ref();
+    let files = files(&input, output, &writes);
+
+    // links are downloaded into the repo, those inside of the app are a part of it
+    let links = config
+        .links
+        .iter()
+        .filter_map(|link| {
+            let relative = config.root.join(&link.path);
+            let relative = relative.strip_prefix(&input).ok()?;
+            let pinned = if link.sha256.is_some() {
+                "pinned"
+            } else {
+                "not pinned"
+            };
+
+            Some(format!(
+                "  {} {BLACK}←{RESET} {BLUE}{}{RESET} {BLACK}({pinned}){RESET}\n",
+                relative.show(),
+                link.url
+            ))
+        })
+        .collect_vec();
+
+    let info = apps.info.get(app).cloned().unwrap_or_default();
+    let mut explanation = String::new();
+    writeln!(explanation, "{GREEN}app{RESET}       {app}")?;
+    for (name, value) in [
+        ("homepage", &info.homepage),
+        ("docs", &info.docs),
+        ("notes", &info.notes),
+    ] {
+        if let Some(value) = value {
+            writeln!(explanation, "{GREEN}{name:<9}{RESET} {value}")?;
+        }
+    }
+    writeln!(explanation, "{GREEN}input{RESET}     {}", input.show())?;
+    writeln!(explanation, "{GREEN}output{RESET}    {}", output.show())?;
+    writeln!(explanation, "{GREEN}files{RESET}     {}", files.len())?;
+    explanation.extend(files);
+    writeln!(explanation, "{GREEN}links{RESET}     {}", links.len())?;
+    explanation.extend(links);
+
+    Ok(explanation)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::analysis::Contents;
+
+    #[test]
+    fn explain_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        fs::write(
+            dir.join("dots.toml"),
+            r#"
+            [[dir]]
+            input = "configs"
+            output = "/out"
+            description = "configs of every app"
+            "#,
+        )
+        .unwrap();
+        fs::create_dir(dir.join("configs")).unwrap();
+        fs::write(dir.join("configs/plain.txt"), "plain").unwrap();
+        fs::write(dir.join("configs/moved.txt"), "moved").unwrap();
+
+        let writes = [
+            WritePath {
+                path: PathBuf::from("/out/plain.txt"),
+                contents: Contents::Text("plain".to_string()),
+                source: Some(dir.join("configs/plain.txt")),
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            },
+            WritePath {
+                path: PathBuf::from("/elsewhere/moved.txt"),
+                contents: Contents::Text("moved".to_string()),
+                source: Some(dir.join("configs/moved.txt")),
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            },
+        ];
+
+        let explanation = explain(&Entry::Dir { position: 1 }, dir, &writes).unwrap();
+        assert!(explanation.contains("/out/plain.txt"));
+        assert!(explanation.contains("configs of every app"));
+        // only the file which is not written into the `output` was moved
+        assert_eq!(explanation.matches("moved by a marker").count(), 1);
+
+        assert!(
+            explain(&Entry::Dir { position: 2 }, dir, &writes)
+                .unwrap_err()
+                .to_string()
+                .contains("there is no `[[dir]]` number 2")
+        );
+    }
+
+    #[test]
+    fn explain_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        fs::write(
+            dir.join("dots.toml"),
+            r#"
+            [[package]]
+            name = "ripgrep"
+
+            [[package]]
+            name = "Xcode"
+            kind = "mas"
+            id = 497799835
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            crate::complete::candidates(CompletionKind::Packages, dir).unwrap(),
+            ["ripgrep", "Xcode"]
+        );
+
+        let explanation = explain(
+            &Entry::Package {
+                name: "Xcode".to_string(),
+            },
+            dir,
+            [],
+        )
+        .unwrap();
+        assert!(explanation.contains("497799835"));
+        assert!(explanation.contains(r#"mas "Xcode", id: 497799835"#));
+
+        assert!(
+            explain(
+                &Entry::Package {
+                    name: "fd".to_string(),
+                },
+                dir,
+                [],
+            )
+            .unwrap_err()
+            .to_string()
+            .contains("the packages are: ripgrep, Xcode")
+        );
+    }
+
+    #[test]
+    fn info() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        fs::write(
+            dir.join("dots.toml"),
+            r#"
+            [apps]
+            dir = "apps"
+
+            [apps.output]
+            helix = "/out/helix"
+
+            [apps.info.helix]
+            homepage = "https://helix-editor.com"
+            notes = "keybindings follow kakoune"
+
+            [[link]]
+            url = "https://example.com/catppuccin.toml"
+            path = "apps/helix/themes/catppuccin.toml"
+            "#,
+        )
+        .unwrap();
+        fs::create_dir_all(dir.join("apps/helix/themes")).unwrap();
+        fs::write(dir.join("apps/helix/config.toml"), "theme = 'catppuccin'").unwrap();
+
+        let writes = [WritePath {
+            path: PathBuf::from("/out/helix/config.toml"),
+            contents: Contents::Text("theme = 'catppuccin'".to_string()),
+            source: Some(dir.join("apps/helix/config.toml")),
+            durable: false,
+            description: None,
+            symlink: false,
+            mode: None,
+        }];
+
+        let info = super::info("helix", dir, &writes).unwrap();
+        assert!(info.contains("https://helix-editor.com"));
+        assert!(info.contains("keybindings follow kakoune"));
+        assert!(!info.contains("docs"));
+        assert!(info.contains("/out/helix/config.toml"));
+        assert!(info.contains("https://example.com/catppuccin.toml"));
+
+        assert!(
+            super::info("kakoune", dir, &writes)
+                .unwrap_err()
+                .to_string()
+                .contains("the apps are: helix")
+        );
+    }
+}
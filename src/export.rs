@@ -0,0 +1,467 @@
+//! Export the files managed by `dots` into formats of other tools
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use eyre::{Context as _, Result, bail, eyre};
+use itertools::Itertools as _;
+
+use crate::analysis::{Contents, WritePath};
+use crate::config::GITHUB;
+use crate::stdx::PathExt as _;
+
+/// Format to export to
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum ExportFormat {
+    /// Nix module for home-manager, with `home.file` and `xdg.configFile` attributes
+    HomeManager,
+    /// Directory of GNU stow packages, one for each `[[dir]]` and one for all links
+    Stow,
+    /// List of Ansible tasks that create the same files with `ansible.builtin.copy`
+    AnsibleCopyTasks,
+}
+
+/// Directories of the machine that paths are exported relative to
+pub struct ExportDirs<'a> {
+    /// Directory where the repo is, e.g. `~/dotfiles`
+    pub root: &'a Path,
+    /// Home directory, e.g. `~`
+    pub home: &'a Path,
+    /// Config directory, e.g. `~/.config`
+    pub config: &'a Path,
+}
+
+/// Fail if any of the `writes` contains a secret, which every format would contain in
+/// plaintext, unless `allow_sensitive`
+pub fn check_sensitive<'a>(
+    writes: impl IntoIterator<Item = &'a WritePath>,
+    allow_sensitive: bool,
+) -> Result<()> {
+    let sensitive = writes
+        .into_iter()
+        .filter(|write| write.contents.is_sensitive())
+        .map(|write| format!("  {}", write.path.show()))
+        .sorted()
+        .collect_vec();
+
+    if sensitive.is_empty() {
+        return Ok(());
+    }
+    if !allow_sensitive {
+        bail!(
+            "refusing to export {} files which contain secrets in plaintext:\n{}\npass `--allow-sensitive` if this is intended",
+            sensitive.len(),
+            sensitive.join("\n")
+        );
+    }
+
+    log::warn!(
+        "exporting {} files which contain secrets in plaintext:\n{}",
+        sensitive.len(),
+        sensitive.join("\n")
+    );
+    Ok(())
+}
+
+/// Nix module for home-manager, which is meant to be placed at the root of the repo
+///
+/// Files which are written unchanged from their source refer to the source,
+/// otherwise the rendered contents are inlined.
+pub fn home_manager<'a>(
+    writes: impl IntoIterator<Item = &'a WritePath>,
+    dirs: &ExportDirs<'_>,
+) -> String {
+    let attrs = writes
+        .into_iter()
+        .sorted_by_key(|write| &write.path)
+        .map(|write| {
+            let (attr, relative) = if let Ok(relative) = write.path.strip_prefix(dirs.config) {
+                ("xdg.configFile", relative)
+            } else if let Ok(relative) = write.path.strip_prefix(dirs.home) {
+                ("home.file", relative)
+            } else {
+                return format!(
+                    "  # skipped {}, it is outside of the home directory\n",
+                    write.path.to_string_lossy()
+                );
+            };
+
+            let name = nix_string(&relative.to_string_lossy());
+
+            let value = if let Some(source) = &write.source
+                && (write.contents.asset().is_some()
+                    || fs::read_to_string(source)
+                        .is_ok_and(|source| source == write.contents.expose()))
+                && let Ok(source) = source.strip_prefix(dirs.root)
+            {
+                // source file can be used as-is
+                format!("source = {}", nix_path(source))
+            } else {
+                format!("text = {}", nix_string(write.contents.expose()))
+            };
+
+            format!("  {attr}.{name}.{value};\n")
+        })
+        .join("");
+
+    format!("# @generated by `dots` <{GITHUB}>\n{{\n{attrs}}}\n")
+}
+
+/// Package that contains every link, when exporting with [`stow`]
+const STOW_LINKS_PACKAGE: &str = "links";
+
+/// Write a tree of GNU stow packages into `out`, returning names of all packages
+///
+/// Each `[[dir]]` becomes a package named after the first component of its `input`,
+/// and all links go into a single package. Packages are meant to be stowed into the home directory.
+pub fn stow<'a>(
+    writes: impl IntoIterator<Item = &'a WritePath>,
+    dirs: &ExportDirs<'_>,
+    out: &Path,
+) -> Result<Vec<String>> {
+    let mut packages = BTreeSet::new();
+
+    for write in writes {
+        let Ok(relative) = write.path.strip_prefix(dirs.home) else {
+            log::warn!(
+                "skipped {}, it is outside of the home directory",
+                write.path.show()
+            );
+            continue;
+        };
+
+        let package = write
+            .source
+            .as_ref()
+            .and_then(|source| source.strip_prefix(dirs.root).ok())
+            .and_then(|source| source.components().next())
+            .map_or_else(
+                || STOW_LINKS_PACKAGE.to_string(),
+                |component| component.as_os_str().to_string_lossy().to_string(),
+            );
+
+        let path = out.join(&package).join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| eyre!("failed to create directory {}", parent.show()))?;
+        }
+        match (&write.contents, &write.source) {
+            (Contents::Text(_) | Contents::Secret(_), _) => {
+                fs::write(&path, write.contents.expose())
+            }
+            (Contents::Asset(_), Some(source)) => fs::copy(source, &path).map(|_| ()),
+            (Contents::Asset(_), None) => bail!(
+                "{} is not text, but there is no file to copy it from",
+                write.path.show()
+            ),
+        }
+        .with_context(|| eyre!("failed to write {}", path.show()))?;
+
+        packages.insert(package);
+    }
+
+    Ok(packages.into_iter().collect())
+}
+
+/// Ansible task file, which creates every parent directory and then copies the rendered
+/// contents of every file
+///
+/// Paths in the home directory are relative to the home directory of the remote user.
+/// The output is JSON, which Ansible reads as YAML.
+pub fn ansible_copy_tasks<'a>(
+    writes: impl IntoIterator<Item = &'a WritePath>,
+    dirs: &ExportDirs<'_>,
+) -> Result<String> {
+    let dest = |path: &Path| match path.strip_prefix(dirs.home) {
+        Ok(relative) => format!("~/{}", relative.to_string_lossy()),
+        Err(_) => path.to_string_lossy().to_string(),
+    };
+
+    let writes = writes
+        .into_iter()
+        .sorted_by_key(|write| &write.path)
+        .collect_vec();
+
+    let parents = writes
+        .iter()
+        .filter_map(|write| write.path.parent())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|parent| {
+            serde_json::json!({
+                "name": format!("create {}", dest(parent)),
+                "ansible.builtin.file": {
+                    "path": dest(parent),
+                    "state": "directory",
+                },
+            })
+        });
+
+    let copies = writes.iter().map(|write| {
+        let copy = match (&write.contents, &write.source) {
+            // assets are copied from the repo, which must be available to Ansible
+            (Contents::Asset(_), Some(source)) => serde_json::json!({
+                "dest": dest(&write.path),
+                "src": source.to_string_lossy(),
+            }),
+            _ => serde_json::json!({
+                "dest": dest(&write.path),
+                "content": jinja_raw(write.contents.expose()),
+            }),
+        };
+
+        serde_json::json!({
+            "name": format!("write {}", dest(&write.path)),
+            "ansible.builtin.copy": copy,
+        })
+    });
+
+    serde_json::to_string_pretty(&parents.chain(copies).collect_vec())
+        .context("failed to serialize ansible tasks")
+}
+
+/// Prevent Ansible from interpreting `s` as a Jinja template
+fn jinja_raw(s: &str) -> String {
+    if ["{{", "{%", "{#"]
+        .iter()
+        .any(|delimiter| s.contains(delimiter))
+    {
+        format!("{{% raw %}}{s}{{% endraw %}}")
+    } else {
+        s.to_string()
+    }
+}
+
+/// A Nix string literal
+fn nix_string(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t");
+
+    format!("\"{escaped}\"")
+}
+
+/// A Nix path relative to the file containing the expression
+fn nix_path(relative: &Path) -> String {
+    let relative = relative.to_string_lossy().replace('\\', "/");
+
+    // path literals can only contain some characters
+    if relative
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || "._-+/".contains(ch))
+    {
+        format!("./{relative}")
+    } else {
+        format!("./. + {}", nix_string(&format!("/{relative}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::sensitive::Sensitive;
+
+    #[test]
+    fn home_manager() {
+        let dirs = ExportDirs {
+            root: Path::new("/home/user/dotfiles"),
+            home: Path::new("/home/user"),
+            config: Path::new("/home/user/.config"),
+        };
+
+        let writes = [
+            WritePath {
+                path: PathBuf::from("/home/user/.config/helix/config.toml"),
+                contents: Contents::Text("theme = \"${x}\"\n".to_string()),
+                source: None,
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            },
+            WritePath {
+                path: PathBuf::from("/home/user/.bashrc"),
+                contents: Contents::Text("foo".to_string()),
+                source: None,
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            },
+            WritePath {
+                path: PathBuf::from("/etc/foo"),
+                contents: Contents::Text("foo".to_string()),
+                source: None,
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            },
+        ];
+
+        assert_eq!(
+            super::home_manager(&writes, &dirs),
+            format!(
+                "# @generated by `dots` <{GITHUB}>\n{{\n{}}}\n",
+                concat!(
+                    "  # skipped /etc/foo, it is outside of the home directory\n",
+                    "  home.file.\".bashrc\".text = \"foo\";\n",
+                    "  xdg.configFile.\"helix/config.toml\".text = \"theme = \\\"\\${x}\\\"\\n\";\n",
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn stow() {
+        let out = tempfile::tempdir().unwrap();
+        let out = out.path();
+
+        let dirs = ExportDirs {
+            root: Path::new("/home/user/dotfiles"),
+            home: Path::new("/home/user"),
+            config: Path::new("/home/user/.config"),
+        };
+
+        let writes = [
+            WritePath {
+                path: PathBuf::from("/home/user/.config/helix/config.toml"),
+                contents: Contents::Text("helix".to_string()),
+                source: Some(PathBuf::from(
+                    "/home/user/dotfiles/configs/helix/config.toml",
+                )),
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            },
+            WritePath {
+                path: PathBuf::from("/home/user/.bashrc"),
+                contents: Contents::Text("bash".to_string()),
+                source: None,
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            },
+        ];
+
+        assert_eq!(
+            super::stow(&writes, &dirs, out).unwrap(),
+            vec!["configs".to_string(), "links".to_string()]
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("configs/.config/helix/config.toml")).unwrap(),
+            "helix"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("links/.bashrc")).unwrap(),
+            "bash"
+        );
+    }
+
+    #[test]
+    fn ansible_copy_tasks() {
+        let dirs = ExportDirs {
+            root: Path::new("/home/user/dotfiles"),
+            home: Path::new("/home/user"),
+            config: Path::new("/home/user/.config"),
+        };
+
+        let writes = [
+            WritePath {
+                path: PathBuf::from("/home/user/.config/helix/config.toml"),
+                contents: Contents::Text("{{ not a template }}".to_string()),
+                source: None,
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            },
+            WritePath {
+                path: PathBuf::from("/home/user/.config/helix/languages.toml"),
+                contents: Contents::Text("foo".to_string()),
+                source: None,
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            },
+        ];
+
+        let tasks: serde_json::Value =
+            serde_json::from_str(&super::ansible_copy_tasks(&writes, &dirs).unwrap()).unwrap();
+
+        assert_eq!(
+            tasks,
+            serde_json::json!([
+                {
+                    "name": "create ~/.config/helix",
+                    "ansible.builtin.file": { "path": "~/.config/helix", "state": "directory" },
+                },
+                {
+                    "name": "write ~/.config/helix/config.toml",
+                    "ansible.builtin.copy": {
+                        "dest": "~/.config/helix/config.toml",
+                        "content": "{% raw %}{{ not a template }}{% endraw %}",
+                    },
+                },
+                {
+                    "name": "write ~/.config/helix/languages.toml",
+                    "ansible.builtin.copy": {
+                        "dest": "~/.config/helix/languages.toml",
+                        "content": "foo",
+                    },
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn check_sensitive() {
+        let writes = [
+            WritePath {
+                path: PathBuf::from("/home/user/.config/helix/config.toml"),
+                contents: Contents::Text("foo".to_string()),
+                source: None,
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            },
+            WritePath {
+                path: PathBuf::from("/home/user/.netrc"),
+                contents: Contents::Secret(Sensitive::containing("password hunter22".to_string())),
+                source: None,
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            },
+        ];
+
+        assert!(super::check_sensitive(&writes[..1], false).is_ok());
+        let err = super::check_sensitive(&writes, false)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(".netrc"));
+        assert!(err.contains("--allow-sensitive"));
+        assert!(super::check_sensitive(&writes, true).is_ok());
+    }
+
+    #[test]
+    fn nix_path() {
+        assert_eq!(super::nix_path(Path::new("configs/foo")), "./configs/foo");
+        assert_eq!(
+            super::nix_path(Path::new("configs/foo bar")),
+            "./. + \"/configs/foo bar\""
+        );
+    }
+}
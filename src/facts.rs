@@ -0,0 +1,97 @@
+//! Facts about the machine, available to templates and computed variables as `facts`, and
+//! environment variables, available to templates as `env`
+
+use crate::environment::Environment;
+
+/// Name of the variable that contains the facts
+pub const VAR: &str = "facts";
+
+/// Name of the variable that contains environment variables
+pub const ENV_VAR: &str = "env";
+
+/// Facts about the machine in the `environment`
+pub fn facts(environment: &Environment) -> toml::Table {
+    let username = environment
+        .var("USER")
+        .or_else(|| environment.var("USERNAME"))
+        .unwrap_or_default();
+
+    toml::Table::from_iter([
+        ("hostname".to_string(), environment.hostname.clone().into()),
+        ("os".to_string(), environment.os.clone().into()),
+        ("family".to_string(), environment.family.clone().into()),
+        ("arch".to_string(), environment.arch.clone().into()),
+        ("username".to_string(), username.into()),
+        ("wsl".to_string(), environment.windows.is_some().into()),
+        ("termux".to_string(), environment.termux.is_some().into()),
+    ])
+}
+
+/// Environment variables of the `environment` which any of the `templates` uses, like
+/// `{{ env.EDITOR }}`
+///
+/// Only the used ones are included, so other variables changing does not render every
+/// template again
+pub fn env<'a>(
+    templates: impl IntoIterator<Item = &'a str>,
+    environment: &Environment,
+) -> toml::Table {
+    let mut env = toml::Table::new();
+
+    for template in templates {
+        for (start, _) in template.match_indices("env.") {
+            let is_word = template[..start]
+                .chars()
+                .next_back()
+                .is_none_or(|ch| !(ch.is_alphanumeric() || ch == '_' || ch == '.'));
+            if !is_word {
+                continue;
+            }
+
+            let rest = &template[start + "env.".len()..];
+            let name = rest
+                .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                .map_or(rest, |end| &rest[..end]);
+            if name.is_empty() || env.contains_key(name) {
+                continue;
+            }
+            if let Some(value) = environment.var(name) {
+                env.insert(name.to_string(), value.into());
+            }
+        }
+    }
+
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env() {
+        let environment = Environment {
+            vars: Some(
+                [("EDITOR", "hx"), ("TERM", "kitty"), ("HOME", "/home/me")]
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .into_iter()
+                    .collect(),
+            ),
+            ..Environment::new("/home/me")
+        };
+
+        assert_eq!(
+            super::env(
+                [
+                    "editor = {{ env.EDITOR }}",
+                    "{% if env.TERM == 'kitty' %}{{ my_env.HOME }}{{ env.UNSET }}{% endif %}",
+                ],
+                &environment
+            ),
+            toml::toml! {
+                EDITOR = "hx"
+                TERM = "kitty"
+            }
+        );
+    }
+}
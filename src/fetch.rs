@@ -0,0 +1,330 @@
+//! Contains [`Limiter`] and [`get`]
+//!
+//! Big repos fetch many links from the same host, e.g. `raw.githubusercontent.com`,
+//! which answers with `429 Too Many Requests` when they are all fetched at once. The links
+//! are fetched through a single [`Limiter`], which limits how often and how many requests
+//! are made, along with fonts, themes and webhooks. Every fetch waits as long as the server asks for before retrying
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use eyre::{Context as _, Result, bail, eyre};
+use serde::Deserialize;
+use simply_colored::*;
+
+use crate::config::Config;
+
+/// Longest that a server can make `dots` wait before retrying
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// Limits of fetching links, in the `[fetch]` section of the config
+#[derive(Deserialize, Debug, Clone)]
+pub struct Fetch {
+    /// Requests per second across every host, unlimited by default
+    pub requests_per_second: Option<f64>,
+    /// Requests across every host which are made at once
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// Requests to the same host which are made at once
+    #[serde(default = "default_per_host")]
+    pub per_host: usize,
+    /// Times a request is retried after `429 Too Many Requests` or `503 Service Unavailable`
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+impl Default for Fetch {
+    fn default() -> Self {
+        Self {
+            requests_per_second: None,
+            concurrency: default_concurrency(),
+            per_host: default_per_host(),
+            retries: default_retries(),
+        }
+    }
+}
+
+/// Default value for [`Fetch::concurrency`]
+const fn default_concurrency() -> usize {
+    16
+}
+
+/// Default value for [`Fetch::per_host`]
+const fn default_per_host() -> usize {
+    4
+}
+
+/// Default value for [`Fetch::retries`]
+const fn default_retries() -> u32 {
+    3
+}
+
+/// Enforces the limits of [`Fetch`] on every request that is made through it, which can
+/// be made from multiple threads at once
+#[derive(Debug, Default)]
+pub struct Limiter {
+    /// Limits to enforce
+    fetch: Fetch,
+    /// Earliest time the next request can be made
+    next: Mutex<Option<Instant>>,
+    /// Number of requests in flight to each host
+    in_flight: Mutex<HashMap<String, usize>>,
+    /// Notified when a request finishes
+    finished: Condvar,
+}
+
+/// A request in flight to `host`, which lets the next one start when dropped
+struct Slot<'a> {
+    /// Limiter that the request counts towards
+    limiter: &'a Limiter,
+    /// Host of the request
+    host: &'a str,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        if let Some(count) = lock(&self.limiter.in_flight).get_mut(self.host) {
+            *count -= 1;
+        }
+        self.limiter.finished.notify_all();
+    }
+}
+
+/// Lock the `mutex`, even if another thread panicked while holding it
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Limiter {
+    /// Limit every request made through it by `fetch`
+    pub fn new(fetch: Fetch) -> Self {
+        Self {
+            fetch,
+            ..Self::default()
+        }
+    }
+
+    /// Limit by the `[fetch]` section of the config closest to `cwd`
+    pub fn discover(cwd: &Path) -> Result<Self> {
+        Ok(Self::new(Config::discover(cwd)?.fetch))
+    }
+
+    /// Requests across every host which are made at once, see [`Fetch::concurrency`]
+    pub fn concurrency(&self) -> usize {
+        self.fetch.concurrency.max(1)
+    }
+
+    /// Wait until fewer than [`Fetch::per_host`] requests are in flight to `host`, and
+    /// fewer than [`Fetch::concurrency`] to every host
+    fn acquire<'a>(&'a self, host: &'a str) -> Slot<'a> {
+        let mut in_flight = lock(&self.in_flight);
+        while in_flight
+            .get(host)
+            .is_some_and(|&count| count >= self.fetch.per_host.max(1))
+            || in_flight.values().sum::<usize>() >= self.concurrency()
+        {
+            in_flight = self
+                .finished
+                .wait(in_flight)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *in_flight.entry(host.to_string()).or_default() += 1;
+
+        Slot {
+            limiter: self,
+            host,
+        }
+    }
+
+    /// Wait until the next request can be made, according to [`Fetch::requests_per_second`]
+    fn throttle(&self) {
+        let Some(requests_per_second) = self.fetch.requests_per_second.filter(|rps| *rps > 0.0)
+        else {
+            return;
+        };
+
+        let now = Instant::now();
+        let start = {
+            let mut next = lock(&self.next);
+            let start = next.map_or(now, |next| next.max(now));
+            *next = Some(start + Duration::from_secs_f64(1.0 / requests_per_second));
+            start
+        };
+        thread::sleep(start - now);
+    }
+
+    /// Fetch the contents at `url`, along with their validators
+    pub fn fetch(&self, url: &str) -> Result<Fetched> {
+        let _slot = self.acquire(host(url));
+
+        let mut attempt = 0;
+        loop {
+            self.throttle();
+
+            log::debug!(target: "network", "fetching {BLUE}{url}{RESET}");
+            let mut response = ureq::get(url)
+                .config()
+                .http_status_as_error(false)
+                .build()
+                .call()
+                .with_context(|| eyre!("failed to fetch {url}"))?;
+            let status = response.status();
+
+            if matches!(status.as_u16(), 429 | 503) && attempt < self.fetch.retries {
+                let wait = response
+                    .headers()
+                    .get("retry-after")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(retry_after)
+                    // without it, wait longer after each attempt
+                    .unwrap_or(Duration::from_secs(1 << attempt.min(8)))
+                    .min(MAX_RETRY_AFTER);
+                log::warn!(target: "network", "{BLUE}{url}{RESET} answered with {status}, retrying in {}s", wait.as_secs());
+
+                thread::sleep(wait);
+                attempt += 1;
+                continue;
+            }
+            if !status.is_success() {
+                bail!("failed to fetch {url}: {status}");
+            }
+
+            let bytes = response
+                .body_mut()
+                .with_config()
+                .limit(u64::MAX)
+                .read_to_vec()
+                .with_context(|| eyre!("failed to fetch {url}"))?;
+            log::debug!(target: "network", "fetched {} bytes from {BLUE}{url}{RESET}", bytes.len());
+
+            let header = |name: &str| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(ToString::to_string)
+            };
+
+            return Ok(Fetched {
+                etag: header("etag"),
+                last_modified: header("last-modified"),
+                bytes,
+            });
+        }
+    }
+
+    /// Send the `json` to `url` with a `POST` request, giving up after `timeout`
+    ///
+    /// The request counts towards the limits, but it is not retried
+    pub fn post(&self, url: &str, json: &str, timeout: Duration) -> Result<()> {
+        let _slot = self.acquire(host(url));
+        self.throttle();
+
+        log::debug!(target: "network", "sending {} bytes to {BLUE}{url}{RESET}", json.len());
+        ureq::post(url)
+            .header("Content-Type", "application/json")
+            .config()
+            .timeout_global(Some(timeout))
+            .build()
+            .send(json)
+            .with_context(|| eyre!("failed to send to {url}"))?;
+
+        Ok(())
+    }
+}
+
+/// Contents at a URL, along with the validators that the server sent for them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fetched {
+    /// Contents of the response
+    pub bytes: Vec<u8>,
+    /// Value of the `ETag` header
+    pub etag: Option<String>,
+    /// Value of the `Last-Modified` header
+    pub last_modified: Option<String>,
+}
+
+/// Fetch the contents at `url` on its own, which is retried but not limited
+pub fn get(url: &str) -> Result<Vec<u8>> {
+    Limiter::default().fetch(url).map(|fetched| fetched.bytes)
+}
+
+/// Host of the `url`, e.g. `raw.githubusercontent.com`
+fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+
+    authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host)
+}
+
+/// How long to wait according to a `Retry-After` header, which is either a number of
+/// seconds or a date
+fn retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = jiff::fmt::rfc2822::parse(value.trim()).ok()?;
+    date.timestamp()
+        .duration_since(jiff::Timestamp::now())
+        .try_into()
+        .ok()
+        .or(Some(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{Response, Server};
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            host("https://raw.githubusercontent.com/owner/repo/main/file"),
+            "raw.githubusercontent.com"
+        );
+        assert_eq!(host("http://me@localhost:8080?x"), "localhost:8080");
+        assert_eq!(retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after("soon"), None);
+    }
+
+    #[test]
+    fn retry() {
+        let server = Server::new([
+            Response::status("429 Too Many Requests").header("Retry-After", "0"),
+            Response::ok("theme"),
+        ]);
+        let url = server.url("/theme");
+
+        let limiter = Limiter::new(Fetch {
+            retries: 1,
+            ..Fetch::default()
+        });
+        assert_eq!(limiter.fetch(&url).unwrap().bytes, b"theme");
+        server.join();
+    }
+
+    #[test]
+    fn timeout() {
+        let server = Server::new([Response::hang()]);
+        let url = server.url("/report");
+
+        let start = Instant::now();
+        assert!(
+            Limiter::default()
+                .post(&url, "{}", Duration::from_millis(200))
+                .is_err()
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+        drop(server.join());
+    }
+}
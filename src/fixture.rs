@@ -0,0 +1,82 @@
+//! Contains [`generate_fixture`]
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use eyre::{Context as _, Result, eyre};
+
+use crate::config::Config;
+use crate::stdx::PathExt as _;
+
+/// Number of `[[dir]]`s in the generated repo
+const DIRS: usize = 10;
+
+/// Number of files in each directory of the generated repo
+const FILES_PER_DIRECTORY: usize = 50;
+
+/// Generate a synthetic dotfiles repo with `files` files in `dir`, for benchmarking
+///
+/// Files are spread over several `[[dir]]`s and nested directories, every third file
+/// is a template and every tenth file has a marker. Everything is written into `{temp_dir}`.
+pub fn generate_fixture(dir: &Path, files: usize) -> Result<()> {
+    let mut config = String::new();
+    for entry in 0..DIRS {
+        writeln!(
+            config,
+            "[[dir]]\ninput = \"dir{entry}\"\noutput = \"{{temp_dir}}/dots-bench/dir{entry}\"\n"
+        )?;
+    }
+
+    let path = dir.join(Config::FILE_NAME);
+    fs::create_dir_all(dir).with_context(|| eyre!("failed to create {}", dir.show()))?;
+    fs::write(&path, config).with_context(|| eyre!("failed to write {}", path.show()))?;
+
+    for file in 0..files {
+        let path = dir
+            .join(format!("dir{}", file % DIRS))
+            .join(format!("nested{}", file / DIRS / FILES_PER_DIRECTORY))
+            .join(format!("file{file}.conf"));
+
+        let mut contents = String::new();
+        if file % 10 == 0 {
+            writeln!(
+                contents,
+                "# @dots --path '{{temp_dir}}/dots-bench/marked/file{file}.conf'"
+            )?;
+        }
+        for line in 0..20 {
+            if file % 3 == 0 {
+                writeln!(
+                    contents,
+                    "key{line} = \"{{{{#if true}}}}{line}{{{{/if}}}}\""
+                )?;
+            } else {
+                writeln!(contents, "key{line} = \"{line}\"")?;
+            }
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| eyre!("failed to create {}", parent.show()))?;
+        }
+        fs::write(&path, contents).with_context(|| eyre!("failed to write {}", path.show()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::World;
+
+    #[test]
+    fn generate() {
+        let dir = tempfile::tempdir().unwrap();
+
+        super::generate_fixture(dir.path(), 100).unwrap();
+
+        let analysis = World::new(dir.path()).unwrap().process().unwrap();
+        assert_eq!(analysis.writes().count(), 100);
+    }
+}
@@ -0,0 +1,179 @@
+//! Contains [`FlatpakOverride`]
+//!
+//! Flatpak apps are sandboxed, and their permissions are changed with
+//! `flatpak override` instead of in any config file. Each `[[flatpak_override]]`
+//! declares the permissions of one app, and they are re-applied whenever they drift
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::process::Command;
+
+use eyre::{Context as _, Result, bail};
+use serde::Deserialize;
+
+/// Permissions of a single app, in the `[[flatpak_override]]` section of the config
+#[derive(Deserialize, Debug)]
+pub struct FlatpakOverride {
+    /// ID of the app, e.g. `org.mozilla.firefox`
+    pub app: String,
+    /// Filesystems that the app can access, e.g. `~/.themes:ro` or `!home`
+    #[serde(default)]
+    pub filesystems: BTreeSet<String>,
+    /// Environment variables of the app
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+/// Permissions that a user override grants
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Grants {
+    /// Filesystems that the app can access
+    pub filesystems: BTreeSet<String>,
+    /// Environment variables of the app
+    pub env: BTreeMap<String, String>,
+}
+
+/// Permissions of a `[[flatpak_override]]`, along with what they currently are
+#[derive(Debug)]
+pub struct FlatpakPermissions {
+    /// ID of the app
+    pub app: String,
+    /// Permissions in the repo
+    pub permissions: Grants,
+    /// Current user override of the app, or `None` if it could not be read
+    pub current: Option<Grants>,
+}
+
+impl FlatpakOverride {
+    /// Read what the override of the app currently is
+    pub fn read(&self) -> Result<FlatpakPermissions> {
+        if self.app.split('.').count() < 3 {
+            bail!(
+                "flatpak app `{}` must be an ID like `org.mozilla.firefox`",
+                self.app
+            );
+        }
+
+        Ok(FlatpakPermissions {
+            current: show(&self.app).ok(),
+            app: self.app.clone(),
+            permissions: Grants {
+                filesystems: self.filesystems.clone(),
+                env: self.env.clone(),
+            },
+        })
+    }
+}
+
+impl FlatpakPermissions {
+    /// Whether the override of the app is exactly the one in the repo
+    ///
+    /// Permissions that were granted outside of `dots`, e.g. with Flatseal, are drift
+    pub fn is_applied(&self) -> bool {
+        self.current.as_ref() == Some(&self.permissions)
+    }
+}
+
+/// Parse the keyfile printed by `flatpak override --show`
+fn parse(keyfile: &str) -> Grants {
+    let mut permissions = Grants::default();
+    let mut section = "";
+
+    for line in keyfile.lines().map(str::trim) {
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = name;
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        match (section, key) {
+            ("Context", "filesystems") => permissions.filesystems.extend(
+                value
+                    .split(';')
+                    .filter(|filesystem| !filesystem.is_empty())
+                    .map(str::to_string),
+            ),
+            ("Environment", key) => {
+                permissions.env.insert(key.to_string(), value.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    permissions
+}
+
+/// Run `flatpak override --user` with `args`
+fn flatpak_override<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<String> {
+    let args = ["override", "--user"]
+        .into_iter()
+        .chain(args)
+        .collect::<Vec<_>>();
+    let output = Command::new("flatpak")
+        .args(&args)
+        .output()
+        .context("failed to run `flatpak override`")?;
+
+    if !output.status.success() {
+        bail!(
+            "`flatpak {}` failed with {}: {}",
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Current user override of `app`
+fn show(app: &str) -> Result<Grants> {
+    flatpak_override(["--show", app]).map(|keyfile| parse(&keyfile))
+}
+
+/// Replace the user override of `app` with exactly `filesystems` and `env`
+pub fn apply(app: &str, filesystems: &[String], env: &BTreeMap<String, String>) -> Result<()> {
+    flatpak_override(["--reset", app])?;
+    if filesystems.is_empty() && env.is_empty() {
+        return Ok(());
+    }
+
+    let args = filesystems
+        .iter()
+        .map(|filesystem| format!("--filesystem={filesystem}"))
+        .chain(
+            env.iter()
+                .map(|(key, value)| format!("--env={key}={value}")),
+        )
+        .collect::<Vec<_>>();
+    flatpak_override(args.iter().map(String::as_str).chain([app]))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let permissions = super::parse(
+            "[Context]\nfilesystems=~/.themes:ro;xdg-config/gtk-3.0:ro;\n\n[Environment]\nGTK_THEME=Adwaita-dark\n",
+        );
+        assert_eq!(
+            permissions,
+            Grants {
+                filesystems: BTreeSet::from([
+                    "~/.themes:ro".to_string(),
+                    "xdg-config/gtk-3.0:ro".to_string()
+                ]),
+                env: BTreeMap::from([("GTK_THEME".to_string(), "Adwaita-dark".to_string())]),
+            }
+        );
+        assert_eq!(super::parse(""), Grants::default());
+    }
+}
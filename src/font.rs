@@ -0,0 +1,211 @@
+//! Contains [`Font`]
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use eyre::{Context as _, ContextCompat as _, Result, bail, eyre};
+use serde::Deserialize;
+use tap::Pipe as _;
+
+use crate::asset::Asset;
+use crate::fetch::Limiter;
+use crate::output_path::OutputPath;
+use crate::stdx::PathExt as _;
+
+/// Directory that fonts of the user are installed into, on Linux and other platforms
+/// which follow the XDG base directory specification
+#[cfg(not(any(target_os = "macos", windows)))]
+const FONT_DIR: &str = "{data_dir}/fonts";
+
+/// Directory that fonts of the user are installed into, on macOS
+#[cfg(target_os = "macos")]
+const FONT_DIR: &str = "~/Library/Fonts";
+
+/// Directory that fonts of the user are installed into, on Windows, where `{cache_dir}`
+/// is `%LOCALAPPDATA%`
+#[cfg(windows)]
+const FONT_DIR: &str = "{cache_dir}/Microsoft/Windows/Fonts";
+
+/// Extensions of font files, which are installed from archives
+const EXTENSIONS: &[&str] = &["ttf", "otf", "ttc"];
+
+/// A font to install, e.g. a Nerd Font
+#[derive(Deserialize, Debug)]
+pub struct Font {
+    /// URL of a font file or a `.zip` of font files
+    pub url: Option<String>,
+    /// Font file or a `.zip` of font files in the repo, relative to the config file
+    pub path: Option<PathBuf>,
+    /// Expected hash of the file downloaded from `url`
+    pub sha256: Option<String>,
+}
+
+/// Directory that fonts are installed into on this platform
+pub fn font_dir() -> Result<PathBuf> {
+    FONT_DIR
+        .parse::<OutputPath>()
+        .context("failed to obtain the font directory")?
+        .into_inner()
+        .pipe(Ok)
+}
+
+impl Font {
+    /// Every font file to install
+    ///
+    /// Downloads and archives are kept in `cache_dir`, so they are only downloaded
+    /// and extracted once
+    pub fn files(&self, root: &Path, cache_dir: &Path, limiter: &Limiter) -> Result<Vec<PathBuf>> {
+        let source = match (&self.path, &self.url) {
+            (Some(path), None) => root.join(path),
+            (None, Some(url)) => self.download(url, cache_dir, limiter)?,
+            _ => bail!("`[[font]]` must have exactly one of `path` and `url`"),
+        };
+
+        if source.extension().is_some_and(|ext| ext == "zip") {
+            let hash = Asset::hash_file(&source)
+                .with_context(|| eyre!("failed to read {}", source.show()))?;
+            extract(&source, &cache_dir.join(hash))
+        } else {
+            Ok(vec![source])
+        }
+    }
+
+    /// Download the font at `url` into `cache_dir` through the `limiter`, unless it was
+    /// downloaded before
+    fn download(&self, url: &str, cache_dir: &Path, limiter: &Limiter) -> Result<PathBuf> {
+        let name = url
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .with_context(|| eyre!("font URL {url} does not end with a file name"))?;
+        let path = cache_dir
+            .join(sha256::digest(url))
+            .join(name.split(['?', '#']).next().unwrap_or(name));
+
+        if path.exists() {
+            return Ok(path);
+        }
+
+        let bytes = limiter
+            .fetch(url)
+            .map(|fetched| fetched.bytes)
+            .with_context(|| eyre!("failed to fetch font {url}"))?;
+
+        let actual_sha256 = sha256::digest(&bytes);
+        if let Some(expected_sha256) = &self.sha256
+            && actual_sha256 != *expected_sha256
+        {
+            bail!(
+                "hash mismatch of font {url}\n  actual     {actual_sha256}\n  expected   {expected_sha256}"
+            );
+        }
+
+        let parent = path.parent().expect("it is inside of `cache_dir`");
+        fs::create_dir_all(parent)
+            .with_context(|| eyre!("failed to create directory {}", parent.show()))?;
+        fs::write(&path, bytes).with_context(|| eyre!("failed to write {}", path.show()))?;
+
+        Ok(path)
+    }
+}
+
+/// Extract every font file in the `.zip` at `archive` into `dir`, ignoring the
+/// directories they are in
+fn extract(archive: &Path, dir: &Path) -> Result<Vec<PathBuf>> {
+    let context = || eyre!("failed to extract fonts from {}", archive.show());
+
+    let mut zip = fs::File::open(archive)
+        .map_err(eyre::Error::from)
+        .and_then(|file| Ok(zip::ZipArchive::new(file)?))
+        .with_context(context)?;
+
+    fs::create_dir_all(dir).with_context(context)?;
+
+    let mut fonts = vec![];
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).with_context(context)?;
+        let Some(name) = entry
+            .enclosed_name()
+            .and_then(|path| Some(path.file_name()?.to_os_string()))
+        else {
+            continue;
+        };
+        let path = dir.join(name);
+
+        let is_font = path
+            .extension()
+            .is_some_and(|ext| EXTENSIONS.iter().any(|font| ext.eq_ignore_ascii_case(font)));
+        if !entry.is_file() || !is_font {
+            continue;
+        }
+
+        if !path.exists() {
+            io::copy(
+                &mut entry,
+                &mut fs::File::create(&path).with_context(context)?,
+            )
+            .with_context(context)?;
+        }
+        fonts.push(path);
+    }
+
+    if fonts.is_empty() {
+        bail!("{} does not contain any fonts", archive.show());
+    }
+
+    Ok(fonts)
+}
+
+/// Refresh the font cache with `fc-cache`, so apps see the fonts without logging out
+///
+/// Failing to do so is not an error, the fonts are still installed
+pub fn refresh_fonts(dir: &Path) {
+    match Command::new("fc-cache").arg("-f").arg(dir).status() {
+        Ok(status) if status.success() => {
+            log::info!(target: "apply", "refreshed the font cache");
+        }
+        Ok(status) => log::warn!("`fc-cache` failed with {status}"),
+        Err(err) => log::warn!("failed to run `fc-cache`: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write as _;
+
+    use super::*;
+
+    #[test]
+    fn extract() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let archive = dir.join("fonts.zip");
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+        for (name, contents) in [
+            ("FiraCode/FiraCode-Regular.ttf", "regular"),
+            ("FiraCode/FiraCode-Bold.OTF", "bold"),
+            ("README.md", "readme"),
+        ] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let extracted = dir.join("extracted");
+        let mut fonts = super::extract(&archive, &extracted).unwrap();
+        fonts.sort();
+
+        assert_eq!(
+            fonts,
+            [
+                extracted.join("FiraCode-Bold.OTF"),
+                extracted.join("FiraCode-Regular.ttf")
+            ]
+        );
+        assert_eq!(fs::read_to_string(&fonts[1]).unwrap(), "regular");
+    }
+}
@@ -0,0 +1,432 @@
+//! Contains [`format`]
+//!
+//! `dots fmt` normalizes `dots.toml`, so large repos stay consistent: tables are grouped
+//! in the same order, the keys of every table are in the same order, and `[[package]]`s
+//! are sorted like a `Brewfile`. Comments move along with the line below them
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use eyre::{Context as _, Result, bail, eyre};
+use toml_edit::{Decor, DocumentMut, Item, RawString, Table};
+
+use crate::config::Config;
+use crate::packages::{Kind, Package};
+use crate::stdx::PathExt as _;
+
+/// Tables in the order they are written, unknown ones come after them
+const TABLES: &[&str] = &[
+    "link",
+    "dir",
+    "dconf",
+    "defaults",
+    "flatpak_override",
+    "mimeapps",
+    "git",
+    "ssh",
+    "autostart",
+    "udev_rule",
+    "sysctl",
+    "timer",
+    "shell",
+    "env",
+    "tool",
+    "package",
+    "font",
+    "vars",
+    "theme",
+    "notify",
+    "report",
+    "fetch",
+    "diff",
+    "apps",
+    "profiles",
+    "ignore",
+];
+
+/// Keys which come first in every table, in this order. The other keys are sorted
+const LEADING_KEYS: &[&str] = &["version", "input", "output", "url", "path", "name", "kind"];
+
+/// Tables of the config which are written together, e.g. a `[[dir]]` along with its
+/// `[dir.output]`
+#[derive(Debug)]
+struct Group {
+    /// Order of the group among the others
+    order: (usize, Option<(Kind, String)>),
+    /// Positions of the headers of the tables in the group, in the order they were written
+    positions: Vec<isize>,
+}
+
+/// Comment lines of `decor`, without the empty lines between them
+fn comments(decor: &Decor) -> String {
+    decor
+        .prefix()
+        .and_then(RawString::as_str)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| line.trim_start().starts_with('#'))
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+/// Sort the keys of `table` and keep only the comments above each of them
+fn sort_keys(table: &mut Table) {
+    let rank = |key: &str| {
+        (
+            LEADING_KEYS
+                .iter()
+                .position(|leading| *leading == key)
+                .unwrap_or(LEADING_KEYS.len()),
+            key.to_string(),
+        )
+    };
+    table.sort_values_by(|key, _, other, _| rank(key.get()).cmp(&rank(other.get())));
+
+    for (mut key, item) in table.iter_mut() {
+        if item.is_value() {
+            let comments = comments(key.leaf_decor());
+            key.leaf_decor_mut().set_prefix(comments);
+        }
+    }
+}
+
+/// Positions of the headers of `table` and every table inside of it
+fn positions(table: &Table, positions: &mut Vec<isize>) {
+    if !table.is_dotted()
+        && let Some(position) = table.position()
+    {
+        positions.push(position);
+    }
+
+    for (_, item) in table.iter() {
+        match item {
+            Item::Table(table) => self::positions(table, positions),
+            Item::ArrayOfTables(tables) => {
+                for table in tables.iter() {
+                    self::positions(table, positions);
+                }
+            }
+            Item::None | Item::Value(_) => {}
+        }
+    }
+}
+
+/// Order of the group of the top-level table at `key`, whose first table is `table`
+fn order(key: &str, table: &Table) -> (usize, Option<(Kind, String)>) {
+    let rank = TABLES
+        .iter()
+        .position(|name| *name == key)
+        .unwrap_or(TABLES.len());
+    // `[[package]]`s are in the order of a `Brewfile`
+    let package = (key == "package")
+        .then(|| toml::from_str::<Package>(&DocumentMut::from(table.clone()).to_string()).ok())
+        .flatten()
+        .map(|package| (package.kind, package.name));
+
+    (rank, package)
+}
+
+/// Every group of tables in `root`, in the order they are written
+fn groups(root: &Table) -> Vec<Group> {
+    let mut groups = vec![];
+
+    for (key, item) in root.iter() {
+        let tables = match item {
+            Item::Table(table) => vec![table],
+            Item::ArrayOfTables(tables) => tables.iter().collect(),
+            Item::None | Item::Value(_) => continue,
+        };
+        for table in tables {
+            let mut positions = vec![];
+            self::positions(table, &mut positions);
+            positions.sort_unstable();
+            if !positions.is_empty() {
+                groups.push(Group {
+                    order: order(key, table),
+                    positions,
+                });
+            }
+        }
+    }
+    groups.sort_by_key(|group| group.positions[0]);
+    groups.sort_by(|group, other| group.order.cmp(&other.order));
+
+    groups
+}
+
+/// Move the tables of `table` to their `positions`, sort their keys, and separate each of
+/// them from the one before it by an empty line
+fn reorder(table: &mut Table, positions: &BTreeMap<isize, isize>) {
+    if !table.is_dotted()
+        && let Some(position) = table.position()
+    {
+        table.set_position(positions.get(&position).copied());
+        let comments = comments(table.decor());
+        table.decor_mut().set_prefix(format!("\n{comments}"));
+    }
+    sort_keys(table);
+
+    for (_, item) in table.iter_mut() {
+        match item {
+            Item::Table(table) => reorder(table, positions),
+            Item::ArrayOfTables(tables) => {
+                for table in tables.iter_mut() {
+                    reorder(table, positions);
+                }
+            }
+            Item::None | Item::Value(_) => {}
+        }
+    }
+}
+
+/// Table of `table`, or of any table inside of it, whose header is at `position`
+fn header(table: &mut Table, position: isize) -> Option<&mut Table> {
+    if !table.is_dotted() && table.position() == Some(position) {
+        return Some(table);
+    }
+
+    table.iter_mut().find_map(|(_, item)| match item {
+        Item::Table(table) => header(table, position),
+        Item::ArrayOfTables(tables) => tables.iter_mut().find_map(|table| header(table, position)),
+        Item::None | Item::Value(_) => None,
+    })
+}
+
+/// Take the comments at the start of the config out of the `decor` of its first line, if
+/// they are separated from the rest by an empty line, as they describe the whole config
+fn take_prologue(decor: &mut Decor) -> String {
+    let prefix = decor
+        .prefix()
+        .and_then(RawString::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let lines = prefix.lines().collect::<Vec<_>>();
+    let comments = lines
+        .iter()
+        .take_while(|line| line.trim_start().starts_with('#'))
+        .count();
+    if comments == 0
+        || lines
+            .get(comments)
+            .is_none_or(|line| !line.trim().is_empty())
+    {
+        return String::new();
+    }
+
+    decor.set_prefix(lines[comments..].join("\n"));
+    lines[..comments]
+        .iter()
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+/// Take the comments at the start of the config out of `root`
+fn prologue(root: &mut Table) -> String {
+    if let Some((mut key, _)) = root.iter_mut().find(|(_, item)| item.is_value()) {
+        return take_prologue(key.leaf_decor_mut());
+    }
+
+    let first = groups(root)
+        .iter()
+        .flat_map(|group| group.positions.first())
+        .min()
+        .copied();
+    first
+        .and_then(|position| header(root, position))
+        .map_or_else(String::new, |table| take_prologue(table.decor_mut()))
+}
+
+/// Normalize the config with `contents`
+///
+/// Fails if the formatted config would mean something else, which is always a bug
+pub fn format(contents: &str) -> Result<String> {
+    let mut document = contents
+        .parse::<DocumentMut>()
+        .context("failed to parse config file")?;
+    let prologue = prologue(document.as_table_mut());
+
+    // tables inside of another table, e.g. `[dir.output]` of a `[[dir]]`, stay with it
+    let positions = groups(document.as_table())
+        .into_iter()
+        .flat_map(|group| group.positions)
+        .zip(1..)
+        .collect::<BTreeMap<_, _>>();
+    reorder(document.as_table_mut(), &positions);
+
+    let epilogue = comments(&Decor::new(document.trailing().clone(), ""));
+    document.set_trailing(if epilogue.is_empty() {
+        String::new()
+    } else {
+        format!("\n{epilogue}")
+    });
+
+    // every part is separated from the one before it by an empty line
+    let body = document.to_string();
+    let body = body.trim_start_matches('\n').trim_end();
+    let formatted = match (prologue.is_empty(), body.is_empty()) {
+        (_, true) => prologue,
+        (true, false) => format!("{body}\n"),
+        (false, false) => format!("{prologue}\n{body}\n"),
+    };
+
+    if normalize(contents).context("failed to parse config file")?
+        != normalize(&formatted).context("failed to format the config, it would be invalid")?
+    {
+        bail!("failed to format the config, it would mean something else");
+    }
+
+    Ok(formatted)
+}
+
+/// Parse the config with `contents`, with its `[[package]]`s in a fixed order
+fn normalize(contents: &str) -> Result<toml::Table> {
+    let mut config = toml::from_str::<toml::Table>(contents)?;
+    if let Some(toml::Value::Array(packages)) = config.get_mut("package") {
+        packages.sort_by_cached_key(ToString::to_string);
+    }
+
+    Ok(config)
+}
+
+/// Normalize the config closest to `cwd` in place, or only check that it is normalized
+/// with `check`
+///
+/// Returns `true` if the config was not normalized
+pub fn format_file(cwd: &Path, check: bool) -> Result<bool> {
+    let path = Config::editable(&Config::find(cwd)?)?;
+    let contents =
+        fs::read_to_string(&path).with_context(|| eyre!("failed to read {}", path.show()))?;
+
+    let formatted = format(&contents)?;
+    if formatted == contents {
+        return Ok(false);
+    }
+    if !check {
+        fs::write(&path, formatted).with_context(|| eyre!("failed to write {}", path.show()))?;
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn format() {
+        let config = "\
+# my config
+
+strict = true
+version = 1
+
+[fetch]
+per_host = 2
+
+[[package]]
+name = \"ripgrep\"
+# fonts need the tap
+[[package]]
+kind = \"tap\"
+name = \"homebrew/cask-fonts\"
+
+[[dir]]
+# apps that this machine uses
+also = [
+    \"~/.config/evil-helix\",
+    # [[not a table]]
+]
+input = \"helix\"
+
+[dir.output]
+linux = \"~/.config/helix\"
+";
+        let formatted = super::format(config).unwrap();
+
+        assert_eq!(
+            formatted,
+            "\
+# my config
+
+version = 1
+strict = true
+
+[[dir]]
+input = \"helix\"
+# apps that this machine uses
+also = [
+    \"~/.config/evil-helix\",
+    # [[not a table]]
+]
+
+[dir.output]
+linux = \"~/.config/helix\"
+
+# fonts need the tap
+[[package]]
+name = \"homebrew/cask-fonts\"
+kind = \"tap\"
+
+[[package]]
+name = \"ripgrep\"
+
+[fetch]
+per_host = 2
+"
+        );
+        assert_eq!(super::format(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn multiline_strings() {
+        let config = "\
+[[dir]]
+input = \"\"\"
+[[not a table]]
+= not a key
+\"\"\"
+description = 'it\"s fine'
+";
+        assert_eq!(
+            super::format(config).unwrap(),
+            "\
+[[dir]]
+input = \"\"\"
+[[not a table]]
+= not a key
+\"\"\"
+description = 'it\"s fine'
+"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let config = "\
+# my config
+
+[fetch] # per host
+per_host = 2
+
+
+[[dir]]
+input = \"helix\" # the editor
+# the end
+";
+        assert_eq!(
+            super::format(config).unwrap(),
+            "\
+# my config
+
+[[dir]]
+input = \"helix\" # the editor
+
+[fetch] # per host
+per_host = 2
+
+# the end
+"
+        );
+    }
+}
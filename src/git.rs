@@ -0,0 +1,119 @@
+//! Contains [`Git`]
+//!
+//! `~/.gitconfig` is often written by other tools, e.g. `git config --global`, so it is
+//! not managed as a whole. Instead, a managed block in it includes the files written
+//! by `dots`, such as a different identity for work repositories
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use eyre::{Context as _, Result, eyre};
+use serde::Deserialize;
+
+use crate::analysis::{Contents, WritePath};
+use crate::block;
+use crate::output_path::OutputPath;
+use crate::stdx::PathExt as _;
+
+/// Includes of the gitconfig, in the `[git]` section of the config
+#[derive(Deserialize, Debug)]
+pub struct Git {
+    /// Gitconfig which includes the files, `~/.gitconfig` by default
+    pub gitconfig: Option<OutputPath>,
+    /// Files which are always included
+    #[serde(default)]
+    pub include: Vec<OutputPath>,
+    /// Files which are included when their condition holds
+    #[serde(default)]
+    pub include_if: Vec<IncludeIf>,
+}
+
+/// A file which is included only when `condition` holds, e.g. inside of some directory
+#[derive(Deserialize, Debug)]
+pub struct IncludeIf {
+    /// Condition of `[includeIf]`, e.g. `gitdir:~/work/`
+    pub condition: String,
+    /// File to include
+    pub path: OutputPath,
+}
+
+impl Git {
+    /// The gitconfig with the includes in its managed block
+    pub fn gitconfig(&self, durable: bool) -> Result<WritePath> {
+        let path = match &self.gitconfig {
+            Some(gitconfig) => gitconfig.as_ref().clone(),
+            None => "~/.gitconfig".parse::<OutputPath>()?.into_inner(),
+        };
+
+        let current = match fs::read_to_string(&path) {
+            Ok(current) => current,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err).with_context(|| eyre!("failed to read {}", path.show())),
+        };
+        let contents = block::replace(&current, "#", &self.includes())
+            .with_context(|| eyre!("invalid managed block in {}", path.show()))?;
+
+        Ok(WritePath {
+            path,
+            contents: Contents::Text(contents),
+            source: None,
+            durable,
+            description: None,
+            symlink: false,
+            mode: None,
+        })
+    }
+
+    /// Stanzas which include the files
+    fn includes(&self) -> String {
+        let include = self
+            .include
+            .iter()
+            .map(|path| format!("[include]\n\tpath = {}\n", quote(path.as_ref())));
+        let include_if = self.include_if.iter().map(|include| {
+            format!(
+                "[includeIf \"{}\"]\n\tpath = {}\n",
+                include.condition.replace('\\', "\\\\").replace('"', "\\\""),
+                quote(include.path.as_ref())
+            )
+        });
+
+        include.chain(include_if).collect()
+    }
+}
+
+/// Quote `path` as a value of a gitconfig
+fn quote(path: &Path) -> String {
+    format!(
+        "\"{}\"",
+        path.to_string_lossy()
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes() {
+        let git = Git {
+            gitconfig: None,
+            include: vec![OutputPath::new("/home/me/.config/git/common".into())],
+            include_if: vec![IncludeIf {
+                condition: "gitdir:~/work/".to_string(),
+                path: OutputPath::new("/home/me/.config/git/work".into()),
+            }],
+        };
+
+        assert_eq!(
+            git.includes(),
+            "[include]\n\
+             \tpath = \"/home/me/.config/git/common\"\n\
+             [includeIf \"gitdir:~/work/\"]\n\
+             \tpath = \"/home/me/.config/git/work\"\n"
+        );
+    }
+}
@@ -0,0 +1,104 @@
+//! Contains [`Ignore`]
+//!
+//! Input directories often contain files which are not meant to be written, such as
+//! `README.md`s, editor swap files or a nested `.git`. They are ignored with patterns
+//! that have the syntax of `.gitignore`, in the `ignore` of each `[[dir]]` and in `[ignore]`
+
+use std::path::Path;
+
+use eyre::{Context as _, Result, eyre};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::Deserialize;
+
+use crate::stdx::PathExt as _;
+
+/// Files inside of the `input` of every `[[dir]]` which are never written, in `[ignore]`
+///
+/// ```toml
+/// [ignore]
+/// patterns = ["*.md", ".git/"]
+/// gitignore = true
+/// ```
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Ignore {
+    /// Patterns with the syntax of `.gitignore`, relative to the `input` of each `[[dir]]`
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Also ignore what the `.gitignore` at the root of each `input` ignores
+    #[serde(default)]
+    pub gitignore: bool,
+}
+
+impl Ignore {
+    /// Files of the `input` directory which are ignored, by `[ignore]` along with the
+    /// `patterns` of its `[[dir]]`
+    pub fn matcher(&self, input: &Path, patterns: &[String]) -> Result<Ignored> {
+        let mut builder = GitignoreBuilder::new(input);
+        for pattern in self.patterns.iter().chain(patterns) {
+            builder
+                .add_line(None, pattern)
+                .with_context(|| eyre!("invalid pattern in `ignore` of {}", input.show()))?;
+        }
+        if self.gitignore
+            && let Some(err) = builder.add(input.join(".gitignore"))
+            && !matches!(err.io_error(), Some(err) if err.kind() == std::io::ErrorKind::NotFound)
+        {
+            return Err(err).with_context(|| eyre!("invalid .gitignore in {}", input.show()));
+        }
+
+        builder
+            .build()
+            .with_context(|| eyre!("failed to compile `ignore` of {}", input.show()))
+            .map(Ignored)
+    }
+}
+
+/// Files of a single `input` directory which are never written
+#[derive(Debug, Clone)]
+pub struct Ignored(Gitignore);
+
+impl Ignored {
+    /// Whether `path` inside of the `input` directory is ignored, or is inside of a
+    /// directory that is
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        path.strip_prefix(self.0.path()).is_ok_and(|relative| {
+            !relative.as_os_str().is_empty()
+                && self
+                    .0
+                    .matched_path_or_any_parents(relative, is_dir)
+                    .is_ignore()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path();
+        std::fs::write(input.join(".gitignore"), "*.swp\n").unwrap();
+
+        let ignore = Ignore {
+            patterns: vec!["*.md".to_string(), ".git/".to_string()],
+            gitignore: true,
+        };
+        let ignored = ignore.matcher(input, &["!keep.md".to_string()]).unwrap();
+
+        assert!(ignored.is_ignored(&input.join("README.md"), false));
+        assert!(ignored.is_ignored(&input.join("nested/README.md"), false));
+        assert!(ignored.is_ignored(&input.join(".git"), true));
+        assert!(ignored.is_ignored(&input.join(".git/config"), false));
+        assert!(ignored.is_ignored(&input.join(".config.toml.swp"), false));
+        assert!(!ignored.is_ignored(&input.join("keep.md"), false));
+        assert!(!ignored.is_ignored(&input.join("config.toml"), false));
+        assert!(!ignored.is_ignored(input, true));
+
+        // `.gitignore` is optional
+        let ignored = ignore.matcher(&input.join("nested"), &[]).unwrap();
+        assert!(!ignored.is_ignored(&input.join("nested/a.swp"), false));
+    }
+}
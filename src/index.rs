@@ -0,0 +1,237 @@
+//! Contains [`ApplyIndex`]
+//!
+//! The index is the manifest of every file that `dots` wrote on this machine, kept in
+//! `{data_dir}/dots/state.json`. It is the only record of which files `dots` manages, and
+//! the [`Journal`](crate::journal::Journal) reads them from it
+//!
+//! The index is only consulted when applying, after every file was rendered: a file whose
+//! rendered contents are what the last apply wrote to it, and which was not modified since
+//! then, is neither backed up, nor compared, nor written again. Rendering itself is skipped
+//! by the [`RenderCache`](crate::cache::RenderCache), since [`World::process`] does
+//! almost no IO
+//!
+//! [`World::process`]: crate::world::World::process
+//!
+//! Files which the repo no longer produces, e.g. because their source or their `[[link]]`
+//! was removed, stay in the index so they can be [pruned](ApplyIndex::prune) instead of
+//! staying on disk forever
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use eyre::{Context as _, Result, eyre};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::stdx::PathExt as _;
+
+/// What `dots` last wrote to each file, so files which have not changed
+/// since then are not touched again
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct ApplyIndex {
+    /// Every written file, keyed by its path
+    #[serde(default)]
+    pub outputs: BTreeMap<PathBuf, Indexed>,
+}
+
+/// A single file written by the last apply
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Indexed {
+    /// Hash of the contents that were written
+    pub hash: String,
+    /// Size of the file after it was written, in bytes
+    pub len: u64,
+    /// When the file was last modified after it was written
+    pub modified: Timestamp,
+    /// `dots` only manages a part of the file, e.g. a managed block, so it is never pruned
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+}
+
+impl ApplyIndex {
+    /// Name of the index file, inside of the journal directory
+    const FILE_NAME: &str = "state.json";
+
+    /// Load the index of the last apply
+    ///
+    /// An index which can not be parsed is discarded, which makes the next apply write every file
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(Self::FILE_NAME);
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(serde_json::from_str(&contents).unwrap_or_else(|err| {
+                log::debug!(target: "apply", "discarding index {}: {err}", path.show());
+                Self::default()
+            })),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| eyre!("failed to read {}", path.show())),
+        }
+    }
+
+    /// Write the index to disk
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(Self::FILE_NAME);
+        let tmp = path.with_extension("json.tmp");
+        let contents = serde_json::to_string_pretty(self).context("failed to serialize index")?;
+
+        // a half-written index would forget which files `dots` manages
+        fs::write(&tmp, contents).with_context(|| eyre!("failed to write {}", tmp.show()))?;
+        fs::rename(&tmp, &path).with_context(|| eyre!("failed to write {}", path.show()))
+    }
+
+    /// Whether contents with the `hash` were already written to `path` by an earlier apply,
+    /// and nothing modified `path` since then, so it does not have to be written again
+    ///
+    /// Only the metadata of `path` is read, not its contents
+    pub fn is_written(&self, path: &Path, hash: &str) -> bool {
+        self.outputs.get(path).is_some_and(|indexed| {
+            indexed.hash == hash && stamp(path) == Some((indexed.len, indexed.modified))
+        })
+    }
+
+    /// Whether `path` was modified after an earlier apply wrote it
+    ///
+    /// Files which are not in the index are not known to be modified
+    pub fn is_modified(&self, path: &Path) -> bool {
+        self.outputs
+            .get(path)
+            .is_some_and(|indexed| stamp(path) != Some((indexed.len, indexed.modified)))
+    }
+
+    /// Remember that contents with the `hash` were just written to `path`
+    pub fn record(&mut self, path: &Path, hash: String) {
+        if let Some((len, modified)) = stamp(path) {
+            self.outputs.insert(
+                path.to_path_buf(),
+                Indexed {
+                    hash,
+                    len,
+                    modified,
+                    partial: false,
+                },
+            );
+        }
+    }
+
+    /// Files written by earlier applies which still exist, but are not in `produced`,
+    /// the files that the repo produces now
+    ///
+    /// Files that `dots` only manages a part of are not included, since they are never removed
+    pub fn orphans(&self, produced: &BTreeSet<PathBuf>) -> Vec<&Path> {
+        self.outputs
+            .iter()
+            .filter(|(path, indexed)| {
+                !indexed.partial && !produced.contains(*path) && fs::symlink_metadata(path).is_ok()
+            })
+            .map(|(path, _)| path.as_path())
+            .collect()
+    }
+
+    /// Remove the files which are not in `produced` and forget them
+    ///
+    /// Files that were modified after `dots` wrote them, and files that `dots` only manages
+    /// a part of, are kept on disk
+    ///
+    /// Returns the removed files
+    pub fn prune(&mut self, produced: &BTreeSet<PathBuf>) -> Result<Vec<PathBuf>> {
+        let (kept, orphans) = mem::take(&mut self.outputs)
+            .into_iter()
+            .partition::<BTreeMap<_, _>, _>(|(path, _)| produced.contains(path));
+        self.outputs = kept;
+
+        let mut removed = vec![];
+        for (path, indexed) in orphans {
+            if fs::symlink_metadata(&path).is_err() {
+                continue;
+            }
+
+            if indexed.partial {
+                log::warn!("kept {}, `dots` only manages a part of it", path.show());
+                continue;
+            }
+
+            if stamp(&path) != Some((indexed.len, indexed.modified)) {
+                log::warn!(
+                    "kept {}, it was modified after `dots` wrote it",
+                    path.show()
+                );
+                continue;
+            }
+
+            fs::remove_file(&path).with_context(|| eyre!("failed to remove {}", path.show()))?;
+            log::info!("removed {}", path.show());
+            removed.push(path);
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Size and modification time of `path` as it exists right now
+fn stamp(path: &Path) -> Option<(u64, Timestamp)> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+
+    Some((metadata.len(), metadata.modified().ok()?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let file = dir.join("foo.txt");
+
+        let mut index = ApplyIndex::default();
+        assert!(!index.is_written(&file, "foo"));
+
+        fs::write(&file, "foo").unwrap();
+        index.record(&file, "foo".to_string());
+        index.save(dir).unwrap();
+
+        let index = ApplyIndex::load(dir).unwrap();
+        assert!(index.is_written(&file, "foo"));
+        // the repo changed
+        assert!(!index.is_written(&file, "bar"));
+
+        // something else modified the file
+        fs::write(&file, "changed").unwrap();
+        assert!(!index.is_written(&file, "foo"));
+    }
+
+    #[test]
+    fn prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let [kept, removed, edited, partial] =
+            ["kept", "removed", "edited", "partial"].map(|name| dir.join(name));
+
+        let mut index = ApplyIndex::default();
+        for path in [&kept, &removed, &edited, &partial] {
+            fs::write(path, "written").unwrap();
+            index.record(path, sha256::digest("written"));
+        }
+        index.outputs.get_mut(&partial).unwrap().partial = true;
+        index.save(dir).unwrap();
+        let mut index = ApplyIndex::load(dir).unwrap();
+        fs::write(&edited, "edited by hand").unwrap();
+
+        let produced = BTreeSet::from([kept.clone()]);
+        assert_eq!(
+            index.orphans(&produced),
+            [edited.as_path(), removed.as_path()]
+        );
+        assert_eq!(index.prune(&produced).unwrap(), [removed.as_path()]);
+
+        assert!(kept.exists());
+        assert!(!removed.exists());
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "edited by hand");
+        assert_eq!(fs::read_to_string(&partial).unwrap(), "written");
+        assert_eq!(index.outputs.keys().collect::<Vec<_>>(), [&kept]);
+    }
+}
@@ -0,0 +1,412 @@
+//! Contains [`Journal`]
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use eyre::{Context as _, Result, eyre};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::environment::Environment;
+use crate::index::ApplyIndex;
+use crate::stdx::PathExt as _;
+
+/// Records the intent to mutate files, before `dots` touches any of them
+///
+/// If `dots` is killed mid-apply (e.g. the laptop's battery dies), some files
+/// will have been removed but not written yet. The journal stays on disk in that
+/// case, so next time `dots` starts it can resume or roll back the apply.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct Journal {
+    /// Every file that the apply is going to write
+    #[serde(rename = "entry", default)]
+    pub entries: Vec<JournalEntry>,
+    /// Every directory that the apply is going to swap with its staging directory
+    #[serde(rename = "swap", default)]
+    pub swaps: Vec<JournalSwap>,
+}
+
+/// A single file that is going to be written
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// Path that is going to be written
+    pub path: PathBuf,
+    /// Copy of the file or the symlink that was at `path` before the apply started
+    ///
+    /// `None` if nothing existed at `path`
+    pub backup: Option<PathBuf>,
+}
+
+/// A directory that is going to be swapped with its staging directory
+///
+/// Only Linux can exchange them atomically. Elsewhere the old directory is moved out
+/// of the way first, so it is only at `old` until the new one is moved into `path`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct JournalSwap {
+    /// Directory that is going to be swapped
+    pub path: PathBuf,
+    /// Where the old directory is while it is being swapped
+    pub old: PathBuf,
+}
+
+impl Journal {
+    /// Name of the journal file, inside of the journal directory
+    const FILE_NAME: &str = "journal.toml";
+
+    /// Name of the directory containing backups, inside of the journal directory
+    const BACKUP_DIR: &str = "backup";
+
+    /// Name of the directory containing files which were removed from exact directories,
+    /// inside of the journal directory
+    const REMOVED_DIR: &str = "removed";
+
+    /// Name of the file containing the time the last apply completed, inside of the journal directory
+    const LAST_APPLY: &str = "last_apply";
+
+    /// Directory where the journal and backups are stored, e.g. `~/.local/share/dots`
+    pub fn dir() -> Result<PathBuf> {
+        Ok(Environment::current()?.data_dir.join("dots"))
+    }
+
+    /// Directory inside of the journal directory `dir` that files which are not in the repo are
+    /// moved into, when they are removed from an exact directory
+    ///
+    /// Unlike the backups of the journal, they are kept after the apply
+    pub fn removed_dir(dir: &Path) -> PathBuf {
+        dir.join(Self::REMOVED_DIR)
+    }
+
+    /// Load the journal of an apply that did not finish
+    ///
+    /// Returns `None` if the last apply finished successfully
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(Self::FILE_NAME);
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| eyre!("failed to parse journal {}", path.show()))
+                .map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| eyre!("failed to read journal {}", path.show())),
+        }
+    }
+
+    /// Back up every file in `paths` and write the journal
+    ///
+    /// If the journal of an apply that did not finish exists, this apply resumes it. Its
+    /// backups are kept, since they have the contents from before that apply started
+    ///
+    /// Nothing must be mutated before this function returns successfully
+    pub fn begin<'a>(dir: &Path, paths: impl IntoIterator<Item = &'a Path>) -> Result<Self> {
+        let backup_dir = dir.join(Self::BACKUP_DIR);
+        let Self { mut entries, swaps } = Self::load(dir)?.unwrap_or_default();
+
+        // backups from an earlier apply that was interrupted before it wrote the journal
+        if entries.is_empty() {
+            match fs::remove_dir_all(&backup_dir) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    return Err(err).with_context(|| {
+                        eyre!("failed to remove old backups {}", backup_dir.show())
+                    });
+                }
+                _ => (),
+            }
+        }
+        fs::create_dir_all(&backup_dir)
+            .with_context(|| eyre!("failed to create backup directory {}", backup_dir.show()))?;
+
+        let journaled = entries
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect::<BTreeSet<_>>();
+        for path in paths {
+            if journaled.contains(path) {
+                continue;
+            }
+
+            let backup = backup_dir.join(entries.len().to_string());
+            let backup = copy(path, &backup)
+                .with_context(|| eyre!("failed to back up {}", path.show()))?
+                .then_some(backup);
+
+            entries.push(JournalEntry {
+                path: path.to_path_buf(),
+                backup,
+            });
+        }
+
+        let journal = Self { entries, swaps };
+        journal.write(dir)?;
+
+        Ok(journal)
+    }
+
+    /// Record the directories in `swaps`, which are only known once every file was
+    /// staged into them
+    pub fn record_swaps(
+        &mut self,
+        dir: &Path,
+        swaps: impl IntoIterator<Item = JournalSwap>,
+    ) -> Result<()> {
+        self.swaps.extend(swaps);
+        self.write(dir)
+    }
+
+    /// Write the journal to disk
+    fn write(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(Self::FILE_NAME);
+        let tmp = path.with_extension("toml.tmp");
+
+        let contents = toml::to_string(self).context("failed to serialize journal")?;
+
+        // write to a temporary file first, so a crash in the middle of writing
+        // the journal does not leave a half-written journal behind
+        fs::write(&tmp, contents).with_context(|| eyre!("failed to write {}", tmp.show()))?;
+        fs::rename(&tmp, &path).with_context(|| eyre!("failed to write {}", path.show()))?;
+
+        Ok(())
+    }
+
+    /// Restore every file in the journal to the state it was in before the apply started
+    pub fn rollback(self, dir: &Path) -> Result<()> {
+        for JournalSwap { path, old } in &self.swaps {
+            // the swap either finished or did not start yet
+            if !old.exists() {
+                continue;
+            }
+            // the new directory was moved in, but the old one was not moved out of the way
+            if path.exists() {
+                fs::remove_dir_all(path)
+                    .with_context(|| eyre!("failed to remove {}", path.show()))?;
+            }
+            fs::rename(old, path).with_context(|| eyre!("failed to restore {}", path.show()))?;
+            log::info!(target: "journal", "restored {}", path.show());
+        }
+
+        for JournalEntry { path, backup } in &self.entries {
+            if let Some(backup) = backup {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| eyre!("failed to create directory {}", parent.show()))?;
+                }
+                // copying onto a symlink would write to where it points instead
+                match fs::remove_file(path) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                        return Err(err).with_context(|| eyre!("failed to remove {}", path.show()));
+                    }
+                    _ => (),
+                }
+                copy(backup, path).with_context(|| eyre!("failed to restore {}", path.show()))?;
+                log::info!(target: "journal", "restored {}", path.show());
+            } else {
+                match fs::remove_file(path) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                        return Err(err).with_context(|| eyre!("failed to remove {}", path.show()));
+                    }
+                    _ => log::info!(target: "journal", "removed {}", path.show()),
+                }
+            }
+        }
+
+        Self::clear(dir)
+    }
+
+    /// Mark the apply as complete, removing the journal and all backups
+    pub fn complete(dir: &Path) -> Result<()> {
+        let last_apply = dir.join(Self::LAST_APPLY);
+        fs::write(&last_apply, Timestamp::now().to_string())
+            .with_context(|| eyre!("failed to write {}", last_apply.show()))?;
+
+        Self::clear(dir)
+    }
+
+    /// When the last apply on this machine completed
+    ///
+    /// Returns `None` if `dots` never applied anything on this machine
+    pub fn last_apply(dir: &Path) -> Result<Option<Timestamp>> {
+        let path = dir.join(Self::LAST_APPLY);
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .trim()
+                .parse()
+                .with_context(|| eyre!("failed to parse {}", path.show()))
+                .map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| eyre!("failed to read {}", path.show())),
+        }
+    }
+
+    /// Every path that `dots` wrote to on this machine and did not prune, according to the
+    /// [`ApplyIndex`] in `dir`
+    pub fn managed(dir: &Path) -> Result<BTreeSet<PathBuf>> {
+        Ok(ApplyIndex::load(dir)?.outputs.into_keys().collect())
+    }
+
+    /// Remove the journal and all backups
+    fn clear(dir: &Path) -> Result<()> {
+        let path = dir.join(Self::FILE_NAME);
+        fs::remove_file(&path).with_context(|| eyre!("failed to remove {}", path.show()))?;
+
+        let backup_dir = dir.join(Self::BACKUP_DIR);
+        fs::remove_dir_all(&backup_dir)
+            .with_context(|| eyre!("failed to remove {}", backup_dir.show()))?;
+
+        Ok(())
+    }
+}
+
+/// Copy the file at `from` to `to`, or the symlink itself if it is one
+///
+/// Returns `false` if nothing that can be copied is at `from`, e.g. a directory
+fn copy(from: &Path, to: &Path) -> io::Result<bool> {
+    let metadata = match fs::symlink_metadata(from) {
+        Ok(metadata) => metadata,
+        // e.g. a parent of `from` is a file
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+            ) =>
+        {
+            return Ok(false);
+        }
+        Err(err) => return Err(err),
+    };
+
+    if metadata.is_symlink() {
+        let target = fs::read_link(from)?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target, to)?;
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_file(target, to)?;
+        Ok(true)
+    } else if metadata.is_file() {
+        fs::copy(from, to).map(|_| true)
+    } else {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        let existing = dir.join("existing.txt");
+        let new = dir.join("new").join("new.txt");
+        fs::write(&existing, "before").unwrap();
+
+        let journal = Journal::begin(dir, [existing.as_path(), new.as_path()]).unwrap();
+
+        // simulate an apply that got killed half-way through
+        fs::remove_file(&existing).unwrap();
+        fs::create_dir_all(new.parent().unwrap()).unwrap();
+        fs::write(&new, "after").unwrap();
+
+        assert_eq!(Journal::load(dir).unwrap(), Some(journal));
+
+        Journal::load(dir).unwrap().unwrap().rollback(dir).unwrap();
+
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "before");
+        assert!(!new.exists());
+        assert_eq!(Journal::load(dir).unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rollback_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        let target = dir.join("target.txt");
+        let link = dir.join("link.txt");
+        fs::write(&target, "target").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let journal = Journal::begin(dir, [link.as_path()]).unwrap();
+
+        // the apply replaced the symlink with a file
+        fs::remove_file(&link).unwrap();
+        fs::write(&link, "after").unwrap();
+
+        journal.rollback(dir).unwrap();
+
+        assert_eq!(fs::read_link(&link).unwrap(), target);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "target");
+    }
+
+    #[test]
+    fn rollback_swap() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        let path = dir.join("nvim");
+        let old = dir.join("nvim.staging.old");
+        let mut journal = Journal::begin(dir, []).unwrap();
+        journal
+            .record_swaps(
+                dir,
+                [JournalSwap {
+                    path: path.clone(),
+                    old: old.clone(),
+                }],
+            )
+            .unwrap();
+
+        // killed after the new directory was moved in, before the old one was moved out
+        fs::create_dir_all(&old).unwrap();
+        fs::write(old.join("init.lua"), "before").unwrap();
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("init.lua"), "after").unwrap();
+
+        Journal::load(dir).unwrap().unwrap().rollback(dir).unwrap();
+
+        assert_eq!(fs::read_to_string(path.join("init.lua")).unwrap(), "before");
+        assert!(!old.exists());
+    }
+
+    #[test]
+    fn resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        let (first, second) = (dir.join("first.txt"), dir.join("second.txt"));
+        fs::write(&first, "before").unwrap();
+        fs::write(&second, "before").unwrap();
+
+        Journal::begin(dir, [first.as_path()]).unwrap();
+        fs::write(&first, "half-written").unwrap();
+
+        // the apply is resumed, and gets interrupted again
+        Journal::begin(dir, [first.as_path(), second.as_path()]).unwrap();
+        fs::write(&second, "half-written").unwrap();
+
+        Journal::load(dir).unwrap().unwrap().rollback(dir).unwrap();
+
+        assert_eq!(fs::read_to_string(&first).unwrap(), "before");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "before");
+    }
+
+    #[test]
+    fn managed() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let file = dir.join("foo.txt");
+
+        assert_eq!(Journal::managed(dir).unwrap(), BTreeSet::new());
+
+        fs::write(&file, "foo").unwrap();
+        let mut index = ApplyIndex::default();
+        index.record(&file, sha256::digest("foo"));
+        index.save(dir).unwrap();
+
+        assert_eq!(Journal::managed(dir).unwrap(), BTreeSet::from([file]));
+    }
+}
@@ -0,0 +1,96 @@
+//! Contains [`output`]
+
+/// Where well-known apps read their config from on Linux, and other platforms
+/// which follow the XDG base directory specification
+#[cfg(not(any(target_os = "macos", windows)))]
+const KNOWN_APPS: &[(&str, &str)] = &[
+    ("alacritty", "{config_dir}/alacritty"),
+    ("bat", "{config_dir}/bat"),
+    ("code", "{config_dir}/Code/User"),
+    ("ghostty", "{config_dir}/ghostty"),
+    ("git", "{config_dir}/git"),
+    ("helix", "{config_dir}/helix"),
+    ("kitty", "{config_dir}/kitty"),
+    ("lazygit", "{config_dir}/lazygit"),
+    ("nushell", "{config_dir}/nushell"),
+    ("nvim", "{config_dir}/nvim"),
+    ("wezterm", "{config_dir}/wezterm"),
+    ("yazi", "{config_dir}/yazi"),
+    ("zed", "{config_dir}/zed"),
+    ("zellij", "{config_dir}/zellij"),
+];
+
+/// Where well-known apps read their config from on macOS
+///
+/// Many of them use `~/Library/Application Support` instead of `{config_dir}`
+#[cfg(target_os = "macos")]
+const KNOWN_APPS: &[(&str, &str)] = &[
+    ("alacritty", "{config_dir}/alacritty"),
+    ("bat", "{config_dir}/bat"),
+    ("code", "~/Library/Application Support/Code/User"),
+    (
+        "ghostty",
+        "~/Library/Application Support/com.mitchellh.ghostty",
+    ),
+    ("git", "{config_dir}/git"),
+    ("helix", "{config_dir}/helix"),
+    ("kitty", "{config_dir}/kitty"),
+    ("lazygit", "~/Library/Application Support/lazygit"),
+    ("nushell", "~/Library/Application Support/nushell"),
+    ("nvim", "{config_dir}/nvim"),
+    ("wezterm", "{config_dir}/wezterm"),
+    ("yazi", "{config_dir}/yazi"),
+    ("zed", "{config_dir}/zed"),
+    (
+        "zellij",
+        "~/Library/Application Support/org.Zellij-Contributors.Zellij",
+    ),
+];
+
+/// Where well-known apps read their config from on Windows, where `{config_dir}` is
+/// `%APPDATA%` and `{cache_dir}` is `%LOCALAPPDATA%`
+#[cfg(windows)]
+const KNOWN_APPS: &[(&str, &str)] = &[
+    ("alacritty", "{config_dir}/alacritty"),
+    ("bat", "{config_dir}/bat"),
+    ("code", "{config_dir}/Code/User"),
+    ("helix", "{config_dir}/helix"),
+    ("lazygit", "{cache_dir}/lazygit"),
+    ("nushell", "{config_dir}/nushell"),
+    ("nvim", "{cache_dir}/nvim"),
+    ("wezterm", "~/.config/wezterm"),
+    ("yazi", "{config_dir}/yazi/config"),
+    ("zed", "{config_dir}/Zed"),
+];
+
+/// Directory that `app` reads its config from on this platform,
+/// as an unparsed [`OutputPath`](crate::output_path::OutputPath)
+///
+/// `None` if the app is not well-known
+pub fn output(app: &str) -> Option<&'static str> {
+    KNOWN_APPS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(app))
+        .map(|(_, output)| *output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output_path::OutputPath;
+
+    use super::*;
+
+    #[test]
+    fn every_output_is_valid() {
+        for (app, output) in KNOWN_APPS {
+            assert!(output.parse::<OutputPath>().is_ok(), "{app}: {output}");
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        assert_eq!(output("Helix"), output("helix"));
+        assert!(output("helix").is_some());
+        assert_eq!(output("app_which_is_not_known"), None);
+    }
+}
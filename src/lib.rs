@@ -1,14 +1,202 @@
 //! `dots` library
 
+mod age;
 mod analysis;
+mod asset;
+mod autostart;
+mod block;
+mod cache;
+mod cancel;
 mod cli;
+mod color;
+mod complete;
+mod completions;
+mod computed;
 mod config;
+mod context;
+mod dconf;
+mod defaults;
+mod diff;
+mod duplicates;
+mod edit;
+mod engine;
+mod env;
+mod environment;
+mod existing;
+mod explain;
+mod export;
+mod facts;
+mod fetch;
+mod fixture;
+mod flatpak;
+mod font;
+mod format;
+mod git;
+mod ignored;
+mod index;
+mod journal;
+mod known_apps;
+mod leaks;
+mod lint;
+mod local_vars;
+mod lockfile;
+mod machines;
+mod migrate;
+mod mimeapps;
+mod notify;
+mod operation;
 mod output_path;
+mod outputs;
+mod packages;
+mod pager;
+mod phase;
+mod plan;
+mod probe;
+mod profile;
+mod protect;
+mod reporter;
+mod schedule;
+mod search;
+mod sensitive;
+#[cfg(test)]
+mod server;
+mod shell;
+mod ssh;
+mod stats;
+mod status;
 mod stdx;
+mod system;
+mod termux;
+mod theme;
+mod timer;
+mod tools;
+mod unused;
+mod url;
+mod user;
+mod vendor;
+mod watch;
+mod when;
 mod world;
+mod wsl;
+mod xattrs;
 
+pub use age::IDENTITY_VAR;
+pub use analysis::Analysis;
+pub use analysis::AnalysisBuilder;
+pub use analysis::Contents;
+pub use analysis::FileState;
+pub use analysis::Overwrites;
 pub use analysis::WritePath;
+pub use analysis::backup_path;
+pub use cache::LinkCache;
+pub use cache::RenderCache;
+pub use cancel::CancelToken;
+pub use cancel::cancellable_sleep;
+pub use cancel::handle_ctrl_c;
+pub use cancel::is_cancelled;
+pub use cli::AbsorbAction;
+pub use cli::CacheAction;
 pub use cli::Cli;
+pub use cli::Command;
+pub use cli::FixtureAction;
+pub use cli::PackagesAction;
+pub use cli::ToolsAction;
+pub use cli::VarsAction;
+pub use complete::CompletionKind;
+pub use complete::candidates;
+pub use completions::Completions;
+pub use config::FirstOverwrite;
+pub use context::RunContext;
+pub use context::strip_colors;
+pub use dconf::absorb_dconf;
+pub use defaults::DefaultsType;
+pub use defaults::DefaultsValue;
+pub use diff::DiffTool;
+pub use diff::diff;
+pub use duplicates::doctor_duplicates;
+pub use duplicates::duplicates;
+pub use edit::edit;
+pub use engine::Delimiters;
+pub use engine::Engine;
+pub use env::EnvVar;
+pub use environment::Environment;
+pub use explain::Entry;
+pub use explain::explain;
+pub use explain::info;
+pub use export::ExportDirs;
+pub use export::ExportFormat;
+pub use export::ansible_copy_tasks;
+pub use export::check_sensitive;
+pub use export::home_manager;
+pub use export::stow;
+pub use fetch::Limiter;
+#[doc(hidden)]
+pub use fixture::generate_fixture;
+pub use flatpak::FlatpakPermissions;
+pub use flatpak::Grants;
+pub use font::font_dir;
+pub use font::refresh_fonts;
+pub use format::format_file;
+pub use ignored::Ignore;
+pub use index::ApplyIndex;
+pub use journal::Journal;
+pub use leaks::ALLOW_SECRET;
+pub use leaks::Leak;
+pub use leaks::leaks;
+pub use lint::Lint;
+pub use lint::lint;
+pub use local_vars::LocalVars;
+pub use local_vars::VarSource;
+pub use lockfile::Lockfile;
+pub use lockfile::update_lockfile;
+pub use machines::Machines;
+pub use migrate::FORMAT_VERSION;
+pub use migrate::migrate_file;
+pub use notify::Event;
+pub use notify::Notify;
+pub use operation::Executor;
+pub use operation::Operation;
+pub use operation::Watchdog;
+pub use output_path::OutputPath;
+pub use packages::PackagesFormat;
+pub use packages::export_packages;
+pub use packages::import_brewfile;
+pub use pager::page;
+pub use phase::Phase;
+pub use plan::ApplyReport;
+pub use plan::Failure;
+pub use plan::Plan;
+pub use plan::SCHEMA_VERSION;
+pub use plan::Summary;
+pub use profile::Profile;
+pub use protect::Protect;
+pub use reporter::Reporter;
+pub use schedule::Schedule;
+pub use schedule::jitter;
+pub use search::search;
+pub use sensitive::REDACTED;
+pub use sensitive::Sensitive;
+pub use sensitive::redact;
+pub use stats::Stats;
+pub use status::status;
+pub use status::status_report;
 pub use stdx::PathExt;
+pub use termux::Termux;
+pub use theme::Scheme;
+pub use theme::Theme;
+pub use timer::unit_dir;
+pub use tools::doctor_tools;
+pub use tools::sync_tools;
+pub use unused::Unused;
+pub use unused::doctor_unused;
+pub use unused::unused;
+pub use user::User;
+pub use vendor::Vendor;
+pub use vendor::check_vendor;
+pub use vendor::vendor;
+pub use watch::Change;
+pub use watch::changes;
 pub use world::Link;
+pub use world::LinkAsset;
 pub use world::World;
+pub use wsl::Windows;
@@ -0,0 +1,232 @@
+//! Contains [`lint`]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::PathBuf;
+
+use eyre::{Context as _, Result, eyre};
+use handlebars::template::{HelperTemplate, Parameter, TemplateElement};
+use handlebars::{PathSeg, Template};
+use itertools::Itertools as _;
+use simply_colored::*;
+
+use crate::config::Marker;
+use crate::engine::Engine;
+use crate::facts;
+use crate::stdx::PathExt as _;
+use crate::world::World;
+
+/// Helpers which are built into handlebars
+const HELPERS: &[&str] = &[
+    "if", "unless", "each", "with", "lookup", "raw", "log", "eq", "ne", "gt", "gte", "lt", "lte",
+    "and", "or", "not", "len",
+];
+
+/// Helpers which change the context inside of their block, so paths inside of it
+/// are not variables of the `[vars]` table
+const CONTEXT_HELPERS: &[&str] = &["each", "with"];
+
+/// Problems with the variables of templates, and with the files of the repo
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Lint {
+    /// Variables which are used by templates, but not defined in `[vars]`, along with
+    /// every template which uses them
+    pub undefined: BTreeMap<String, BTreeSet<PathBuf>>,
+    /// Variables which are defined in `[vars]`, but not used by any template
+    pub unused: BTreeSet<String>,
+    /// Files inside of the `input` of a `[[dir]]` which are neither written nor compiled,
+    /// such as shell fragments of an unknown shell
+    pub unused_files: BTreeSet<PathBuf>,
+}
+
+impl Lint {
+    /// Whether there are no problems
+    pub fn is_empty(&self) -> bool {
+        self.undefined.is_empty() && self.unused.is_empty() && self.unused_files.is_empty()
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (var, templates) in &self.undefined {
+            let templates = templates.iter().map(|path| path.show()).join(", ");
+            writeln!(
+                f,
+                "{RED}undefined{RESET} variable `{var}`, used by {templates}"
+            )?;
+        }
+        for var in &self.unused {
+            writeln!(f, "{YELLOW}unused{RESET} variable `{var}`")?;
+        }
+        for path in &self.unused_files {
+            writeln!(
+                f,
+                "{YELLOW}unused{RESET} file {}, it is neither written nor compiled",
+                path.show()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Find variables which are used by templates of the `world` but not defined, and variables
+/// which are defined but not used
+pub fn lint(world: &World) -> Result<Lint> {
+    let mut lint = Lint::default();
+    let mut used = BTreeSet::new();
+
+    for file in &world.files {
+        let Some(text) = file.contents.text() else {
+            continue;
+        };
+        let marker = Marker::find(text).and_then(Result::ok);
+        let delimiters = marker
+            .as_ref()
+            .and_then(|marker| marker.delimiters().ok().flatten())
+            .or_else(|| file.delimiters.clone());
+
+        // variables of other engines are not checked
+        if marker
+            .and_then(|marker| marker.engine)
+            .unwrap_or(file.engine)
+            != Engine::Handlebars
+        {
+            continue;
+        }
+
+        let contents = match &delimiters {
+            Some(delimiters) => Engine::Handlebars.translate(text, delimiters)?,
+            None => text.to_string(),
+        };
+        let template = Template::compile(&contents)
+            .with_context(|| eyre!("failed to parse template {}", file.old_location.show()))?;
+
+        for var in variables(&template) {
+            if !world.vars.contains_key(&var) && !world.secrets.contains_key(&var) {
+                lint.undefined
+                    .entry(var.clone())
+                    .or_default()
+                    .insert(file.old_location.clone());
+            }
+            used.insert(var);
+        }
+    }
+
+    lint.unused = world
+        .vars
+        .keys()
+        .chain(world.secrets.keys())
+        // the facts are there whether they are used or not
+        .filter(|var| !used.contains(*var) && *var != facts::VAR && *var != facts::ENV_VAR)
+        .cloned()
+        .collect();
+    lint.unused_files = world.unused_inputs.iter().cloned().collect();
+
+    Ok(lint)
+}
+
+/// Every top-level variable that the `template` uses
+fn variables(template: &Template) -> BTreeSet<String> {
+    let mut variables = BTreeSet::new();
+    collect(template, &mut variables);
+    variables
+}
+
+/// Add every top-level variable used by the `template` to `variables`
+fn collect(template: &Template, variables: &mut BTreeSet<String>) {
+    for element in &template.elements {
+        if let TemplateElement::Expression(helper)
+        | TemplateElement::HtmlExpression(helper)
+        | TemplateElement::HelperBlock(helper) = element
+        {
+            collect_helper(helper, variables);
+        }
+    }
+}
+
+/// Add every top-level variable used by a `{{ }}` expression or a block to `variables`
+fn collect_helper(helper: &HelperTemplate, variables: &mut BTreeSet<String>) {
+    let name = match &helper.name {
+        Parameter::Name(name) => Some(name.as_str()),
+        _ => None,
+    };
+    let is_helper = name.is_some_and(|name| HELPERS.contains(&name))
+        || !helper.params.is_empty()
+        || !helper.hash.is_empty();
+
+    if is_helper {
+        for param in helper.params.iter().chain(helper.hash.values()) {
+            collect_parameter(param, variables);
+        }
+    } else {
+        collect_parameter(&helper.name, variables);
+    }
+
+    if name.is_some_and(|name| CONTEXT_HELPERS.contains(&name)) {
+        // only the `{{else}}` of these blocks uses the same context
+        if let Some(inverse) = &helper.inverse {
+            collect(inverse, variables);
+        }
+    } else {
+        for template in helper.template.iter().chain(&helper.inverse) {
+            collect(template, variables);
+        }
+    }
+}
+
+/// Add the top-level variable that the `parameter` refers to, if any, to `variables`
+fn collect_parameter(parameter: &Parameter, variables: &mut BTreeSet<String>) {
+    match parameter {
+        Parameter::Name(name) => {
+            variables.insert(name.clone());
+        }
+        Parameter::Path(handlebars::Path::Relative((segments, _))) => {
+            if let Some(PathSeg::Named(name)) = segments.first()
+                && name != "this"
+            {
+                variables.insert(name.clone());
+            }
+        }
+        Parameter::Subexpression(subexpression) => {
+            if let TemplateElement::Expression(helper) = subexpression.element.as_ref() {
+                collect_helper(helper, variables);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variables() {
+        let template = Template::compile(concat!(
+            "{{ color.base }} {{font}}\n",
+            "{{#if dark}}{{ dark_theme }}{{else}}{{ light_theme }}{{/if}}\n",
+            "{{#each plugins}}{{ this.name }} {{ name }}{{/each}}\n",
+            "{{ lookup sizes (lower size) }}\n",
+            "{{ @root.ignored }}\n",
+        ))
+        .unwrap();
+
+        assert_eq!(
+            super::variables(&template),
+            BTreeSet::from(
+                [
+                    "color",
+                    "font",
+                    "dark",
+                    "dark_theme",
+                    "light_theme",
+                    "plugins",
+                    "sizes",
+                    "size",
+                ]
+                .map(String::from)
+            )
+        );
+    }
+}
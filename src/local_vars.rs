@@ -0,0 +1,231 @@
+//! Contains [`LocalVars`]
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead as _, Write as _};
+use std::path::Path;
+
+use eyre::{Context as _, Result, bail, eyre};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::facts;
+use crate::stdx::PathExt as _;
+use crate::theme;
+
+/// Variables which only apply to this machine, set with `dots vars set`
+///
+/// They are stored in the data directory instead of the repo, and take precedence over
+/// `[vars]`, so machine-specific values can be tweaked without editing the shared config.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct LocalVars {
+    /// Value of each variable, keyed by its name
+    #[serde(flatten)]
+    pub vars: toml::Table,
+}
+
+impl LocalVars {
+    /// Name of the file containing the variables, inside of the journal directory
+    const FILE_NAME: &str = "vars.toml";
+
+    /// Variables which `dots` provides, and which can not be set
+    const RESERVED: [&str; 3] = [facts::VAR, theme::VAR, facts::ENV_VAR];
+
+    /// Load the variables from the journal directory `dir`
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(Self::FILE_NAME);
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                toml::from_str(&contents).with_context(|| eyre!("failed to parse {}", path.show()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| eyre!("failed to read {}", path.show())),
+        }
+    }
+
+    /// Write the variables into the journal directory `dir`
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir).with_context(|| eyre!("failed to create {}", dir.show()))?;
+
+        let path = dir.join(Self::FILE_NAME);
+        let contents = toml::to_string(self).context("failed to serialize variables")?;
+        fs::write(&path, contents).with_context(|| eyre!("failed to write {}", path.show()))
+    }
+
+    /// Set the variable `name` to `value`, as given to `dots vars set`
+    pub fn set(&mut self, name: String, value: &str) -> Result<()> {
+        if Self::RESERVED.contains(&name.as_str()) {
+            bail!("`{name}` can not be set, it is provided by `dots`");
+        }
+        self.vars.insert(name, parse_value(value));
+        Ok(())
+    }
+
+    /// Ask for every `prompt` variable of the repo at `cwd` which was not answered on this
+    /// machine yet, and keep the answers with the variables in the journal directory `dir`
+    ///
+    /// Nothing is asked or written if `dots` is not `interactive`, creating the
+    /// [`World`](crate::World) fails instead
+    pub fn answer_prompts(dir: &Path, cwd: &Path, interactive: bool) -> Result<()> {
+        if !interactive {
+            return Ok(());
+        }
+
+        let vars = Config::discover(cwd)?.vars;
+        let mut local = Self::load(dir)?;
+        let unanswered = local.unanswered(&vars).collect::<Vec<_>>();
+        if unanswered.is_empty() {
+            return Ok(());
+        }
+
+        let answers = unanswered
+            .into_iter()
+            .map(|(name, question)| Ok((name.to_string(), ask(name, question)?)))
+            .collect::<Result<Vec<_>>>()?;
+        local.vars.extend(answers);
+        local.save(dir)
+    }
+
+    /// Name and question of every `prompt` variable in `vars` which was not answered on this
+    /// machine yet
+    pub fn unanswered<'a>(
+        &'a self,
+        vars: &'a toml::Table,
+    ) -> impl Iterator<Item = (&'a str, &'a str)> {
+        vars.iter()
+            .filter(|(name, _)| !self.vars.contains_key(*name))
+            .filter_map(|(name, value)| Some((name.as_str(), prompt(value)?)))
+    }
+}
+
+/// Key of the table which makes a variable asked for once on each machine
+const PROMPT: &str = "prompt";
+
+/// Key of the table which makes a variable decrypted from a file
+const SECRET: &str = "secret";
+
+/// Value of the only `key` of the table `value`
+fn only<'a>(value: &'a toml::Value, key: &str) -> Option<&'a str> {
+    let table = value.as_table()?;
+    (table.len() == 1)
+        .then(|| table.get(key)?.as_str())
+        .flatten()
+}
+
+/// Question of a variable that is asked for once on each machine, whose answer is kept
+/// with the variables set with `dots vars set`, e.g. `email = { prompt = "Email of commits" }`
+pub fn prompt(value: &toml::Value) -> Option<&str> {
+    only(value, PROMPT)
+}
+
+/// File that the value of a variable is decrypted from with `age`, relative to the config
+/// file, e.g. `token = { secret = "secrets/token.age" }`
+pub fn secret(value: &toml::Value) -> Option<&str> {
+    only(value, SECRET)
+}
+
+/// Ask for the value of the variable `name` with its `question`
+fn ask(name: &str, question: &str) -> Result<toml::Value> {
+    eprint!("{question} ({name}): ");
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    Ok(parse_value(answer.trim()))
+}
+
+/// Parse the value given to `dots vars set`
+///
+/// It is a TOML value such as `16`, `true` or `["a", "b"]`, and a string otherwise
+fn parse_value(value: &str) -> toml::Value {
+    format!("value = {value}")
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| value.into())
+}
+
+/// Where the value of a variable comes from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum VarSource {
+    /// Defined in `[vars]`
+    Config,
+    /// Computed from an `expr` in `[vars]`
+    Computed,
+    /// Answered on this machine, when asked with a `prompt` in `[vars]`
+    Prompt,
+    /// Decrypted from the file of a `secret` in `[vars]`
+    Secret,
+    /// The palette of `[theme]`
+    Theme,
+    /// Facts about the machine
+    Fact,
+    /// Set on this machine with `dots vars set`
+    Local,
+}
+
+impl fmt::Display for VarSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Config => "config",
+            Self::Computed => "computed",
+            Self::Prompt => "prompt",
+            Self::Secret => "secret",
+            Self::Theme => "theme",
+            Self::Fact => "fact",
+            Self::Local => "local",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_value() {
+        assert_eq!(super::parse_value("16"), toml::Value::Integer(16));
+        assert_eq!(super::parse_value("true"), toml::Value::Boolean(true));
+        assert_eq!(super::parse_value("\"16\""), toml::Value::from("16"));
+        assert_eq!(
+            super::parse_value("JetBrains Mono"),
+            toml::Value::from("JetBrains Mono")
+        );
+    }
+
+    #[test]
+    fn prompt_and_secret() {
+        let value = |contents: &str| contents.parse::<toml::Table>().unwrap()["var"].clone();
+
+        assert_eq!(
+            prompt(&value("var = { prompt = \"Email of commits\" }")),
+            Some("Email of commits")
+        );
+        assert_eq!(
+            secret(&value("var = { secret = \"secrets/token.age\" }")),
+            Some("secrets/token.age")
+        );
+        assert_eq!(
+            prompt(&value("var = { prompt = \"a\", expr = \"1\" }")),
+            None
+        );
+        assert_eq!(secret(&value("var = \"secrets/token.age\"")), None);
+    }
+
+    #[test]
+    fn load_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("dots");
+        assert_eq!(LocalVars::load(&dir).unwrap(), LocalVars::default());
+
+        let mut local = LocalVars::default();
+        local.set("font_size".to_string(), "14").unwrap();
+        assert!(local.set("facts".to_string(), "{}").is_err());
+        local.save(&dir).unwrap();
+
+        assert_eq!(LocalVars::load(&dir).unwrap(), local);
+    }
+}
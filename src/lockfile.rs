@@ -0,0 +1,194 @@
+//! Contains [`Lockfile`]
+//!
+//! The first applied fetch of every `[[link]]` is recorded in `dots.lock` next to the config, so
+//! later runs, and other machines, detect when the server returns something else instead
+//! of silently writing it. `dots update` deliberately refreshes the recorded links
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use eyre::{Context as _, Result, bail, eyre};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::fetch::Fetched;
+use crate::stdx::PathExt as _;
+use crate::world::World;
+
+/// Every locked link, in `dots.lock` of the repo
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Lockfile {
+    /// Locked links, keyed by their resolved URL
+    #[serde(rename = "link", default)]
+    pub links: BTreeMap<String, Locked>,
+}
+
+/// A single link, as it was when it was first fetched
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Locked {
+    /// Hash of the contents
+    pub sha256: String,
+    /// `ETag` that the server sent with the contents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// `Last-Modified` that the server sent with the contents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+/// Links which were fetched for the first time, which are locked once they are applied
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NewlyLocked {
+    /// Repo whose `dots.lock` the links are locked in
+    pub root: PathBuf,
+    /// Locked links, keyed by their resolved URL
+    pub links: BTreeMap<String, Locked>,
+}
+
+impl NewlyLocked {
+    /// Add the links to `dots.lock` of the repo, if there are any
+    pub fn save(self) -> Result<()> {
+        if self.links.is_empty() {
+            return Ok(());
+        }
+
+        let mut lockfile = Lockfile::load(&self.root)?;
+        lockfile.links.extend(self.links);
+        lockfile.save(&self.root)
+    }
+}
+
+impl Locked {
+    /// Lock the `fetched` contents
+    pub fn new(fetched: &Fetched) -> Self {
+        Self {
+            sha256: sha256::digest(&fetched.bytes),
+            etag: fetched.etag.clone(),
+            last_modified: fetched.last_modified.clone(),
+        }
+    }
+}
+
+impl Lockfile {
+    /// Name of the lockfile, next to the config file
+    pub const FILE_NAME: &str = "dots.lock";
+
+    /// Load the locked links of the repo at `root`, none if nothing was locked
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = root.join(Self::FILE_NAME);
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                toml::from_str(&contents).with_context(|| eyre!("failed to parse {}", path.show()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| eyre!("failed to read {}", path.show())),
+        }
+    }
+
+    /// Save the locked links into the repo at `root`
+    pub fn save(&self, root: &Path) -> Result<()> {
+        let path = root.join(Self::FILE_NAME);
+        let contents = toml::to_string(self).context("failed to serialize locked links")?;
+        fs::write(&path, contents).with_context(|| eyre!("failed to write {}", path.show()))
+    }
+
+    /// Check that the `fetched` contents of `url` are the ones that were locked
+    ///
+    /// Returns the entry to lock if `url` was never locked
+    pub fn verify(&self, url: &str, fetched: &Fetched) -> Result<Option<Locked>> {
+        let locked = Locked::new(fetched);
+
+        match self.links.get(url) {
+            None => Ok(Some(locked)),
+            Some(expected) if expected.sha256 == locked.sha256 => Ok(None),
+            Some(expected) => bail!(
+                "link {url} changed since it was locked in {}, expected {} but got {}. \
+                 Run `dots update` to accept the new contents",
+                Self::FILE_NAME,
+                expected.sha256,
+                locked.sha256
+            ),
+        }
+    }
+}
+
+/// Fetch the `[[link]]`s at `urls` of the repo at `cwd` again, every link if there are none,
+/// and lock their current contents
+///
+/// Returns the number of locked links which changed
+pub fn update_lockfile(cwd: &Path, urls: &[String]) -> Result<usize> {
+    let root = Config::find(cwd)?;
+    let previous = Lockfile::load(&root)?;
+
+    let mut lockfile = previous.clone();
+    lockfile
+        .links
+        .retain(|url, _| !urls.is_empty() && !urls.contains(url));
+
+    // the world locks every link which is not locked
+    let world = World::with_lockfile(cwd, lockfile.clone()).map_err(|errs| {
+        for err in errs {
+            log::error!("{err}");
+        }
+        eyre!(
+            "encountered errors, {} was not changed",
+            Lockfile::FILE_NAME
+        )
+    })?;
+    lockfile.links.extend(world.newly_locked.links);
+
+    for url in urls {
+        if !lockfile.links.contains_key(url) {
+            log::warn!("{url} is not the URL of a link");
+        }
+    }
+
+    let updated = lockfile
+        .links
+        .iter()
+        .filter(|(url, locked)| previous.links.get(*url) != Some(locked))
+        .count();
+    lockfile.save(&root)?;
+
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify() {
+        let fetched = |contents: &str| Fetched {
+            bytes: contents.as_bytes().to_vec(),
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+        };
+
+        let mut lockfile = Lockfile::default();
+        let locked = lockfile
+            .verify("https://example.com/theme", &fetched("mocha"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(locked.sha256, sha256::digest("mocha"));
+        assert_eq!(locked.etag.as_deref(), Some("\"v1\""));
+        lockfile
+            .links
+            .insert("https://example.com/theme".to_string(), locked);
+
+        assert_eq!(
+            lockfile
+                .verify("https://example.com/theme", &fetched("mocha"))
+                .unwrap(),
+            None
+        );
+        assert!(
+            lockfile
+                .verify("https://example.com/theme", &fetched("latte"))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Contains [`Machines`]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use eyre::{Context as _, Result, eyre};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use simply_colored::*;
+
+use crate::stdx::PathExt as _;
+
+/// Registry of every machine that the dotfiles repo was applied on
+///
+/// It is stored in the [journal directory](crate::Journal::dir), outside of the repo,
+/// so applying never changes the repo. Machines which share the directory, e.g. by
+/// syncing it, see each other. This makes it obvious when a machine has fallen behind the repo.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct Machines {
+    /// Machines, keyed by their hostname
+    #[serde(rename = "machine", default)]
+    pub machines: BTreeMap<String, Machine>,
+}
+
+/// The last apply on a single machine
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Machine {
+    /// [`World::hash`](crate::World::hash) of the repo that was applied
+    pub hash: String,
+    /// Git commit of the repo that was applied, if it is a git repo
+    pub commit: Option<String>,
+    /// Version of `dots` that applied the repo
+    pub version: String,
+    /// Profiles whose entries were applied
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub profiles: BTreeSet<String>,
+    /// When the apply completed
+    pub applied_at: Timestamp,
+}
+
+impl Machines {
+    /// Name of the registry file, inside of the journal directory
+    const FILE_NAME: &str = "machines.toml";
+
+    /// Load the registry from the journal directory `dir`
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(Self::FILE_NAME);
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                toml::from_str(&contents).with_context(|| eyre!("failed to parse {}", path.show()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| eyre!("failed to read {}", path.show())),
+        }
+    }
+
+    /// Record in the journal directory `dir` that the repo at `root` with the given `hash`
+    /// was just applied on this machine, with the `profiles`
+    pub fn record(dir: &Path, root: &Path, hash: String, profiles: BTreeSet<String>) -> Result<()> {
+        let mut machines = Self::load(dir)?;
+
+        machines.machines.insert(
+            hostname(),
+            Machine {
+                hash,
+                commit: git_commit(root),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                profiles,
+                applied_at: Timestamp::now(),
+            },
+        );
+
+        let path = dir.join(Self::FILE_NAME);
+        let contents = toml::to_string(&machines).context("failed to serialize machines")?;
+        fs::write(&path, contents).with_context(|| eyre!("failed to write {}", path.show()))
+    }
+
+    /// Show every machine, and whether it applied the repo with the current `hash`
+    pub fn show(&self, hash: &str) -> String {
+        let this_machine = hostname();
+
+        self.machines
+            .iter()
+            .map(|(hostname, machine)| {
+                let marker = if *hostname == this_machine { "*" } else { " " };
+                let status = if machine.hash == hash {
+                    format!("{GREEN}up to date{RESET}")
+                } else {
+                    format!("{YELLOW}behind{RESET}")
+                };
+
+                format!("{marker} {hostname}  {machine}  {status}\n")
+            })
+            .collect()
+    }
+}
+
+impl Display for Machine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let commit = self.commit.as_deref().map_or("-", |commit| {
+            commit.get(..Machine::SHORT_COMMIT).unwrap_or(commit)
+        });
+
+        write!(
+            f,
+            "{}  v{}  {CYAN}{commit}{RESET}",
+            self.applied_at, self.version
+        )?;
+        if !self.profiles.is_empty() {
+            write!(
+                f,
+                "  {BLACK}{}{RESET}",
+                self.profiles.iter().cloned().collect::<Vec<_>>().join(",")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Machine {
+    /// Number of characters of the commit to show
+    const SHORT_COMMIT: usize = 7;
+}
+
+/// Hostname of this machine
+pub fn hostname() -> String {
+    crate::environment::hostname()
+}
+
+/// Commit that `HEAD` points to, if `root` is inside of a git repo
+fn git_commit(root: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        let repo = dir.join("repo");
+
+        assert_eq!(Machines::load(dir).unwrap(), Machines::default());
+
+        Machines::record(
+            dir,
+            &repo,
+            "hash".to_string(),
+            BTreeSet::from(["work".to_string()]),
+        )
+        .unwrap();
+        assert!(!repo.join(Machines::FILE_NAME).exists());
+
+        let machines = Machines::load(dir).unwrap();
+        let machine = &machines.machines[&hostname()];
+        assert_eq!(machine.hash, "hash");
+        assert_eq!(machine.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(machine.profiles, BTreeSet::from(["work".to_string()]));
+
+        assert!(machines.show("hash").contains("up to date"));
+        assert!(machines.show("other hash").contains("behind"));
+    }
+}
@@ -0,0 +1,286 @@
+//! Contains [`migrate`]
+//!
+//! The format of `dots.toml` changes as it grows. Each change is a [`Migration`] from
+//! one version of the format to the next. Configs in the previous version are still
+//! read, with a warning, and `dots migrate` upgrades them in place while keeping their
+//! comments and formatting
+//!
+//! The format did not change yet, so there are no migrations
+
+use std::fs;
+use std::path::Path;
+
+use eyre::{Context as _, ContextCompat as _, Result, bail, eyre};
+use toml_edit::{DocumentMut, Item, RawString};
+
+use crate::config::Config;
+use crate::stdx::PathExt as _;
+
+/// Current version of the format of the config
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A change of the format, from version `from` to the next one
+struct Migration {
+    /// Version that this migration upgrades from
+    from: u32,
+    /// What changed, shown when a config still uses the old format
+    description: &'static str,
+    /// Upgrade the config, keeping its comments and formatting, returning whether it changed
+    document: fn(&mut DocumentMut) -> bool,
+}
+
+/// Every change of the format, in order
+const MIGRATIONS: &[Migration] = &[];
+
+/// Version of the format that the `migrations` upgrade to
+fn latest(migrations: &[Migration]) -> u32 {
+    migrations.last().map_or(1, |migration| migration.from + 1)
+}
+
+/// Version of the format of `document`, which is 1 for configs without a `version`
+fn version(document: &DocumentMut, latest: u32) -> Result<u32> {
+    let Some(version) = document.get("version") else {
+        return Ok(1);
+    };
+
+    let version = version
+        .as_integer()
+        .and_then(|version| u32::try_from(version).ok())
+        .with_context(|| eyre!("`version` must be a positive integer, found {version}"))?;
+    if version > latest {
+        bail!(
+            "the config has version {version}, but this `dots` only reads up to {latest}. update `dots`"
+        );
+    }
+
+    Ok(version)
+}
+
+/// Apply the `migrations` from `version` to `document`
+///
+/// Returns the description of every change that it needed
+fn apply(document: &mut DocumentMut, version: u32, migrations: &[Migration]) -> Vec<&'static str> {
+    let mut changes = vec![];
+
+    for migration in migrations
+        .iter()
+        .filter(|migration| migration.from >= version)
+    {
+        if (migration.document)(document) {
+            changes.push(migration.description);
+        }
+    }
+
+    changes
+}
+
+/// Upgrade the config with `contents` to the current version when reading it, warning
+/// about every change that it needed
+///
+/// Returns `None` if it already has the current format
+pub fn upgrade(contents: &str) -> Result<Option<toml::Table>> {
+    upgrade_with(contents, MIGRATIONS)
+}
+
+/// [`upgrade`] with the `migrations`
+fn upgrade_with(contents: &str, migrations: &[Migration]) -> Result<Option<toml::Table>> {
+    let mut document = contents
+        .parse::<DocumentMut>()
+        .context("failed to parse config file")?;
+    let latest = latest(migrations);
+    let version = version(&document, latest)?;
+    if version == latest {
+        return Ok(None);
+    }
+    // only the previous version is read, older ones must be migrated
+    if version + 1 < latest {
+        bail!(
+            "the config has version {version}, which is no longer supported. run `dots migrate` to upgrade it"
+        );
+    }
+
+    let changes = apply(&mut document, version, migrations);
+    if changes.is_empty() {
+        return Ok(None);
+    }
+    for change in changes {
+        log::warn!("the config uses an old format: {change}. run `dots migrate` to upgrade it");
+    }
+
+    toml::from_str(&document.to_string())
+        .context("failed to upgrade the config, it would be invalid")
+        .map(Some)
+}
+
+/// Upgrade the text of a config to the current version, keeping its comments
+///
+/// Returns the upgraded config, along with the description of every change that it needed
+pub fn migrate(contents: &str) -> Result<(String, Vec<&'static str>)> {
+    migrate_with(contents, MIGRATIONS)
+}
+
+/// [`migrate`] with the `migrations`
+fn migrate_with(contents: &str, migrations: &[Migration]) -> Result<(String, Vec<&'static str>)> {
+    let mut document = contents
+        .parse::<DocumentMut>()
+        .context("failed to parse config file")?;
+    let latest = latest(migrations);
+    let version = version(&document, latest)?;
+    if version == latest {
+        return Ok((contents.to_string(), vec![]));
+    }
+
+    let changes = apply(&mut document, version, migrations);
+    set_version(&mut document, latest);
+    let migrated = document.to_string();
+
+    toml::from_str::<toml::Table>(&migrated)
+        .context("failed to migrate the config, it would be invalid")?;
+
+    Ok((migrated, changes))
+}
+
+/// Upgrade the config closest to `cwd` in place
+///
+/// Returns the description of every change that it needed
+pub fn migrate_file(cwd: &Path) -> Result<Vec<&'static str>> {
+    let path = Config::editable(&Config::find(cwd)?)?;
+    let contents =
+        fs::read_to_string(&path).with_context(|| eyre!("failed to read {}", path.show()))?;
+
+    let (migrated, changes) = migrate(&contents)?;
+    if migrated != contents {
+        fs::write(&path, migrated).with_context(|| eyre!("failed to write {}", path.show()))?;
+    }
+
+    Ok(changes)
+}
+
+/// Set `version` of the config to `version`, adding it as the first key after the comments
+/// at the start
+fn set_version(document: &mut DocumentMut, version: u32) {
+    let version = toml_edit::value(i64::from(version));
+    let table = document.as_table_mut();
+    if let Some(item) = table.get_mut("version") {
+        *item = version;
+        return;
+    }
+
+    // the comments at the start describe the whole config, so they stay at the start
+    let comments = table.iter_mut().next().and_then(|(mut key, item)| {
+        let decor = match item {
+            Item::Table(table) => table.decor_mut(),
+            Item::ArrayOfTables(tables) => tables.get_mut(0)?.decor_mut(),
+            Item::Value(_) => key.leaf_decor_mut(),
+            Item::None => return None,
+        };
+        let comments = decor.prefix().cloned();
+        decor.set_prefix("\n");
+        comments
+    });
+
+    table.insert("version", version);
+    table.sort_values_by(|key, _, other, _| {
+        (key.get() != "version").cmp(&(other.get() != "version"))
+    });
+    if let Some(mut key) = table.key_mut("version") {
+        key.leaf_decor_mut()
+            .set_prefix(comments.unwrap_or_else(|| RawString::from("")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use toml_edit::DocumentMut;
+
+    use super::Migration;
+
+    /// Migrations of a format in which `operation_timeout` was renamed to `timeout`
+    const MIGRATIONS: &[Migration] = &[Migration {
+        from: 1,
+        description: "`operation_timeout` is now called `timeout`",
+        document: rename,
+    }];
+
+    /// Rename `operation_timeout` to `timeout`, keeping its comments
+    fn rename(document: &mut DocumentMut) -> bool {
+        let table = document.as_table_mut();
+        let Some((key, item)) = table.remove_entry("operation_timeout") else {
+            return false;
+        };
+
+        let renamed = toml_edit::Key::new("timeout").with_leaf_decor(key.leaf_decor().clone());
+        table.insert_formatted(&renamed, item);
+
+        true
+    }
+
+    #[test]
+    fn migrations() {
+        assert_eq!(super::latest(super::MIGRATIONS), super::FORMAT_VERSION);
+    }
+
+    #[test]
+    fn migrate() {
+        let old = "\
+# my config
+durable = true
+# give up quickly, the NFS share is slow
+operation_timeout = 30
+
+[[dir]]
+input = \"configs\"
+output = \"/out\"
+";
+
+        let (migrated, changes) = super::migrate_with(old, MIGRATIONS).unwrap();
+        assert_eq!(
+            migrated,
+            "\
+# my config
+version = 2
+
+durable = true
+# give up quickly, the NFS share is slow
+timeout = 30
+
+[[dir]]
+input = \"configs\"
+output = \"/out\"
+"
+        );
+        assert_eq!(changes.len(), 1);
+
+        // migrating again changes nothing
+        assert_eq!(
+            super::migrate_with(&migrated, MIGRATIONS).unwrap(),
+            (migrated.clone(), vec![])
+        );
+
+        // a config in the current format is left as it is
+        assert_eq!(super::migrate(old).unwrap(), (old.to_string(), vec![]));
+    }
+
+    #[test]
+    fn upgrade() {
+        let config = super::upgrade_with("operation_timeout = 30\n", MIGRATIONS)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            config,
+            toml::from_str::<toml::Table>("timeout = 30\n").unwrap()
+        );
+
+        assert_eq!(
+            super::upgrade_with("durable = true\n", MIGRATIONS).unwrap(),
+            None
+        );
+        assert_eq!(
+            super::upgrade_with("version = 2\n", MIGRATIONS).unwrap(),
+            None
+        );
+        assert!(super::upgrade_with("version = 3\n", MIGRATIONS).is_err());
+        assert_eq!(super::upgrade("version = 1\n").unwrap(), None);
+        assert!(super::upgrade("version = 2\n").is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! Contains [`MimeApps`]
+//!
+//! Default applications are kept in `mimeapps.list`, which other programs also write
+//! to. Instead of replacing the whole file, only the associations in the config are
+//! changed and everything else in it is kept as-is
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use eyre::{Context as _, Result, eyre};
+use serde::Deserialize;
+use tap::Pipe as _;
+
+use crate::analysis::{Contents, WritePath};
+use crate::output_path::OutputPath;
+use crate::stdx::PathExt as _;
+
+/// Associations of an app with MIME types, in the `[[mimeapps]]` section of the config
+#[derive(Deserialize, Debug)]
+pub struct MimeApps {
+    /// Desktop entry of the app, e.g. `firefox.desktop`
+    pub app: String,
+    /// MIME types to associate the app with, e.g. `text/html` or `x-scheme-handler/https`
+    pub types: Vec<String>,
+    /// Whether the app is the default for the types, or only one of the apps that can open them
+    #[serde(default)]
+    pub association: Association,
+}
+
+/// How an app is associated with MIME types
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Association {
+    /// The app is the default, in `[Default Applications]`
+    #[default]
+    Default,
+    /// The app is offered for the types, in `[Added Associations]`
+    Added,
+}
+
+impl Association {
+    /// Section of `mimeapps.list` with the association
+    fn section(self) -> &'static str {
+        match self {
+            Self::Default => "Default Applications",
+            Self::Added => "Added Associations",
+        }
+    }
+}
+
+/// Path to `mimeapps.list` of the user
+pub fn path() -> Result<PathBuf> {
+    "{config_dir}/mimeapps.list"
+        .parse::<OutputPath>()?
+        .into_inner()
+        .pipe(Ok)
+}
+
+/// Merge every `[[mimeapps]]` into the `mimeapps.list` at `path`
+pub fn merge(mimeapps: &[MimeApps], path: &Path, durable: bool) -> Result<WritePath> {
+    let current = match fs::read_to_string(path) {
+        Ok(current) => current,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).with_context(|| eyre!("failed to read {}", path.show())),
+    };
+
+    let merged = mimeapps.iter().fold(current, |contents, mimeapps| {
+        mimeapps.types.iter().fold(contents, |contents, mime| {
+            set(&contents, mimeapps.association, mime, &mimeapps.app)
+        })
+    });
+
+    Ok(WritePath {
+        path: path.to_path_buf(),
+        contents: Contents::Text(merged),
+        source: None,
+        durable,
+        description: None,
+        symlink: false,
+        mode: None,
+    })
+}
+
+/// Associate `app` with `mime` in the keyfile `contents`, keeping every other line
+fn set(contents: &str, association: Association, mime: &str, app: &str) -> String {
+    let header = format!("[{}]", association.section());
+    let mut lines = contents.lines().map(str::to_string).collect::<Vec<_>>();
+
+    let Some(start) = lines.iter().position(|line| line.trim() == header) else {
+        if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+            lines.push(String::new());
+        }
+        lines.extend([header, format!("{mime}={app};")]);
+        return lines.join("\n") + "\n";
+    };
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| line.trim_start().starts_with('['))
+        .map_or(lines.len(), |i| start + 1 + i);
+
+    let existing = (start + 1..end).find(|&i| {
+        lines[i]
+            .split_once('=')
+            .is_some_and(|(key, _)| key.trim() == mime)
+    });
+
+    match existing {
+        Some(i) => {
+            let apps = lines[i].split_once('=').map_or("", |(_, apps)| apps.trim());
+            lines[i] = match association {
+                Association::Default => format!("{mime}={app};"),
+                // the app is added in front of the others, which are kept
+                Association::Added => {
+                    let others = apps
+                        .split(';')
+                        .filter(|other| !other.is_empty() && *other != app)
+                        .map(|other| format!("{other};"))
+                        .collect::<String>();
+                    format!("{mime}={app};{others}")
+                }
+            };
+        }
+        None => {
+            // after the last key of the section, before the blank lines that separate it
+            let at = (start + 1..end)
+                .rev()
+                .find(|&i| !lines[i].trim().is_empty())
+                .map_or(start + 1, |i| i + 1);
+            lines.insert(at, format!("{mime}={app};"));
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set() {
+        let current = "[Default Applications]\n\
+                       text/html=chromium.desktop;\n\
+                       image/png=feh.desktop;\n\
+                       \n\
+                       [Added Associations]\n\
+                       text/plain=gedit.desktop;hx.desktop;\n";
+
+        assert_eq!(
+            super::set(
+                current,
+                Association::Default,
+                "text/html",
+                "firefox.desktop"
+            ),
+            "[Default Applications]\n\
+             text/html=firefox.desktop;\n\
+             image/png=feh.desktop;\n\
+             \n\
+             [Added Associations]\n\
+             text/plain=gedit.desktop;hx.desktop;\n"
+        );
+        assert_eq!(
+            super::set(
+                current,
+                Association::Default,
+                "x-scheme-handler/https",
+                "firefox.desktop"
+            ),
+            "[Default Applications]\n\
+             text/html=chromium.desktop;\n\
+             image/png=feh.desktop;\n\
+             x-scheme-handler/https=firefox.desktop;\n\
+             \n\
+             [Added Associations]\n\
+             text/plain=gedit.desktop;hx.desktop;\n"
+        );
+        assert_eq!(
+            super::set(current, Association::Added, "text/plain", "hx.desktop"),
+            "[Default Applications]\n\
+             text/html=chromium.desktop;\n\
+             image/png=feh.desktop;\n\
+             \n\
+             [Added Associations]\n\
+             text/plain=hx.desktop;gedit.desktop;\n"
+        );
+        assert_eq!(
+            super::set("", Association::Default, "text/html", "firefox.desktop"),
+            "[Default Applications]\ntext/html=firefox.desktop;\n"
+        );
+    }
+}
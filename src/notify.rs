@@ -0,0 +1,213 @@
+//! Contains [`Notify`]
+
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use eyre::{Context as _, ContextCompat as _, Result, eyre};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::fetch::Limiter;
+use crate::machines::hostname;
+use crate::sensitive::redact;
+
+/// Longest that sending a notification to the [`Notify::webhook`] can take
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where to send notifications when something needs attention, for machines that run
+/// `dots check` or `dots apply` unattended
+#[derive(Deserialize, Debug)]
+pub struct Notify {
+    /// URL which receives a `POST` request with a JSON [`Notification`]
+    pub webhook: Option<String>,
+    /// Command to run, with the `DOTS_EVENT`, `DOTS_HOSTNAME` and `DOTS_MESSAGE`
+    /// environment variables set
+    pub command: Option<String>,
+    /// Events to notify about, every event by default
+    #[serde(default = "default_events")]
+    pub events: Vec<Event>,
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self {
+            webhook: None,
+            command: None,
+            events: default_events(),
+        }
+    }
+}
+
+/// Default value for [`Notify::events`]
+fn default_events() -> Vec<Event> {
+    vec![Event::Drift, Event::ApplyFailed]
+}
+
+/// What happened
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Event {
+    /// A managed file was modified or removed by something other than `dots`
+    Drift,
+    /// An apply failed
+    ApplyFailed,
+}
+
+impl Event {
+    /// Name of the event, as it is sent
+    fn name(self) -> &'static str {
+        match self {
+            Self::Drift => "drift",
+            Self::ApplyFailed => "apply-failed",
+        }
+    }
+}
+
+/// Body of the `POST` request sent to the [`Notify::webhook`]
+#[derive(Serialize, Debug)]
+pub struct Notification<'a> {
+    /// What happened
+    pub event: Event,
+    /// Machine where it happened
+    pub hostname: String,
+    /// Human-readable description of what happened
+    pub message: &'a str,
+}
+
+impl Notify {
+    /// Read the `[notify]` section of the config closest to `cwd`
+    pub fn discover(cwd: &Path) -> Result<Self> {
+        Ok(Config::discover(cwd)?.notify)
+    }
+
+    /// Send the notification to every configured destination, unless it is not about
+    /// one of the [`Notify::events`]
+    ///
+    /// Secrets are redacted from the `message`, which can contain errors that quote them
+    pub fn send(&self, limiter: &Limiter, event: Event, message: &str) -> Result<()> {
+        if !self.events.contains(&event) {
+            return Ok(());
+        }
+
+        let message = redact(message);
+        let notification = Notification {
+            event,
+            hostname: hostname(),
+            message: &message,
+        };
+
+        if let Some(webhook) = &self.webhook {
+            let body =
+                serde_json::to_string(&notification).context("failed to serialize notification")?;
+
+            limiter
+                .post(webhook, &body, TIMEOUT)
+                .with_context(|| eyre!("failed to send notification to {webhook}"))?;
+        }
+
+        if let Some(command) = &self.command {
+            let words = shellwords::split(command)
+                .with_context(|| eyre!("invalid notify command: {command}"))?;
+            let (program, args) = words
+                .split_first()
+                .with_context(|| eyre!("notify command is empty"))?;
+
+            let status = Command::new(program)
+                .args(args)
+                .env("DOTS_EVENT", event.name())
+                .env("DOTS_HOSTNAME", &notification.hostname)
+                .env("DOTS_MESSAGE", notification.message)
+                .status()
+                .with_context(|| eyre!("failed to run notify command: {command}"))?;
+
+            if !status.success() {
+                eyre::bail!("notify command `{command}` failed with {status}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::sensitive::Sensitive;
+    use crate::server::{Response, Server};
+
+    #[test]
+    fn webhook() {
+        let server = Server::new([Response::ok("")]);
+        let notify = Notify {
+            webhook: Some(server.url("/dots")),
+            ..Notify::default()
+        };
+
+        notify
+            .send(&Limiter::default(), Event::Drift, "2 files differ")
+            .unwrap();
+
+        let notification =
+            serde_json::from_slice::<serde_json::Value>(&server.join()[0].body).unwrap();
+        assert_eq!(
+            notification,
+            serde_json::json!({
+                "event": "drift",
+                "hostname": hostname(),
+                "message": "2 files differ",
+            })
+        );
+    }
+    #[test]
+    fn redacted() {
+        let _token = Sensitive::new("ghp_notify8f3a2c".to_string());
+        let server = Server::new([Response::ok("")]);
+        let notify = Notify {
+            webhook: Some(server.url("/dots")),
+            ..Notify::default()
+        };
+
+        notify
+            .send(
+                &Limiter::default(),
+                Event::ApplyFailed,
+                "invalid template: token = ghp_notify8f3a2c",
+            )
+            .unwrap();
+
+        let notification =
+            serde_json::from_slice::<serde_json::Value>(&server.join()[0].body).unwrap();
+        assert_eq!(
+            notification["message"],
+            "invalid template: token = [redacted]"
+        );
+    }
+
+    #[test]
+    fn events() {
+        // nothing listens on the port, so every notification that is sent fails
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let notify = Notify {
+            webhook: Some(format!("http://{addr}/dots")),
+            events: vec![Event::ApplyFailed],
+            ..Notify::default()
+        };
+
+        let limiter = Limiter::default();
+        assert!(
+            notify
+                .send(&limiter, Event::Drift, "2 files differ")
+                .is_ok()
+        );
+        assert!(notify.send(&limiter, Event::ApplyFailed, "failed").is_err());
+
+        let notify = toml::from_str::<Notify>("webhook = 'http://localhost'").unwrap();
+        assert_eq!(notify.events, [Event::Drift, Event::ApplyFailed]);
+    }
+}
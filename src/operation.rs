@@ -0,0 +1,1353 @@
+//! Contains [`Operation`]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::io::Write as _;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use std::{fmt, fs, io, iter, mem};
+
+use eyre::{Context as _, ContextCompat as _, Result, bail, eyre};
+use itertools::Itertools as _;
+use serde::{Deserialize, Serialize};
+use simply_colored::*;
+
+use crate::PathExt as _;
+use crate::analysis::{Contents, WritePath, backup_path};
+use crate::asset::Asset;
+use crate::dconf;
+use crate::defaults::{self, DefaultsType};
+use crate::diff::Diff;
+use crate::flatpak;
+use crate::index::ApplyIndex;
+use crate::journal::JournalSwap;
+use crate::probe;
+use crate::protect::Protect;
+use crate::termux::Termux;
+use crate::user::User;
+use crate::xattrs;
+
+/// A single change to the filesystem that an apply makes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "dots-schema", derive(schemars::JsonSchema))]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Operation {
+    /// Write contents to a file
+    Write(WritePath),
+    /// Create a symlink at `path` which points to `target`
+    Symlink {
+        /// Where the symlink is created
+        path: PathBuf,
+        /// What the symlink points to
+        target: PathBuf,
+    },
+    /// Remove the file at `path`
+    Delete {
+        /// File to remove
+        path: PathBuf,
+    },
+    /// Move the file at `path` out of the exact directory `dir`, because it is not in the repo,
+    /// and remove the directories inside of `dir` that it leaves empty
+    Evict {
+        /// File to move
+        path: PathBuf,
+        /// Output directory of the `[[dir]]` with `exact = true`
+        dir: PathBuf,
+    },
+    /// Create the directory at `path`, along with its parents
+    Mkdir {
+        /// Directory to create
+        path: PathBuf,
+    },
+    /// Set the permissions of `path`
+    SetMode {
+        /// File whose permissions are set
+        path: PathBuf,
+        /// Unix permission bits, e.g. `0o600`
+        mode: u32,
+    },
+    /// Make the file at `path` writable and mutable again, so it can be replaced
+    Unlock {
+        /// File to unlock, nothing happens if it does not exist
+        path: PathBuf,
+    },
+    /// Remove write permission from the file at `path`, so other programs can not change it
+    Lock {
+        /// File to lock
+        path: PathBuf,
+        /// Also set the immutable attribute, so even its owner can not change it
+        immutable: bool,
+    },
+    /// Swap the directory at `staging` in place of the directory at `path`, at once
+    SwapDir {
+        /// Directory to replace
+        path: PathBuf,
+        /// Directory which contains the new files
+        staging: PathBuf,
+        /// Move the old directory to `<name>.orig.dots` instead of removing it
+        backup: bool,
+    },
+    /// Run a command after the files before it were applied
+    RunHook {
+        /// Command to run, split into words like a shell would
+        command: String,
+    },
+    /// Set a macOS preference with `defaults write`
+    WriteDefaults {
+        /// Domain of the preference, e.g. `com.apple.dock`
+        domain: String,
+        /// Name of the preference, e.g. `autohide`
+        key: String,
+        /// Type of the value
+        #[serde(rename = "type")]
+        value_type: DefaultsType,
+        /// Value of the preference
+        value: String,
+    },
+    /// Replace the permissions of a Flatpak app with `flatpak override --user`
+    OverrideFlatpak {
+        /// ID of the app, e.g. `org.mozilla.firefox`
+        app: String,
+        /// Filesystems that the app can access
+        filesystems: Vec<String>,
+        /// Environment variables of the app
+        env: BTreeMap<String, String>,
+    },
+    /// Write settings into a dconf directory with `dconf load`
+    LoadDconf {
+        /// Directory of the settings, e.g. `/org/gnome/desktop/interface/`
+        path: String,
+        /// Keyfile with the settings, in the format of `dconf dump`
+        settings: String,
+    },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Write(write) => write!(f, "write to {}", write.path.show()),
+            Self::Symlink { path, target } => {
+                write!(f, "create symlink {} → {}", path.show(), target.show())
+            }
+            Self::Delete { path } => write!(f, "remove {}", path.show()),
+            Self::Evict { path, .. } => {
+                write!(f, "remove {}, which is not in the repo", path.show())
+            }
+            Self::Mkdir { path } => write!(f, "create directory {}", path.show()),
+            Self::SetMode { path, mode } => write!(f, "set mode of {} to {mode:o}", path.show()),
+            Self::Unlock { path } => write!(f, "make {} writable", path.show()),
+            Self::Lock { path, immutable } => {
+                let lock = if *immutable { "immutable" } else { "read-only" };
+                write!(f, "make {} {lock}", path.show())
+            }
+            Self::SwapDir { path, staging, .. } => {
+                write!(f, "swap {} into {}", staging.show(), path.show())
+            }
+            Self::RunHook { command } => write!(f, "run hook `{command}`"),
+            Self::LoadDconf { path, .. } => write!(f, "load dconf settings into {path}"),
+            Self::OverrideFlatpak { app, .. } => write!(f, "override flatpak permissions of {app}"),
+            Self::WriteDefaults {
+                domain, key, value, ..
+            } => write!(f, "set {domain} {key} to {value}"),
+        }
+    }
+}
+
+impl Operation {
+    /// Path that the operation changes
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Write(write) => Some(&write.path),
+            Self::Symlink { path, .. }
+            | Self::Delete { path }
+            | Self::Evict { path, .. }
+            | Self::Mkdir { path }
+            | Self::SetMode { path, .. }
+            | Self::Unlock { path }
+            | Self::Lock { path, .. }
+            | Self::SwapDir { path, .. } => Some(path),
+            Self::RunHook { .. }
+            | Self::LoadDconf { .. }
+            | Self::WriteDefaults { .. }
+            | Self::OverrideFlatpak { .. } => None,
+        }
+    }
+
+    /// Directory that it swaps, which is recorded in the [`Journal`](crate::Journal) so
+    /// an interrupted swap can be rolled back
+    pub fn journaled_swap(&self) -> Option<JournalSwap> {
+        let Self::SwapDir { path, staging, .. } = self else {
+            return None;
+        };
+
+        Some(JournalSwap {
+            path: path.clone(),
+            old: exchanged_at(staging),
+        })
+    }
+
+    /// Path whose previous contents must be backed up in the [`Journal`](crate::Journal),
+    /// so the operation can be rolled back
+    pub fn journaled_path(&self) -> Option<&Path> {
+        match self {
+            Self::Write(write) => Some(&write.path),
+            Self::Symlink { path, .. } | Self::Delete { path } | Self::Evict { path, .. } => {
+                Some(path)
+            }
+            // files inside of the directory are journaled by the writes that create them
+            Self::Mkdir { .. }
+            | Self::SetMode { .. }
+            | Self::Unlock { .. }
+            | Self::Lock { .. }
+            | Self::SwapDir { .. }
+            | Self::RunHook { .. }
+            | Self::LoadDconf { .. }
+            | Self::WriteDefaults { .. }
+            | Self::OverrideFlatpak { .. } => None,
+        }
+    }
+}
+
+/// How long an operation may take, before it is considered to be stuck
+///
+/// Writes to network filesystems, such as sshfs or NFS, can hang indefinitely
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchdog {
+    /// Warn about operations which take longer than this
+    pub stuck_after: Duration,
+    /// Give up on operations which take longer than this, and continue with the next one
+    ///
+    /// A thread can not be stopped, so the operation keeps running in the background.
+    /// [`Executor::wait`] waits for it before the index is saved. `None` waits for as long
+    /// as it takes
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self {
+            stuck_after: Duration::from_secs(10),
+            timeout: None,
+        }
+    }
+}
+
+/// Executes [`Operation`]s one after another
+///
+/// [`Analysis::finish`](crate::Analysis::finish) drives it after probing and journaling every path,
+/// prefer that unless the operations must be executed one at a time.
+pub struct Executor<'a> {
+    /// Existing files which are renamed to `<name>.orig.dots` instead of being replaced
+    pub backups: &'a BTreeSet<PathBuf>,
+    /// User which owns every created file
+    pub owner: Option<&'a User>,
+    /// Records every written file
+    pub index: &'a mut ApplyIndex,
+    /// Every path that a file or symlink was created at
+    pub written: Vec<PathBuf>,
+    /// When to warn about and give up on operations that are stuck
+    pub watchdog: Watchdog,
+    /// Keep extended attributes of files that are replaced, such as SELinux contexts
+    pub preserve_xattrs: bool,
+    /// Paths which must never be touched
+    pub protect: Protect,
+    /// Directory that files are moved into when they are evicted from exact directories,
+    /// see [`Journal::removed_dir`](crate::Journal::removed_dir)
+    ///
+    /// `None` moves them to `<name>.orig.dots` next to where they are
+    pub removed_dir: Option<PathBuf>,
+    /// Termux, if `dots` runs inside of it, whose shared storage can not have symlinks
+    pub termux: Option<Termux>,
+    /// Thread that executes operations, so they can be given up on
+    worker: Option<Worker>,
+    /// Threads with an operation that was given up on, along with its description
+    abandoned: Vec<(Worker, String)>,
+}
+
+impl<'a> Executor<'a> {
+    /// Create an executor, which has not executed anything yet
+    ///
+    /// Inside of `termux`, files are copied into its shared storage instead of being linked
+    pub fn new(
+        backups: &'a BTreeSet<PathBuf>,
+        owner: Option<&'a User>,
+        index: &'a mut ApplyIndex,
+        watchdog: Watchdog,
+        termux: Option<Termux>,
+    ) -> Self {
+        Self {
+            backups,
+            owner,
+            index,
+            written: vec![],
+            watchdog,
+            preserve_xattrs: false,
+            protect: Protect::default(),
+            removed_dir: None,
+            termux,
+            worker: None,
+            abandoned: vec![],
+        }
+    }
+
+    /// Execute a single operation
+    pub fn execute(&mut self, operation: Operation) -> Result<()> {
+        let description = operation.to_string();
+
+        if let Some((path, pattern)) = self.protected(&operation) {
+            bail!(
+                "refusing to {description}, {} is protected by `{pattern}`",
+                path.show()
+            );
+        }
+        let swapped = match &operation {
+            Operation::SwapDir { path, staging, .. } => Some((staging.clone(), path.clone())),
+            _ => None,
+        };
+        let task = Task {
+            backup: operation
+                .path()
+                .is_some_and(|path| self.backups.contains(path)),
+            owner: self.owner.cloned(),
+            preserve_xattrs: self.preserve_xattrs,
+            removed_dir: self.removed_dir.clone(),
+            termux: self.termux.clone(),
+            operation,
+        };
+
+        let Watchdog {
+            stuck_after,
+            timeout,
+        } = self.watchdog;
+        let stuck_after = timeout.map_or(stuck_after, |timeout| timeout.min(stuck_after));
+
+        let worker = self.worker.get_or_insert_with(Worker::spawn);
+        worker
+            .tasks
+            .send(task)
+            .map_err(|_| eyre!("failed to {description}, the worker thread stopped"))?;
+
+        let result = match worker.results.recv_timeout(stuck_after) {
+            Err(RecvTimeoutError::Timeout) => {
+                log::warn!(
+                    target: "apply",
+                    "{YELLOW}stuck{RESET} trying to {description} for {}s",
+                    stuck_after.as_secs()
+                );
+
+                let result = match timeout {
+                    Some(timeout) => worker
+                        .results
+                        .recv_timeout(timeout.saturating_sub(stuck_after)),
+                    None => worker.results.recv().map_err(RecvTimeoutError::from),
+                };
+
+                if matches!(result, Err(RecvTimeoutError::Timeout)) {
+                    // the stuck thread is left behind, the next operation gets a new one
+                    if let Some(worker) = self.worker.take() {
+                        self.abandoned.push((worker, description.clone()));
+                    }
+                    bail!(
+                        "skipped, failed to {description} in {}s",
+                        timeout.unwrap_or_default().as_secs()
+                    );
+                }
+
+                result
+            }
+            result => result,
+        }
+        .map_err(|_| eyre!("failed to {description}, the worker thread stopped"))?;
+
+        if let Some(created) = result? {
+            self.created(created);
+        }
+
+        if let Some((staging, path)) = swapped {
+            self.moved(&staging, &path);
+        }
+
+        Ok(())
+    }
+
+    /// Wait for every operation that was given up on to finish, so none of them changes a file
+    /// after the index is saved
+    ///
+    /// The files they created are recorded, as they are on disk now
+    pub fn wait(&mut self) {
+        for (
+            Worker {
+                tasks,
+                results,
+                thread,
+            },
+            description,
+        ) in mem::take(&mut self.abandoned)
+        {
+            // the thread stops after its operation
+            drop(tasks);
+
+            if !thread.is_finished() {
+                log::warn!(target: "apply", "waiting for {description} to finish");
+            }
+            if thread.join().is_err() {
+                log::error!(target: "apply", "failed to {description}, the worker thread panicked");
+                continue;
+            }
+
+            if let Ok(Ok(Some(created))) = results.try_recv() {
+                log::info!(target: "apply", "finished {description} after it was given up on");
+                self.created(created);
+            }
+        }
+    }
+
+    /// Record that a file or symlink was `created`
+    fn created(&mut self, Created { path, hash }: Created) {
+        if let Some(hash) = hash {
+            self.index.record(&path, hash);
+        }
+        self.written.push(path);
+    }
+
+    /// A path that the `operation` would touch, which is protected by the returned glob
+    fn protected(&self, operation: &Operation) -> Option<(PathBuf, &str)> {
+        let path = operation.path()?;
+
+        // swapping a directory removes everything inside of it
+        let inside = match operation {
+            Operation::SwapDir { .. } => walkdir::WalkDir::new(path)
+                .into_iter()
+                .flatten()
+                .map(walkdir::DirEntry::into_path)
+                .collect(),
+            _ => vec![],
+        };
+
+        iter::once(path.to_path_buf())
+            .chain(inside)
+            .find_map(|path| Some((path.clone(), self.protect.protecting(&path)?)))
+    }
+
+    /// Every file that was written into the directory `from` is now inside of the directory `to`
+    fn moved(&mut self, from: &Path, to: &Path) {
+        let relocate = |path: &Path| {
+            path.strip_prefix(from)
+                .map_or_else(|_| path.to_path_buf(), |relative| to.join(relative))
+        };
+
+        for path in &mut self.written {
+            *path = relocate(path);
+        }
+
+        self.index.outputs = mem::take(&mut self.index.outputs)
+            .into_iter()
+            .map(|(path, indexed)| (relocate(&path), indexed))
+            .collect();
+    }
+}
+
+/// Thread which executes [`Task`]s one after another
+struct Worker {
+    /// Tasks to execute
+    tasks: mpsc::Sender<Task>,
+    /// Result of each task, in the same order
+    results: mpsc::Receiver<Result<Option<Created>>>,
+    /// The thread itself
+    thread: thread::JoinHandle<()>,
+}
+
+impl Worker {
+    /// Start the thread, which stops when the [`Worker`] is dropped
+    fn spawn() -> Self {
+        let (tasks, task_receiver) = mpsc::channel::<Task>();
+        let (result_sender, results) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            for task in task_receiver {
+                if result_sender.send(task.run()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            tasks,
+            results,
+            thread,
+        }
+    }
+}
+
+/// A file or symlink that was created by a [`Task`]
+struct Created {
+    /// Where it was created
+    path: PathBuf,
+    /// [`WritePath::hash`] of a file, to record in the [`ApplyIndex`]
+    hash: Option<String>,
+}
+
+/// An [`Operation`] along with everything needed to execute it on another thread
+struct Task {
+    /// What to do
+    operation: Operation,
+    /// Whether the existing file at the path of the `operation` is backed up, instead of being replaced
+    backup: bool,
+    /// User which owns every created file
+    owner: Option<User>,
+    /// Keep extended attributes of the file that is replaced
+    preserve_xattrs: bool,
+    /// See [`Executor::removed_dir`]
+    removed_dir: Option<PathBuf>,
+    /// See [`Executor::termux`]
+    termux: Option<Termux>,
+}
+
+impl Task {
+    /// Execute the operation
+    fn run(self) -> Result<Option<Created>> {
+        match &self.operation {
+            Operation::Write(write) => self.write(write).map(Some),
+            Operation::Symlink { path, target } => self.symlink(path, target).map(Some),
+            Operation::Delete { path } => {
+                remove(path)?;
+                log::warn!(target: "apply", "{RED}removed{RESET} {}", path.show());
+                Ok(None)
+            }
+            Operation::Evict { path, dir } => {
+                evict(path, dir, self.removed_dir.as_deref())?;
+                Ok(None)
+            }
+            Operation::Mkdir { path } => {
+                self.create_dir(path)?;
+                log::info!(target: "apply", "created directory {}", path.show());
+                Ok(None)
+            }
+            Operation::SetMode { path, mode } => {
+                set_mode(path, *mode)?;
+                log::info!(target: "apply", "set mode of {} to {mode:o}", path.show());
+                Ok(None)
+            }
+            Operation::Unlock { path } => {
+                if path.exists() {
+                    unlock(path)?;
+                    log::debug!(target: "apply", "made {} writable", path.show());
+                }
+                Ok(None)
+            }
+            Operation::Lock { path, immutable } => {
+                lock(path, *immutable)?;
+                log::debug!(target: "apply", "made {} read-only", path.show());
+                Ok(None)
+            }
+            Operation::SwapDir {
+                path,
+                staging,
+                backup,
+            } => {
+                swap_dir(path, staging, *backup)?;
+                log::info!(target: "apply", "swapped in {}", path.show());
+                Ok(None)
+            }
+            Operation::RunHook { command } => run_hook(command).map(|()| None),
+            Operation::WriteDefaults {
+                domain,
+                key,
+                value_type,
+                value,
+            } => {
+                defaults::write(domain, key, *value_type, value)?;
+                log::info!(target: "apply", "set {domain} {key} to {value}");
+                Ok(None)
+            }
+            Operation::LoadDconf { path, settings } => {
+                dconf::load(path, settings)?;
+                log::info!(target: "apply", "loaded dconf settings into {path}");
+                Ok(None)
+            }
+            Operation::OverrideFlatpak {
+                app,
+                filesystems,
+                env,
+            } => {
+                flatpak::apply(app, filesystems, env)?;
+                log::info!(target: "apply", "overrode flatpak permissions of {app}");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Write a single file
+    fn write(&self, write: &WritePath) -> Result<Created> {
+        let WritePath {
+            path,
+            contents,
+            source,
+            durable,
+            description: _,
+            symlink,
+            mode,
+        } = write;
+
+        if *symlink && let Some(source) = source {
+            return Ok(Created {
+                hash: Some(write.hash()),
+                ..self.symlink(path, source)?
+            });
+        }
+
+        // assets are copied from their source, which must still have the contents they were
+        // read with
+        let copied = match (contents, source) {
+            (Contents::Text(_) | Contents::Secret(_), _) => None,
+            (Contents::Asset(asset), Some(source)) => Some((asset, source)),
+            (Contents::Asset(_), None) => bail!(
+                "{} is not text, but there is no file to copy it from",
+                path.show()
+            ),
+        };
+
+        // a missing or binary file is treated as empty, for the purpose of showing the diff.
+        // assets are not diffed, so there is no need to read them
+        let previous_contents = if copied.is_some() {
+            String::new()
+        } else {
+            fs::read_to_string(path).unwrap_or_default()
+        };
+        let xattrs = xattrs::read(path);
+
+        let dir = path
+            .parent()
+            .with_context(|| eyre!("failed to obtain parent of {}", path.show()))?;
+        self.create_dir(dir)?;
+
+        // written next to the file and renamed over it, so the file is never missing or
+        // half-written, even if the apply is interrupted
+        let tmp = tmp_path(path);
+        remove(&tmp)?;
+        let written = create(&tmp, *mode)
+            .and_then(|mut file| {
+                match copied {
+                    Some((_, source)) => {
+                        io::copy(&mut fs::File::open(source)?, &mut file).map(|_| ())?
+                    }
+                    None => file.write_all(contents.expose().as_bytes())?,
+                }
+                if *durable {
+                    file.sync_all()?;
+                }
+                Ok(())
+            })
+            .with_context(|| eyre!("failed to write to {}", path.show()))
+            .and_then(|()| {
+                if let Some((asset, source)) = copied {
+                    let hash = Asset::hash_file(&tmp)
+                        .with_context(|| eyre!("failed to hash {}", tmp.show()))?;
+                    if hash != asset.hash {
+                        bail!(
+                            "{} changed since it was read, not copying it to {}",
+                            source.show(),
+                            path.show()
+                        );
+                    }
+                }
+                if self.backup {
+                    backup(path)?;
+                }
+                fs::rename(&tmp, path).with_context(|| eyre!("failed to write to {}", path.show()))
+            });
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        written?;
+
+        self.chown(path);
+
+        // the file is already written, so it is recorded even if its attributes are not
+        let dropped = xattrs::dropped(path, xattrs);
+        if self.preserve_xattrs {
+            if let Err(err) = xattrs::restore(path, &dropped) {
+                log::error!(target: "apply", "{err:?}");
+            }
+        } else if !dropped.is_empty() {
+            log::warn!(
+                target: "apply",
+                "dropped {} extended attributes of {}, such as its SELinux context. set `preserve_xattrs = true` to keep them",
+                dropped.len(),
+                path.show()
+            );
+        }
+
+        if *durable {
+            sync(path)?;
+        }
+
+        let created = Created {
+            path: path.clone(),
+            hash: Some(write.hash()),
+        };
+
+        if let Contents::Asset(asset) = contents {
+            log::info!(target: "apply", "wrote to {} {BLACK}({} bytes){RESET}", path.show(), asset.len);
+            return Ok(created);
+        }
+
+        let diff = Diff::new(&previous_contents, contents.expose());
+        log::info!(target: "apply", "wrote to {} {}", path.show(), diff.show_stat());
+
+        // the hunks would show the secrets
+        if !contents.is_sensitive() && log::log_enabled!(target: "apply", log::Level::Debug) {
+            let hunks = diff.show_hunks();
+            if !hunks.is_empty() {
+                log::debug!(target: "apply", "\n{hunks}");
+            }
+        }
+
+        Ok(created)
+    }
+
+    /// Create a symlink at `path` which points to `target`
+    fn symlink(&self, path: &Path, target: &Path) -> Result<Created> {
+        self.replace(path)?;
+
+        // shared storage on Android does not support symlinks
+        if self
+            .termux
+            .as_ref()
+            .is_some_and(|termux| termux.is_shared(path))
+        {
+            fs::copy(target, path)
+                .with_context(|| eyre!("failed to copy {} to {}", target.show(), path.show()))?;
+            log::info!(target: "apply", "copied {} → {}, shared storage can not have symlinks", target.show(), path.show());
+
+            return Ok(Created {
+                path: path.to_path_buf(),
+                hash: None,
+            });
+        }
+
+        #[cfg(unix)]
+        let result = std::os::unix::fs::symlink(target, path);
+        #[cfg(windows)]
+        let result = std::os::windows::fs::symlink_file(target, path);
+
+        result.with_context(|| {
+            eyre!(
+                "failed to create symlink {} → {}",
+                path.show(),
+                target.show()
+            )
+        })?;
+
+        log::info!(target: "apply", "linked {} → {}", path.show(), target.show());
+
+        Ok(Created {
+            path: path.to_path_buf(),
+            hash: None,
+        })
+    }
+
+    /// Make room for a new file at `path`: move the existing file to a backup
+    /// or remove it, and create the parent directory
+    fn replace(&self, path: &Path) -> Result<()> {
+        if self.backup {
+            backup(path)?;
+        } else {
+            remove(path)?;
+
+            log::warn!(target: "apply", "{RED}removed{RESET} {}", path.show());
+        }
+
+        let dir = path
+            .parent()
+            .with_context(|| eyre!("failed to obtain parent of {}", path.show()))?;
+
+        self.create_dir(dir)
+    }
+
+    /// Create the directory at `dir` along with its parents, all of which are owned by the `owner`
+    fn create_dir(&self, dir: &Path) -> Result<()> {
+        // directories which do not exist yet
+        let created = dir
+            .ancestors()
+            .take_while(|ancestor| !ancestor.exists())
+            .map(Path::to_path_buf)
+            .collect_vec();
+
+        fs::create_dir_all(dir)
+            .with_context(|| eyre!("failed to create directory {}", dir.show()))?;
+
+        for dir in &created {
+            self.chown(dir);
+        }
+
+        Ok(())
+    }
+
+    /// Make the `owner` own `path`
+    fn chown(&self, path: &Path) {
+        if let Some(owner) = &self.owner
+            && let Err(err) = owner.chown(path)
+        {
+            log::error!(target: "apply", "{err}");
+        }
+    }
+}
+
+/// Flush the file at `path` and the directory containing it to disk, so that both
+/// its contents and its name survive a crash
+fn sync(path: &Path) -> Result<()> {
+    let context = || eyre!("failed to flush {} to disk", path.show());
+
+    fs::File::open(path)
+        .and_then(|file| file.sync_all())
+        .with_context(context)?;
+
+    // only unix can flush the entries of a directory
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        fs::File::open(dir)
+            .and_then(|dir| dir.sync_all())
+            .with_context(context)?;
+    }
+
+    Ok(())
+}
+
+/// Replace the directory at `path` with the directory at `staging`, so that nothing ever sees
+/// a directory which contains some of the new files and some of the old files
+fn swap_dir(path: &Path, staging: &Path, backup: bool) -> Result<()> {
+    if !path.exists() {
+        return fs::rename(staging, path)
+            .with_context(|| eyre!("failed to move {} to {}", staging.show(), path.show()));
+    }
+
+    exchange(staging, path)
+        .with_context(|| eyre!("failed to swap {} with {}", staging.show(), path.show()))?;
+
+    // the old directory is at `staging` now
+    if backup {
+        let backup = backup_path(path);
+        if backup.exists() {
+            fs::remove_dir_all(&backup)
+                .with_context(|| eyre!("failed to remove old backup {}", backup.show()))?;
+        }
+        fs::rename(staging, &backup)
+            .with_context(|| eyre!("failed to back up {} to {}", path.show(), backup.show()))?;
+
+        log::warn!(target: "apply", "{YELLOW}moved{RESET} the old {} to {}", path.show(), backup.show());
+    } else {
+        fs::remove_dir_all(staging)
+            .with_context(|| eyre!("failed to remove old directory {}", staging.show()))?;
+    }
+
+    Ok(())
+}
+
+/// Atomically exchange the directories `a` and `b`
+#[cfg(target_os = "linux")]
+fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt as _;
+
+    let c_a = CString::new(a.as_os_str().as_bytes())?;
+    let c_b = CString::new(b.as_os_str().as_bytes())?;
+
+    // SAFETY: both paths are valid NUL-terminated strings
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            c_a.as_ptr(),
+            libc::AT_FDCWD,
+            c_b.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+
+    if result == 0 {
+        return Ok(());
+    }
+
+    match io::Error::last_os_error() {
+        // the filesystem does not support exchanging
+        err if matches!(err.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) => {
+            exchange_with_renames(a, b)
+        }
+        err => Err(err),
+    }
+}
+
+/// Exchange the directories `a` and `b`, which is not atomic since only Linux can exchange
+/// them, see [`exchange_with_renames`]
+#[cfg(not(target_os = "linux"))]
+fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    exchange_with_renames(a, b)
+}
+
+/// Exchange the directories `a` and `b` with three renames, where it can not be done
+/// atomically. `b` does not exist only between the first two renames, while it is at
+/// [`exchanged_at`], which the [`Journal`](crate::Journal) restores if the apply is interrupted
+fn exchange_with_renames(a: &Path, b: &Path) -> io::Result<()> {
+    let tmp = exchanged_at(a);
+
+    fs::rename(b, &tmp)?;
+    fs::rename(a, b)?;
+    fs::rename(&tmp, a)
+}
+
+/// Where the directory that is exchanged with `a` is moved to, while they are exchanged
+/// with [`exchange_with_renames`]
+fn exchanged_at(a: &Path) -> PathBuf {
+    let mut tmp = a.as_os_str().to_os_string();
+    tmp.push(".old");
+    PathBuf::from(tmp)
+}
+
+/// Move the file at `path` to `<name>.orig.dots`
+fn backup(path: &Path) -> Result<()> {
+    let backup = backup_path(path);
+    fs::rename(path, &backup)
+        .with_context(|| eyre!("failed to back up {} to {}", path.show(), backup.show()))?;
+
+    log::warn!(target: "apply", "{YELLOW}moved{RESET} {} to {}", path.show(), backup.show());
+
+    Ok(())
+}
+
+/// Move the file at `path` into `removed_dir`, at the same path as it has on the system, and
+/// remove the directories inside of `dir` which are empty afterwards
+///
+/// Without a `removed_dir`, it is moved to `<name>.orig.dots` instead
+fn evict(path: &Path, dir: &Path, removed_dir: Option<&Path>) -> Result<()> {
+    let Some(removed_dir) = removed_dir else {
+        return backup(path);
+    };
+
+    let removed = removed_dir.join(
+        path.components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect::<PathBuf>(),
+    );
+    if let Some(parent) = removed.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| eyre!("failed to create directory {}", parent.show()))?;
+    }
+    // the journal directory can be on another filesystem
+    fs::rename(path, &removed)
+        .or_else(|_| fs::copy(path, &removed).and_then(|_| fs::remove_file(path)))
+        .with_context(|| eyre!("failed to move {} to {}", path.show(), removed.show()))?;
+    log::warn!(target: "apply", "{YELLOW}moved{RESET} {} to {}", path.show(), removed.show());
+
+    // removing a directory which is not empty fails, which leaves it and its parents
+    for parent in path
+        .ancestors()
+        .skip(1)
+        .take_while(|parent| *parent != dir && parent.starts_with(dir))
+    {
+        if fs::remove_dir(parent).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Create the file at `path`, with the permissions `mode` if there are any
+///
+/// The file is created with them, so there is no moment when it has the default ones
+fn create(path: &Path, mode: Option<u32>) -> io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::OpenOptionsExt as _;
+
+        options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    {
+        _ = mode;
+    }
+
+    options.open(path)
+}
+
+/// Where the new contents of the file at `path` are written, before they are renamed over it
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".dots-tmp");
+    path.with_file_name(name)
+}
+
+/// Remove the file at `path`, if there is one
+fn remove(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result.with_context(|| eyre!("failed to remove file {}", path.show())),
+    }
+}
+
+/// Set the permissions of `path` to `mode`
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| eyre!("failed to set mode of {} to {mode:o}", path.show()))
+}
+
+/// Set the permissions of `path` to `mode`
+#[cfg(not(unix))]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    log::warn!(target: "apply", "not setting mode of {} to {mode:o}, it is only supported on unix", path.show());
+    Ok(())
+}
+
+/// Remove write permission from `path`, and set the immutable attribute if `immutable`
+fn lock(path: &Path, immutable: bool) -> Result<()> {
+    let mut permissions = fs::metadata(path)
+        .with_context(|| eyre!("failed to read permissions of {}", path.show()))?
+        .permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(path, permissions)
+        .with_context(|| eyre!("failed to make {} read-only", path.show()))?;
+
+    if immutable {
+        set_immutable(path, true)?;
+    }
+
+    Ok(())
+}
+
+/// Make `path` writable again, removing the immutable attribute if it has it
+fn unlock(path: &Path) -> Result<()> {
+    if probe::is_immutable(path) {
+        set_immutable(path, false)?;
+    }
+
+    let mut permissions = fs::metadata(path)
+        .with_context(|| eyre!("failed to read permissions of {}", path.show()))?
+        .permissions();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        // only the owner can write to it, like `set_readonly(false)` would do for everyone
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    permissions.set_readonly(false);
+
+    fs::set_permissions(path, permissions)
+        .with_context(|| eyre!("failed to make {} writable", path.show()))
+}
+
+/// Set or remove the immutable attribute of `path`
+fn set_immutable(path: &Path, immutable: bool) -> Result<()> {
+    let (program, flag) = if cfg!(target_os = "macos") {
+        ("chflags", if immutable { "uchg" } else { "nouchg" })
+    } else if cfg!(target_os = "linux") {
+        ("chattr", if immutable { "+i" } else { "-i" })
+    } else {
+        log::warn!(target: "apply", "not making {} immutable, it is only supported on Linux and macOS", path.show());
+        return Ok(());
+    };
+
+    let output = Command::new(program)
+        .arg(flag)
+        .arg(path)
+        .output()
+        .with_context(|| eyre!("failed to run `{program}`"))?;
+    if !output.status.success() {
+        bail!(
+            "`{program} {flag} {}` failed with {}: {}",
+            path.show(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Run the `command`, failing if it exits unsuccessfully
+fn run_hook(command: &str) -> Result<()> {
+    let words = shellwords::split(command).with_context(|| eyre!("invalid hook: {command}"))?;
+    let (program, args) = words
+        .split_first()
+        .with_context(|| eyre!("hook is empty"))?;
+
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| eyre!("failed to run hook: {command}"))?;
+
+    if !status.success() {
+        bail!("hook `{command}` failed with {status}");
+    }
+
+    log::info!(target: "apply", "ran hook `{command}`");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn execute() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        let mut index = ApplyIndex::default();
+        let backups = BTreeSet::new();
+        let mut executor = Executor::new(&backups, None, &mut index, Watchdog::default(), None);
+
+        let file = dir.join("nested/file.txt");
+        let secret = dir.join("secret.txt");
+        let link = dir.join("link.txt");
+
+        for operation in [
+            Operation::Mkdir {
+                path: dir.join("empty"),
+            },
+            Operation::Write(WritePath {
+                path: file.clone(),
+                contents: Contents::Text("foo".to_string()),
+                source: None,
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            }),
+            Operation::Write(WritePath {
+                path: secret.clone(),
+                contents: Contents::Text("bar".to_string()),
+                source: None,
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: Some(0o640),
+            }),
+            #[cfg(unix)]
+            Operation::Symlink {
+                path: link.clone(),
+                target: file.clone(),
+            },
+            Operation::SetMode {
+                path: file.clone(),
+                mode: 0o600,
+            },
+            Operation::Delete {
+                path: dir.join("does-not-exist"),
+            },
+        ] {
+            executor.execute(operation).unwrap();
+        }
+
+        assert!(dir.join("empty").is_dir());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "foo");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            assert_eq!(fs::read_link(&link).unwrap(), file);
+            assert_eq!(
+                fs::metadata(&file).unwrap().permissions().mode() & 0o777,
+                0o600
+            );
+            // created with the mode, instead of getting it afterwards
+            assert_eq!(
+                fs::metadata(&secret).unwrap().permissions().mode() & 0o777,
+                0o640
+            );
+        }
+
+        executor
+            .execute(Operation::Delete { path: file.clone() })
+            .unwrap();
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let file = dir.join("file.txt");
+        fs::write(&file, "old").unwrap();
+
+        let mut index = ApplyIndex::default();
+        let backups = BTreeSet::new();
+        let mut executor = Executor::new(&backups, None, &mut index, Watchdog::default(), None);
+        executor
+            .execute(Operation::Write(WritePath {
+                path: file.clone(),
+                contents: Contents::Text("new".to_string()),
+                source: None,
+                durable: true,
+                description: None,
+                symlink: false,
+                mode: None,
+            }))
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        // the temporary file was renamed over the target
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn asset() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let source = dir.join("wallpaper.png");
+        fs::write(&source, [0xff, 0xfe]).unwrap();
+        let asset = Asset {
+            hash: Asset::hash_file(&source).unwrap(),
+            len: 2,
+        };
+
+        let mut index = ApplyIndex::default();
+        let backups = BTreeSet::new();
+        let mut executor = Executor::new(&backups, None, &mut index, Watchdog::default(), None);
+        let write = |name: &str, source: Option<&Path>| {
+            Operation::Write(WritePath {
+                path: dir.join(name),
+                contents: Contents::Asset(asset.clone()),
+                source: source.map(Path::to_path_buf),
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: Some(0o600),
+            })
+        };
+
+        executor.execute(write("copy.png", Some(&source))).unwrap();
+        assert_eq!(fs::read(dir.join("copy.png")).unwrap(), [0xff, 0xfe]);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            // the mode is applied, instead of taking the permissions of the source
+            assert_eq!(
+                fs::metadata(dir.join("copy.png"))
+                    .unwrap()
+                    .permissions()
+                    .mode()
+                    & 0o777,
+                0o600
+            );
+        }
+
+        assert!(executor.execute(write("missing.png", None)).is_err());
+
+        fs::write(&source, [0xff]).unwrap();
+        assert!(
+            executor
+                .execute(write("changed.png", Some(&source)))
+                .is_err()
+        );
+        assert!(!dir.join("changed.png").exists());
+        assert!(!tmp_path(&dir.join("changed.png")).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        let mut index = ApplyIndex::default();
+        let backups = BTreeSet::new();
+        let mut executor = Executor::new(
+            &backups,
+            None,
+            &mut index,
+            Watchdog {
+                stuck_after: Duration::from_millis(10),
+                timeout: Some(Duration::from_millis(50)),
+            },
+            None,
+        );
+
+        let err = executor
+            .execute(Operation::RunHook {
+                command: format!(
+                    "sh -c 'sleep 0.5 && touch {}'",
+                    dir.join("stuck").to_string_lossy()
+                ),
+            })
+            .unwrap_err();
+        assert!(err.to_string().starts_with("skipped"));
+
+        // operations after the stuck one still run
+        executor
+            .execute(Operation::Mkdir {
+                path: dir.join("after"),
+            })
+            .unwrap();
+        assert!(dir.join("after").is_dir());
+        assert!(!dir.join("stuck").exists());
+
+        // the stuck operation finishes before the executor is done
+        executor.wait();
+        assert!(dir.join("stuck").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn termux() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let source = dir.join("repo/foo.txt");
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::write(&source, "foo").unwrap();
+
+        let mut index = ApplyIndex::default();
+        let backups = BTreeSet::new();
+        let termux = Termux {
+            prefix: dir.join("usr"),
+            shared_storage: dir.join("shared"),
+        };
+        let mut executor = Executor::new(
+            &backups,
+            None,
+            &mut index,
+            Watchdog::default(),
+            Some(termux),
+        );
+
+        let link = |path: PathBuf| {
+            Operation::Write(WritePath {
+                path,
+                contents: Contents::Text("foo".to_string()),
+                source: Some(source.clone()),
+                durable: false,
+                description: None,
+                symlink: true,
+                mode: None,
+            })
+        };
+        executor.execute(link(dir.join("home/foo.txt"))).unwrap();
+        executor.execute(link(dir.join("shared/foo.txt"))).unwrap();
+        executor.wait();
+
+        assert!(
+            fs::symlink_metadata(dir.join("home/foo.txt"))
+                .unwrap()
+                .is_symlink()
+        );
+        // shared storage can not have symlinks
+        let copied = fs::symlink_metadata(dir.join("shared/foo.txt")).unwrap();
+        assert!(copied.is_file());
+        assert_eq!(
+            fs::read_to_string(dir.join("shared/foo.txt")).unwrap(),
+            "foo"
+        );
+    }
+}
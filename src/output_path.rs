@@ -2,16 +2,19 @@
 
 use std::{fmt::Display, path::PathBuf, str::FromStr};
 
-use etcetera::BaseStrategy as _;
-use eyre::{Context as _, bail, eyre};
+use eyre::{Context as _, ContextCompat as _, bail};
 
+use crate::environment::Environment;
 use crate::stdx::PathExt as _;
+use crate::termux::Termux;
 
 /// Represents a path that will be written to.
 ///
 /// The `FromStr` impl for this allow for interpolation, i.e.
 /// if the config directory is `~/.config`, then `{config}/helix` will
 /// parse as `~/.config/helix`
+///
+/// `{{` and `}}` are a literal `{` and `}`
 #[nutype::nutype(derive(AsRef, Clone, Debug, From, PartialEq))]
 pub struct OutputPath(PathBuf);
 
@@ -25,12 +28,13 @@ impl FromStr for OutputPath {
     type Err = eyre::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let strategy = etcetera::choose_base_strategy()
-            .with_context(|| eyre!("failed to obtain base strategy"))?;
+        let environment = Environment::current()?;
 
         // expand tilde: ~/foo -> /home/user/foo
-        let s = if let Some(s) = s.strip_prefix("~/") {
-            strategy.home_dir().join(s).to_string_lossy().to_string()
+        let s = if s == "~" {
+            environment.home_dir.to_string_lossy().to_string()
+        } else if let Some(s) = s.strip_prefix("~/") {
+            environment.home_dir.join(s).to_string_lossy().to_string()
         } else {
             s.to_string()
         };
@@ -39,10 +43,24 @@ impl FromStr for OutputPath {
         let mut total = String::new();
 
         while let Some(ch) = chars.next() {
+            if ch == '}' {
+                // `}}` is an escaped `}`
+                if chars.clone().next() == Some('}') {
+                    chars.next();
+                }
+                total.push(ch);
+                continue;
+            }
             if ch != '{' {
                 total.push(ch);
                 continue;
             }
+            // `{{` is an escaped `{`
+            if chars.clone().next() == Some('{') {
+                chars.next();
+                total.push(ch);
+                continue;
+            }
 
             // if it's '{', now everything inside is a variable
             let mut variable = String::new();
@@ -52,17 +70,7 @@ impl FromStr for OutputPath {
                 variable.push(ch);
             }
 
-            let path = match variable.as_str() {
-                "data_dir" => strategy.data_dir(),
-                "config_dir" => strategy.config_dir(),
-                "cache_dir" => strategy.cache_dir(),
-                s if s.starts_with('$') => {
-                    let env = s.strip_prefix("$").expect("it starts with `$`");
-                    let var = std::env::var(env).context("env variable not found")?;
-                    var.into()
-                }
-                var => bail!("unknown variable: {var}"),
-            };
+            let path = resolve(&variable, &environment)?;
             let path = path.to_string_lossy().to_string();
 
             total.push_str(&path);
@@ -72,6 +80,70 @@ impl FromStr for OutputPath {
     }
 }
 
+/// Value of the `{variable}` in an [`OutputPath`]
+fn resolve(variable: &str, environment: &Environment) -> eyre::Result<PathBuf> {
+    let path = match variable {
+        "data_dir" => environment.data_dir.clone(),
+        "config_dir" => environment.config_dir.clone(),
+        "cache_dir" => environment.cache_dir.clone(),
+        "state_dir" => environment
+            .state_dir
+            .clone()
+            .context("`{state_dir}` is not available on this platform")?,
+        "runtime_dir" => environment
+            .runtime_dir
+            .clone()
+            // Termux has no runtime directory, but files in its temporary directory are
+            // just as private
+            .or_else(|| environment.termux.as_ref().map(Termux::tmp))
+            .context("`{runtime_dir}` is not available, `$XDG_RUNTIME_DIR` is not set")?,
+        "exe_dir" => std::env::current_exe()
+            .context("failed to obtain path of the `dots` executable")?
+            .parent()
+            .context("`dots` executable has no parent directory")?
+            .to_path_buf(),
+        // Termux has no `/tmp`, which is used when `$TMPDIR` is not set
+        "temp_dir" => match (environment.var("TMPDIR"), &environment.termux) {
+            (Some(temp_dir), _) => temp_dir.into(),
+            (None, Some(termux)) => termux.tmp(),
+            (None, None) if environment.family == "unix" => PathBuf::from("/tmp"),
+            (None, None) => std::env::temp_dir(),
+        },
+        "termux_prefix" => environment
+            .termux
+            .as_ref()
+            .context("`{termux_prefix}` is only available inside of Termux")?
+            .prefix
+            .clone(),
+        "shared_storage" => environment
+            .termux
+            .as_ref()
+            .context("`{shared_storage}` is only available inside of Termux")?
+            .shared_storage
+            .clone(),
+        "win_home" => environment
+            .windows
+            .as_ref()
+            .context("`{win_home}` is only available inside of WSL")?
+            .home
+            .clone(),
+        "win_appdata" => environment
+            .windows
+            .as_ref()
+            .context("`{win_appdata}` is only available inside of WSL")?
+            .appdata
+            .clone(),
+        s if s.starts_with('$') => {
+            let env = s.strip_prefix("$").expect("it starts with `$`");
+            let var = environment.var(env).context("env variable not found")?;
+            var.into()
+        }
+        var => bail!("unknown variable: {var}"),
+    };
+
+    Ok(path)
+}
+
 impl<'de> serde::Deserialize<'de> for OutputPath {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -87,17 +159,68 @@ impl<'de> serde::Deserialize<'de> for OutputPath {
 mod tests {
     use super::*;
 
+    use std::collections::BTreeMap;
+
     #[test]
     fn parse() {
+        Environment::new("/home/me").scope(|| {
+            assert_eq!(
+                "{config_dir}".parse::<OutputPath>().unwrap(),
+                PathBuf::from("/home/me/.config").into()
+            );
+        });
+    }
+
+    #[test]
+    fn parse_dirs() {
+        let environment = Environment {
+            vars: Some(BTreeMap::from([(
+                "TMPDIR".to_string(),
+                "/tmp/me".to_string(),
+            )])),
+            ..Environment::new("/home/me")
+        };
+        environment.scope(|| {
+            assert_eq!(
+                "{temp_dir}/foo".parse::<OutputPath>().unwrap(),
+                PathBuf::from("/tmp/me/foo").into()
+            );
+            assert!("{termux_prefix}".parse::<OutputPath>().is_err());
+        });
+
+        let termux = Environment {
+            termux: Some(Termux {
+                prefix: "/data/data/com.termux/files/usr".into(),
+                shared_storage: "/storage/emulated/0".into(),
+            }),
+            ..Environment::new("/data/data/com.termux/files/home")
+        };
+        termux.scope(|| {
+            assert_eq!(
+                "{temp_dir}".parse::<OutputPath>().unwrap(),
+                PathBuf::from("/data/data/com.termux/files/usr/tmp").into()
+            );
+        });
+
         assert_eq!(
-            "{config_dir}".parse::<OutputPath>().unwrap(),
-            etcetera::choose_base_strategy()
+            "{exe_dir}".parse::<OutputPath>().unwrap(),
+            std::env::current_exe()
                 .unwrap()
-                .config_dir()
+                .parent()
+                .unwrap()
+                .to_path_buf()
                 .into()
         );
     }
 
+    #[test]
+    fn parse_escaped() {
+        assert_eq!(
+            "{temp_dir}/{{foo}}/}}".parse::<OutputPath>().unwrap(),
+            std::env::temp_dir().join("{foo}/}").into()
+        );
+    }
+
     #[test]
     fn parse_fail() {
         let err = "{$ENV_VARIABLE_WHICH_DOES_NOT_EXIST_241503142350}"
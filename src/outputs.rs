@@ -0,0 +1,218 @@
+//! Contains [`Output`]
+//!
+//! A template can use the rendered contents and the location of another managed file,
+//! e.g. a script which sources a generated theme. The other file is named by its path
+//! in the repo, and is rendered first
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+
+use eyre::{Result, bail};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError, RenderErrorReason,
+    ScopedJson,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+/// A rendered file, as seen by the templates of other files
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    /// Where the file is written to
+    pub path: PathBuf,
+    /// Rendered contents of the file
+    pub contents: String,
+    /// The contents contain secrets, so the files which use them do too
+    #[serde(skip)]
+    pub sensitive: bool,
+}
+
+/// Name of a file at `path` inside of the repo, such as `kitty/theme.conf`
+pub fn name(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Every file that `template` uses with `output`, such as `output "kitty/theme.conf"`
+/// in handlebars or `output(file="kitty/theme.conf")` in tera
+pub fn references(template: &str) -> BTreeSet<String> {
+    let mut references = BTreeSet::new();
+
+    for (start, _) in template.match_indices("output") {
+        let is_word = template[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|ch| !(ch.is_alphanumeric() || ch == '_' || ch == '.'));
+        if !is_word {
+            continue;
+        }
+
+        let rest = template[start + "output".len()..].trim_start();
+        let rest = rest.strip_prefix('(').unwrap_or(rest).trim_start();
+        let rest = rest
+            .strip_prefix("file")
+            .map(|rest| rest.trim_start())
+            .and_then(|rest| rest.strip_prefix('='))
+            .unwrap_or(rest)
+            .trim_start();
+
+        let Some(quote) = rest.chars().next().filter(|ch| matches!(ch, '"' | '\'')) else {
+            continue;
+        };
+        if let Some((name, _)) = rest[1..].split_once(quote) {
+            references.insert(name.to_string());
+        }
+    }
+
+    references
+}
+
+/// Order in which to render files called `names` so each one is rendered after the files it
+/// `references`
+///
+/// Files which do not depend on each other keep their order
+pub fn order(names: &[String], references: &[BTreeSet<String>]) -> Result<Vec<usize>> {
+    /// Whether a file is being visited, or already ordered
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum State {
+        Visiting,
+        Done,
+    }
+
+    /// Order `index` after every file it references
+    fn visit(
+        index: usize,
+        names: &[String],
+        references: &[BTreeSet<String>],
+        indices: &BTreeMap<&str, usize>,
+        states: &mut [Option<State>],
+        order: &mut Vec<usize>,
+    ) -> Result<()> {
+        match states[index] {
+            Some(State::Done) => return Ok(()),
+            Some(State::Visiting) => {
+                bail!(
+                    "templates which use each other with `output`, including {}",
+                    names[index]
+                );
+            }
+            None => (),
+        }
+        states[index] = Some(State::Visiting);
+
+        for reference in &references[index] {
+            if let Some(&dependency) = indices.get(reference.as_str()) {
+                visit(dependency, names, references, indices, states, order)?;
+            }
+        }
+
+        states[index] = Some(State::Done);
+        order.push(index);
+
+        Ok(())
+    }
+
+    // a file with copies in `also` is referenced by its first output
+    let mut indices = BTreeMap::new();
+    for (index, name) in names.iter().enumerate() {
+        indices.entry(name.as_str()).or_insert(index);
+    }
+
+    let mut states = vec![None; names.len()];
+    let mut order = Vec::with_capacity(names.len());
+    for index in 0..names.len() {
+        visit(index, names, references, &indices, &mut states, &mut order)?;
+    }
+
+    Ok(order)
+}
+
+/// Register `output` into `handlebars`, used like `{{ lookup (output "kitty/theme.conf") "path" }}`
+pub fn register<'reg>(handlebars: &mut Handlebars<'reg>, outputs: &'reg BTreeMap<String, Output>) {
+    handlebars.register_helper("output", Box::new(OutputHelper(outputs)));
+}
+
+/// Register `output` into `tera` as a function, used like
+/// `{% set theme = output(file="kitty/theme.conf") %}{{ theme.path }}`
+pub fn register_function(tera: &mut tera::Tera, outputs: &BTreeMap<String, Output>) {
+    let outputs = outputs.clone();
+    tera.register_function(
+        "output",
+        move |args: &std::collections::HashMap<String, Value>| {
+            let name = args.get("file").and_then(Value::as_str).ok_or_else(|| {
+                tera::Error::msg("`output` takes the file, e.g. `file=\"kitty/theme.conf\"`")
+            })?;
+
+            lookup(&outputs, name).map_err(tera::Error::msg)
+        },
+    );
+}
+
+/// The file called `name`, as a value of a template
+fn lookup(outputs: &BTreeMap<String, Output>, name: &str) -> Result<Value, String> {
+    let output = outputs
+        .get(name)
+        .ok_or_else(|| format!("`output`: {name} is not a managed file which could be rendered"))?;
+
+    serde_json::to_value(output).map_err(|err| format!("`output`: {err}"))
+}
+
+/// Handlebars helper that returns another rendered file
+struct OutputHelper<'a>(&'a BTreeMap<String, Output>);
+
+impl HelperDef for OutputHelper<'_> {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let name = h
+            .param(0)
+            .and_then(|param| param.value().as_str())
+            .ok_or_else(|| {
+                RenderErrorReason::Other(
+                    "`output` takes the file, e.g. `output \"kitty/theme.conf\"`".to_string(),
+                )
+            })?;
+
+        lookup(self.0, name)
+            .map(ScopedJson::Derived)
+            .map_err(|err| RenderErrorReason::Other(err).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order() {
+        let names = ["a.sh", "kitty/theme.conf", "b.sh"].map(ToString::to_string);
+        let references = [
+            super::references(r#"source {{ lookup (output "kitty/theme.conf") "path" }}"#),
+            BTreeSet::new(),
+            super::references(r#"{% set a = output(file = 'a.sh') %}{{ my_output "x" }}"#),
+        ];
+        assert_eq!(
+            references[0],
+            BTreeSet::from(["kitty/theme.conf".to_string()])
+        );
+        assert_eq!(references[2], BTreeSet::from(["a.sh".to_string()]));
+
+        assert_eq!(super::order(&names, &references).unwrap(), [1, 0, 2]);
+
+        let cycle = [
+            BTreeSet::from(["b.sh".to_string()]),
+            BTreeSet::new(),
+            BTreeSet::from(["a.sh".to_string()]),
+        ];
+        assert!(super::order(&names, &cycle).is_err());
+    }
+}
@@ -0,0 +1,280 @@
+//! Contains [`Package`]
+//!
+//! On macOS, machines are usually set up from a `Brewfile` with `brew bundle`. Each
+//! `[[package]]` is exported into one, and an existing `Brewfile` is imported into
+//! `[[package]]`s, so the repo can take over from it
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use eyre::{Context as _, ContextCompat as _, Result, eyre};
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::stdx::PathExt as _;
+
+/// Format that packages are exported to
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum PackagesFormat {
+    /// `Brewfile`, which `brew bundle` installs
+    Brewfile,
+}
+
+/// A package installed with Homebrew, in the `[[package]]` section of the config
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    /// Name of the package, e.g. `ripgrep` or `homebrew/cask-fonts`
+    pub name: String,
+    /// What kind of package it is
+    #[serde(default)]
+    pub kind: Kind,
+    /// ID of the app in the App Store, which is required for `kind = "mas"`
+    pub id: Option<u64>,
+}
+
+/// Kind of a [`Package`], in the order that a `Brewfile` lists them
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// A repository of formulae, `tap "..."`
+    Tap,
+    /// A formula, `brew "..."`
+    #[default]
+    Brew,
+    /// An app, `cask "..."`
+    Cask,
+    /// An app from the App Store, `mas "...", id: ...`
+    Mas,
+}
+
+impl Kind {
+    /// Name of the kind in a `Brewfile`, and in the config
+    pub(crate) const fn name(self) -> &'static str {
+        match self {
+            Self::Tap => "tap",
+            Self::Brew => "brew",
+            Self::Cask => "cask",
+            Self::Mas => "mas",
+        }
+    }
+}
+
+/// Quote `s` as a string, in the syntax that both TOML and a `Brewfile` read
+fn quote(s: &str) -> String {
+    toml::Value::String(s.to_string()).to_string()
+}
+
+/// Render `packages` as a `Brewfile`, which `brew bundle` installs
+pub fn brewfile(packages: &[Package]) -> Result<String> {
+    let mut packages = packages.iter().collect::<Vec<_>>();
+    // keep the order of the config within each kind, taps must come first
+    packages.sort_by_key(|package| package.kind);
+
+    let mut brewfile = String::new();
+    for package in packages {
+        let name = quote(&package.name);
+        _ = match package.kind {
+            Kind::Mas => {
+                let id = package.id.with_context(|| {
+                    eyre!(
+                        "`[[package]]` {} has `kind = \"mas\"`, but no `id`",
+                        package.name
+                    )
+                })?;
+                writeln!(brewfile, "mas {name}, id: {id}")
+            }
+            kind => writeln!(brewfile, "{} {name}", kind.name()),
+        };
+    }
+
+    Ok(brewfile)
+}
+
+/// Parse every package of the `Brewfile` with `contents`
+///
+/// Entries which `dots` has no kind for, such as `vscode`, are skipped with a warning
+pub fn parse_brewfile(contents: &str) -> Result<Vec<Package>> {
+    let mut packages = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || eyre!("invalid line {} of Brewfile: {line}", i + 1);
+
+        let (kind, rest) = line.split_once(char::is_whitespace).with_context(invalid)?;
+        let kind = match kind {
+            "tap" => Kind::Tap,
+            "brew" => Kind::Brew,
+            "cask" => Kind::Cask,
+            "mas" => Kind::Mas,
+            kind => {
+                log::warn!("skipping `{kind}` on line {} of Brewfile: {line}", i + 1);
+                continue;
+            }
+        };
+
+        // the name is the first argument, options such as `id:` follow it
+        let rest = rest.trim_start();
+        let quote = rest
+            .chars()
+            .next()
+            .filter(|c| matches!(c, '"' | '\''))
+            .with_context(invalid)?;
+        let (name, options) = rest[1..].split_once(quote).with_context(invalid)?;
+
+        let id = match kind {
+            Kind::Mas => Some(
+                options
+                    .split(',')
+                    .find_map(|option| option.trim().strip_prefix("id:"))
+                    .and_then(|id| id.trim().parse::<u64>().ok())
+                    .with_context(|| eyre!("`mas` on line {} of Brewfile has no `id`", i + 1))?,
+            ),
+            _ => None,
+        };
+
+        packages.push(Package {
+            name: name.to_string(),
+            kind,
+            id,
+        });
+    }
+
+    Ok(packages)
+}
+
+/// `[[package]]` entries of `packages`, to be appended to the config
+fn to_toml(packages: &[Package]) -> String {
+    let mut toml = String::new();
+    for package in packages {
+        _ = writeln!(toml, "\n[[package]]\nname = {}", quote(&package.name));
+        if package.kind != Kind::default() {
+            _ = writeln!(toml, "kind = {}", quote(package.kind.name()));
+        }
+        if let Some(id) = package.id {
+            _ = writeln!(toml, "id = {id}");
+        }
+    }
+
+    toml
+}
+
+/// Print the `[[package]]`s of the repo at `cwd` in the `format`
+pub fn export_packages(cwd: &Path, format: PackagesFormat) -> Result<()> {
+    let config = Config::discover(cwd)?;
+    match format {
+        PackagesFormat::Brewfile => print!("{}", brewfile(&config.packages)?),
+    }
+
+    Ok(())
+}
+
+/// Append every package of the `Brewfile` at `path`, which the repo at `cwd` does
+/// not declare yet, to its config
+///
+/// Returns the number of packages which were imported
+pub fn import_brewfile(cwd: &Path, path: &Path) -> Result<usize> {
+    let contents =
+        fs::read_to_string(path).with_context(|| eyre!("failed to read {}", path.show()))?;
+    let packages = parse_brewfile(&contents)?;
+
+    let config = Config::discover(cwd)?;
+    let new = packages
+        .into_iter()
+        .filter(|package| {
+            !config
+                .packages
+                .iter()
+                .any(|declared| declared.kind == package.kind && declared.name == package.name)
+        })
+        .collect::<Vec<_>>();
+    if new.is_empty() {
+        return Ok(0);
+    }
+
+    let config_file = Config::editable(&config.root)?;
+    let mut contents = fs::read_to_string(&config_file)
+        .with_context(|| eyre!("failed to read {}", config_file.show()))?;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(&to_toml(&new));
+    fs::write(&config_file, contents)
+        .with_context(|| eyre!("failed to write {}", config_file.show()))?;
+
+    Ok(new.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brewfile_round_trip() {
+        let brewfile = "\
+# taps first
+tap \"homebrew/cask-fonts\"
+brew \"ripgrep\"
+brew 'neovim', args: [\"HEAD\"]
+cask \"firefox\"
+vscode \"rust-lang.rust-analyzer\"
+mas \"Xcode\", id: 497799835
+";
+        let packages = parse_brewfile(brewfile).unwrap();
+        assert_eq!(
+            packages,
+            [
+                Package {
+                    name: "homebrew/cask-fonts".to_string(),
+                    kind: Kind::Tap,
+                    id: None,
+                },
+                Package {
+                    name: "ripgrep".to_string(),
+                    kind: Kind::Brew,
+                    id: None,
+                },
+                Package {
+                    name: "neovim".to_string(),
+                    kind: Kind::Brew,
+                    id: None,
+                },
+                Package {
+                    name: "firefox".to_string(),
+                    kind: Kind::Cask,
+                    id: None,
+                },
+                Package {
+                    name: "Xcode".to_string(),
+                    kind: Kind::Mas,
+                    id: Some(497_799_835),
+                },
+            ]
+        );
+
+        assert_eq!(
+            super::brewfile(&packages).unwrap(),
+            "\
+tap \"homebrew/cask-fonts\"
+brew \"ripgrep\"
+brew \"neovim\"
+cask \"firefox\"
+mas \"Xcode\", id: 497799835
+"
+        );
+
+        // importing and parsing again gives back the same packages
+        let config = format!("[[package]]\nname = \"bat\"\n{}", to_toml(&packages));
+        let config = toml::from_str::<toml::Table>(&config).unwrap();
+        let declared = config["package"]
+            .clone()
+            .try_into::<Vec<Package>>()
+            .unwrap();
+        assert_eq!(declared[1..], packages);
+
+        assert!(parse_brewfile("brew ripgrep").is_err());
+        assert!(parse_brewfile("mas \"Xcode\"").is_err());
+    }
+}
@@ -0,0 +1,67 @@
+//! Contains [`page`]
+
+use std::env;
+use std::io::{self, IsTerminal as _, Write as _};
+use std::process::{Command, Stdio};
+
+use eyre::{Context as _, Result};
+
+/// Pager to use when `$PAGER` is not set
+const DEFAULT_PAGER: &str = "less";
+
+/// Options passed to `less` when `$LESS` is not set, same as `git` uses
+///
+/// - `F`: quit if the output fits on a single screen
+/// - `R`: show colors
+/// - `X`: do not clear the screen when quitting
+const DEFAULT_LESS: &str = "FRX";
+
+/// Print `output` to stdout, piping it through `$PAGER` if stdout is a terminal
+///
+/// Like `git`, the pager exits immediately if `output` fits on a single screen.
+/// If the pager fails to start, `output` is printed directly.
+pub fn page(output: &str, enabled: bool) -> Result<()> {
+    if !enabled || !io::stdout().is_terminal() {
+        return print(output);
+    }
+
+    let pager = env::var("PAGER").unwrap_or_else(|_| DEFAULT_PAGER.to_string());
+    let Some((program, args)) = shellwords::split(&pager)
+        .ok()
+        .and_then(|words| words.split_first().map(|(p, a)| (p.clone(), a.to_vec())))
+    else {
+        return print(output);
+    };
+
+    let mut command = Command::new(&program);
+    command.args(args).stdin(Stdio::piped());
+
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", DEFAULT_LESS);
+    }
+
+    let Ok(mut child) = command.spawn() else {
+        log::debug!("failed to spawn pager `{pager}`");
+        return print(output);
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(output.as_bytes()) {
+            // the user quit the pager before reading all of the output
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => (),
+            result => result.context("failed to write to pager")?,
+        }
+    }
+
+    child.wait().context("failed to wait for pager")?;
+
+    Ok(())
+}
+
+/// Print `output` to stdout
+fn print(output: &str) -> Result<()> {
+    io::stdout()
+        .lock()
+        .write_all(output.as_bytes())
+        .context("failed to write to stdout")
+}
@@ -0,0 +1,150 @@
+//! Contains [`Phase`]
+//!
+//! Operations run in phases, and inside of each phase in stages: directories are
+//! created, then files are written or removed, then their modes are fixed and settings
+//! are loaded, and hooks run last. Every operation of a phase finishes before the next
+//! phase starts, and if any of them failed the later phases are skipped
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::operation::Operation;
+
+/// When the files of an entry are applied, relative to the other entries
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Before every other entry, e.g. a file that the hooks of other entries read
+    Pre,
+    /// Together with most entries
+    #[default]
+    Main,
+    /// After every other entry
+    Post,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pre => "pre",
+            Self::Main => "main",
+            Self::Post => "post",
+        })
+    }
+}
+
+/// Stage of `operation` inside of its phase
+fn stage(operation: &Operation) -> u8 {
+    match operation {
+        Operation::Unlock { .. } => 0,
+        Operation::Mkdir { .. } => 1,
+        Operation::Write(_)
+        | Operation::Symlink { .. }
+        | Operation::SwapDir { .. }
+        | Operation::Delete { .. }
+        | Operation::Evict { .. } => 2,
+        Operation::SetMode { .. } => 3,
+        Operation::LoadDconf { .. }
+        | Operation::WriteDefaults { .. }
+        | Operation::OverrideFlatpak { .. } => 4,
+        Operation::RunHook { .. } => 5,
+        Operation::Lock { .. } => 6,
+    }
+}
+
+/// Phase of `operation`, which is [`Phase::Main`] unless its path is in `phases`
+///
+/// Directories are created in the earliest phase of a file inside of them
+pub fn of(operation: &Operation, phases: &BTreeMap<PathBuf, Phase>) -> Phase {
+    match operation {
+        Operation::Mkdir { path } => phases
+            .iter()
+            .filter(|(file, _)| file.starts_with(path))
+            .map(|(_, phase)| *phase)
+            .chain([Phase::Main])
+            .min()
+            .unwrap_or_default(),
+        operation => operation
+            .path()
+            .and_then(|path| phases.get(path))
+            .copied()
+            .unwrap_or_default(),
+    }
+}
+
+/// Sort `operations` by their phase, and by their stage inside of it
+///
+/// Operations of the same stage keep their order
+pub fn order(operations: &mut [Operation], phases: &BTreeMap<PathBuf, Phase>) {
+    operations.sort_by_key(|operation| (of(operation, phases), stage(operation)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::analysis::{Contents, WritePath};
+
+    #[test]
+    fn order() {
+        let write = |path: &str| {
+            Operation::Write(WritePath {
+                path: path.into(),
+                contents: Contents::Text(String::new()),
+                source: None,
+                durable: false,
+                description: None,
+                symlink: false,
+                mode: None,
+            })
+        };
+        let hook = |command: &str| Operation::RunHook {
+            command: command.to_string(),
+        };
+
+        let mut operations = vec![
+            hook("systemctl --user daemon-reload"),
+            write("/a"),
+            Operation::SetMode {
+                path: "/pre".into(),
+                mode: 0o600,
+            },
+            write("/pre"),
+            write("/post"),
+            write("/b"),
+            Operation::Mkdir {
+                path: "/dir".into(),
+            },
+            Operation::Mkdir { path: "/".into() },
+        ];
+        let phases = BTreeMap::from([
+            (PathBuf::from("/pre"), Phase::Pre),
+            (PathBuf::from("/post"), Phase::Post),
+        ]);
+        super::order(&mut operations, &phases);
+
+        assert_eq!(
+            operations,
+            [
+                Operation::Mkdir { path: "/".into() },
+                write("/pre"),
+                Operation::SetMode {
+                    path: "/pre".into(),
+                    mode: 0o600,
+                },
+                Operation::Mkdir {
+                    path: "/dir".into()
+                },
+                write("/a"),
+                write("/b"),
+                hook("systemctl --user daemon-reload"),
+                write("/post"),
+            ]
+        );
+    }
+}
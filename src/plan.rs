@@ -0,0 +1,141 @@
+//! Contains [`Plan`], [`Summary`] and [`ApplyReport`]
+//!
+//! These are printed as JSON by `dots plan` and `dots apply --json`, for editor plugins
+//! and other tools which wrap `dots`. Fields are never removed or renamed within
+//! a major version, so they can be relied on. With the `dots-schema` feature,
+//! they implement [`schemars::JsonSchema`].
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::operation::Operation;
+
+/// Version of the JSON format, which is increased when it changes in a breaking way
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Everything an apply is going to do
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "dots-schema", derive(schemars::JsonSchema))]
+pub struct Plan {
+    /// [`SCHEMA_VERSION`] of this plan
+    pub version: u32,
+    /// Every change to make, in order
+    pub operations: Vec<Operation>,
+    /// Existing files which are renamed to `<name>.orig.dots` instead of being replaced
+    pub backups: BTreeSet<PathBuf>,
+    /// Errors of entries that failed, which are not part of the `operations`
+    pub errors: Vec<String>,
+    /// Operations which are going to fail, e.g. because of missing permissions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocked: Vec<Failure>,
+}
+
+/// What an apply is going to do to each file, printed by `dots apply --dry-run`
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "dots-schema", derive(schemars::JsonSchema))]
+pub struct Summary {
+    /// Files which do not exist yet
+    pub created: Vec<PathBuf>,
+    /// Existing files whose contents are replaced
+    pub modified: Vec<PathBuf>,
+    /// Files which already have the contents that would be written to them
+    pub unchanged: Vec<PathBuf>,
+    /// Files which are removed, or renamed to `<name>.orig.dots` if they are in the `backups`
+    /// of the [`Plan`]
+    pub removed: Vec<PathBuf>,
+}
+
+/// What an apply did
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "dots-schema", derive(schemars::JsonSchema))]
+pub struct ApplyReport {
+    /// [`SCHEMA_VERSION`] of this report
+    pub version: u32,
+    /// Every file and symlink that was written
+    pub written: Vec<PathBuf>,
+    /// Files which were not touched, because they are unchanged since the last apply
+    pub unchanged: Vec<PathBuf>,
+    /// Operations that failed
+    pub failed: Vec<Failure>,
+    /// The apply was stopped with Ctrl-C before every operation was executed
+    pub cancelled: bool,
+    /// Errors of entries that failed, before anything was applied
+    pub errors: Vec<String>,
+}
+
+/// An operation that failed
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "dots-schema", derive(schemars::JsonSchema))]
+pub struct Failure {
+    /// Path that the operation changes, `None` for hooks
+    pub path: Option<PathBuf>,
+    /// Why it failed
+    pub error: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{Contents, WritePath};
+
+    /// Changing the format breaks tools that read it
+    #[test]
+    fn stable() {
+        let plan = Plan {
+            version: SCHEMA_VERSION,
+            operations: vec![
+                Operation::Write(WritePath {
+                    path: PathBuf::from("/home/user/.bashrc"),
+                    contents: Contents::Text("foo".to_string()),
+                    source: None,
+                    durable: false,
+                    description: None,
+                    symlink: false,
+                    mode: None,
+                }),
+                Operation::Symlink {
+                    path: PathBuf::from("/home/user/.vimrc"),
+                    target: PathBuf::from("/home/user/dotfiles/vimrc"),
+                },
+            ],
+            backups: BTreeSet::from([PathBuf::from("/home/user/.bashrc")]),
+            errors: vec![],
+            blocked: vec![],
+        };
+
+        let json = serde_json::json!({
+            "version": 1,
+            "operations": [
+                {
+                    "kind": "write",
+                    "path": "/home/user/.bashrc",
+                    "contents": "foo",
+                    "source": null,
+                    "asset": null,
+                    "durable": false,
+                },
+                {
+                    "kind": "symlink",
+                    "path": "/home/user/.vimrc",
+                    "target": "/home/user/dotfiles/vimrc",
+                },
+            ],
+            "backups": ["/home/user/.bashrc"],
+            "errors": [],
+        });
+
+        assert_eq!(serde_json::to_value(&plan).unwrap(), json);
+        assert_eq!(serde_json::from_value::<Plan>(json).unwrap(), plan);
+    }
+
+    #[cfg(feature = "dots-schema")]
+    #[test]
+    fn schema() {
+        let schema = serde_json::to_value(schemars::schema_for!(Plan)).unwrap();
+
+        assert_eq!(schema["properties"]["version"]["type"], "integer");
+        assert!(schema["$defs"]["Operation"]["oneOf"].is_array());
+    }
+}
@@ -0,0 +1,248 @@
+//! Contains [`probe`]
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use eyre::{Result, bail};
+use itertools::Itertools as _;
+
+use crate::stdx::PathExt as _;
+
+/// Reason why a file can not be written
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum Obstacle {
+    /// File is on a read-only filesystem, which is mounted at this path
+    ReadOnlyFilesystem(PathBuf),
+    /// The user can not write into this directory, which would contain the file
+    PermissionDenied(PathBuf),
+    /// File has the immutable attribute
+    Immutable,
+}
+
+impl Obstacle {
+    /// Why a single file can not be written
+    pub fn reason(&self) -> String {
+        match self {
+            Self::ReadOnlyFilesystem(mount) => {
+                format!("{} is a read-only filesystem", mount.to_string_lossy())
+            }
+            Self::PermissionDenied(dir) => {
+                format!("no permission to write into {}", dir.to_string_lossy())
+            }
+            Self::Immutable => "the file has the immutable attribute".to_string(),
+        }
+    }
+}
+
+/// Find every path that can not be written, so it can be reported before anything is mutated
+pub fn obstacles<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Vec<(Obstacle, &'a Path)> {
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            if let Some(mount) = read_only_mount(path) {
+                Some((Obstacle::ReadOnlyFilesystem(mount), path))
+            } else if let Some(dir) = denied_dir(path) {
+                Some((Obstacle::PermissionDenied(dir), path))
+            } else if is_immutable(path) {
+                Some((Obstacle::Immutable, path))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Fail if any of the `paths` can not be written, grouping the paths by the reason
+pub fn probe<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Result<()> {
+    let obstacles = obstacles(paths);
+
+    if obstacles.is_empty() {
+        return Ok(());
+    }
+
+    bail!("{}", report(obstacles));
+}
+
+/// Human-readable report of every obstacle, grouped by the reason
+fn report(obstacles: Vec<(Obstacle, &Path)>) -> String {
+    let groups = obstacles.into_iter().fold(
+        BTreeMap::<_, Vec<_>>::new(),
+        |mut groups, (obstacle, path)| {
+            groups.entry(obstacle).or_default().push(path);
+            groups
+        },
+    );
+
+    let groups = groups
+        .into_iter()
+        .map(|(obstacle, paths)| {
+            let reason = match obstacle {
+                Obstacle::ReadOnlyFilesystem(mount) => format!(
+                    "{} files under {} can not be written, it is a read-only filesystem",
+                    paths.len(),
+                    mount.show()
+                ),
+                Obstacle::PermissionDenied(dir) => format!(
+                    "{} files under {} can not be written, you have no permission to write into it (run `dots` as a user who can, e.g. with `sudo`)",
+                    paths.len(),
+                    dir.show()
+                ),
+                Obstacle::Immutable => format!(
+                    "{} files can not be written, they have the immutable attribute (see `chattr -i`)",
+                    paths.len()
+                ),
+            };
+
+            let paths = paths.iter().map(|path| format!("  {}", path.show())).join("\n");
+
+            format!("{reason}:\n{paths}")
+        })
+        .join("\n");
+
+    format!(
+        "{groups}\nskip these files by removing them from the config, \
+         or change their `output` to a writable location"
+    )
+}
+
+/// If `path` would be written to a read-only filesystem, returns where the filesystem is mounted
+#[cfg(unix)]
+fn read_only_mount(path: &Path) -> Option<PathBuf> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt as _;
+    use std::os::unix::fs::MetadataExt as _;
+
+    // the file itself may not exist yet, the directory that it will be created in matters
+    let existing = path.ancestors().find(|ancestor| ancestor.exists())?;
+
+    let c_path = CString::new(existing.as_os_str().as_bytes()).ok()?;
+    // SAFETY: all zeroes is a valid `statvfs`, it only contains integers
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid NUL-terminated string, and `stat` is a valid pointer
+    if unsafe { libc::statvfs(c_path.as_ptr(), &raw mut stat) } != 0 {
+        return None;
+    }
+
+    if stat.f_flag & libc::ST_RDONLY == 0 {
+        return None;
+    }
+
+    // the mount point is the last ancestor which is still on the same device
+    let device = existing.metadata().ok()?.dev();
+    existing
+        .ancestors()
+        .take_while(|ancestor| ancestor.metadata().is_ok_and(|meta| meta.dev() == device))
+        .last()
+        .map(Path::to_path_buf)
+}
+
+/// If `path` would be written to a read-only filesystem, returns where the filesystem is mounted
+#[cfg(not(unix))]
+fn read_only_mount(_path: &Path) -> Option<PathBuf> {
+    None
+}
+
+/// If the user can not create or replace the file at `path`, returns the directory
+/// which it has no permission to write into
+///
+/// Files are replaced by renaming over them, so the directory matters and not the file
+#[cfg(unix)]
+fn denied_dir(path: &Path) -> Option<PathBuf> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt as _;
+
+    // missing directories are created inside of the closest one that exists
+    let dir = path
+        .parent()?
+        .ancestors()
+        .find(|ancestor| ancestor.is_dir())?;
+
+    let c_path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    // SAFETY: `c_path` is a valid NUL-terminated string
+    let writable = unsafe { libc::access(c_path.as_ptr(), libc::W_OK | libc::X_OK) } == 0;
+
+    (!writable).then(|| dir.to_path_buf())
+}
+
+/// If the user can not create or replace the file at `path`, returns the directory
+/// which it has no permission to write into
+#[cfg(not(unix))]
+fn denied_dir(_path: &Path) -> Option<PathBuf> {
+    None
+}
+
+/// Whether the file at `path` has the immutable attribute, which makes it impossible to remove
+#[cfg(target_os = "linux")]
+pub(crate) fn is_immutable(path: &Path) -> bool {
+    use std::os::fd::AsRawFd as _;
+
+    /// From `linux/fs.h`
+    const FS_IMMUTABLE_FL: libc::c_int = 0x0000_0010;
+
+    let Ok(file) = std::fs::File::open(path) else {
+        return false;
+    };
+
+    let mut flags: libc::c_int = 0;
+    // SAFETY: `file` is open for the duration of the call, and the kernel writes an `int` into `flags`
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &raw mut flags) };
+
+    result == 0 && flags & FS_IMMUTABLE_FL != 0
+}
+
+/// Whether the file at `path` has the immutable attribute, which makes it impossible to remove
+#[cfg(target_os = "macos")]
+pub(crate) fn is_immutable(path: &Path) -> bool {
+    use std::os::macos::fs::MetadataExt as _;
+
+    /// From `sys/stat.h`
+    const UF_IMMUTABLE: u32 = 0x0000_0002;
+    /// From `sys/stat.h`
+    const SF_IMMUTABLE: u32 = 0x0002_0000;
+
+    path.metadata()
+        .is_ok_and(|meta| meta.st_flags() & (UF_IMMUTABLE | SF_IMMUTABLE) != 0)
+}
+
+/// Whether the file at `path` has the immutable attribute, which makes it impossible to remove
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn is_immutable(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_by_obstacle() {
+        let store = PathBuf::from("/nix/store");
+        let a = PathBuf::from("/nix/store/a");
+        let b = PathBuf::from("/nix/store/b");
+        let c = PathBuf::from("/home/user/c");
+        let etc = PathBuf::from("/etc/udev/rules.d");
+        let d = PathBuf::from("/etc/udev/rules.d/90-keyboard.rules");
+
+        let report = report(vec![
+            (Obstacle::ReadOnlyFilesystem(store.clone()), a.as_path()),
+            (Obstacle::Immutable, c.as_path()),
+            (Obstacle::ReadOnlyFilesystem(store), b.as_path()),
+            (Obstacle::PermissionDenied(etc.clone()), d.as_path()),
+        ]);
+
+        assert!(report.contains("2 files under"));
+        assert!(report.contains("you have no permission to write into it"));
+        assert_eq!(
+            Obstacle::PermissionDenied(etc).reason(),
+            "no permission to write into /etc/udev/rules.d"
+        );
+        assert!(report.contains("1 files can not be written, they have the immutable attribute"));
+    }
+
+    #[test]
+    fn writable() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(obstacles([dir.path().join("foo").as_path()]), vec![]);
+    }
+}
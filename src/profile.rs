@@ -0,0 +1,119 @@
+//! Contains [`Profile`]
+//!
+//! One repo usually serves several machines, e.g. a work laptop and a home desktop.
+//! Entries tagged with `profiles` are only applied when one of their profiles is active,
+//! which is selected with `--profile`, by `default_profiles` or by the `when` of the profile
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use eyre::{Result, bail};
+use serde::Deserialize;
+
+use crate::environment::Environment;
+use crate::when::When;
+
+/// A set of entries which are only applied on some machines, in `[profiles.<name>]`
+///
+/// ```toml
+/// [profiles.work]
+/// description = "VPN and the work git identity"
+/// when = { hostname = "work-laptop" }
+///
+/// [[dir]]
+/// input = "vpn"
+/// output = "{config_dir}/vpn"
+/// profiles = ["work"]
+/// ```
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// What the profile is for
+    pub description: Option<String>,
+    /// Activate the profile on machines which match, without selecting it
+    #[serde(default)]
+    pub when: When,
+}
+
+/// Profiles which are active: the `selected` ones if there are any, and otherwise the
+/// `default` ones along with every profile whose `when` matches the `environment`
+pub fn active(
+    profiles: &BTreeMap<String, Profile>,
+    selected: Option<&[String]>,
+    default: &[String],
+    environment: &Environment,
+) -> Result<BTreeSet<String>> {
+    let active = match selected {
+        Some(selected) => selected.iter().cloned().collect::<BTreeSet<_>>(),
+        None => default
+            .iter()
+            .cloned()
+            .chain(
+                profiles
+                    .iter()
+                    .filter(|(_, profile)| {
+                        !profile.when.is_always() && profile.when.matches(environment)
+                    })
+                    .map(|(name, _)| name.clone()),
+            )
+            .collect(),
+    };
+
+    if let Some(undefined) = active.iter().find(|name| !profiles.contains_key(*name)) {
+        bail!("profile `{undefined}` is not defined in `[profiles]`");
+    }
+
+    Ok(active)
+}
+
+/// Whether an entry tagged with `profiles` is applied, which entries without any always are
+pub fn includes(active: &BTreeSet<String>, profiles: &[String]) -> bool {
+    profiles.is_empty() || profiles.iter().any(|profile| active.contains(profile))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active() {
+        let profiles = BTreeMap::from([
+            ("home".to_string(), Profile::default()),
+            (
+                "work".to_string(),
+                Profile {
+                    description: None,
+                    when: When {
+                        hostname: Some("work-laptop".to_string()),
+                        ..When::default()
+                    },
+                },
+            ),
+        ]);
+        let laptop = Environment {
+            hostname: "work-laptop".to_string(),
+            ..Environment::new("/home/me")
+        };
+        let desktop = Environment::new("/home/me");
+        let home = ["home".to_string()];
+
+        assert_eq!(
+            super::active(&profiles, None, &home, &laptop).unwrap(),
+            BTreeSet::from(["home".to_string(), "work".to_string()])
+        );
+        assert_eq!(
+            super::active(&profiles, None, &home, &desktop).unwrap(),
+            BTreeSet::from(["home".to_string()])
+        );
+        // selecting profiles replaces every other way of activating them
+        assert_eq!(
+            super::active(&profiles, Some(&[]), &home, &laptop).unwrap(),
+            BTreeSet::new()
+        );
+        assert!(super::active(&profiles, Some(&["gaming".to_string()]), &[], &laptop).is_err());
+
+        let active = BTreeSet::from(["work".to_string()]);
+        assert!(includes(&active, &[]));
+        assert!(includes(&active, &["home".to_string(), "work".to_string()]));
+        assert!(!includes(&active, &["home".to_string()]));
+    }
+}
@@ -0,0 +1,81 @@
+//! Contains [`Protect`]
+
+use std::path::Path;
+
+use eyre::{Context as _, Result, eyre};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Paths that are never written to or removed, no matter what the config says
+///
+/// `*` matches within a single component of the path, `**` matches across components
+#[derive(Debug, Clone, Default)]
+pub struct Protect {
+    /// Every glob, in the same order as in `globs`
+    patterns: Vec<String>,
+    /// Compiled `patterns`
+    globs: GlobSet,
+}
+
+impl Protect {
+    /// Protect every path that matches any of the `patterns`
+    pub fn new(patterns: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<Self> {
+        let patterns = patterns
+            .into_iter()
+            .map(|pattern| pattern.as_ref().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+
+        let mut globs = GlobSetBuilder::new();
+        for pattern in &patterns {
+            globs.add(
+                GlobBuilder::new(pattern)
+                    .literal_separator(true)
+                    .build()
+                    .with_context(|| eyre!("invalid glob in `protect`: {pattern}"))?,
+            );
+        }
+
+        Ok(Self {
+            globs: globs
+                .build()
+                .context("failed to compile the globs in `protect`")?,
+            patterns,
+        })
+    }
+
+    /// The glob that protects `path`, if any
+    pub fn protecting(&self, path: &Path) -> Option<&str> {
+        self.globs
+            .matches(path)
+            .first()
+            .map(|&i| self.patterns[i].as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protecting() {
+        let protect =
+            Protect::new(["/home/user/.ssh/id_*", "/home/user/.config/secret/**"]).unwrap();
+
+        assert_eq!(
+            protect.protecting(Path::new("/home/user/.ssh/id_ed25519")),
+            Some("/home/user/.ssh/id_*")
+        );
+        assert_eq!(
+            protect.protecting(Path::new("/home/user/.config/secret/a/b")),
+            Some("/home/user/.config/secret/**")
+        );
+        assert_eq!(
+            protect.protecting(Path::new("/home/user/.ssh/config")),
+            None
+        );
+        // `*` does not cross directories
+        assert_eq!(
+            protect.protecting(Path::new("/home/user/.ssh/id_dir/foo")),
+            None
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Contains [`Reporter`]
+
+use std::io::Write as _;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use eyre::{Context as _, ContextCompat as _, Result, bail, eyre};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::fetch::Limiter;
+use crate::machines::hostname;
+use crate::plan::ApplyReport;
+use crate::sensitive::redact;
+
+/// Longest that sending the report to the [`Reporter::webhook`] can take, so a dashboard
+/// which is down does not hang the apply
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where to send the [`ApplyReport`] after each apply, e.g. a dashboard which records when
+/// each machine last applied and what changed
+#[derive(Deserialize, Debug, Default)]
+pub struct Reporter {
+    /// URL which receives a `POST` request with a JSON [`Summary`]
+    pub webhook: Option<String>,
+    /// Command to run, with the JSON [`Summary`] on its stdin and the `DOTS_HOSTNAME`,
+    /// `DOTS_WRITTEN` and `DOTS_FAILED` environment variables set
+    pub command: Option<String>,
+}
+
+/// Body of the `POST` request sent to the [`Reporter::webhook`]
+#[derive(Serialize, Debug)]
+pub struct Summary<'a> {
+    /// Machine that applied the repo
+    pub hostname: String,
+    /// When the apply completed
+    pub applied_at: Timestamp,
+    /// What the apply did
+    #[serde(flatten)]
+    pub report: &'a ApplyReport,
+}
+
+impl Reporter {
+    /// Read the `[report]` section of the config closest to `cwd`
+    pub fn discover(cwd: &Path) -> Result<Self> {
+        Ok(Config::discover(cwd)?.report)
+    }
+
+    /// Send the `report` to every configured destination
+    pub fn send(&self, limiter: &Limiter, report: &ApplyReport) -> Result<()> {
+        if self.webhook.is_none() && self.command.is_none() {
+            return Ok(());
+        }
+
+        let summary = Summary {
+            hostname: hostname(),
+            applied_at: Timestamp::now(),
+            report,
+        };
+        let body = serde_json::to_string(&summary).context("failed to serialize apply report")?;
+        let body = redact(&body).into_owned();
+
+        if let Some(webhook) = &self.webhook {
+            limiter
+                .post(webhook, &body, TIMEOUT)
+                .with_context(|| eyre!("failed to send apply report to {webhook}"))?;
+        }
+
+        if let Some(command) = &self.command {
+            let words = shellwords::split(command)
+                .with_context(|| eyre!("invalid report command: {command}"))?;
+            let (program, args) = words
+                .split_first()
+                .with_context(|| eyre!("report command is empty"))?;
+
+            let mut child = Command::new(program)
+                .args(args)
+                .env("DOTS_HOSTNAME", &summary.hostname)
+                .env("DOTS_WRITTEN", report.written.len().to_string())
+                .env("DOTS_FAILED", report.failed.len().to_string())
+                .stdin(Stdio::piped())
+                .spawn()
+                .with_context(|| eyre!("failed to run report command: {command}"))?;
+
+            // the command may not read its stdin, which is not an error
+            if let Some(mut stdin) = child.stdin.take() {
+                let _ = stdin.write_all(body.as_bytes());
+            }
+
+            let status = child
+                .wait()
+                .with_context(|| eyre!("failed to run report command: {command}"))?;
+            if !status.success() {
+                bail!("report command `{command}` failed with {status}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{Response, Server};
+
+    #[test]
+    fn webhook() {
+        let server = Server::new([Response::ok("")]);
+        let reporter = Reporter {
+            webhook: Some(server.url("/report")),
+            command: None,
+        };
+
+        let report = ApplyReport {
+            written: vec!["/home/me/.config/kitty/kitty.conf".into()],
+            ..ApplyReport::default()
+        };
+        reporter.send(&Limiter::default(), &report).unwrap();
+
+        let request = &server.join()[0];
+        assert!(request.line.starts_with("POST /report "));
+        assert!(
+            request
+                .headers
+                .iter()
+                .any(|header| header.eq_ignore_ascii_case("content-type: application/json"))
+        );
+        let summary = serde_json::from_slice::<serde_json::Value>(&request.body).unwrap();
+        assert_eq!(summary["hostname"], hostname());
+        assert_eq!(summary["written"][0], "/home/me/.config/kitty/kitty.conf");
+        assert!(summary["applied_at"].is_string());
+    }
+}
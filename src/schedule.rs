@@ -0,0 +1,88 @@
+//! Contains [`Schedule`]
+//!
+//! Fleets of machines often apply the same repo at the same time, e.g. from cron at
+//! the start of every hour. Each apply first sleeps for a random jitter, so they do not
+//! all fetch the same links at once
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher as _;
+use std::str::FromStr;
+use std::time::Duration;
+
+use eyre::{Result, bail, eyre};
+
+/// How often `dots apply --schedule` applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule(pub Duration);
+
+impl FromStr for Schedule {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        const HOUR: u64 = 60 * 60;
+
+        let period = match s {
+            "hourly" => Duration::from_secs(HOUR),
+            "daily" => Duration::from_secs(24 * HOUR),
+            "weekly" => Duration::from_secs(7 * 24 * HOUR),
+            period => duration(period)?,
+        };
+        if period.is_zero() {
+            bail!("schedule must not be empty");
+        }
+
+        Ok(Self(period))
+    }
+}
+
+/// Parse a duration like `90s`, `10m`, `2h` or `1d`, where a number alone is seconds
+pub fn duration(s: &str) -> Result<Duration> {
+    let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| eyre!("invalid duration `{s}`, expected e.g. `30s`, `10m` or `2h`"))?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        unit => bail!("unknown unit `{unit}` of duration `{s}`, expected `s`, `m`, `h` or `d`"),
+    };
+
+    Ok(Duration::from_secs(number.saturating_mul(seconds)))
+}
+
+/// A random duration which is shorter than `max`
+pub fn jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return max;
+    }
+    // the hasher is seeded randomly for each process
+    let random = RandomState::new().hash_one(std::process::id());
+
+    Duration::from_millis(random % u64::try_from(max.as_millis()).unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(duration("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(duration("45").unwrap(), Duration::from_secs(45));
+        assert!(duration("10 minutes").is_err());
+        assert!(duration("m").is_err());
+        assert_eq!(
+            "hourly".parse::<Schedule>().unwrap(),
+            Schedule(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            "30m".parse::<Schedule>().unwrap(),
+            Schedule(Duration::from_secs(1800))
+        );
+        assert!("0s".parse::<Schedule>().is_err());
+        assert!(jitter(Duration::from_secs(600)) < Duration::from_secs(600));
+        assert_eq!(jitter(Duration::ZERO), Duration::ZERO);
+    }
+}
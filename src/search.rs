@@ -0,0 +1,85 @@
+//! Contains [`search`]
+
+use std::fs;
+
+use itertools::Itertools as _;
+use regex::Regex;
+use simply_colored::*;
+
+use crate::analysis::WritePath;
+use crate::stdx::PathExt as _;
+
+/// Search for `pattern` like `ripgrep` does, annotating each file with where it is written to
+///
+/// Searches the source files in the `input` directories, or what will actually be
+/// written if `rendered` is `true`. Links only have rendered contents, and files which contain
+/// secrets only have their sources.
+pub fn search<'a>(
+    writes: impl IntoIterator<Item = &'a WritePath>,
+    pattern: &Regex,
+    rendered: bool,
+) -> String {
+    writes
+        .into_iter()
+        .sorted_by_key(|write| (&write.source, &write.path))
+        .filter_map(|write| {
+            let (heading, contents) = if rendered {
+                // what is written would show the secrets
+                if write.contents.is_sensitive() {
+                    return None;
+                }
+                let from = write
+                    .source
+                    .as_ref()
+                    .map_or_else(|| "link".to_string(), |source| source.show());
+                (
+                    format!("{} {BLACK}(from {from}{BLACK}){RESET}", write.path.show()),
+                    write.contents.text().to_string(),
+                )
+            } else {
+                let source = write.source.as_ref()?;
+                (
+                    format!("{} {BLACK}→{RESET} {}", source.show(), write.path.show()),
+                    // not being able to read the source, e.g. because it is binary, is not a match
+                    fs::read_to_string(source).ok()?,
+                )
+            };
+
+            let matches = search_in(&contents, pattern);
+
+            (!matches.is_empty()).then(|| format!("{heading}\n{matches}\n"))
+        })
+        .join("")
+}
+
+/// Every line in `contents` that matches the `pattern`, prefixed with its line number
+fn search_in(contents: &str, pattern: &Regex) -> String {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| pattern.is_match(line))
+        .map(|(number, line)| {
+            let line = pattern.replace_all(line, |captures: &regex::Captures<'_>| {
+                format!("{RED}{}{RESET}", &captures[0])
+            });
+
+            format!("{GREEN}{}{RESET}:{line}\n", number + 1)
+        })
+        .join("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_numbers() {
+        let pattern = Regex::new("ba.").unwrap();
+
+        assert_eq!(
+            search_in("foo\nbar\nbaz\n", &pattern),
+            format!("{GREEN}2{RESET}:{RED}bar{RESET}\n{GREEN}3{RESET}:{RED}baz{RESET}\n")
+        );
+        assert_eq!(search_in("foo\n", &pattern), "");
+    }
+}
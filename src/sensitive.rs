@@ -0,0 +1,128 @@
+//! Contains [`Sensitive`]
+//!
+//! Secrets, such as decrypted files, must never end up in logs, diffs, JSON output or
+//! error messages. Every value of the secret store, the `secret` variables and the
+//! encrypted SSH hosts, is wrapped in a [`Sensitive`] as soon as it is decrypted, which
+//! registers it so [`redact`] can remove it from anything that is shown
+
+use std::borrow::Cow;
+use std::fmt;
+use std::iter;
+use std::sync::{LazyLock, RwLock};
+
+use serde::{Serialize, Serializer};
+
+/// Shown in place of a secret
+pub const REDACTED: &str = "[redacted]";
+
+/// Secrets and lines of secrets which are shorter than this are not redacted, as they
+/// would match almost everywhere, e.g. `}`, `Host` or a PIN such as `1234`
+const MIN_LEN: usize = 8;
+
+/// Every secret which was read by this process
+static SECRETS: LazyLock<RwLock<Vec<String>>> = LazyLock::new(RwLock::default);
+
+/// A secret, which is shown as [`REDACTED`] by `Debug`, `Display` and `Serialize`
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Sensitive<T>(T);
+
+impl<T: AsRef<str>> Sensitive<T> {
+    /// Wrap the secret, after which it is redacted everywhere
+    pub fn new(secret: T) -> Self {
+        let mut secrets = SECRETS.write().unwrap_or_else(|err| err.into_inner());
+        let whole = secret.as_ref().trim();
+        // each line on its own too, as diffs and errors often show only some of them
+        let lines = secret.as_ref().lines().map(str::trim);
+        for line in iter::once(whole).chain(lines) {
+            if line.len() >= MIN_LEN && !secrets.iter().any(|secret| secret == line) {
+                secrets.push(line.to_string());
+            }
+        }
+        // longer secrets first, so a secret which contains another is redacted as a whole
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+
+        Self(secret)
+    }
+
+    /// Text which contains secrets that are already wrapped, e.g. a file rendered from a
+    /// `secret` variable. Only those secrets are redacted, not the rest of the text
+    pub fn containing(text: T) -> Self {
+        Self(text)
+    }
+
+    /// Whether `text` contains the secret, which can not be told for secrets that are too
+    /// short to be redacted
+    pub fn is_in(&self, text: &str) -> bool {
+        let secret = self.0.as_ref().trim();
+        secret.len() >= MIN_LEN && text.contains(secret)
+    }
+
+    /// The secret itself, which must not be shown
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    /// Unwrap the secret, which must not be shown
+    pub fn into_exposed(self) -> T {
+        self.0
+    }
+}
+
+impl<T> fmt::Debug for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> Serialize for Sensitive<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+/// Replace every secret in `text` with [`REDACTED`]
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let secrets = SECRETS.read().unwrap_or_else(|err| err.into_inner());
+
+    let mut text = Cow::Borrowed(text);
+    for secret in secrets.iter() {
+        if text.contains(secret.as_str()) {
+            text = Cow::Owned(text.replace(secret.as_str(), REDACTED));
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact() {
+        let secret = Sensitive::new("Host w1\n  IdentityFile ~/.ssh/work-8f3a\n".to_string());
+
+        assert_eq!(format!("{secret} {secret:?}"), "[redacted] [redacted]");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), "\"[redacted]\"");
+        assert_eq!(
+            super::redact("+  IdentityFile ~/.ssh/work-8f3a\n+Host w1"),
+            "+  [redacted]\n+Host w1"
+        );
+        assert!(matches!(super::redact("nothing secret"), Cow::Borrowed(_)));
+
+        let token = Sensitive::new("ghp_8f3a2c91\n");
+        assert_eq!(super::redact("token = ghp_8f3a2c91"), "token = [redacted]");
+        assert!(token.is_in("Authorization: ghp_8f3a2c91"));
+
+        // would redact every number with these digits
+        let pin = Sensitive::new("7291\n");
+        assert_eq!(super::redact("pin = 7291"), "pin = 7291");
+        assert!(!pin.is_in("pin = 7291"));
+    }
+}
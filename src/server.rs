@@ -0,0 +1,155 @@
+//! Contains [`Server`]
+//!
+//! Tests use it instead of the network, both the unit tests and the ones in `tests/`
+
+use std::io::{BufRead as _, BufReader, Read as _, Write as _};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+/// HTTP server on a free port of `localhost`, which answers one connection with each
+/// [`Response`] in order and then stops
+pub struct Server {
+    /// Address that the server listens on
+    addr: SocketAddr,
+    /// Thread that answers the connections
+    thread: JoinHandle<Vec<Request>>,
+}
+
+/// A request that the [`Server`] received
+pub struct Request {
+    /// First line, e.g. `POST /report HTTP/1.1`
+    pub line: String,
+    /// Every header, e.g. `content-length: 2`
+    pub headers: Vec<String>,
+    /// Body, as long as its `content-length`
+    pub body: Vec<u8>,
+    /// Connection of the request, which is closed once the request is dropped
+    _stream: TcpStream,
+}
+
+/// What the [`Server`] answers a single request with
+pub struct Response {
+    /// Status, e.g. `200 OK`
+    status: &'static str,
+    /// Headers other than `Content-Length` and `Connection`
+    headers: Vec<String>,
+    /// Body
+    body: Vec<u8>,
+    /// Never answer, while keeping the connection open
+    hang: bool,
+}
+
+impl Response {
+    /// A `200 OK` with the `body`
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: "200 OK",
+            headers: vec![],
+            body: body.into(),
+            hang: false,
+        }
+    }
+
+    /// An empty response with the `status`, e.g. `429 Too Many Requests`
+    pub fn status(status: &'static str) -> Self {
+        Self {
+            status,
+            ..Self::ok("")
+        }
+    }
+
+    /// Never answer the request, until the [`Request`] is dropped
+    pub fn hang() -> Self {
+        Self {
+            hang: true,
+            ..Self::ok("")
+        }
+    }
+
+    /// Also send the header `name` with the `value`
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push(format!("{name}: {value}"));
+        self
+    }
+}
+
+impl Server {
+    /// Start answering connections with the `responses`
+    pub fn new(responses: impl IntoIterator<Item = Response> + Send + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let thread = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (stream, _) = listener.accept().unwrap();
+                    answer(stream, &response)
+                })
+                .collect()
+        });
+
+        Self { addr, thread }
+    }
+
+    /// URL of the `path` on the server, e.g. `/theme.nu`
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.addr)
+    }
+
+    /// Wait until every response was sent, returning the requests they answered
+    pub fn join(self) -> Vec<Request> {
+        self.thread.join().unwrap()
+    }
+}
+
+/// Read the request from the `stream` and answer it with the `response`
+fn answer(mut stream: TcpStream, response: &Response) -> Request {
+    let mut reader = BufReader::new(&stream);
+    // the headers end with an empty line
+    let mut head = reader
+        .by_ref()
+        .lines()
+        .map_while(Result::ok)
+        .take_while(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .into_iter();
+    let line = head.next().unwrap_or_default();
+    let headers = head.collect::<Vec<_>>();
+
+    let len = headers
+        .iter()
+        .find_map(|header| {
+            header
+                .to_lowercase()
+                .strip_prefix("content-length: ")?
+                .parse()
+                .ok()
+        })
+        .unwrap_or(0);
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).unwrap();
+
+    if !response.hang {
+        let headers = response
+            .headers
+            .iter()
+            .map(|header| format!("{header}\r\n"))
+            .collect::<String>();
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n",
+            response.status,
+            response.body.len()
+        )
+        .unwrap();
+        stream.write_all(&response.body).unwrap();
+    }
+
+    Request {
+        line,
+        headers,
+        body,
+        _stream: stream,
+    }
+}
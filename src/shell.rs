@@ -0,0 +1,169 @@
+//! Contains [`ShellIntegration`]
+//!
+//! Each `[[dir]]` can contribute aliases and functions as small fragments, which are
+//! compiled into a single file per shell. The rc file of each shell only needs to source
+//! that file, instead of every package editing it
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eyre::{Context as _, Result, eyre};
+use serde::Deserialize;
+
+use crate::analysis::{Contents, WritePath};
+use crate::output_path::OutputPath;
+use crate::stdx::PathExt as _;
+
+/// Shell integration of the `[[dir]]`s, in the `[shell]` section of the config
+#[derive(Deserialize, Debug)]
+pub struct ShellIntegration {
+    /// Directory inside of the `input` of each `[[dir]]` that contains its fragments
+    ///
+    /// Fragments are not written as files of the `[[dir]]`
+    #[serde(default = "default_fragments")]
+    pub fragments: PathBuf,
+    /// Directory that the compiled files are written to, `{config_dir}/dots` by default
+    ///
+    /// Files compiled from `[env]` are written here too
+    pub output: Option<OutputPath>,
+}
+
+/// First line of every file that is compiled for shells
+pub const GENERATED: &str = "# @generated by `dots`. Do not edit by hand.\n";
+
+/// Directory of the files compiled for shells, [`ShellIntegration::output`] or
+/// `{config_dir}/dots` by default
+pub fn output_dir(shell: Option<&ShellIntegration>) -> Result<PathBuf> {
+    match shell.and_then(|shell| shell.output.as_ref()) {
+        Some(output) => Ok(output.as_ref().clone()),
+        None => Ok("{config_dir}/dots".parse::<OutputPath>()?.into_inner()),
+    }
+}
+
+/// Default value for [`ShellIntegration::fragments`]
+fn default_fragments() -> PathBuf {
+    PathBuf::from("shell")
+}
+
+/// A shell that fragments are compiled for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Shell {
+    /// POSIX `sh`, and shells compatible with it like `bash` and `zsh`
+    Sh,
+    /// Nushell
+    Nu,
+    /// Fish
+    Fish,
+}
+
+impl Shell {
+    /// Every shell
+    pub const ALL: [Self; 3] = [Self::Sh, Self::Nu, Self::Fish];
+
+    /// Extension of files for the shell
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Sh => "sh",
+            Self::Nu => "nu",
+            Self::Fish => "fish",
+        }
+    }
+
+    /// Shell of a file with the extension `ext`
+    fn from_extension(ext: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|shell| shell.extension() == ext)
+    }
+}
+
+impl ShellIntegration {
+    /// Directory of the fragments of a `[[dir]]` with the `input` directory
+    pub fn fragment_dir(&self, root: &Path, input: &Path) -> PathBuf {
+        root.join(input).join(&self.fragments)
+    }
+
+    /// Files in the fragment directories of every `[[dir]]` in `inputs` which are not
+    /// fragments of any shell, so they are neither compiled nor written
+    pub fn unused<'a>(
+        &self,
+        root: &Path,
+        inputs: impl IntoIterator<Item = &'a Path>,
+    ) -> Vec<PathBuf> {
+        inputs
+            .into_iter()
+            .flat_map(|input| {
+                walkdir::WalkDir::new(self.fragment_dir(root, input)).sort_by_file_name()
+            })
+            .flatten()
+            .filter(|entry| entry.file_type().is_file())
+            .filter(|entry| {
+                entry
+                    .path()
+                    .extension()
+                    .and_then(|ext| Shell::from_extension(&ext.to_string_lossy()))
+                    .is_none()
+            })
+            .map(walkdir::DirEntry::into_path)
+            .collect()
+    }
+
+    /// Compile the fragments of every `[[dir]]` in `inputs` into `aliases.<ext>` for each
+    /// shell that has any
+    ///
+    /// Fragments are concatenated in the order of their `[[dir]]`s, then by their path
+    pub fn compile<'a>(
+        &self,
+        root: &Path,
+        inputs: impl IntoIterator<Item = &'a Path>,
+        durable: bool,
+    ) -> Result<Vec<WritePath>> {
+        let output = output_dir(Some(self))?;
+        let mut compiled = BTreeMap::<Shell, String>::new();
+
+        for input in inputs {
+            let dir = self.fragment_dir(root, input);
+            if !dir.is_dir() {
+                continue;
+            }
+
+            for entry in walkdir::WalkDir::new(&dir).sort_by_file_name() {
+                let entry =
+                    entry.with_context(|| eyre!("failed to read fragments in {}", dir.show()))?;
+                let path = entry.path();
+                let Some(shell) = path
+                    .extension()
+                    .and_then(|ext| Shell::from_extension(&ext.to_string_lossy()))
+                else {
+                    continue;
+                };
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+
+                let fragment = fs::read_to_string(path)
+                    .with_context(|| eyre!("failed to read fragment {}", path.show()))?;
+                let compiled = compiled
+                    .entry(shell)
+                    .or_insert_with(|| GENERATED.to_string());
+                compiled.push_str(&format!(
+                    "\n# from {}\n{}\n",
+                    path.strip_prefix(root).unwrap_or(path).to_string_lossy(),
+                    fragment.trim_end()
+                ));
+            }
+        }
+
+        Ok(compiled
+            .into_iter()
+            .map(|(shell, contents)| WritePath {
+                path: output.join(format!("aliases.{}", shell.extension())),
+                contents: Contents::Text(contents),
+                source: None,
+                durable,
+                description: None,
+                symlink: false,
+                mode: None,
+            })
+            .collect())
+    }
+}
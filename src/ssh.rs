@@ -0,0 +1,209 @@
+//! Contains [`Ssh`]
+//!
+//! `~/.ssh/config` is assembled from a fragment for each host, some of which can be
+//! encrypted with `age`. `ssh` refuses to use a config that others can write to, so
+//! the permissions of the file and of `~/.ssh` are always kept strict
+
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use eyre::{Context as _, ContextCompat as _, Result, bail, eyre};
+use serde::Deserialize;
+
+use crate::age;
+use crate::analysis::{Contents, WritePath};
+use crate::operation::Operation;
+use crate::output_path::OutputPath;
+use crate::sensitive::Sensitive;
+use crate::shell::GENERATED;
+use crate::stdx::PathExt as _;
+
+/// Permissions of the SSH config
+const FILE_MODE: u32 = 0o600;
+
+/// Permissions of the directory that contains the SSH config
+const DIR_MODE: u32 = 0o700;
+
+/// The SSH config, in the `[ssh]` section of the config
+#[derive(Deserialize, Debug)]
+pub struct Ssh {
+    /// Directory with a fragment for each host, relative to the config file
+    ///
+    /// Fragments are concatenated in the order of their names. Fragments which end
+    /// with `.age` are decrypted with `identity`
+    pub hosts: PathBuf,
+    /// Identity that decrypts fragments which end with `.age`
+    pub identity: Option<OutputPath>,
+    /// Where the SSH config is written to, `~/.ssh/config` by default
+    pub output: Option<OutputPath>,
+}
+
+/// The assembled SSH config, along with whether it is already in place
+#[derive(Debug)]
+pub struct SshConfig {
+    /// The SSH config
+    pub config: WritePath,
+    /// The file and its directory exist with the same contents and strict permissions
+    pub secure: bool,
+}
+
+impl Ssh {
+    /// Assemble the SSH config from the fragments, and validate it with `ssh -G`
+    pub fn assemble(&self, root: &Path, durable: bool) -> Result<SshConfig> {
+        let hosts = root.join(&self.hosts);
+        let output = match &self.output {
+            Some(output) => output.as_ref().clone(),
+            None => "~/.ssh/config".parse::<OutputPath>()?.into_inner(),
+        };
+
+        let context = || eyre!("failed to read SSH hosts {}", hosts.show());
+        let mut fragments = fs::read_dir(&hosts)
+            .with_context(context)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<io::Result<Vec<_>>>()
+            .with_context(context)?;
+        fragments.retain(|path| path.is_file());
+        fragments.sort();
+
+        let mut contents = GENERATED.to_string();
+        let mut sensitive = false;
+        for fragment in &fragments {
+            let host = if fragment.extension().is_some_and(|ext| ext == "age") {
+                let identity = self.identity.as_ref().with_context(|| {
+                    eyre!(
+                        "SSH host {} is encrypted, but `[ssh]` has no `identity`",
+                        fragment.show()
+                    )
+                })?;
+                sensitive = true;
+                decrypt(fragment, identity.as_ref())?.into_exposed()
+            } else {
+                fs::read_to_string(fragment)
+                    .with_context(|| eyre!("failed to read SSH host {}", fragment.show()))?
+            };
+            contents.push('\n');
+            contents.push_str(host.trim_end());
+            contents.push('\n');
+        }
+
+        validate(&contents)?;
+
+        let dir = output
+            .parent()
+            .with_context(|| eyre!("SSH config {} has no parent", output.show()))?;
+        let secure = fs::read_to_string(&output).is_ok_and(|current| current == contents)
+            && mode(&output) == Some(FILE_MODE)
+            && mode(dir) == Some(DIR_MODE);
+
+        Ok(SshConfig {
+            config: WritePath {
+                path: output,
+                contents: if sensitive {
+                    Contents::Secret(Sensitive::containing(contents))
+                } else {
+                    Contents::Text(contents)
+                },
+                source: None,
+                durable,
+                description: None,
+                symlink: false,
+                mode: Some(FILE_MODE),
+            },
+            secure,
+        })
+    }
+}
+
+impl SshConfig {
+    /// Operations that write the SSH config and restrict its permissions
+    pub fn operations(self) -> Vec<Operation> {
+        if self.secure {
+            return vec![Operation::Write(self.config)];
+        }
+
+        let file = self.config.path.clone();
+        let mut operations = vec![Operation::Write(self.config)];
+        if cfg!(unix) {
+            if let Some(dir) = file.parent() {
+                operations.push(Operation::SetMode {
+                    path: dir.to_path_buf(),
+                    mode: DIR_MODE,
+                });
+            }
+            operations.push(Operation::SetMode {
+                path: file,
+                mode: FILE_MODE,
+            });
+        }
+        operations
+    }
+}
+
+/// Permission bits of `path`, `None` if it does not exist or the platform has no permissions
+fn mode(path: &Path) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        fs::metadata(path)
+            .ok()
+            .map(|metadata| metadata.permissions().mode() & 0o777)
+    }
+    #[cfg(not(unix))]
+    {
+        _ = path;
+        None
+    }
+}
+
+/// Decrypt the fragment at `path` with `age`, after which it is redacted from everything
+/// that `dots` shows
+fn decrypt(path: &Path, identity: &Path) -> Result<Sensitive<String>> {
+    age::decrypt(path, identity)
+        .map(Sensitive::new)
+        .context("failed to decrypt SSH host")
+}
+
+/// Check that `ssh` can parse `contents`, if it is installed
+///
+/// The config is written to a temporary file that only the current user can read,
+/// since it may contain decrypted hosts
+fn validate(contents: &str) -> Result<()> {
+    let mut file = tempfile::Builder::new()
+        .prefix("dots-ssh-config-")
+        .tempfile()
+        .context("failed to create a temporary SSH config")?;
+    file.write_all(contents.as_bytes())
+        .and_then(|()| file.flush())
+        .with_context(|| eyre!("failed to write {}", file.path().show()))?;
+    let path = file.path();
+
+    let output = Command::new("ssh")
+        .arg("-G")
+        .arg("-F")
+        .arg(path)
+        .arg("dots.invalid")
+        .output();
+
+    let output = match output {
+        Ok(output) => output,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            log::debug!("`ssh` is not installed, not validating the SSH config");
+            return Ok(());
+        }
+        Err(err) => return Err(err).context("failed to run `ssh -G`"),
+    };
+
+    if !output.status.success() {
+        bail!(
+            "invalid SSH config: {}",
+            String::from_utf8_lossy(&output.stderr)
+                .trim()
+                .replace(&*path.to_string_lossy(), "config")
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+//! Contains [`Stats`]
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+
+use jiff::Timestamp;
+use simply_colored::*;
+
+use crate::stdx::PathExt as _;
+use crate::world::World;
+
+/// Summary of the dotfiles repo, shown by `dots stats`
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Number of files in the `input` of each `[[dir]]`
+    pub files_per_dir: BTreeMap<PathBuf, usize>,
+    /// Number of files which use templating
+    pub templates: usize,
+    /// Number of files which do not use templating
+    pub plain: usize,
+    /// Number of links which have a `sha256`
+    pub pinned_links: usize,
+    /// Number of links without a `sha256`
+    pub unpinned_links: usize,
+    /// Total size of every file that will be written, in bytes
+    pub total_size: u64,
+    /// When the last apply on this machine completed
+    pub last_apply: Option<Timestamp>,
+}
+
+impl Stats {
+    /// Start of a handlebars expression
+    const TEMPLATE_START: &str = "{{";
+
+    /// Collect statistics about the inputs
+    ///
+    /// `total_size` and `last_apply` are not known at this point, and must be filled in later
+    pub fn new(world: &World) -> Self {
+        let mut stats = Self::default();
+
+        for file in &world.files {
+            *stats.files_per_dir.entry(file.input.clone()).or_default() += 1;
+
+            if file
+                .contents
+                .text()
+                .is_some_and(|text| text.contains(Self::TEMPLATE_START))
+            {
+                stats.templates += 1;
+            } else {
+                stats.plain += 1;
+            }
+        }
+
+        for link in &world.links {
+            if link.sha256.is_some() {
+                stats.pinned_links += 1;
+            } else {
+                stats.unpinned_links += 1;
+            }
+        }
+
+        stats
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let files = self.templates + self.plain;
+        let links = self.pinned_links + self.unpinned_links;
+
+        writeln!(f, "{GREEN}files{RESET}       {files}")?;
+        for (dir, count) in &self.files_per_dir {
+            writeln!(f, "  {} {count}", dir.show())?;
+        }
+        writeln!(f, "{GREEN}templates{RESET}   {}", self.templates)?;
+        writeln!(f, "{GREEN}plain{RESET}       {}", self.plain)?;
+        writeln!(
+            f,
+            "{GREEN}links{RESET}       {links} ({} pinned, {} unpinned)",
+            self.pinned_links, self.unpinned_links
+        )?;
+        writeln!(
+            f,
+            "{GREEN}size{RESET}        {}",
+            show_size(self.total_size)
+        )?;
+        match self.last_apply {
+            Some(last_apply) => writeln!(f, "{GREEN}last apply{RESET}  {last_apply}"),
+            None => writeln!(f, "{GREEN}last apply{RESET}  never"),
+        }
+    }
+}
+
+/// Human-readable size, e.g. `12.3 KiB`
+fn show_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+
+    format!("{size:.1} {unit}")
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn show_size() {
+        assert_eq!(super::show_size(12), "12 B");
+        assert_eq!(super::show_size(1536), "1.5 KiB");
+        assert_eq!(super::show_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
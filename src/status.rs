@@ -0,0 +1,161 @@
+//! Contains [`status`]
+//!
+//! `dots status` compares what the repo describes to what is on disk, for every file that
+//! `dots` manages now or managed before. Files which were managed before come from the
+//! [`ApplyIndex`], the files that `dots` wrote on this machine and did not prune yet
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use simply_colored::*;
+
+use crate::analysis::{FileState, WritePath};
+use crate::index::ApplyIndex;
+use crate::stdx::PathExt as _;
+
+/// State of a single file, as shown by `dots status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    /// File on disk is what the repo describes
+    UpToDate,
+    /// File on disk is what the last apply wrote, but the repo changed since then
+    Outdated,
+    /// File on disk was modified since `dots` wrote it, or it was never written by `dots`
+    Modified,
+    /// File does not exist
+    Missing,
+    /// File was written by `dots` before, but the repo no longer contains it
+    Extra,
+}
+
+impl Status {
+    /// Color of the status in the output of `dots status`
+    const fn color(self) -> &'static str {
+        match self {
+            Self::UpToDate => GREEN,
+            Self::Outdated => BLUE,
+            Self::Modified => YELLOW,
+            Self::Missing => RED,
+            Self::Extra => MAGENTA,
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::UpToDate => "up to date",
+            Self::Outdated => "outdated",
+            Self::Modified => "modified",
+            Self::Missing => "missing",
+            Self::Extra => "extra",
+        })
+    }
+}
+
+/// State of every file in `writes`, and of every file in the `index` which is not written
+/// anymore but still exists
+///
+/// `index` holds what `dots` last wrote to each file
+pub fn status<'a>(
+    writes: impl IntoIterator<Item = &'a WritePath>,
+    index: &ApplyIndex,
+) -> Vec<(PathBuf, Status)> {
+    let mut statuses = writes
+        .into_iter()
+        .map(|write| {
+            let status = match write.state() {
+                FileState::UpToDate => Status::UpToDate,
+                FileState::Missing => Status::Missing,
+                FileState::Modified
+                    if index
+                        .outputs
+                        .get(&write.path)
+                        .is_some_and(|indexed| index.is_written(&write.path, &indexed.hash)) =>
+                {
+                    Status::Outdated
+                }
+                FileState::Modified => Status::Modified,
+            };
+            (write.path.clone(), status)
+        })
+        .collect::<Vec<_>>();
+
+    let written = statuses
+        .iter()
+        .map(|(path, _)| path.clone())
+        .collect::<BTreeSet<_>>();
+    statuses.extend(
+        index
+            .outputs
+            .keys()
+            .filter(|path| !written.contains(*path) && fs::symlink_metadata(path).is_ok())
+            .map(|path| (path.clone(), Status::Extra)),
+    );
+
+    statuses.sort();
+    statuses.dedup();
+    statuses
+}
+
+/// Output of `dots status`, a line for each file
+pub fn status_report(statuses: &[(PathBuf, Status)]) -> String {
+    statuses
+        .iter()
+        .map(|(path, status)| format!("{}{status:<10}{RESET} {}\n", status.color(), path.show()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::Contents;
+
+    #[test]
+    fn status() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let write = |name: &str, contents: &str| WritePath {
+            path: dir.join(name),
+            contents: Contents::Text(contents.to_string()),
+            source: None,
+            durable: false,
+            description: None,
+            symlink: false,
+            mode: None,
+        };
+
+        let writes = [
+            write("same", "same"),
+            write("outdated", "new"),
+            write("edited", "written"),
+            write("missing", "missing"),
+        ];
+        let mut index = ApplyIndex::default();
+        for (name, contents) in [
+            ("same", "same"),
+            ("outdated", "old"),
+            ("edited", "written"),
+            ("removed", "removed from the repo"),
+            ("gone", "gone"),
+        ] {
+            fs::write(dir.join(name), contents).unwrap();
+            index.record(&dir.join(name), sha256::digest(contents));
+        }
+        fs::write(dir.join("edited"), "edited by hand").unwrap();
+        fs::remove_file(dir.join("gone")).unwrap();
+
+        assert_eq!(
+            super::status(&writes, &index),
+            [
+                (dir.join("edited"), Status::Modified),
+                (dir.join("missing"), Status::Missing),
+                (dir.join("outdated"), Status::Outdated),
+                (dir.join("removed"), Status::Extra),
+                (dir.join("same"), Status::UpToDate),
+            ]
+        );
+    }
+}
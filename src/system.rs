@@ -0,0 +1,272 @@
+//! Contains [`Snippet`]
+//!
+//! Some settings of the system are dropped into `/etc` as snippets, e.g. udev rules
+//! which remap a keyboard or a higher inotify limit in `sysctl.d`. They are only read
+//! after a reload, so each snippet is written along with the command that reloads it.
+//! Writing into `/etc` needs root, so they are only applied with `--system`, which
+//! refuses to run without root
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eyre::{Context as _, Result, bail, eyre};
+use itertools::Itertools as _;
+use serde::Deserialize;
+
+use crate::analysis::{Contents, WritePath};
+use crate::operation::Operation;
+use crate::stdx::PathExt as _;
+
+/// Directory of the udev rules of the system
+const UDEV_DIR: &str = "/etc/udev/rules.d";
+
+/// File with every `[sysctl]` parameter
+const SYSCTL_FILE: &str = "/etc/sysctl.d/90-dots.conf";
+
+/// A udev rules file, in the `[[udev_rule]]` section of the config
+#[derive(Deserialize, Debug)]
+pub struct UdevRule {
+    /// Name of the rules, e.g. `90-keyboard`, which orders them among the other rules
+    pub name: String,
+    /// File with the rules, relative to the config file
+    pub file: PathBuf,
+}
+
+/// A snippet in `/etc`, along with whether it is already installed
+#[derive(Debug)]
+pub struct Snippet {
+    /// The snippet
+    pub write: WritePath,
+    /// Command which makes the system read the snippet
+    pub reload: &'static str,
+    /// The snippet on disk is already the same
+    pub installed: bool,
+}
+
+impl Snippet {
+    /// Snippet with `contents` at `path`
+    fn new(path: PathBuf, contents: String, reload: &'static str) -> Self {
+        let installed = fs::read_to_string(&path).is_ok_and(|current| current == contents);
+
+        Self {
+            write: WritePath {
+                path,
+                contents: Contents::Text(contents),
+                source: None,
+                durable: true,
+                description: None,
+                symlink: false,
+                mode: None,
+            },
+            reload,
+            installed,
+        }
+    }
+}
+
+impl UdevRule {
+    /// Read the rules in the repo into a snippet of `/etc/udev/rules.d`
+    pub fn snippet(&self, root: &Path) -> Result<Snippet> {
+        if self.name.is_empty()
+            || !self
+                .name
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+        {
+            bail!(
+                "name of udev rule `{}` can only contain letters, digits, `-` and `_`",
+                self.name
+            );
+        }
+        let file = root.join(&self.file);
+        let contents = fs::read_to_string(&file)
+            .with_context(|| eyre!("failed to read udev rules {}", file.show()))?;
+
+        Ok(Snippet::new(
+            Path::new(UDEV_DIR).join(format!("{}.rules", self.name)),
+            contents,
+            "udevadm control --reload",
+        ))
+    }
+}
+
+/// Every `[sysctl]` parameter, as a snippet of `/etc/sysctl.d`
+pub fn sysctl(parameters: &BTreeMap<String, toml::Value>) -> Result<Snippet> {
+    let lines = parameters
+        .iter()
+        .map(|(key, value)| {
+            if key.is_empty()
+                || !key
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '_' | '-' | '/'))
+            {
+                bail!("invalid sysctl parameter `{key}`");
+            }
+            let value = match value {
+                toml::Value::String(value) => value.clone(),
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Boolean(value) => u8::from(*value).to_string(),
+                _ => {
+                    bail!("value of sysctl parameter `{key}` must be a string, integer or boolean")
+                }
+            };
+
+            Ok(format!("{key} = {value}\n"))
+        })
+        .collect::<Result<String>>()?;
+
+    Ok(Snippet::new(
+        PathBuf::from(SYSCTL_FILE),
+        format!("# managed by dots\n{lines}"),
+        "sysctl --system",
+    ))
+}
+
+/// Operations that write every snippet, then reload each of the ones which changed once
+///
+/// Installed snippets are written too, which leaves them unchanged, so they remain managed files
+pub fn operations(snippets: Vec<Snippet>) -> Vec<Operation> {
+    let reloads = snippets
+        .iter()
+        .filter(|snippet| !snippet.installed)
+        .map(|snippet| snippet.reload)
+        .unique()
+        .map(|command| Operation::RunHook {
+            command: command.to_string(),
+        })
+        .collect_vec();
+
+    snippets
+        .into_iter()
+        .map(|snippet| Operation::Write(snippet.write))
+        .chain(reloads)
+        .collect()
+}
+
+/// Whether `dots` runs as root, which is needed to write into `/etc`
+#[cfg(unix)]
+pub fn is_root() -> bool {
+    // SAFETY: `geteuid` is always successful
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Whether `dots` runs as root, which is needed to write into `/etc`
+#[cfg(not(unix))]
+pub fn is_root() -> bool {
+    false
+}
+
+/// Keep the `snippets` only if they are `enabled` with `--system`, which fails unless
+/// `dots` runs as root
+///
+/// Without `--system`, snippets which are not installed are only reported
+pub fn gate(snippets: Vec<Snippet>, enabled: bool, is_root: bool) -> Result<Vec<Snippet>> {
+    if snippets.is_empty() {
+        return Ok(snippets);
+    }
+
+    if !enabled {
+        let outdated = snippets.iter().filter(|snippet| !snippet.installed).count();
+        if outdated > 0 {
+            log::warn!(
+                "{outdated} udev rules or sysctl snippets in /etc are not up to date. run `sudo dots apply --system` to write them"
+            );
+        }
+        return Ok(vec![]);
+    }
+
+    if !is_root {
+        bail!(
+            "`--system` writes udev rules and sysctl parameters into /etc, which needs root. run `sudo dots apply --system`"
+        );
+    }
+
+    Ok(snippets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations() {
+        let sysctl = sysctl(&BTreeMap::from([
+            (
+                "fs.inotify.max_user_watches".to_string(),
+                toml::Value::Integer(524_288),
+            ),
+            ("kernel.sysrq".to_string(), toml::Value::Boolean(true)),
+        ]))
+        .unwrap();
+        assert_eq!(
+            sysctl.write.contents.text(),
+            "# managed by dots\nfs.inotify.max_user_watches = 524288\nkernel.sysrq = 1\n"
+        );
+
+        let udev = |name: &str, installed| Snippet {
+            installed,
+            ..Snippet::new(
+                Path::new(UDEV_DIR).join(format!("{name}.rules")),
+                String::new(),
+                "udevadm control --reload",
+            )
+        };
+        let operations = super::operations(vec![
+            udev("90-keyboard", false),
+            udev("91-mouse", false),
+            udev("92-tablet", true),
+            Snippet {
+                installed: false,
+                ..sysctl
+            },
+        ]);
+
+        // udev is only reloaded once, and installed snippets are not reloaded
+        assert_eq!(
+            operations[..4]
+                .iter()
+                .map(|operation| operation.path().unwrap())
+                .collect_vec(),
+            [
+                Path::new("/etc/udev/rules.d/90-keyboard.rules"),
+                Path::new("/etc/udev/rules.d/91-mouse.rules"),
+                Path::new("/etc/udev/rules.d/92-tablet.rules"),
+                Path::new(SYSCTL_FILE),
+            ]
+        );
+        assert_eq!(
+            operations[4..],
+            [
+                Operation::RunHook {
+                    command: "udevadm control --reload".to_string()
+                },
+                Operation::RunHook {
+                    command: "sysctl --system".to_string()
+                },
+            ]
+        );
+
+        assert!(super::sysctl(&BTreeMap::from([("a b".to_string(), 1.into())])).is_err());
+    }
+
+    #[test]
+    fn gate() {
+        let snippet = || {
+            Snippet::new(
+                Path::new(UDEV_DIR).join("90-keyboard.rules"),
+                String::new(),
+                "udevadm control --reload",
+            )
+        };
+
+        assert!(
+            super::gate(vec![snippet()], false, true)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(super::gate(vec![snippet()], true, false).is_err());
+        assert_eq!(super::gate(vec![snippet()], true, true).unwrap().len(), 1);
+        assert!(super::gate(vec![], true, false).unwrap().is_empty());
+    }
+}
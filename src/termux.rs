@@ -0,0 +1,94 @@
+//! Contains [`Termux`]
+//!
+//! On Android, Termux keeps `$HOME` and everything it installs under the prefix of the
+//! app, e.g. `/data/data/com.termux/files`, and there is no `/tmp` or
+//! `$XDG_RUNTIME_DIR`. Shared storage, which other apps can read, does not support
+//! symlinks, so files are copied there instead
+
+use std::path::{Path, PathBuf};
+
+use crate::environment::Environment;
+
+/// Prefix that Termux installs into, when `$PREFIX` is not set
+const DEFAULT_PREFIX: &str = "/data/data/com.termux/files/usr";
+
+/// Shared storage of the phone, which `~/storage/shared` points to
+const SHARED_STORAGE: &str = "/storage/emulated/0";
+
+/// Directories of Termux
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Termux {
+    /// Prefix that packages are installed into, e.g. `/data/data/com.termux/files/usr`
+    pub prefix: PathBuf,
+    /// Shared storage of the phone
+    pub shared_storage: PathBuf,
+}
+
+/// Termux, given whether `$TERMUX_VERSION` is set and the value of `$PREFIX`
+fn detect(version: bool, prefix: Option<PathBuf>) -> Option<Termux> {
+    let is_termux = version
+        || prefix
+            .as_ref()
+            .is_some_and(|prefix| prefix.to_string_lossy().contains("/com.termux/"));
+    if !is_termux {
+        return None;
+    }
+
+    Some(Termux {
+        prefix: prefix.unwrap_or_else(|| PathBuf::from(DEFAULT_PREFIX)),
+        shared_storage: PathBuf::from(SHARED_STORAGE),
+    })
+}
+
+impl Termux {
+    /// Directory for temporary files, which the runtime directory falls back to
+    pub fn tmp(&self) -> PathBuf {
+        self.prefix.join("tmp")
+    }
+
+    /// Whether `path` is on shared storage, which does not support symlinks
+    pub fn is_shared(&self, path: &Path) -> bool {
+        path.starts_with(&self.shared_storage)
+            || path.starts_with("/sdcard")
+            // `termux-setup-storage` links it into `~/storage/shared`
+            || path
+                .ancestors()
+                .any(|ancestor| ancestor.ends_with("storage/shared"))
+    }
+}
+
+/// Termux, if the `environment` is inside of it, see [`Environment::termux`]
+pub fn termux(environment: &Environment) -> Option<Termux> {
+    detect(
+        environment.var("TERMUX_VERSION").is_some(),
+        environment.var("PREFIX").map(PathBuf::from),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        assert_eq!(super::detect(false, Some("/usr".into())), None);
+        assert_eq!(super::detect(false, None), None);
+
+        let termux = super::detect(false, Some("/data/data/com.termux/files/usr".into())).unwrap();
+        assert_eq!(termux.prefix, Path::new(DEFAULT_PREFIX));
+        assert_eq!(
+            termux.tmp(),
+            Path::new("/data/data/com.termux/files/usr/tmp")
+        );
+        assert!(termux.is_shared(Path::new("/storage/emulated/0/Documents/notes.md")));
+        assert!(termux.is_shared(Path::new(
+            "/data/data/com.termux/files/home/storage/shared/notes.md"
+        )));
+        assert!(!termux.is_shared(Path::new("/data/data/com.termux/files/home/.bashrc")));
+
+        assert_eq!(
+            super::detect(true, None).unwrap().prefix,
+            Path::new(DEFAULT_PREFIX)
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Contains [`Theme`]
+//!
+//! Palettes are read from [base16](https://github.com/tinted-theming/home) and
+//! base24 schemes, so every community scheme can be used as-is
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eyre::{Context as _, Result, bail, eyre};
+use serde::Deserialize;
+
+use crate::fetch::Limiter;
+use crate::stdx::PathExt as _;
+
+/// Name of the variable that contains the palette
+pub const VAR: &str = "theme";
+
+/// Where to read the color scheme from, in the `[theme]` section of the config
+#[derive(Deserialize, Debug)]
+pub struct Theme {
+    /// Scheme file in the repo, relative to the config file
+    pub scheme: Option<PathBuf>,
+    /// URL to download the scheme from, instead of a file in the repo
+    pub url: Option<String>,
+    /// Expected hash of the scheme downloaded from `url`
+    pub sha256: Option<String>,
+}
+
+/// A base16 or base24 scheme
+#[derive(Debug, PartialEq, Eq)]
+pub struct Scheme {
+    /// Name of the scheme, e.g. `Catppuccin Mocha`
+    pub name: String,
+    /// Who made the scheme
+    pub author: String,
+    /// `dark` or `light`, if the scheme says
+    pub variant: Option<String>,
+    /// Every color of the scheme by its name, e.g. `base00` is `#1e1e2e`
+    ///
+    /// base16 schemes have `base00` to `base0F`, base24 schemes also have `base10` to `base17`
+    pub palette: BTreeMap<String, String>,
+}
+
+/// Both the old and the new format of schemes
+///
+/// The old one has the colors at the top level and uses `scheme` for the name,
+/// the new one has them in `palette`
+#[derive(Deserialize)]
+struct RawScheme {
+    /// Name, in the old format
+    scheme: Option<String>,
+    /// Name, in the new format
+    name: Option<String>,
+    #[serde(default)]
+    author: String,
+    variant: Option<String>,
+    palette: Option<BTreeMap<String, String>>,
+    /// Colors, in the old format
+    #[serde(flatten)]
+    rest: BTreeMap<String, serde_yaml::Value>,
+}
+
+impl Theme {
+    /// Read the scheme, downloading it through the `limiter` if it is a link
+    pub fn load(&self, root: &Path, limiter: &Limiter) -> Result<Scheme> {
+        let contents = match (&self.scheme, &self.url) {
+            (Some(scheme), None) => {
+                let path = root.join(scheme);
+                fs::read_to_string(&path)
+                    .with_context(|| eyre!("failed to read scheme {}", path.show()))?
+            }
+            (None, Some(url)) => {
+                let contents = limiter
+                    .fetch(url)
+                    .and_then(|fetched| {
+                        String::from_utf8(fetched.bytes)
+                            .with_context(|| eyre!("{url} is not valid UTF-8"))
+                    })
+                    .with_context(|| eyre!("failed to fetch scheme {url}"))?;
+
+                let actual_sha256 = sha256::digest(&contents);
+                if let Some(expected_sha256) = &self.sha256
+                    && actual_sha256 != *expected_sha256
+                {
+                    bail!(
+                        "hash mismatch of scheme {url}\n  actual     {actual_sha256}\n  expected   {expected_sha256}"
+                    );
+                }
+
+                contents
+            }
+            _ => bail!("`[theme]` must have exactly one of `scheme` and `url`"),
+        };
+
+        Scheme::parse(&contents)
+    }
+}
+
+impl Scheme {
+    /// Parse a base16 or base24 scheme
+    pub fn parse(yaml: &str) -> Result<Self> {
+        let raw = serde_yaml::from_str::<RawScheme>(yaml).context("invalid scheme")?;
+
+        let palette = raw.palette.unwrap_or_else(|| {
+            raw.rest
+                .into_iter()
+                .filter_map(|(key, value)| Some((key, value.as_str()?.to_string())))
+                .collect()
+        });
+
+        let palette = palette
+            .into_iter()
+            .filter(|(key, _)| is_color(key))
+            .map(|(key, color)| {
+                let hex = color.trim_start_matches('#');
+                if hex.len() != 6 || !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+                    bail!("invalid color `{color}` of `{key}` in scheme");
+                }
+                Ok((key, format!("#{}", hex.to_ascii_lowercase())))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+
+        if let Some(missing) = (0..16)
+            .map(|i| format!("base{i:02X}"))
+            .find(|key| !palette.contains_key(key))
+        {
+            bail!("scheme is missing `{missing}`");
+        }
+
+        Ok(Self {
+            name: raw.name.or(raw.scheme).unwrap_or_default(),
+            author: raw.author,
+            variant: raw.variant,
+            palette,
+        })
+    }
+
+    /// Variables of templates, e.g. `{{ theme.base00 }}` and `{{ theme.name }}`
+    pub fn vars(&self) -> toml::Table {
+        let mut vars = self
+            .palette
+            .iter()
+            .map(|(key, color)| (key.clone(), toml::Value::from(color.as_str())))
+            .collect::<toml::Table>();
+        vars.insert("name".to_string(), self.name.as_str().into());
+        vars.insert("author".to_string(), self.author.as_str().into());
+        if let Some(variant) = &self.variant {
+            vars.insert("variant".to_string(), variant.as_str().into());
+        }
+        vars
+    }
+}
+
+/// Whether `key` is the name of a color, from `base00` to `base17`
+fn is_color(key: &str) -> bool {
+    key.strip_prefix("base")
+        .is_some_and(|n| n.len() == 2 && u8::from_str_radix(n, 16).is_ok_and(|n| n < 0x18))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let colors = (0..16)
+            .map(|i| format!("base{i:02X}: \"{i:02x}{i:02x}{i:02x}\"\n"))
+            .collect::<String>();
+
+        let old =
+            Scheme::parse(&format!("scheme: \"Old\"\nauthor: \"someone\"\n{colors}")).unwrap();
+        assert_eq!(old.name, "Old");
+        assert_eq!(old.palette["base0F"], "#0f0f0f");
+        assert_eq!(old.palette.len(), 16);
+
+        let new = Scheme::parse(&format!(
+            "system: \"base24\"\nname: \"New\"\nauthor: \"someone\"\nvariant: \"dark\"\npalette:\n{}  base17: \"#ABCDEF\"\n",
+            colors
+                .lines()
+                .map(|line| format!("  {line}\n"))
+                .collect::<String>()
+        ))
+        .unwrap();
+        assert_eq!(new.name, "New");
+        assert_eq!(new.variant.as_deref(), Some("dark"));
+        assert_eq!(new.palette["base17"], "#abcdef");
+
+        assert!(Scheme::parse("scheme: \"Empty\"").is_err());
+    }
+}
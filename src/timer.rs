@@ -0,0 +1,194 @@
+//! Contains [`Timer`]
+//!
+//! Scheduled jobs are installed as systemd user timers, which are a `.service` and
+//! a `.timer` in `{config_dir}/systemd/user`. The timer is enabled with a symlink,
+//! the same way as `systemctl --user enable` does it
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eyre::{Result, bail};
+use serde::Deserialize;
+use tap::Pipe as _;
+
+use crate::analysis::{Contents, WritePath};
+use crate::operation::Operation;
+use crate::output_path::OutputPath;
+
+/// A job which runs on a schedule, in the `[[timer]]` section of the config
+#[derive(Deserialize, Debug)]
+pub struct Timer {
+    /// Name of the job, the units are called `dots-<name>`
+    pub name: String,
+    /// Command to run, with `sh`
+    pub command: String,
+    /// When to run it, as a systemd calendar event, e.g. `daily` or `Mon *-*-* 09:00`
+    pub schedule: String,
+    /// Run the job when the machine boots, if it was off when the job should have run
+    #[serde(default = "default_persistent")]
+    pub persistent: bool,
+}
+
+/// Default value for [`Timer::persistent`]
+fn default_persistent() -> bool {
+    true
+}
+
+/// Units of a `[[timer]]`, along with whether they are already installed
+#[derive(Debug)]
+pub struct TimerUnits {
+    /// Name of the `.timer` unit
+    pub unit: String,
+    /// The `.service`
+    pub service: WritePath,
+    /// The `.timer`
+    pub timer: WritePath,
+    /// Symlink which enables the timer
+    pub wants: PathBuf,
+    /// The units are installed, and the timer is enabled
+    pub installed: bool,
+}
+
+/// Directory of the systemd units of the user
+pub fn unit_dir() -> Result<PathBuf> {
+    "{config_dir}/systemd/user"
+        .parse::<OutputPath>()?
+        .into_inner()
+        .pipe(Ok)
+}
+
+impl Timer {
+    /// Resolve the units of the timer inside of `unit_dir`
+    pub fn units(&self, unit_dir: &Path) -> Result<TimerUnits> {
+        if self.name.is_empty()
+            || !self
+                .name
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+        {
+            bail!(
+                "name of timer `{}` can only contain letters, digits, `-` and `_`",
+                self.name
+            );
+        }
+
+        let name = format!("dots-{}", self.name);
+        let unit = format!("{name}.timer");
+
+        let service = format!(
+            "[Unit]\nDescription={name}, managed by dots\n\n\
+             [Service]\nType=oneshot\nExecStart=/bin/sh -lc \"{}\"\n",
+            escape(&self.command)
+        );
+        let timer = format!(
+            "[Unit]\nDescription={name}, managed by dots\n\n\
+             [Timer]\nOnCalendar={}\nPersistent={}\n\n\
+             [Install]\nWantedBy=timers.target\n",
+            self.schedule, self.persistent
+        );
+
+        let write = |path: PathBuf, contents: String| WritePath {
+            path,
+            contents: Contents::Text(contents),
+            source: None,
+            durable: false,
+            description: None,
+            symlink: false,
+            mode: None,
+        };
+        let service = write(unit_dir.join(format!("{name}.service")), service);
+        let timer = write(unit_dir.join(&unit), timer);
+        let wants = unit_dir.join("timers.target.wants").join(&unit);
+
+        let installed = [&service, &timer]
+            .iter()
+            .all(|write| fs::read_to_string(&write.path).is_ok_and(|c| c == write.contents.text()))
+            && fs::read_link(&wants).is_ok_and(|target| target == timer.path);
+
+        Ok(TimerUnits {
+            unit,
+            service,
+            timer,
+            wants,
+            installed,
+        })
+    }
+}
+
+impl TimerUnits {
+    /// Operations that install the units, enable the timer and restart it
+    ///
+    /// The units are written even if they are installed, which leaves them unchanged,
+    /// so they remain managed files
+    pub fn operations(self) -> Vec<Operation> {
+        let target = self.timer.path.clone();
+        let writes = [Operation::Write(self.service), Operation::Write(self.timer)];
+
+        if self.installed {
+            return writes.into();
+        }
+
+        writes
+            .into_iter()
+            .chain([
+                Operation::Symlink {
+                    path: self.wants,
+                    target,
+                },
+                Operation::RunHook {
+                    command: "systemctl --user daemon-reload".to_string(),
+                },
+                Operation::RunHook {
+                    command: format!("systemctl --user restart {}", self.unit),
+                },
+            ])
+            .collect()
+    }
+}
+
+/// Escape `command` for a double-quoted argument of `ExecStart`, where systemd
+/// also expands `$` and `%`
+fn escape(command: &str) -> String {
+    command
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "$$")
+        .replace('%', "%%")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units() {
+        let dir = tempfile::tempdir().unwrap();
+        let timer = Timer {
+            name: "check".to_string(),
+            command: "dots check || echo \"$HOME\" 100%".to_string(),
+            schedule: "daily".to_string(),
+            persistent: true,
+        };
+
+        let units = timer.units(dir.path()).unwrap();
+        assert_eq!(units.unit, "dots-check.timer");
+        assert!(!units.installed);
+        assert!(
+            units
+                .service
+                .contents
+                .text()
+                .contains("ExecStart=/bin/sh -lc \"dots check || echo \\\"$$HOME\\\" 100%%\"")
+        );
+        assert!(units.timer.contents.text().contains("OnCalendar=daily\n"));
+
+        assert!(
+            Timer {
+                name: "a b".to_string(),
+                ..timer
+            }
+            .units(dir.path())
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Contains [`Tool`]
+//!
+//! Configs often depend on CLI tools, e.g. a shell prompt on `starship`. Each `[[tool]]`
+//! declares how to check that a tool is installed and how to install it on each platform
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::process::Command;
+use std::sync::LazyLock;
+
+use eyre::{Context as _, ContextCompat as _, Result, bail, eyre};
+use regex::Regex;
+use serde::Deserialize;
+use simply_colored::*;
+
+use crate::config::Config;
+
+/// A CLI tool that the configs depend on, in the `[[tool]]` section of the config
+#[derive(Deserialize, Debug)]
+pub struct Tool {
+    /// Name of the tool, e.g. `ripgrep`
+    pub name: String,
+    /// Command which prints the version of the tool, `<name> --version` by default
+    pub check: Option<String>,
+    /// Version that must be installed, e.g. `14` matches `14.1.0`
+    pub version: Option<String>,
+    /// Command which installs the tool, for each platform, e.g. `linux` or `macos`
+    #[serde(default)]
+    pub install: BTreeMap<String, String>,
+}
+
+/// Whether a [`Tool`] is installed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// Installed, and has the expected version if there is one
+    Installed {
+        /// Version printed by the check, if it printed one
+        version: Option<String>,
+    },
+    /// Installed, but with a different version
+    Outdated {
+        /// Version printed by the check
+        version: Option<String>,
+        /// Version that must be installed
+        expected: String,
+    },
+    /// The check failed
+    Missing,
+}
+
+/// The first thing in the output of a check that looks like a version
+static VERSION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\d+(\.\d+)+|\d+").expect("valid regex"));
+
+impl Tool {
+    /// Command which checks that the tool is installed
+    fn check(&self) -> String {
+        self.check
+            .clone()
+            .unwrap_or_else(|| format!("{} --version", self.name))
+    }
+
+    /// Run the check of the tool
+    pub fn status(&self) -> Status {
+        let Ok(output) = run(&self.check()) else {
+            return Status::Missing;
+        };
+        let version = VERSION
+            .find(&output)
+            .map(|version| version.as_str().to_string());
+
+        match &self.version {
+            Some(expected)
+                if !version
+                    .as_deref()
+                    .is_some_and(|version| matches(version, expected)) =>
+            {
+                Status::Outdated {
+                    version,
+                    expected: expected.clone(),
+                }
+            }
+            _ => Status::Installed { version },
+        }
+    }
+
+    /// Install the tool with the command for this platform
+    pub fn install(&self) -> Result<()> {
+        let command = self.install.get(std::env::consts::OS).with_context(|| {
+            eyre!(
+                "`[[tool]]` {} has no install command for {}",
+                self.name,
+                std::env::consts::OS
+            )
+        })?;
+
+        log::info!("installing {} with `{command}`", self.name);
+        run(command).with_context(|| eyre!("failed to install {}", self.name))?;
+
+        Ok(())
+    }
+}
+
+/// Whether `version` is `expected`, or a more specific version of it
+fn matches(version: &str, expected: &str) -> bool {
+    let mut version = version.split('.');
+    expected
+        .split('.')
+        .all(|expected| version.next() == Some(expected))
+}
+
+/// Run `command` split into words like a shell would, returning what it printed
+fn run(command: &str) -> Result<String> {
+    let words = shellwords::split(command).with_context(|| eyre!("invalid command: {command}"))?;
+    let (program, args) = words
+        .split_first()
+        .with_context(|| eyre!("command is empty"))?;
+
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| eyre!("failed to run `{command}`"))?;
+    if !output.status.success() {
+        bail!(
+            "`{command}` failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    // some tools print their version to stderr, e.g. `ssh -V`
+    Ok(format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    ))
+}
+
+/// Install every `[[tool]]` which is missing or has a different version
+pub fn sync_tools(cwd: &Path) -> Result<()> {
+    let config = Config::discover(cwd)?;
+
+    let mut failed = 0;
+    for tool in &config.tools {
+        if matches!(tool.status(), Status::Installed { .. }) {
+            log::debug!("{} is already installed", tool.name);
+            continue;
+        }
+        match tool.install().map(|()| tool.status()) {
+            Ok(Status::Installed { .. }) => log::info!("installed {}", tool.name),
+            Ok(status) => {
+                failed += 1;
+                log::error!(
+                    "{} is still not installed after installing it: {status:?}",
+                    tool.name
+                );
+            }
+            Err(err) => {
+                failed += 1;
+                log::error!("{err:#}");
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("failed to install {failed} tools");
+    }
+
+    Ok(())
+}
+
+/// Report of every `[[tool]]` and whether it is installed, for `dots doctor`
+pub fn doctor_tools(cwd: &Path) -> Result<String> {
+    let config = Config::discover(cwd)?;
+
+    let mut report = String::new();
+    if config.tools.is_empty() {
+        return Ok(report);
+    }
+
+    _ = writeln!(report, "{BOLD}tools{RESET}");
+    for tool in &config.tools {
+        _ = match tool.status() {
+            Status::Installed { version } => writeln!(
+                report,
+                "  {GREEN}✓{RESET} {} {}",
+                tool.name,
+                version.unwrap_or_default()
+            ),
+            Status::Outdated { version, expected } => writeln!(
+                report,
+                "  {YELLOW}!{RESET} {} {}, expected {expected}",
+                tool.name,
+                version.as_deref().unwrap_or("with an unknown version")
+            ),
+            Status::Missing => writeln!(
+                report,
+                "  {RED}✗{RESET} {} is not installed, run `dots tools sync`",
+                tool.name
+            ),
+        };
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version() {
+        assert_eq!(
+            VERSION
+                .find("ripgrep 14.1.0 (rev e50df40a19)")
+                .unwrap()
+                .as_str(),
+            "14.1.0"
+        );
+        assert!(matches("14.1.0", "14"));
+        assert!(matches("14.1.0", "14.1"));
+        assert!(!matches("14.1.0", "1"));
+        assert!(!matches("14", "14.1"));
+    }
+}
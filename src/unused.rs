@@ -0,0 +1,99 @@
+//! Contains [`unused`]
+//!
+//! Repos accumulate sources which are never written, e.g. a file that another `[[dir]]`
+//! always overwrites, or one moved with `@dots --path` over the location of another.
+//! `dots doctor --unused` reports them, so they can be removed
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use simply_colored::*;
+
+use crate::analysis::Analysis;
+use crate::stdx::PathExt as _;
+
+/// A source which is never written, and why
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Unused {
+    /// In a fragment directory, but not a fragment of any shell
+    NotFragment(PathBuf),
+    /// Every path that the source is written to is written again by another source after it
+    Overwritten {
+        /// Source which is never written
+        source: PathBuf,
+        /// Path it would be written to
+        path: PathBuf,
+        /// Source which is written to `path` instead, `None` for a link
+        by: Option<PathBuf>,
+    },
+}
+
+/// Every source which is never written, out of the `unused_inputs` of the world and the
+/// sources written by the `analysis`
+pub fn unused(unused_inputs: &[PathBuf], analysis: &Analysis) -> Vec<Unused> {
+    // the last write of each path is the one that ends up on disk
+    let mut last = BTreeMap::<&Path, Option<&Path>>::new();
+    let mut written = BTreeMap::<&Path, BTreeSet<&Path>>::new();
+    for write in analysis.writes() {
+        last.insert(&write.path, write.source.as_deref());
+        if let Some(source) = &write.source {
+            written.entry(source).or_default().insert(&write.path);
+        }
+    }
+
+    let overwritten = written.into_iter().filter_map(|(source, paths)| {
+        if paths.iter().any(|path| last[path] == Some(source)) {
+            return None;
+        }
+        let path = paths.first()?;
+
+        Some(Unused::Overwritten {
+            source: source.to_path_buf(),
+            path: path.to_path_buf(),
+            by: last[path].map(Path::to_path_buf),
+        })
+    });
+
+    unused_inputs
+        .iter()
+        .cloned()
+        .map(Unused::NotFragment)
+        .chain(overwritten)
+        .collect()
+}
+
+/// Report of every [`unused`] source relative to the repo at `root`, for `dots doctor --unused`
+pub fn doctor_unused(root: &Path, unused: &[Unused]) -> String {
+    let mut report = String::new();
+    if unused.is_empty() {
+        return report;
+    }
+
+    let relative = |path: &Path| {
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    _ = writeln!(report, "{BOLD}unused sources{RESET}");
+    for unused in unused {
+        _ = match unused {
+            Unused::NotFragment(source) => writeln!(
+                report,
+                "  {YELLOW}!{RESET} {} is in a fragment directory, but is not a fragment of any shell",
+                relative(source)
+            ),
+            Unused::Overwritten { source, path, by } => writeln!(
+                report,
+                "  {YELLOW}!{RESET} {} is never written, {} is written to {} after it",
+                relative(source),
+                by.as_deref().map_or_else(|| "a link".to_string(), relative),
+                path.show()
+            ),
+        };
+    }
+
+    report
+}
@@ -0,0 +1,99 @@
+//! Contains [`resolve`]
+
+use eyre::{ContextCompat as _, Result, bail, eyre};
+
+/// Resolve the URL of a `[[link]]`
+///
+/// - `{variable}` is replaced with the variable from `[vars]`, e.g. `{flavor}` or `{theme.variant}`,
+///   so changing the variable changes which file is fetched. `{{` and `}}` are a literal `{` and `}`
+/// - `github:owner/repo@rev:path/to/file` is a file in a GitHub repository
+pub fn resolve(url: &str, vars: &toml::Table) -> Result<String> {
+    let url = interpolate(url, vars)?;
+
+    let Some(github) = url.strip_prefix("github:") else {
+        return Ok(url);
+    };
+
+    let invalid = || eyre!("invalid link `{url}`, expected `github:owner/repo@rev:path`");
+    let (repo, rest) = github.split_once('@').with_context(invalid)?;
+    let (rev, path) = rest.split_once(':').with_context(invalid)?;
+    if repo.split('/').count() != 2 || rev.is_empty() || path.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(format!(
+        "https://raw.githubusercontent.com/{repo}/{rev}/{}",
+        path.trim_start_matches('/')
+    ))
+}
+
+/// Replace every `{variable}` in `url` with its value
+fn interpolate(url: &str, vars: &toml::Table) -> Result<String> {
+    let mut interpolated = String::with_capacity(url.len());
+    let mut chars = url.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.clone().next() == Some('{') => {
+                chars.next();
+                interpolated.push('{');
+            }
+            '}' if chars.clone().next() == Some('}') => {
+                chars.next();
+                interpolated.push('}');
+            }
+            '{' => {
+                let name = chars
+                    .by_ref()
+                    .take_while(|&ch| ch != '}')
+                    .collect::<String>();
+                interpolated.push_str(&lookup(&name, vars)?);
+            }
+            ch => interpolated.push(ch),
+        }
+    }
+
+    Ok(interpolated)
+}
+
+/// Value of the variable `name`, where `.` separates the keys of tables
+fn lookup(name: &str, vars: &toml::Table) -> Result<String> {
+    let mut keys = name.split('.');
+    let first = keys.next().unwrap_or_default();
+    let undefined = || eyre!("variable `{name}` in link is not defined in `[vars]`");
+
+    let value = keys.try_fold(vars.get(first).with_context(undefined)?, |value, key| {
+        value.get(key).with_context(undefined)
+    })?;
+
+    Ok(match value {
+        toml::Value::String(string) => string.clone(),
+        toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+            value.to_string()
+        }
+        _ => bail!("variable `{name}` in link must be a string, number or boolean"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn resolve() {
+        let vars = toml::toml! {
+            flavor = "mocha"
+            rev = "f1b7ac3"
+            theme = { variant = "dark" }
+        };
+
+        assert_eq!(
+            super::resolve("github:catppuccin/kitty@{rev}:themes/{flavor}.conf", &vars).unwrap(),
+            "https://raw.githubusercontent.com/catppuccin/kitty/f1b7ac3/themes/mocha.conf"
+        );
+        assert_eq!(
+            super::resolve("https://example.com/{theme.variant}/{{x}}", &vars).unwrap(),
+            "https://example.com/dark/{x}"
+        );
+        assert!(super::resolve("https://example.com/{flavour}", &vars).is_err());
+        assert!(super::resolve("github:catppuccin/kitty:themes/mocha.conf", &vars).is_err());
+    }
+}
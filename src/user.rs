@@ -0,0 +1,121 @@
+//! Contains [`User`]
+
+use std::path::{Path, PathBuf};
+
+use eyre::{Context as _, Result, eyre};
+
+use crate::stdx::PathExt as _;
+
+/// User that the applied files are owned by, when it is not the user running `dots`
+///
+/// Useful when building container images as root
+#[derive(Debug, Clone)]
+pub struct User {
+    /// Name of the user
+    pub name: String,
+    /// ID of the user
+    pub uid: u32,
+    /// ID of the primary group of the user
+    pub gid: u32,
+    /// Home directory of the user, according to the user database
+    pub home: PathBuf,
+}
+
+impl User {
+    /// Look up the user called `name` in the user database
+    #[cfg(unix)]
+    pub fn from_name(name: &str) -> Result<Self> {
+        use std::ffi::CString;
+
+        let c_name = CString::new(name).with_context(|| eyre!("invalid user name: {name}"))?;
+
+        // SAFETY: `c_name` is a valid NUL-terminated string. The returned entry may be
+        // overwritten by the next call, so everything is copied out of it immediately
+        let passwd = unsafe { libc::getpwnam(c_name.as_ptr()) };
+
+        // SAFETY: a non-null pointer points to a valid entry
+        let passwd =
+            unsafe { passwd.as_ref() }.ok_or_else(|| eyre!("user {name} does not exist"))?;
+
+        // SAFETY: `passwd` is a valid entry
+        Ok(unsafe { Self::from_passwd(passwd) })
+    }
+
+    /// Look up the user running `dots` in the user database
+    #[cfg(unix)]
+    pub fn current() -> Result<Self> {
+        // SAFETY: `getuid` is always successful. The returned entry may be overwritten by
+        // the next call, so everything is copied out of it immediately
+        let passwd = unsafe { libc::getpwuid(libc::getuid()) };
+
+        // SAFETY: a non-null pointer points to a valid entry
+        let passwd = unsafe { passwd.as_ref() }
+            .ok_or_else(|| eyre!("the user running `dots` is not in the user database"))?;
+
+        // SAFETY: `passwd` is a valid entry
+        Ok(unsafe { Self::from_passwd(passwd) })
+    }
+
+    /// Copy the user out of an entry of the user database
+    ///
+    /// # Safety
+    ///
+    /// `passwd` must be a valid entry, as returned by `getpwnam` or `getpwuid`
+    #[cfg(unix)]
+    unsafe fn from_passwd(passwd: &libc::passwd) -> Self {
+        use std::ffi::CStr;
+        use std::os::unix::ffi::OsStrExt as _;
+
+        // SAFETY: `pw_name` and `pw_dir` of a valid entry are valid NUL-terminated strings
+        let (name, home) = unsafe {
+            (
+                CStr::from_ptr(passwd.pw_name),
+                CStr::from_ptr(passwd.pw_dir),
+            )
+        };
+
+        Self {
+            name: name.to_string_lossy().into_owned(),
+            uid: passwd.pw_uid,
+            gid: passwd.pw_gid,
+            home: std::ffi::OsStr::from_bytes(home.to_bytes()).into(),
+        }
+    }
+
+    /// Look up the user called `name` in the user database
+    #[cfg(not(unix))]
+    pub fn from_name(name: &str) -> Result<Self> {
+        eyre::bail!("applying files as user {name} is only supported on unix");
+    }
+
+    /// Make the user own `path`
+    #[cfg(unix)]
+    pub fn chown(&self, path: &Path) -> Result<()> {
+        std::os::unix::fs::chown(path, Some(self.uid), Some(self.gid))
+            .with_context(|| eyre!("failed to change owner of {} to {}", path.show(), self.name))
+    }
+
+    /// Make the user own `path`
+    #[cfg(not(unix))]
+    pub fn chown(&self, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root() {
+        let root = User::from_name("root").unwrap();
+        assert_eq!(root.uid, 0);
+
+        assert!(
+            User::from_name("user_which_does_not_exist_241503142350")
+                .unwrap_err()
+                .to_string()
+                .contains("does not exist")
+        );
+    }
+}
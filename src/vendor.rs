@@ -0,0 +1,229 @@
+//! Contains [`Vendor`]
+//!
+//! `dots vendor` saves the contents of every `[[link]]` into `vendor/` of the repo, so
+//! the repo can be applied without network. Vendored links are read from there instead
+//! of being fetched, for as long as their URL stays the same. `dots vendor --check`
+//! compares them with their upstreams, so vendored links do not silently fall behind
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use eyre::{Context as _, Result, bail, eyre};
+use serde::{Deserialize, Serialize};
+
+use crate::diff::Diff;
+use crate::fetch;
+use crate::stdx::PathExt as _;
+use crate::world::World;
+
+/// Every vendored link, in `vendor/links.toml` of the repo
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct Vendor {
+    /// Vendored links, keyed by their URL
+    #[serde(rename = "link", default)]
+    pub links: BTreeMap<String, Vendored>,
+}
+
+/// A single vendored link
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Vendored {
+    /// File with the contents of the link, relative to `vendor/`
+    pub path: PathBuf,
+    /// Hash of the contents when they were vendored
+    pub sha256: String,
+}
+
+impl Vendor {
+    /// Directory of the vendored links, relative to the config file
+    pub const DIR: &str = "vendor";
+
+    /// Name of the file which lists every vendored link, inside of [`Vendor::DIR`]
+    pub const FILE_NAME: &str = "links.toml";
+
+    /// Load the vendored links of the repo at `root`, none if nothing was vendored
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = root.join(Self::DIR).join(Self::FILE_NAME);
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                toml::from_str(&contents).with_context(|| eyre!("failed to parse {}", path.show()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| eyre!("failed to read {}", path.show())),
+        }
+    }
+
+    /// Save the list of vendored links into the repo at `root`
+    pub fn save(&self, root: &Path) -> Result<()> {
+        let path = root.join(Self::DIR).join(Self::FILE_NAME);
+        let contents = toml::to_string(self).context("failed to serialize vendored links")?;
+        fs::write(&path, contents).with_context(|| eyre!("failed to write {}", path.show()))
+    }
+
+    /// File with the vendored contents of the link at `url`, `None` if it is not vendored
+    pub fn path(&self, root: &Path, url: &str) -> Option<PathBuf> {
+        let vendored = self.links.get(url)?;
+
+        Some(root.join(Self::DIR).join(&vendored.path))
+    }
+
+    /// Vendored contents of the link at `url`, `None` if it is not vendored
+    pub fn read(&self, root: &Path, url: &str) -> Option<Result<Vec<u8>>> {
+        let path = self.path(root, url)?;
+
+        Some(fs::read(&path).with_context(|| eyre!("failed to read vendored link {}", path.show())))
+    }
+}
+
+/// A vendored link whose upstream changed
+#[derive(Debug)]
+pub struct Outdated {
+    /// URL of the link
+    pub url: String,
+    /// Vendored contents, `None` if the link was never vendored
+    pub vendored: Option<Vec<u8>>,
+    /// Contents of the link upstream
+    pub upstream: Vec<u8>,
+}
+
+impl Outdated {
+    /// Log how the upstream differs, along with the changed hunks when logging with `-v`
+    pub fn log(&self) {
+        let Some(vendored) = &self.vendored else {
+            log::warn!("{} is not vendored", self.url);
+            return;
+        };
+
+        let (vendored, upstream) = (
+            String::from_utf8_lossy(vendored),
+            String::from_utf8_lossy(&self.upstream),
+        );
+        let diff = Diff::new(&vendored, &upstream);
+        log::warn!("{} changed upstream {}", self.url, diff.show_stat());
+        log::debug!("{}", diff.show_hunks());
+    }
+}
+
+/// Read the repo at `cwd`, along with its vendored links
+fn load(cwd: &Path) -> Result<(World, Vendor)> {
+    let world = World::new(cwd).map_err(|errs| {
+        for err in errs {
+            log::error!("{err}");
+        }
+        eyre!("encountered errors")
+    })?;
+    let vendor = Vendor::load(&world.root)?;
+
+    Ok((world, vendor))
+}
+
+/// Fetch every `[[link]]` of the repo at `cwd` from upstream, and save it into `vendor/`
+///
+/// Returns the number of links which were vendored
+pub fn vendor(cwd: &Path) -> Result<usize> {
+    let (world, _) = load(cwd)?;
+    let dir = world.root.join(Vendor::DIR);
+
+    // links which were removed from the config are not kept
+    let mut vendor = Vendor::default();
+    for link in &world.links {
+        // the world reads links which are already vendored from `vendor/`
+        // links which are not text are vendored too
+        let contents = fetch::get(&link.url)?;
+        let sha256 = sha256::digest(contents.as_slice());
+        if let Some(expected) = &link.sha256
+            && *expected != sha256
+        {
+            bail!(
+                "hash mismatch of link {}, expected {expected} but got {sha256}",
+                link.url
+            );
+        }
+
+        let path = dir.join(&link.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| eyre!("failed to create {}", parent.show()))?;
+        }
+        fs::write(&path, &contents).with_context(|| eyre!("failed to write {}", path.show()))?;
+        log::debug!(target: "network", "vendored {} into {}", link.url, path.show());
+
+        vendor.links.insert(
+            link.url.clone(),
+            Vendored {
+                path: link.path.clone(),
+                sha256,
+            },
+        );
+    }
+
+    fs::create_dir_all(&dir).with_context(|| eyre!("failed to create {}", dir.show()))?;
+    vendor.save(&world.root)?;
+
+    Ok(vendor.links.len())
+}
+
+/// Fetch every `[[link]]` of the repo at `cwd` from upstream, and return the ones which
+/// differ from their vendored copies or are not vendored
+pub fn check_vendor(cwd: &Path) -> Result<Vec<Outdated>> {
+    let (world, vendor) = load(cwd)?;
+
+    world
+        .links
+        .iter()
+        .map(|link| {
+            let vendored = vendor.read(&world.root, &link.url).transpose()?;
+            let upstream = fetch::get(&link.url)?;
+
+            Ok((vendored.as_ref() != Some(&upstream)).then(|| Outdated {
+                url: link.url.clone(),
+                vendored,
+                upstream,
+            }))
+        })
+        .filter_map(Result::transpose)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{Response, Server};
+
+    #[test]
+    fn check() {
+        let server = Server::new([Response::ok("latte")]);
+        let url = server.url("/theme.nu");
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        fs::write(
+            dir.join("dots.toml"),
+            format!("[[link]]\nurl = \"{url}\"\npath = \"theme.nu\"\n"),
+        )
+        .unwrap();
+        fs::create_dir(dir.join(Vendor::DIR)).unwrap();
+        fs::write(dir.join(Vendor::DIR).join("theme.nu"), "mocha").unwrap();
+        Vendor {
+            links: BTreeMap::from([(
+                url.clone(),
+                Vendored {
+                    path: "theme.nu".into(),
+                    sha256: sha256::digest("mocha"),
+                },
+            )]),
+        }
+        .save(dir)
+        .unwrap();
+
+        let outdated = super::check_vendor(dir).unwrap();
+        server.join();
+
+        assert_eq!(outdated.len(), 1);
+        assert_eq!(outdated[0].url, url);
+        assert_eq!(outdated[0].vendored.as_deref(), Some(b"mocha".as_slice()));
+        assert_eq!(outdated[0].upstream, b"latte");
+    }
+}
@@ -0,0 +1,128 @@
+//! Contains [`changes`]
+//!
+//! With `dots watch`, the repo is authoritative: managed files which are changed by
+//! anything other than `dots` are reported, and with `--enforce` they are put back
+//! right away. What was changed can be saved, to review it before it is lost
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use eyre::{Context as _, Result, eyre};
+
+use crate::analysis::{Analysis, Contents, FileState};
+use crate::diff::Diff;
+use crate::stdx::PathExt as _;
+
+/// A managed file which was changed outside of `dots`
+#[derive(Debug)]
+pub struct Change {
+    /// The managed file
+    pub path: PathBuf,
+    /// Whether the file was modified or removed
+    pub state: FileState,
+    /// Contents of the modified file, `None` if it was removed, is not text or contains secrets
+    pub contents: Option<String>,
+    /// What `dots` wrote to the file
+    expected: String,
+}
+
+impl Change {
+    /// Log the change, along with the changed hunks when logging with `-v`
+    pub fn log(&self) {
+        let Some(contents) = &self.contents else {
+            log::warn!("{} was {} outside of `dots`", self.path.show(), self.state);
+            return;
+        };
+
+        let diff = Diff::new(&self.expected, contents);
+        log::warn!(
+            "{} was {} outside of `dots` {}",
+            self.path.show(),
+            self.state,
+            diff.show_stat()
+        );
+        log::debug!("{}", diff.show_hunks());
+    }
+
+    /// Save the modified file into `dir`, at the same path as it has on this machine
+    ///
+    /// Returns where it was saved to, `None` if there is nothing to save
+    pub fn save(&self, dir: &Path) -> Result<Option<PathBuf>> {
+        if self.state != FileState::Modified {
+            return Ok(None);
+        }
+
+        let saved = self
+            .path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .fold(dir.to_path_buf(), |saved, component| saved.join(component));
+        if let Some(parent) = saved.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| eyre!("failed to create {}", parent.show()))?;
+        }
+        fs::copy(&self.path, &saved)
+            .with_context(|| eyre!("failed to save {} to {}", self.path.show(), saved.show()))?;
+
+        Ok(Some(saved))
+    }
+}
+
+/// Every managed file which differs from what `dots` writes to it
+pub fn changes(analysis: &Analysis) -> Vec<Change> {
+    analysis
+        .writes()
+        .filter_map(|write| {
+            let state = write.state();
+            (state != FileState::UpToDate).then(|| Change {
+                path: write.path.clone(),
+                state,
+                // the hunks of files which contain secrets would show them
+                contents: matches!(write.contents, Contents::Text(_))
+                    .then(|| fs::read_to_string(&write.path).ok())
+                    .flatten(),
+                expected: write.contents.text().to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let foo = dir.join("home/foo");
+        fs::create_dir_all(foo.parent().unwrap()).unwrap();
+        fs::write(&foo, "changed").unwrap();
+
+        let analysis = Analysis::builder()
+            .write(foo.clone(), "foo")
+            .write(dir.join("home/bar"), "bar")
+            .write(dir.join("home/baz"), "baz")
+            .build();
+        fs::write(dir.join("home/baz"), "baz").unwrap();
+
+        let changes = changes(&analysis);
+        assert_eq!(
+            changes
+                .iter()
+                .map(|change| (change.path.clone(), change.state))
+                .collect::<Vec<_>>(),
+            [
+                (foo.clone(), FileState::Modified),
+                (dir.join("home/bar"), FileState::Missing)
+            ]
+        );
+        assert_eq!(changes[0].contents.as_deref(), Some("changed"));
+
+        let review = dir.join("review");
+        let saved = changes[0].save(&review).unwrap().unwrap();
+        assert!(saved.starts_with(&review) && saved.ends_with("home/foo"));
+        assert_eq!(fs::read_to_string(saved).unwrap(), "changed");
+        assert_eq!(changes[1].save(&review).unwrap(), None);
+    }
+}
@@ -0,0 +1,89 @@
+//! Contains [`When`]
+
+use serde::{Deserialize, Serialize};
+
+use crate::environment::Environment;
+
+/// Conditions of an entry, which is skipped on machines that do not match all of them
+///
+/// ```toml
+/// [[dir]]
+/// input = "linux"
+/// output = "{config}"
+/// when = { os = "linux", hostname = "workstation" }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct When {
+    /// Operating system, e.g. `"linux"`, `"macos"` or `"windows"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    /// Hostname of the machine
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Architecture of the CPU, e.g. `"x86_64"` or `"aarch64"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    /// Environment variable which has to be set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+}
+
+impl When {
+    /// Whether there are no conditions, so every machine matches
+    pub fn is_always(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether the machine in the `environment` matches every condition
+    pub fn matches(&self, environment: &Environment) -> bool {
+        self.os.as_deref().is_none_or(|os| os == environment.os)
+            && self
+                .hostname
+                .as_deref()
+                .is_none_or(|hostname| hostname == environment.hostname)
+            && self
+                .arch
+                .as_deref()
+                .is_none_or(|arch| arch == environment.arch)
+            && self
+                .env
+                .as_deref()
+                .is_none_or(|var| environment.var(var).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        let environment = Environment {
+            hostname: "workstation".to_string(),
+            vars: Some(
+                [("WAYLAND_DISPLAY".to_string(), "wayland-1".to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..Environment::new("/home/me")
+        };
+        let matches = |when: &str| toml::from_str::<When>(when).unwrap().matches(&environment);
+
+        assert!(matches(""));
+        assert!(matches(r#"os = "linux""#));
+        assert!(matches(
+            r#"os = "linux"
+            hostname = "workstation"
+            arch = "x86_64"
+            env = "WAYLAND_DISPLAY""#
+        ));
+        assert!(!matches(r#"os = "macos""#));
+        assert!(!matches(
+            r#"os = "linux"
+            hostname = "laptop""#
+        ));
+        assert!(!matches(r#"env = "DISPLAY""#));
+        assert!(toml::from_str::<When>(r#"distro = "arch""#).is_err());
+    }
+}
@@ -1,25 +1,64 @@
 //! See [`World`] for more info
 
-use eyre::ContextCompat as _;
+use std::fs;
+use std::iter;
+use std::mem;
 use std::path::{self, Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
 
 use itertools::Itertools as _;
 use tap::Pipe as _;
 
 use crate::{
-    config::Config,
+    config::{Config, FirstOverwrite, OnError},
     output_path::OutputPath,
-    stdx::{self, PathExt as _},
+    stdx::PathExt as _,
 };
 
-use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
 
-use crate::analysis::Analysis;
+use crate::age;
+use crate::analysis::{Analysis, Contents, WritePath};
+use crate::asset::{self, Asset, Input};
+use crate::cache::{LinkCache, RenderCache};
+use crate::cancel::CancelToken;
+use crate::completions::Completions;
+use crate::computed;
 use crate::config::GITHUB;
 use crate::config::Marker;
+use crate::config::Mode;
+use crate::dconf::DconfSettings;
+use crate::defaults::{Defaults, DefaultsValue};
+use crate::diff::DiffTool;
+use crate::engine::{Delimiters, Engine};
+use crate::env;
+use crate::environment::Environment;
+use crate::existing;
+use crate::facts;
+use crate::fetch::Limiter;
+use crate::flatpak::{FlatpakOverride, FlatpakPermissions};
+use crate::font;
+use crate::local_vars::{self, LocalVars, VarSource};
+use crate::lockfile::{Lockfile, NewlyLocked};
+use crate::mimeapps;
+use crate::operation::{Operation, Watchdog};
+use crate::outputs::{self, Output};
+use crate::phase::{self, Phase};
+use crate::profile;
+use crate::protect::Protect;
+use crate::sensitive::Sensitive;
+use crate::shell;
+use crate::ssh::SshConfig;
+use crate::system::{self, Snippet};
+use crate::termux::Termux;
+use crate::theme;
+use crate::timer::{self, TimerUnits};
+use crate::vendor::Vendor;
+use crate::wsl::LineEndings;
 
-use eyre::{Context as _, Error, Result, bail, eyre};
-use handlebars::Handlebars;
+use eyre::{Context as _, ContextCompat as _, Error, Result, bail, eyre};
 use simply_colored::*;
 
 /// This structure represents inputs to the application, with all
@@ -28,10 +67,96 @@ use simply_colored::*;
 pub struct World {
     /// Path which contains the config file
     pub root: PathBuf,
+    /// Profiles whose entries are applied
+    pub profiles: BTreeSet<String>,
     /// Contents of the config file
     pub links: Vec<Link>,
     /// Files to create
     pub files: Vec<File>,
+    /// Fonts to install
+    pub fonts: Vec<WritePath>,
+    /// Files compiled from the shell fragments of every `[[dir]]` and from `[env]`
+    pub shell: Vec<WritePath>,
+    /// Completions installed on this machine with `dots completions --install`
+    ///
+    /// They are not part of the repo, so the `World` never reads them, and they are empty
+    /// unless added with [`World::install_completions`]
+    pub completions: Vec<WritePath>,
+    /// Flush every file and its directory to disk after writing it, unless its entry
+    /// overrides it
+    pub durable: bool,
+    /// Run `fc-cache` after installing fonts
+    pub fc_cache: bool,
+    /// Settings of every `[[dconf]]`
+    pub dconf: Vec<DconfSettings>,
+    /// Preferences of every `[[defaults]]`, empty on platforms other than macOS
+    pub defaults: Vec<DefaultsValue>,
+    /// Snippets of every `[[udev_rule]]` and of `[sysctl]`, empty on platforms other than Linux
+    pub system: Vec<Snippet>,
+    /// Entries of every `[[autostart]]`, for the autostart mechanism of this platform
+    pub autostart: Vec<WritePath>,
+    /// Permissions of every `[[flatpak_override]]`, empty on platforms other than Linux
+    pub flatpak: Vec<FlatpakPermissions>,
+    /// `mimeapps.list` with every `[[mimeapps]]` merged into it
+    pub mimeapps: Option<WritePath>,
+    /// Gitconfig with the includes of `[git]` in its managed block
+    pub gitconfig: Option<WritePath>,
+    /// SSH config assembled from the fragments of `[ssh]`
+    pub ssh: Option<SshConfig>,
+    /// Units of every `[[timer]]`, empty on platforms without systemd
+    pub timers: Vec<TimerUnits>,
+    /// What to do when some entries fail
+    pub on_error: OnError,
+    /// Errors that happened while creating the `World`, but did not abort it
+    ///
+    /// There can only be any if `on_error` is not [`OnError::Abort`]
+    pub errors: Vec<Error>,
+    /// Indexes of `[[dir]]`s that have at least a single file which failed
+    pub failed_dirs: BTreeSet<usize>,
+    /// Turn conditions that would otherwise only be logged into errors, e.g. links without
+    /// a `sha256`, invalid markers or variables which templates use but are not defined
+    ///
+    /// From `strict` of the config, or from [`Environment::strict`]
+    pub strict: bool,
+    /// Refuse to apply sources which look like they contain secrets
+    pub scan_secrets: bool,
+    /// What to do with files that exist before `dots` writes to them for the first time
+    pub first_overwrite: FirstOverwrite,
+    /// Ask for confirmation before an apply that overwrites more than this many existing files
+    pub confirm_overwrites: usize,
+    /// When to warn about and give up on operations that are stuck
+    pub watchdog: Watchdog,
+    /// Keep extended attributes of files that are replaced, such as SELinux contexts
+    pub preserve_xattrs: bool,
+    /// Program which shows differences instead of the built-in diff
+    pub diff_tool: DiffTool,
+    /// Remove files that earlier applies wrote, but the repo no longer produces
+    pub prune: bool,
+    /// Output directories which are swapped in at once
+    pub atomic_dirs: BTreeSet<PathBuf>,
+    /// Output directory of each `[[dir]]` with `exact = true` along with the files which exist
+    /// inside of it, keyed by the index of the `[[dir]]`
+    pub exact: BTreeMap<usize, (PathBuf, Vec<PathBuf>)>,
+    /// Paths which are never touched
+    pub protect: Protect,
+    /// Home of the Windows user when running inside of WSL, files in which get CRLF
+    /// line endings
+    pub windows_home: Option<PathBuf>,
+    /// Termux, if `dots` runs inside of it, whose shared storage can not have symlinks
+    pub termux: Option<Termux>,
+    /// Files inside of the `input` of a `[[dir]]` which are neither written nor compiled
+    pub unused_inputs: Vec<PathBuf>,
+    /// Variables available in templates
+    pub vars: toml::Table,
+    /// Where the value of each variable in `vars` comes from
+    pub var_sources: BTreeMap<String, VarSource>,
+    /// Values of the `secret` variables by their names, which are only available to templates
+    /// and are not in `vars`. The files that use them are [`Contents::Secret`]
+    pub secrets: BTreeMap<String, Sensitive<String>>,
+    /// Templates rendered by earlier runs
+    pub cache: RenderCache,
+    /// Links which were fetched for the first time, which are locked once they are applied
+    pub newly_locked: NewlyLocked,
 }
 
 /// Represents a URL
@@ -50,27 +175,134 @@ pub struct Link {
     /// This marker is not interpreted. Instead, the marker is written to the
     /// file as-is
     pub marker: Option<String>,
+    /// Flush the file and its directory to disk after writing it
+    pub durable: bool,
+    /// Why the entry exists
+    pub description: Option<String>,
+    /// Make the file read-only after writing it
+    pub readonly: bool,
+    /// Also make the read-only file immutable, so not even its owner can change it
+    pub immutable: bool,
+    /// Phase in which the file is written
+    pub phase: Phase,
+    /// Contents which are not text, such as a wallpaper, in which case `contents` are empty
+    pub asset: Option<LinkAsset>,
+}
+
+/// Contents of a [`Link`] which are not text
+///
+/// They are copied from a file instead of being held in memory, like the assets of `[[dir]]`s
+#[derive(Debug, Clone)]
+pub struct LinkAsset {
+    /// Hash and size of the contents
+    pub asset: Asset,
+    /// File which contains them, in `vendor/` or in the cache directory
+    pub source: PathBuf,
+    /// SHA-256 of the contents, which can be pinned with `sha256`
+    pub sha256: String,
+}
+
+impl LinkAsset {
+    /// Directory where links which are not text are saved to after being fetched, inside of
+    /// [`RenderCache::dir`]
+    const DIR: &str = "links";
+
+    /// Contents `bytes` of a link, which are in the file at `source` if it was vendored, or
+    /// else saved into `dir`, the [`LinkAsset::DIR`] inside of the cache directory
+    fn new(bytes: Vec<u8>, source: Option<PathBuf>, dir: &Path) -> Result<Self> {
+        let hash = blake3::hash(&bytes).to_hex().to_string();
+
+        let source = match source {
+            Some(source) => source,
+            None => {
+                let source = dir.join(&hash);
+                // the name is the hash, so an existing file has the same contents
+                if !source.exists() {
+                    fs::create_dir_all(dir)
+                        .with_context(|| eyre!("failed to create {}", dir.show()))?;
+                    fs::write(&source, &bytes)
+                        .with_context(|| eyre!("failed to write {}", source.show()))?;
+                }
+                source
+            }
+        };
+
+        Ok(Self {
+            asset: Asset {
+                hash,
+                len: bytes.len() as u64,
+            },
+            source,
+            sha256: sha256::digest(bytes.as_slice()),
+        })
+    }
 }
 
 /// A single file to be mapped from the input (`old_location`) to the output (`new_location`)
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct File {
     /// Old location of the file
     pub old_location: PathBuf,
-    /// Contents of the file
-    pub contents: String,
+    /// Contents of the file, which are copied as-is if they are not text
+    pub contents: Input,
     /// Output path
     pub output: OutputPath,
     /// Input directory
     pub input: PathBuf,
+    /// Index of the `[[dir]]` this file comes from
+    pub dir: usize,
+    /// Flush the file and its directory to disk after writing it
+    pub durable: bool,
+    /// Why the entry exists
+    pub description: Option<String>,
+    /// Make the file read-only after writing it
+    pub readonly: bool,
+    /// Also make the read-only file immutable, so not even its owner can change it
+    pub immutable: bool,
+    /// Template engine that renders the file, unless its marker overrides it
+    pub engine: Engine,
+    /// Link the output to the file instead of copying it, unless it is rendered differently
+    pub symlink: bool,
+    /// Line endings of the file, unless they are unchanged
+    pub line_endings: Option<LineEndings>,
+    /// Delimiters used instead of `{{` and `}}`, unless its marker overrides them
+    pub delimiters: Option<Delimiters>,
+    /// Phase in which the file is written
+    pub phase: Phase,
 }
 
 impl World {
-    /// This function is the "core" of `dots`, it is pure and does no IO (except for logging)
+    /// Only write the snippets of `/etc` if they are `enabled` with `--system`
+    ///
+    /// Fails if they are enabled, but `dots` does not run as root
+    pub fn gate_system(&mut self, enabled: bool) -> Result<()> {
+        self.system = system::gate(mem::take(&mut self.system), enabled, system::is_root())?;
+        Ok(())
+    }
+
+    /// Also write the completions of every shell in `completions`
+    pub fn install_completions(&mut self, completions: &Completions) -> Result<()> {
+        self.completions = completions.writes(self.durable)?;
+        Ok(())
+    }
+
+    /// This function is the "core" of `dots`. It does no IO, except for logging and for
+    /// reading the current contents of the files whose templates use `existing`
     ///
     /// We want to keep it like this as it makes it easier to reason about and test.
+    ///
+    /// Every file is rendered, unless it is in the [`RenderCache`]. Files which are unchanged
+    /// since the last apply are only skipped by [`Analysis::finish`], using the [`ApplyIndex`]
+    ///
+    /// [`ApplyIndex`]: crate::index::ApplyIndex
     pub fn process(self) -> Result<Analysis, Vec<Error>> {
-        let mut errors = vec![];
+        let mut errors = self.errors;
+        let mut failed_dirs = self.failed_dirs;
+        let strict = self.strict;
+        let cache = self.cache;
+        let mut rendered = RenderCache::default();
+        let windows_home = self.windows_home.as_deref();
+        let mut phases = BTreeMap::new();
 
         let links = self
             .links
@@ -78,25 +310,64 @@ impl World {
             .map(
                 |Link {
                      contents,
+                     asset,
                      path,
                      sha256,
                      marker,
                      url,
+                     durable,
+                     description,
+                     readonly,
+                     immutable,
+                     phase,
                  }| {
-                    let actual_sha256 = sha256::digest(&contents);
+                    let actual_sha256 = match &asset {
+                        Some(asset) => asset.sha256.clone(),
+                        None => sha256::digest(&contents),
+                    };
 
-                    if let Some(expected_sha256) = sha256
+                    if let Some(expected_sha256) = &sha256
                         && actual_sha256 != *expected_sha256
                     {
                         let mismatch = format!("link       {BLUE}{url}{RESET}");
+                        let about = description
+                            .as_ref()
+                            .map(|description| format!("\n  about      {description}"))
+                            .unwrap_or_default();
                         let actual = format!("actual     {CYAN}{actual_sha256}{RESET}");
                         let expected = format!("expected   {CYAN}{expected_sha256}{RESET}");
-                        bail!("hash mismatch\n  {mismatch}\n  {actual}\n  {expected}");
+                        bail!("hash mismatch\n  {mismatch}{about}\n  {actual}\n  {expected}");
+                    } else if sha256.is_none() {
+                        let message = format!(
+                            "link {BLUE}{url}{RESET} is not pinned, \
+                             add `sha256 = \"{actual_sha256}\"` to pin it"
+                        );
+                        if strict {
+                            bail!(message);
+                        }
+                        log::debug!(target: "network", "{message}");
                     }
 
                     // download the link's contents to *this* path
                     let path = self.root.join(path);
 
+                    // links which are not text are copied as-is, without a marker or a notice
+                    if let Some(LinkAsset { asset, source, .. }) = asset {
+                        return Ok((
+                            crate::analysis::WritePath {
+                                path,
+                                contents: Contents::Asset(asset),
+                                source: Some(source),
+                                durable,
+                                description,
+                                symlink: false,
+                                mode: None,
+                            },
+                            readonly.then_some(immutable),
+                            phase,
+                        ));
+                    }
+
                     // add the marker if necessary
                     let marker = marker.as_ref().map_or(String::new(), |marker_args| {
                         format!("{}{marker_args}", Marker::MARKER)
@@ -133,170 +404,1113 @@ impl World {
                     });
 
                     let contents = format!("{marker}{generated_notice}{contents}");
+                    let contents = match LineEndings::of(&path, windows_home) {
+                        Some(line_endings) => line_endings.apply(&contents),
+                        None => contents,
+                    };
 
-                    Ok(crate::analysis::WritePath { path, contents })
+                    Ok((
+                        crate::analysis::WritePath {
+                            path,
+                            contents: Contents::Text(contents),
+                            source: None,
+                            durable,
+                            description,
+                            symlink: false,
+                            mode: None,
+                        },
+                        readonly.then_some(immutable),
+                        phase,
+                    ))
                 },
             )
             .partition_result::<Vec<_>, Vec<_>, _, _>()
-            .pipe(|(oks, errs)| {
+            .pipe(|(oks, errs): (Vec<_>, Vec<_>)| {
                 errors.extend(errs);
-                oks
+                oks.into_iter()
+                    .map(|(write, lock, phase)| {
+                        if phase != Phase::Main {
+                            phases.insert(write.path.clone(), phase);
+                        }
+                        (write, lock)
+                    })
+                    .collect_vec()
             });
 
-        let files = self
+        // files which use other files with `output` are rendered after them
+        let names = self
             .files
-            .into_iter()
-            .map(
-                |File {
-                     old_location,
-                     contents,
-                     output,
-                     input,
-                 }| {
-                    let relative_location = old_location
-                        .strip_prefix(&self.root)?
-                        .strip_prefix(&input)?;
-
-                    let (file_contents, new_location) = if let Some(first_line) =
-                        contents.lines().next()
-                        && let Some(marker_start_pos) = first_line.find(Marker::MARKER)
-                        && let Some(marker_args) =
-                            first_line.get(marker_start_pos + Marker::MARKER.len()..)
-                        && let Ok(args) = marker_args.parse::<Marker>()
-                        && let Some(path) = args.path
-                    {
-                        (
-                            // remove the first line which contains the `@dotfilers`
-                            contents.lines().skip(1).collect_vec().join(","),
-                            path,
-                        )
-                    } else {
-                        (
-                            contents,
-                            output
-                                .as_ref()
-                                .join(relative_location)
-                                .pipe(OutputPath::new),
-                        )
-                    };
+            .iter()
+            .map(|file| {
+                outputs::name(
+                    file.old_location
+                        .strip_prefix(&self.root)
+                        .unwrap_or(&file.old_location),
+                )
+            })
+            .collect_vec();
+        let references = self
+            .files
+            .iter()
+            .map(|file| match &file.contents {
+                Input::Text(text) => outputs::references(text),
+                Input::Asset(_) => BTreeSet::new(),
+            })
+            .collect_vec();
+        let referenced = references.iter().flatten().collect::<BTreeSet<_>>();
+        let order = outputs::order(&names, &references).unwrap_or_else(|err| {
+            errors.push(err);
+            (0..names.len()).collect()
+        });
 
-                    let mut handlebars = Handlebars::new();
-                    handlebars
-                        .register_template_string("t1", file_contents)
-                        .with_context(|| eyre!("failed to parse template for {new_location}"))?;
+        // secrets are only exposed to the templates
+        let vars = if self.secrets.is_empty() {
+            Cow::Borrowed(&self.vars)
+        } else {
+            let mut vars = self.vars.clone();
+            vars.extend(
+                self.secrets
+                    .iter()
+                    .map(|(name, secret)| (name.clone(), secret.expose().clone().into())),
+            );
+            Cow::Owned(vars)
+        };
 
-                    let contents = handlebars
-                        .render("t1", &BTreeMap::<u8, u8>::new())
-                        .with_context(|| eyre!("failed to render template for {new_location}"))?;
+        let render = |File {
+                          old_location,
+                          contents,
+                          output,
+                          input,
+                          dir,
+                          durable,
+                          description,
+                          readonly,
+                          immutable,
+                          engine,
+                          symlink,
+                          line_endings,
+                          delimiters,
+                          phase,
+                      }: File,
+                      others: &BTreeMap<String, Output>| {
+            let relative_location = old_location
+                .strip_prefix(&self.root)
+                .map_err(|err| (dir, err))?
+                .strip_prefix(&input)
+                .map_err(|err| (dir, err.into()))?;
 
-                    Ok::<_, Error>(crate::analysis::WritePath {
-                        path: new_location.into_inner(),
-                        contents,
-                    })
+            // `foo.conf.tmpl` is written to `foo.conf`
+            let relative_location =
+                if matches!(contents, Input::Text(_)) && is_template(relative_location) {
+                    relative_location.with_extension("")
+                } else {
+                    relative_location.to_path_buf()
+                };
+
+            // assets can not have markers, and are not templates
+            let contents = match contents {
+                Input::Text(text) => text,
+                Input::Asset(asset) => {
+                    return Ok((
+                        dir,
+                        None,
+                        crate::analysis::WritePath {
+                            path: output.as_ref().join(relative_location),
+                            contents: Contents::Asset(asset),
+                            source: Some(old_location),
+                            durable,
+                            description,
+                            symlink,
+                            mode: None,
+                        },
+                        readonly.then_some(immutable),
+                        phase,
+                    ));
+                }
+            };
+
+            // shown in errors, so it is clear what the file is for
+            let about = description
+                .as_ref()
+                .map(|description| format!(" ({description})"))
+                .unwrap_or_default();
+
+            let marker = Marker::find(&contents)
+                .transpose()
+                .with_context(|| eyre!("invalid marker in {}{about}", old_location.show()));
+
+            let marker = match marker {
+                Ok(marker) => marker,
+                Err(err) if strict => return Err((dir, err)),
+                Err(err) => {
+                    log::warn!("{err:?}");
+                    None
+                }
+            };
+
+            // the marker is removed, so the file in the repo has different contents
+            let has_marker = marker.is_some();
+            let (file_contents, new_location, engine, delimiters) = if let Some(marker) = marker {
+                let delimiters = marker
+                    .delimiters()
+                    .with_context(|| eyre!("invalid marker in {}{about}", old_location.show()))
+                    .map_err(|err| (dir, err))?
+                    .or(delimiters);
+                (
+                    // remove the first line which contains the `@dots`
+                    contents
+                        .split_once('\n')
+                        .map_or("", |(_, rest)| rest)
+                        .to_string(),
+                    marker.path.unwrap_or_else(|| {
+                        output
+                            .as_ref()
+                            .join(&relative_location)
+                            .pipe(OutputPath::new)
+                    }),
+                    marker.engine.unwrap_or(engine),
+                    delimiters,
+                )
+            } else {
+                (
+                    contents,
+                    output
+                        .as_ref()
+                        .join(&relative_location)
+                        .pipe(OutputPath::new),
+                    engine,
+                    delimiters,
+                )
+            };
+
+            // a file changes when a file it uses changes
+            let used = outputs::references(&file_contents)
+                .into_iter()
+                .filter_map(|name| others.get(&name))
+                .collect_vec();
+            // templates which only render without `strict` must not be reused with it
+            let key = if used.is_empty() {
+                RenderCache::key(&file_contents, &(engine, &delimiters, &self.vars, strict))
+            } else {
+                RenderCache::key(
+                    &file_contents,
+                    &(engine, &delimiters, &self.vars, strict, &used),
+                )
+            };
+
+            let template_is_empty = file_contents.trim().is_empty();
+            // files on disk are not part of the key, so templates which read them are never cached
+            let cached = cache
+                .rendered
+                .get(&key)
+                .filter(|_| !existing::is_used(&file_contents));
+            let contents = if let Some(contents) = cached {
+                contents.clone()
+            } else {
+                engine
+                    .render(&file_contents, delimiters.as_ref(), &vars, others, strict)
+                    .with_context(|| eyre!("invalid template for {new_location}{about}"))
+                    .map_err(|err| (dir, err))?
+            };
+
+            let contents = match line_endings
+                .or_else(|| LineEndings::of(new_location.as_ref(), windows_home))
+            {
+                Some(line_endings) => line_endings.apply(&contents),
+                None => contents,
+            };
+
+            if contents.is_empty() && !template_is_empty {
+                log::warn!(
+                    "template for {new_location} rendered to nothing, check the variables it uses"
+                );
+            }
+
+            let symlink = symlink && !has_marker && contents == file_contents;
+            // files which use a secret, directly or through a file they use, contain it
+            let sensitive = used.iter().any(|output| output.sensitive)
+                || self.secrets.iter().any(|(name, secret)| {
+                    mentions(&file_contents, name) || secret.is_in(&contents)
+                });
+
+            Ok::<_, (usize, Error)>((
+                dir,
+                Some(key),
+                crate::analysis::WritePath {
+                    path: new_location.into_inner(),
+                    contents: if sensitive {
+                        Contents::Secret(Sensitive::containing(contents))
+                    } else {
+                        Contents::Text(contents)
+                    },
+                    source: Some(old_location),
+                    durable,
+                    description,
+                    symlink,
+                    mode: None,
                 },
-            )
+                readonly.then_some(immutable),
+                phase,
+            ))
+        };
+
+        let mut files = self.files.into_iter().map(Some).collect_vec();
+        let mut rendered_outputs = BTreeMap::new();
+        let files = order
+            .into_iter()
+            .filter_map(|index| {
+                let result = render(files[index].take()?, &rendered_outputs);
+                if let Ok((_, _, write, _, _)) = &result
+                    && referenced.contains(&names[index])
+                {
+                    rendered_outputs
+                        .entry(names[index].clone())
+                        .or_insert_with(|| Output {
+                            path: write.path.clone(),
+                            contents: write.contents.expose().to_string(),
+                            sensitive: write.contents.is_sensitive(),
+                        });
+                }
+                Some((index, result))
+            })
+            .sorted_by_key(|(index, _)| *index)
+            .map(|(_, result)| result)
             .partition_result::<Vec<_>, Vec<_>, _, _>()
-            .pipe(|(oks, errs)| {
-                errors.extend(errs);
-                oks
+            .pipe(|(oks, errs): (Vec<_>, Vec<_>)| {
+                for (dir, err) in errs {
+                    failed_dirs.insert(dir);
+                    errors.push(err);
+                }
+                oks.into_iter()
+                    .map(|(dir, key, write, lock, phase)| {
+                        // only templates used by this run are kept, so the cache does not grow forever.
+                        // secrets are never written to the cache
+                        if let Some(key) = key
+                            && !write.contents.is_sensitive()
+                        {
+                            rendered
+                                .rendered
+                                .insert(key, write.contents.text().to_string());
+                        }
+                        if phase != Phase::Main {
+                            phases.insert(write.path.clone(), phase);
+                        }
+                        (dir, write, lock)
+                    })
+                    .collect_vec()
             });
 
-        if !errors.is_empty() {
-            return Err(errors);
-        }
+        let files = match self.on_error {
+            OnError::Abort if !errors.is_empty() => return Err(errors),
+            OnError::Abort | OnError::ApplyValid => files,
+            OnError::SkipEntry => files
+                .into_iter()
+                .filter(|(dir, _, _)| !failed_dirs.contains(dir))
+                .collect(),
+        };
+
+        let written = files
+            .iter()
+            .map(|(_, write, _)| write.path.clone())
+            .collect::<BTreeSet<_>>();
+
+        // files of a `[[dir]]` which failed are missing, their old versions must stay
+        let extra = self
+            .exact
+            .into_iter()
+            .filter(|(dir, _)| !failed_dirs.contains(dir))
+            .flat_map(|(_, (output, existing))| {
+                existing.into_iter().map(move |path| (path, output.clone()))
+            })
+            .filter(|(path, _)| {
+                !written.contains(path) && !path.to_string_lossy().ends_with(".orig.dots")
+            })
+            .collect_vec();
+
+        // read-only files are made writable before writing them, and locked again at the end.
+        // locking a symlink would lock the file in the repo that it points to
+        let locks = links
+            .iter()
+            .filter_map(|(write, lock)| Some((write.path.clone(), (*lock)?)))
+            .chain(
+                files
+                    .iter()
+                    .filter(|(_, write, _)| !write.symlink)
+                    .filter_map(|(_, write, lock)| Some((write.path.clone(), (*lock)?))),
+            )
+            .collect_vec();
+        let unlocks = locks
+            .iter()
+            .map(|(path, _)| Operation::Unlock { path: path.clone() })
+            .collect_vec();
+
+        // other programs write to these files too
+        let partial = self
+            .mimeapps
+            .iter()
+            .chain(&self.gitconfig)
+            .map(|write| write.path.clone())
+            .collect();
+
+        let mut operations = unlocks
+            .into_iter()
+            .chain(
+                links
+                    .into_iter()
+                    .map(|(write, _)| write)
+                    .chain(files.into_iter().map(|(_, write, _)| write))
+                    .chain(self.shell)
+                    .chain(self.completions)
+                    .chain(self.fonts)
+                    .chain(self.autostart)
+                    .chain(self.mimeapps)
+                    .chain(self.gitconfig)
+                    .map(Operation::Write),
+            )
+            .chain(
+                self.dconf
+                    .into_iter()
+                    .filter(|dconf| !dconf.is_applied())
+                    .map(|dconf| Operation::LoadDconf {
+                        path: dconf.path,
+                        settings: dconf.settings,
+                    }),
+            )
+            .chain(
+                self.defaults
+                    .into_iter()
+                    .filter(|defaults| !defaults.is_applied())
+                    .map(|defaults| Operation::WriteDefaults {
+                        domain: defaults.domain,
+                        key: defaults.key,
+                        value_type: defaults.kind,
+                        value: defaults.value,
+                    }),
+            )
+            .chain(
+                self.flatpak
+                    .into_iter()
+                    .filter(|flatpak| !flatpak.is_applied())
+                    .map(|flatpak| Operation::OverrideFlatpak {
+                        app: flatpak.app,
+                        filesystems: flatpak.permissions.filesystems.into_iter().collect(),
+                        env: flatpak.permissions.env,
+                    }),
+            )
+            .chain(self.ssh.into_iter().flat_map(SshConfig::operations))
+            .chain(self.timers.into_iter().flat_map(TimerUnits::operations))
+            .chain(system::operations(self.system))
+            .chain(
+                extra
+                    .into_iter()
+                    .map(|(path, dir)| Operation::Evict { path, dir }),
+            )
+            .chain(
+                locks
+                    .into_iter()
+                    .map(|(path, immutable)| Operation::Lock { path, immutable }),
+            )
+            .collect_vec();
+        phase::order(&mut operations, &phases);
 
         Ok(Analysis {
-            writes: links.into_iter().chain(files).collect(),
+            operations,
+            errors,
+            backups: BTreeSet::new(),
+            watchdog: self.watchdog,
+            preserve_xattrs: self.preserve_xattrs,
+            atomic_dirs: self.atomic_dirs,
+            partial,
+            protect: self.protect,
+            allow_truncate: false,
+            cache: rendered,
+            phases,
+            newly_locked: self.newly_locked,
+            termux: self.termux,
+            cancel: CancelToken::ctrl_c(),
         })
     }
 
+    /// URL and actual sha256 of every link, along with whether it matches the pinned `sha256`
+    ///
+    /// Links without a `sha256` are never mismatched
+    pub fn link_hashes(&self) -> Vec<(&str, String, bool)> {
+        self.links
+            .iter()
+            .map(|link| {
+                let actual = match &link.asset {
+                    Some(asset) => asset.sha256.clone(),
+                    None => sha256::digest(&link.contents),
+                };
+                let mismatched = link.sha256.as_ref().is_some_and(|pinned| *pinned != actual);
+                (link.url.as_str(), actual, mismatched)
+            })
+            .collect()
+    }
+
+    /// Hash of every source file and link, which changes whenever the repo changes
+    ///
+    /// Does not depend on the machine, so it can be compared across machines
+    pub fn hash(&self) -> String {
+        let files = self
+            .files
+            .iter()
+            .map(|file| {
+                let location = file
+                    .old_location
+                    .strip_prefix(&self.root)
+                    .unwrap_or(&file.old_location);
+                let contents = match &file.contents {
+                    Input::Text(text) => text,
+                    Input::Asset(asset) => &asset.hash,
+                };
+                format!("{}\0{contents}", location.to_string_lossy())
+            })
+            .sorted();
+
+        let links = self
+            .links
+            .iter()
+            .map(|link| {
+                format!(
+                    "{}\0{:?}\0{:?}\0{:?}",
+                    link.url, link.path, link.sha256, link.marker
+                )
+            })
+            .sorted();
+
+        let fonts = self
+            .fonts
+            .iter()
+            .filter_map(|font| {
+                Some(format!(
+                    "{}\0{}",
+                    font.path.show(),
+                    font.contents.asset()?.hash
+                ))
+            })
+            .sorted();
+
+        let shell = self
+            .shell
+            .iter()
+            .map(|shell| format!("{}\0{}", shell.path.show(), shell.contents.text()))
+            .sorted();
+
+        let ssh = self
+            .ssh
+            .iter()
+            .map(|ssh| format!("{}\0{}", ssh.config.path.show(), ssh.config.contents.text()));
+
+        files
+            .chain(links)
+            .chain(shell)
+            .chain(ssh)
+            .chain(fonts)
+            .join("\0")
+            .pipe(sha256::digest)
+    }
+
     /// Create the `World`
     pub fn new(cwd: &Path) -> Result<Self, Vec<Error>> {
-        // Directory which contains the config file
-        let root = cwd
-            .pipe_ref(stdx::traverse_upwards)
-            .find(|dir| dir.join(Config::FILE_NAME).exists())
-            .with_context(|| {
+        Self::build(cwd, None)
+    }
+
+    /// Create the `World`, checking links against the locked links of `lockfile` instead of
+    /// the ones in `dots.lock` of the repo
+    pub(crate) fn with_lockfile(cwd: &Path, lockfile: Lockfile) -> Result<Self, Vec<Error>> {
+        Self::build(cwd, Some(lockfile))
+    }
+
+    /// Create the `World`, with the locked links of `lockfile` if there is one
+    fn build(cwd: &Path, lockfile: Option<Lockfile>) -> Result<Self, Vec<Error>> {
+        let environment = Environment::current().map_err(single_err)?;
+        let mut config = Config::discover(cwd).map_err(single_err)?;
+        let profiles = profile::active(
+            &config.profiles,
+            environment.profiles.as_deref(),
+            &config.default_profiles,
+            &environment,
+        )
+        .map_err(single_err)?;
+        if let Some(undefined) = config
+            .dirs
+            .iter()
+            .flat_map(|dir| &dir.profiles)
+            .chain(config.links.iter().flat_map(|link| &link.profiles))
+            .find(|name| !config.profiles.contains_key(*name))
+        {
+            return Err(single_err(eyre!(
+                "profile `{undefined}` is not defined in `[profiles]`"
+            )));
+        }
+        // entries for other machines are skipped
+        config.dirs.retain(|dir| {
+            dir.when.matches(&environment) && profile::includes(&profiles, &dir.profiles)
+        });
+        config.links.retain(|link| {
+            link.when.matches(&environment) && profile::includes(&profiles, &link.profiles)
+        });
+        let limiter = Limiter::new(config.fetch.clone());
+        let watchdog = config.watchdog();
+        let durable = config.durable;
+        let default_engine = config.engine;
+        let default_mode = config.mode;
+        let mut vars = config.vars;
+        let mut var_sources = vars
+            .iter()
+            .map(|(name, value)| {
+                let source = if computed::is_computed(value) {
+                    VarSource::Computed
+                } else if local_vars::prompt(value).is_some() {
+                    VarSource::Prompt
+                } else if local_vars::secret(value).is_some() {
+                    VarSource::Secret
+                } else {
+                    VarSource::Config
+                };
+                (name.clone(), source)
+            })
+            .collect::<BTreeMap<_, _>>();
+        let local = match &environment.local_vars {
+            Some(dir) => LocalVars::load(dir).map_err(single_err)?,
+            None => LocalVars::default(),
+        };
+        // prompts are only asked by `dots apply` and `dots vars`, see `LocalVars::answer_prompts`
+        let unanswered = local
+            .unanswered(&vars)
+            .map(|(name, question)| {
                 eyre!(
-                    "failed to find directory that contains a `{}`. traversed upwards from {}",
-                    Config::FILE_NAME,
-                    cwd.show()
+                    "`{name}` was not answered on this machine: {question}. \
+                     run `dots apply` in a terminal or `dots vars set {name} <value>`"
                 )
             })
-            .map_err(single_err)?;
-
-        let config = root
-            .join(Config::FILE_NAME)
-            .pipe(std::fs::read_to_string)
-            .with_context(|| eyre!("failed to read config file {}", Config::FILE_NAME))
-            .map_err(single_err)?
-            .pipe_deref(toml::de::from_str::<Config>)
-            .context("failed to parse config file")
-            .map_err(single_err)?
-            .pipe(|mut conf| {
-                conf.root = root;
-                conf
-            });
+            .collect_vec();
+        if !unanswered.is_empty() {
+            return Err(unanswered);
+        }
+        for (name, value) in local.vars {
+            if var_sources.get(&name) != Some(&VarSource::Prompt) {
+                var_sources.insert(name.clone(), VarSource::Local);
+            }
+            vars.insert(name, value);
+        }
+        let secret_files = vars
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((name.clone(), config.root.join(local_vars::secret(value)?)))
+            })
+            .collect_vec();
+        let mut secrets = BTreeMap::new();
+        for (name, file) in secret_files {
+            let identity = environment
+                .var(age::IDENTITY_VAR)
+                .with_context(|| eyre!("`{name}` is a secret, pass `--identity` to decrypt it"));
+            let secret = identity
+                .and_then(|identity| age::decrypt(&file, Path::new(&identity)))
+                .with_context(|| eyre!("failed to decrypt `{name}`"))
+                .map_err(single_err)?;
+            // redacted everywhere from now on
+            let secret = Sensitive::new(secret.trim_end().to_string());
+            // computed variables can use it, until they are resolved
+            vars.insert(name.clone(), secret.expose().clone().into());
+            secrets.insert(name, secret);
+        }
+        if let Some(theme) = &config.theme {
+            if vars.contains_key(theme::VAR) {
+                return Err(single_err(eyre!(
+                    "`theme` in `[vars]` conflicts with the palette of `[theme]`"
+                )));
+            }
+            let scheme = theme.load(&config.root, &limiter).map_err(single_err)?;
+            vars.insert(theme::VAR.to_string(), scheme.vars().into());
+            var_sources.insert(theme::VAR.to_string(), VarSource::Theme);
+        }
+        if vars.contains_key(facts::VAR) {
+            return Err(single_err(eyre!(
+                "`facts` in `[vars]` conflicts with the facts about the machine"
+            )));
+        }
+        vars.insert(facts::VAR.to_string(), facts::facts(&environment).into());
+        var_sources.insert(facts::VAR.to_string(), VarSource::Fact);
+        let mut vars = computed::resolve(vars).map_err(single_err)?;
+        for name in secrets.keys() {
+            vars.remove(name);
+        }
+        let protect = Protect::new(
+            config
+                .protect
+                .iter()
+                .map(|pattern| pattern.as_ref().as_path()),
+        )
+        .map_err(single_err)?;
+        let atomic_dirs = config
+            .dirs
+            .iter()
+            .filter(|dir| dir.atomic)
+            .map(|dir| dir.output.as_ref().to_path_buf())
+            .collect();
+        let exact = config
+            .dirs
+            .iter()
+            .enumerate()
+            .filter(|(_, dir)| dir.exact)
+            .map(|(i, dir)| {
+                let existing = walkdir::WalkDir::new(dir.output.as_ref())
+                    .into_iter()
+                    .flatten()
+                    .filter(|entry| !entry.file_type().is_dir())
+                    .map(walkdir::DirEntry::into_path)
+                    .collect();
+                (i, (dir.output.as_ref().to_path_buf(), existing))
+            })
+            .collect();
 
         let mut errors = vec![];
+        let mut failed_dirs = BTreeSet::new();
 
-        let links = config
-            .links
-            .into_iter()
-            .map(
-                |crate::config::Link {
-                     url,
-                     path,
-                     sha256,
-                     marker,
-                 }| {
-                    Ok::<_, Error>(Link {
-                        contents: ureq::get(&url).call()?.body_mut().read_to_string()?,
-                        path,
-                        sha256,
-                        marker,
-                        url,
+        let fonts = if config.fonts.is_empty() {
+            vec![]
+        } else {
+            let font_dir = font::font_dir().map_err(single_err)?;
+            let cache_dir = RenderCache::dir().map_err(single_err)?.join("fonts");
+
+            config
+                .fonts
+                .iter()
+                .map(|font| font.files(&config.root, &cache_dir, &limiter))
+                .flatten_ok()
+                .map(|file| {
+                    let file = file?;
+                    let name = file
+                        .file_name()
+                        .with_context(|| eyre!("font {} has no file name", file.show()))?;
+                    let asset = Asset {
+                        hash: Asset::hash_file(&file)
+                            .with_context(|| eyre!("failed to read font {}", file.show()))?,
+                        len: fs::metadata(&file)
+                            .with_context(|| eyre!("failed to read font {}", file.show()))?
+                            .len(),
+                    };
+
+                    Ok::<_, Error>(WritePath {
+                        path: font_dir.join(name),
+                        contents: Contents::Asset(asset),
+                        source: Some(file),
+                        durable,
+                        description: None,
+                        symlink: false,
+                        mode: None,
                     })
-                },
+                })
+                .partition_result::<Vec<_>, Vec<_>, _, _>()
+                .pipe(|(oks, errs)| {
+                    errors.extend(errs);
+                    oks
+                })
+        };
+
+        let shell = match &config.shell {
+            Some(shell) => shell
+                .compile(
+                    &config.root,
+                    config.dirs.iter().map(|dir| dir.input.as_path()),
+                    durable,
+                )
+                .unwrap_or_else(|err| {
+                    errors.push(err);
+                    vec![]
+                }),
+            None => vec![],
+        };
+        let shell = if config.env.is_empty() {
+            shell
+        } else {
+            match shell::output_dir(config.shell.as_ref()).and_then(|output| {
+                env::compile(
+                    &config.env,
+                    &output,
+                    env::environment_d()?.as_deref(),
+                    durable,
+                )
+            }) {
+                Ok(env) => shell.into_iter().chain(env).collect(),
+                Err(err) => {
+                    errors.push(err);
+                    shell
+                }
+            }
+        };
+        let fragments = config.shell.as_ref().map(|shell| shell.fragments.clone());
+        let unused_inputs = config.shell.as_ref().map_or_else(Vec::new, |shell| {
+            shell.unused(
+                &config.root,
+                config.dirs.iter().map(|dir| dir.input.as_path()),
             )
+        });
+
+        let dconf = config
+            .dconf
+            .iter()
+            .map(|dconf| dconf.read(&config.root))
+            .partition_result::<Vec<_>, Vec<_>, _, _>()
+            .pipe(|(oks, errs)| {
+                errors.extend(errs);
+                oks
+            });
+
+        let defaults = if cfg!(target_os = "macos") {
+            config
+                .defaults
+                .iter()
+                .map(Defaults::read)
+                .partition_result::<Vec<_>, Vec<_>, _, _>()
+                .pipe(|(oks, errs)| {
+                    errors.extend(errs);
+                    oks
+                })
+        } else {
+            if !config.defaults.is_empty() {
+                log::debug!("`[[defaults]]` are only applied on macOS");
+            }
+            vec![]
+        };
+
+        let system = if cfg!(target_os = "linux") {
+            config
+                .udev_rules
+                .iter()
+                .map(|rule| rule.snippet(&config.root))
+                .chain((!config.sysctl.is_empty()).then(|| system::sysctl(&config.sysctl)))
+                .partition_result::<Vec<_>, Vec<_>, _, _>()
+                .pipe(|(oks, errs)| {
+                    errors.extend(errs);
+                    oks
+                })
+        } else {
+            if !config.udev_rules.is_empty() || !config.sysctl.is_empty() {
+                log::debug!("`[[udev_rule]]` and `[sysctl]` are only applied on Linux");
+            }
+            vec![]
+        };
+
+        let autostart = config
+            .autostart
+            .iter()
+            .map(|autostart| autostart.entry(&environment.os))
             .partition_result::<Vec<_>, Vec<_>, _, _>()
             .pipe(|(oks, errs)| {
                 errors.extend(errs);
                 oks
             });
 
+        let flatpak = if cfg!(target_os = "linux") {
+            config
+                .flatpak_overrides
+                .iter()
+                .map(FlatpakOverride::read)
+                .partition_result::<Vec<_>, Vec<_>, _, _>()
+                .pipe(|(oks, errs)| {
+                    errors.extend(errs);
+                    oks
+                })
+        } else {
+            if !config.flatpak_overrides.is_empty() {
+                log::debug!("`[[flatpak_override]]` are only applied on Linux");
+            }
+            vec![]
+        };
+
+        let mimeapps = if cfg!(any(target_os = "macos", windows)) {
+            if !config.mimeapps.is_empty() {
+                log::debug!("`[[mimeapps]]` are only applied on platforms with `mimeapps.list`");
+            }
+            None
+        } else if config.mimeapps.is_empty() {
+            None
+        } else {
+            mimeapps::path()
+                .and_then(|path| mimeapps::merge(&config.mimeapps, &path, durable))
+                .map_err(|err| errors.push(err))
+                .ok()
+        };
+
+        let gitconfig = config
+            .git
+            .as_ref()
+            .and_then(|git| git.gitconfig(durable).map_err(|err| errors.push(err)).ok());
+
+        let ssh = config.ssh.as_ref().and_then(|ssh| {
+            ssh.assemble(&config.root, durable)
+                .map_err(|err| errors.push(err))
+                .ok()
+        });
+
+        let timers = if cfg!(any(target_os = "macos", windows)) {
+            if !config.timers.is_empty() {
+                log::debug!("`[[timer]]`s are only installed on platforms with systemd");
+            }
+            vec![]
+        } else if config.timers.is_empty() {
+            vec![]
+        } else {
+            let unit_dir = timer::unit_dir().map_err(single_err)?;
+            config
+                .timers
+                .iter()
+                .map(|timer| timer.units(&unit_dir))
+                .partition_result::<Vec<_>, Vec<_>, _, _>()
+                .pipe(|(oks, errs)| {
+                    errors.extend(errs);
+                    oks
+                })
+        };
+
+        let vendor = Vendor::load(&config.root)
+            .map_err(|err| errors.push(err))
+            .unwrap_or_default();
+
+        let lockfile = match lockfile {
+            Some(lockfile) => lockfile,
+            None => Lockfile::load(&config.root)
+                .map_err(|err| errors.push(err))
+                .unwrap_or_default(),
+        };
+        // resolved on this thread, the threads which fetch the links do not see `Environment::scope`
+        let cache_dir = RenderCache::dir().map_err(single_err)?;
+        let link_cache = LinkCache::new(&cache_dir);
+        let asset_dir = cache_dir.join(LinkAsset::DIR);
+
+        let root = &config.root;
+        let fetch_link = |crate::config::Link {
+                              url,
+                              description,
+                              path,
+                              sha256,
+                              marker,
+                              durable: link_durable,
+                              readonly,
+                              immutable,
+                              phase,
+                              when: _,
+                              profiles: _,
+                          }| {
+            let url = crate::url::resolve(&url, &vars)?;
+            // vendored links are pinned by `vendor/links.toml` instead
+            let (bytes, source, locked) = match vendor.path(root, &url) {
+                Some(path) => {
+                    let bytes = fs::read(&path)
+                        .with_context(|| eyre!("failed to read vendored link {}", path.show()))?;
+                    (bytes, Some(path), None)
+                }
+                None => {
+                    let fetched = link_cache.get(&limiter, &url, sha256.as_deref())?;
+                    let locked = lockfile.verify(&url, &fetched)?;
+                    (fetched.bytes, None, locked)
+                }
+            };
+            let (contents, asset) = match String::from_utf8(bytes) {
+                Ok(contents) => (contents, None),
+                Err(err) => {
+                    let asset = LinkAsset::new(err.into_bytes(), source, &asset_dir)?;
+                    (String::new(), Some(asset))
+                }
+            };
+
+            let link = Link {
+                contents,
+                asset,
+                path,
+                sha256,
+                marker,
+                url,
+                durable: link_durable.unwrap_or(durable),
+                description,
+                readonly,
+                immutable,
+                phase,
+            };
+
+            Ok::<_, Error>((link, locked))
+        };
+
+        // links are fetched by `[fetch] concurrency` workers, which take them in turns
+        let workers = limiter.concurrency().min(config.links.len());
+        let queue = Mutex::new(config.links.into_iter().enumerate());
+        let links = thread::scope(|scope| {
+            let fetch_link = &fetch_link;
+            let queue = &queue;
+            (0..workers)
+                .map(|_| {
+                    scope.spawn(move || {
+                        iter::from_fn(|| {
+                            queue.lock().unwrap_or_else(PoisonError::into_inner).next()
+                        })
+                        .map(|(index, link)| (index, fetch_link(link)))
+                        .collect_vec()
+                    })
+                })
+                .collect_vec()
+                .into_iter()
+                .flat_map(|handle| {
+                    handle.join().unwrap_or_else(|_| {
+                        vec![(usize::MAX, Err(eyre!("fetching a link panicked")))]
+                    })
+                })
+                .collect_vec()
+        })
+        // in the order of `[[link]]`, not in the order that they were fetched
+        .into_iter()
+        .sorted_by_key(|(index, _)| *index)
+        .map(|(_, link)| link)
+        .partition_result::<Vec<_>, Vec<_>, _, _>()
+        .pipe(|(oks, errs)| {
+            errors.extend(errs);
+            oks
+        });
+
+        // links are locked on their first fetch, once they are applied
+        let mut newly_locked = NewlyLocked {
+            root: config.root.clone(),
+            links: BTreeMap::new(),
+        };
+        let links = links
+            .into_iter()
+            .map(|(link, locked)| {
+                if let Some(locked) = locked {
+                    newly_locked.links.insert(link.url.clone(), locked);
+                }
+                link
+            })
+            .collect_vec();
+
+        let ignored = config
+            .dirs
+            .iter()
+            .map(|dir| {
+                config
+                    .ignore
+                    .matcher(&config.root.join(&dir.input), &dir.ignore)
+            })
+            .collect::<Result<Vec<_>>>()
+            .map_err(single_err)?;
         let files = config
             .dirs
             .into_iter()
-            .flat_map(|crate::config::Dir { input, output }| {
-                walkdir::WalkDir::new(config.root.join(&input))
-                    .into_iter()
-                    .flatten()
-                    .filter(|dir_entry| dir_entry.file_type().is_file())
-                    .map(move |file| {
-                        // location of the `input` file
-                        let old_location = path::absolute(file.path())?;
-
-                        let contents =
-                            std::fs::read_to_string(&old_location).with_context(|| {
-                                eyre!("failed to read path {}", old_location.show())
-                            })?;
-
-                        Ok::<_, Error>(File {
-                            old_location,
-                            contents,
-                            output: output.clone(),
-                            input: input.clone(),
+            .zip(ignored)
+            .enumerate()
+            .flat_map(
+                |(
+                    dir,
+                    (
+                        crate::config::Dir {
+                            input,
+                            description,
+                            output,
+                            durable: dir_durable,
+                            readonly,
+                            immutable,
+                            also,
+                            engine,
+                            mode,
+                            line_endings,
+                            delimiters,
+                            phase,
+                            ..
+                        },
+                        ignored,
+                    ),
+                )| {
+                    // shell fragments are compiled instead of being written
+                    let fragments = fragments
+                        .as_ref()
+                        .map(|fragments| config.root.join(&input).join(fragments));
+
+                    walkdir::WalkDir::new(config.root.join(&input))
+                        .into_iter()
+                        .filter_entry(move |dir_entry| {
+                            !ignored.is_ignored(dir_entry.path(), dir_entry.file_type().is_dir())
                         })
-                    })
-            })
+                        .flatten()
+                        .filter(|dir_entry| dir_entry.file_type().is_file())
+                        .filter(move |dir_entry| {
+                            fragments
+                                .as_ref()
+                                .is_none_or(|fragments| !dir_entry.path().starts_with(fragments))
+                        })
+                        .map(move |file| {
+                            // location of the `input` file
+                            let old_location =
+                                path::absolute(file.path()).map_err(|err| (dir, err.into()))?;
+
+                            let contents = asset::read(&old_location).map_err(|err| (dir, err))?;
+
+                            // `.tmpl` files are templates, even if their `[[dir]]` is not
+                            let engine = match engine.unwrap_or(default_engine) {
+                                Engine::None
+                                    if matches!(contents, Input::Text(_))
+                                        && is_template(&old_location) =>
+                                {
+                                    Engine::Handlebars
+                                }
+                                engine => engine,
+                            };
+
+                            Ok::<_, (usize, Error)>(File {
+                                old_location,
+                                contents,
+                                output: output.clone(),
+                                input: input.clone(),
+                                dir,
+                                durable: dir_durable.unwrap_or(durable),
+                                description: description.clone(),
+                                readonly,
+                                immutable,
+                                engine,
+                                symlink: mode.unwrap_or(default_mode) == Mode::Symlink,
+                                line_endings,
+                                delimiters: delimiters.clone(),
+                                phase,
+                            })
+                        })
+                        .flat_map(move |file| {
+                            let Ok(file) = file else {
+                                return vec![file];
+                            };
+                            let copies = also
+                                .iter()
+                                .map(|output| {
+                                    Ok(File {
+                                        output: output.clone(),
+                                        ..file.clone()
+                                    })
+                                })
+                                .collect_vec();
+
+                            iter::once(Ok(file)).chain(copies).collect_vec()
+                        })
+                },
+            )
             .partition_result::<Vec<_>, Vec<_>, _, _>()
             .pipe(|(oks, errs)| {
-                errors.extend(errs);
+                for (dir, err) in errs {
+                    failed_dirs.insert(dir);
+                    errors.push(err);
+                }
                 oks
             });
 
-        if !errors.is_empty() {
+        // an `env` in `[vars]` predates environment variables in templates, and is kept
+        if !vars.contains_key(facts::ENV_VAR) {
+            let env = facts::env(
+                files.iter().filter_map(|file| file.contents.text()),
+                &environment,
+            );
+            vars.insert(facts::ENV_VAR.to_string(), env.into());
+            var_sources.insert(facts::ENV_VAR.to_string(), VarSource::Fact);
+        }
+
+        if config.on_error == OnError::Abort && !errors.is_empty() {
             return Err(errors);
         }
 
@@ -304,6 +1518,43 @@ impl World {
             root: config.root,
             links,
             files,
+            fonts,
+            shell,
+            completions: vec![],
+            durable,
+            fc_cache: config.fc_cache,
+            dconf,
+            defaults,
+            system,
+            autostart,
+            flatpak,
+            mimeapps,
+            gitconfig,
+            ssh,
+            timers,
+            on_error: config.on_error,
+            errors,
+            failed_dirs,
+            strict: config.strict || environment.strict,
+            scan_secrets: config.scan_secrets,
+            first_overwrite: config.first_overwrite,
+            confirm_overwrites: config.confirm_overwrites,
+            watchdog,
+            preserve_xattrs: config.preserve_xattrs,
+            diff_tool: config.diff,
+            prune: config.prune,
+            atomic_dirs,
+            exact,
+            protect,
+            windows_home: environment.windows.map(|windows| windows.home),
+            termux: environment.termux,
+            unused_inputs,
+            vars,
+            var_sources,
+            secrets,
+            cache: RenderCache::default(),
+            newly_locked,
+            profiles,
         })
     }
 }
@@ -314,3 +1565,19 @@ impl World {
 fn single_err(err: impl Into<Error>) -> Vec<Error> {
     vec![err.into()]
 }
+
+/// Whether the `template` mentions the variable `name`, as a word of its own
+fn mentions(template: &str, name: &str) -> bool {
+    let is_boundary = |ch: Option<char>| ch.is_none_or(|ch| !(ch.is_alphanumeric() || ch == '_'));
+
+    template.match_indices(name).any(|(start, _)| {
+        is_boundary(template[..start].chars().next_back())
+            && is_boundary(template[start + name.len()..].chars().next())
+    })
+}
+
+/// Whether the file at `path` is a template because of its `.tmpl` extension
+fn is_template(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "tmpl")
+}
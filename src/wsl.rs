@@ -0,0 +1,137 @@
+//! Contains [`Windows`]
+//!
+//! Inside of WSL, the Windows side of the machine is mounted at `/mnt/c`. A repo
+//! applied inside of WSL can also manage the configs of Windows apps, such as Windows
+//! Terminal, by writing to `{win_home}` and `{win_appdata}`. Windows apps expect CRLF
+//! line endings, so files written to the Windows side get them
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::LazyLock;
+
+use serde::Deserialize;
+
+use crate::environment::Environment;
+
+/// Directories of the Windows user, as seen from inside of WSL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Windows {
+    /// Home of the Windows user, e.g. `/mnt/c/Users/me`
+    pub home: PathBuf,
+    /// Roaming app data of the Windows user, e.g. `/mnt/c/Users/me/AppData/Roaming`
+    pub appdata: PathBuf,
+}
+
+/// The Windows side, once `dots` found out that it runs inside of WSL
+///
+/// Finding its directories runs `cmd.exe`, so it is only done once
+static WINDOWS: LazyLock<Option<Windows>> = LazyLock::new(|| {
+    let windows = Windows {
+        home: windows_dir("USERPROFILE")?,
+        appdata: windows_dir("APPDATA")?,
+    };
+    log::debug!("running inside of WSL, the Windows home is {windows:?}");
+
+    Some(windows)
+});
+
+/// The kernel is the one of WSL, which is only read once
+static MICROSOFT_KERNEL: LazyLock<bool> = LazyLock::new(|| {
+    fs::read_to_string("/proc/sys/kernel/osrelease")
+        .is_ok_and(|release| release.to_lowercase().contains("microsoft"))
+});
+
+/// Line endings of written files
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEndings {
+    /// `\n`, as on Linux and macOS
+    Lf,
+    /// `\r\n`, as on Windows
+    Crlf,
+}
+
+impl LineEndings {
+    /// Convert every line ending of `contents`
+    pub fn apply(self, contents: &str) -> String {
+        let lf = contents.replace("\r\n", "\n");
+        match self {
+            Self::Lf => lf,
+            Self::Crlf => lf.replace('\n', "\r\n"),
+        }
+    }
+
+    /// Line endings of a file at `path` which does not set them, where `windows` is the
+    /// Windows home inside of WSL
+    pub fn of(path: &Path, windows: Option<&Path>) -> Option<Self> {
+        windows
+            .is_some_and(|windows| path.starts_with(windows))
+            .then_some(Self::Crlf)
+    }
+}
+
+/// Whether the `environment` is inside of WSL
+fn is_wsl(environment: &Environment) -> bool {
+    if environment.os != "linux" {
+        return false;
+    }
+
+    environment.var("WSL_DISTRO_NAME").is_some() || *MICROSOFT_KERNEL
+}
+
+/// Directory in the environment variable `var` of Windows, as a path inside of WSL
+fn windows_dir(var: &str) -> Option<PathBuf> {
+    let output = Command::new("cmd.exe")
+        .args(["/c", &format!("echo %{var}%")])
+        .output()
+        .ok()?;
+    let windows_path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || windows_path.is_empty() || windows_path.contains('%') {
+        log::debug!("failed to obtain `%{var}%` of Windows");
+        return None;
+    }
+
+    let output = Command::new("wslpath")
+        .args(["-u", &windows_path])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+}
+
+/// The Windows side of the `environment`, if it is inside of WSL, see [`Environment::windows`]
+pub fn windows(environment: &Environment) -> Option<Windows> {
+    if !is_wsl(environment) {
+        return None;
+    }
+
+    WINDOWS.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(LineEndings::Crlf.apply("a\nb\r\nc"), "a\r\nb\r\nc");
+        assert_eq!(LineEndings::Lf.apply("a\r\nb\n"), "a\nb\n");
+
+        let home = Path::new("/mnt/c/Users/me");
+        assert_eq!(
+            LineEndings::of(
+                Path::new("/mnt/c/Users/me/AppData/Roaming/Code/User/settings.json"),
+                Some(home)
+            ),
+            Some(LineEndings::Crlf)
+        );
+        assert_eq!(
+            LineEndings::of(Path::new("/home/me/.bashrc"), Some(home)),
+            None
+        );
+        assert_eq!(LineEndings::of(Path::new("/mnt/c/Users/me/x"), None), None);
+    }
+}
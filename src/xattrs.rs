@@ -0,0 +1,100 @@
+//! Extended attributes of files, such as SELinux contexts (`security.selinux`)
+//! and user attributes (`user.*`)
+//!
+//! They are lost when a file is replaced, because the new file is a different file.
+
+use std::ffi::OsString;
+use std::path::Path;
+
+use eyre::{Context as _, Result, eyre};
+
+use crate::stdx::PathExt as _;
+
+/// Name and value of every extended attribute of a file
+pub type Xattrs = Vec<(OsString, Vec<u8>)>;
+
+/// Extended attributes of the file at `path`
+///
+/// Empty if the file does not exist, or the filesystem does not support them
+#[cfg(unix)]
+pub fn read(path: &Path) -> Xattrs {
+    let Ok(names) = xattr::list(path) else {
+        return vec![];
+    };
+
+    names
+        .filter_map(|name| {
+            let value = xattr::get(path, &name).ok()??;
+            Some((name, value))
+        })
+        .collect()
+}
+
+/// Extended attributes of the file at `path`
+#[cfg(not(unix))]
+pub fn read(_path: &Path) -> Xattrs {
+    vec![]
+}
+
+/// Attributes of `old` which the file at `path` does not have, or has with another value
+///
+/// A new file already gets some attributes, such as the default SELinux context of its
+/// directory, which are usually the same as the ones of the file it replaced
+pub fn dropped(path: &Path, old: Xattrs) -> Xattrs {
+    if old.is_empty() {
+        return old;
+    }
+    let new = read(path);
+
+    old.into_iter()
+        .filter(|attribute| !new.contains(attribute))
+        .collect()
+}
+
+/// Set every extended attribute of the file at `path`
+#[cfg(unix)]
+pub fn restore(path: &Path, xattrs: &Xattrs) -> Result<()> {
+    for (name, value) in xattrs {
+        xattr::set(path, name, value).with_context(|| {
+            eyre!(
+                "failed to restore extended attribute {} of {}",
+                name.to_string_lossy(),
+                path.show()
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Set every extended attribute of the file at `path`
+#[cfg(not(unix))]
+pub fn restore(_path: &Path, _xattrs: &Xattrs) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+
+    #[test]
+    fn restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo");
+        fs::write(&path, "foo").unwrap();
+
+        if xattr::set(&path, "user.dots", b"bar").is_err() {
+            // the filesystem does not support user attributes
+            return;
+        }
+        let xattrs = super::read(&path);
+
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, "foo").unwrap();
+        let dropped = super::dropped(&path, xattrs.clone());
+        assert!(dropped.contains(&("user.dots".into(), b"bar".to_vec())));
+
+        super::restore(&path, &dropped).unwrap();
+        assert!(super::dropped(&path, xattrs).is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+#![cfg(test)]
+//! Tests which set the XDG environment variables
+//!
+//! They are in their own binary, because changing the environment
+//! would affect all other tests running in parallel
+
+use std::{env, fs};
+
+use dots::{World, WritePath};
+use pretty_assertions::assert_eq;
+use tempfile::tempdir;
+
+#[test]
+#[cfg(not(any(target_os = "macos", windows)))]
+fn xdg_overrides() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let xdg = dir.join("xdg");
+
+    // SAFETY: this is the only test in this binary, no other threads read the environment
+    unsafe {
+        env::set_var("XDG_CONFIG_HOME", xdg.join("config"));
+        env::set_var("XDG_DATA_HOME", xdg.join("data"));
+        env::set_var("XDG_CACHE_HOME", xdg.join("cache"));
+        env::set_var("XDG_STATE_HOME", xdg.join("state"));
+    }
+
+    fs::write(
+        dir.join("dots.toml"),
+        r#"
+        [[dir]]
+        input = "configs"
+        output = "{config_dir}"
+        [[dir]]
+        input = "configs"
+        output = "{data_dir}"
+        [[dir]]
+        input = "configs"
+        output = "{cache_dir}"
+        [[dir]]
+        input = "configs"
+        output = "{state_dir}"
+        "#,
+    )
+    .unwrap();
+    fs::create_dir(dir.join("configs")).unwrap();
+    fs::write(dir.join("configs/foo.txt"), "foo").unwrap();
+
+    let mut writes = World::new(dir)
+        .unwrap()
+        .process()
+        .unwrap()
+        .writes()
+        .map(|WritePath { path, .. }| path.clone())
+        .collect::<Vec<_>>();
+    writes.sort();
+
+    assert_eq!(
+        writes,
+        ["cache", "config", "data", "state"].map(|base| xdg.join(base).join("foo.txt"))
+    );
+}